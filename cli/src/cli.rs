@@ -41,7 +41,7 @@ pub enum Subcommand {
 #[derive(Debug, StructOpt)]
 pub struct ValidationWorkerCommand {
 	#[allow(missing_docs)]
-	pub mem_id: String,
+	pub socket_path: String,
 }
 
 #[allow(missing_docs)]
@@ -84,6 +84,43 @@ pub struct RunCmd {
 	/// elapsed (i.e. until a block at height `pause_block + delay` is imported).
 	#[structopt(long = "grandpa-pause", number_of_values(2))]
 	pub grandpa_pause: Vec<u32>,
+
+	/// The number of workers used to validate parachain candidates in parallel.
+	#[structopt(long = "validation-workers")]
+	pub validation_workers: Option<usize>,
+
+	/// The time in seconds a worker may spend validating a single parachain
+	/// candidate before it is killed. This is a backstop against stalled
+	/// workers and must be well above the time it takes to exhaust the fuel
+	/// budget of a candidate.
+	#[structopt(long = "validation-timeout")]
+	pub validation_timeout: Option<u64>,
+
+	/// The maximum size in bytes of parachain validation code accepted by the
+	/// validation workers.
+	#[structopt(long = "validation-max-code-size")]
+	pub validation_max_code_size: Option<usize>,
+
+	/// The maximum size in bytes of the encoded parameters passed to the
+	/// validation workers.
+	#[structopt(long = "validation-max-params-size")]
+	pub validation_max_params_size: Option<usize>,
+
+	/// Do not restart validation workers which exited or were killed after a
+	/// timeout.
+	#[structopt(long = "validation-no-respawn")]
+	pub validation_no_respawn: bool,
+
+	/// Do not restrict the privileges of validation workers. Only use this if
+	/// the system does not support the restrictions.
+	#[structopt(long = "validation-no-sandbox")]
+	pub validation_no_sandbox: bool,
+
+	/// Let validation workers keep the network namespace of the node if they
+	/// can not be moved into a new one, instead of failing to start. Creating
+	/// sockets is forbidden to them in any case.
+	#[structopt(long = "validation-allow-shared-network")]
+	pub validation_allow_shared_network: bool,
 }
 
 #[allow(missing_docs)]
@@ -118,6 +118,24 @@ pub fn run() -> Result<()> {
 				Some((cli.run.grandpa_pause[0], cli.run.grandpa_pause[1]))
 			};
 
+			let mut validation_pool_config = service::ValidationPoolConfig::default();
+			if let Some(workers) = cli.run.validation_workers {
+				validation_pool_config.num_workers = workers;
+			}
+			if let Some(timeout) = cli.run.validation_timeout {
+				validation_pool_config.execution_timeout = std::time::Duration::from_secs(timeout);
+			}
+			if let Some(max_code_size) = cli.run.validation_max_code_size {
+				validation_pool_config.max_code_size = max_code_size;
+			}
+			if let Some(max_params_size) = cli.run.validation_max_params_size {
+				validation_pool_config.max_params_size = max_params_size;
+			}
+			validation_pool_config.respawn_workers = !cli.run.validation_no_respawn;
+			validation_pool_config.sandbox.enabled = !cli.run.validation_no_sandbox;
+			validation_pool_config.sandbox.allow_shared_network =
+				cli.run.validation_allow_shared_network;
+
 			if chain_spec.is_kusama() {
 				info!("----------------------------");
 				info!("This chain is not in any way");
@@ -135,6 +153,7 @@ pub fn run() -> Result<()> {
 						authority_discovery_disabled,
 						6000,
 						grandpa_pause,
+						validation_pool_config,
 					).map(|(components, _, _)| components)
 				})
 			} else if chain_spec.is_westend() {
@@ -148,6 +167,7 @@ pub fn run() -> Result<()> {
 						authority_discovery_disabled,
 						6000,
 						grandpa_pause,
+						validation_pool_config,
 					).map(|(components, _, _)| components)
 				})
 			} else {
@@ -161,6 +181,7 @@ pub fn run() -> Result<()> {
 						authority_discovery_disabled,
 						6000,
 						grandpa_pause,
+						validation_pool_config,
 					).map(|(components, _, _)| components)
 				})
 			}
@@ -204,7 +225,7 @@ pub fn run() -> Result<()> {
 				Err(sc_cli::Error::Input("Cannot run validation worker in browser".into()))
 			} else {
 				#[cfg(all(not(feature = "browser"), not(feature = "service-rewr")))]
-				service::run_validation_worker(&cmd.mem_id)?;
+				service::run_validation_worker(&cmd.socket_path)?;
 				Ok(())
 			}
 		},
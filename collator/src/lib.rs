@@ -401,6 +401,7 @@ where
 			false,
 			6000,
 			None,
+			Default::default(),
 		)?;
 		let spawn_handle = task_manager.spawn_handle();
 		let future = build_collator_service(
@@ -420,6 +421,7 @@ where
 			false,
 			6000,
 			None,
+			Default::default(),
 		)?;
 		let spawn_handle = task_manager.spawn_handle();
 		let future = build_collator_service(
@@ -439,6 +441,7 @@ where
 			false,
 			6000,
 			None,
+			Default::default(),
 		)?;
 		let spawn_handle = task_manager.spawn_handle();
 		let future = build_collator_service(
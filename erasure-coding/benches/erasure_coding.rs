@@ -0,0 +1,103 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use polkadot_erasure_coding::{
+	obtain_chunks_v0, reconstruct_from_systematic_chunks_v0, reconstruct_v0,
+	systematic_recovery_threshold,
+};
+use primitives::v0::{AvailableData, BlockData, PoVBlock};
+
+const N_VALIDATORS: &[usize] = &[10, 100, 1000];
+
+const PAYLOAD_SIZES: &[usize] = &[1024, 64 * 1024, 1024 * 1024, 5 * 1024 * 1024];
+
+fn available_data(size: usize) -> AvailableData {
+	AvailableData {
+		pov_block: PoVBlock {
+			block_data: BlockData((0..size).map(|i| i as u8).collect()),
+		},
+		omitted_validation: Default::default(),
+	}
+}
+
+fn cases() -> impl Iterator<Item = (usize, usize)> {
+	N_VALIDATORS.iter().flat_map(|&n| PAYLOAD_SIZES.iter().map(move |&size| (n, size)))
+}
+
+fn id(n_validators: usize, size: usize) -> BenchmarkId {
+	BenchmarkId::new(format!("{}_validators", n_validators), format!("{}_bytes", size))
+}
+
+fn encode(c: &mut Criterion) {
+	let mut group = c.benchmark_group("encode");
+	group.sample_size(10);
+
+	for (n_validators, size) in cases() {
+		let data = available_data(size);
+		group.throughput(Throughput::Bytes(size as u64));
+		group.bench_with_input(id(n_validators, size), &data, |b, data| {
+			b.iter(|| obtain_chunks_v0(n_validators, data).unwrap())
+		});
+	}
+
+	group.finish();
+}
+
+fn reconstruct(c: &mut Criterion) {
+	let mut group = c.benchmark_group("reconstruct");
+	group.sample_size(10);
+
+	for (n_validators, size) in cases() {
+		let chunks = obtain_chunks_v0(n_validators, &available_data(size)).unwrap();
+		let threshold = systematic_recovery_threshold(n_validators).unwrap();
+		// the last chunks, so that as many data shards as possible are missing.
+		let present: Vec<_> = chunks.iter()
+			.enumerate()
+			.skip(n_validators - threshold)
+			.map(|(index, chunk)| (&chunk[..], index))
+			.collect();
+
+		group.throughput(Throughput::Bytes(size as u64));
+		group.bench_with_input(id(n_validators, size), &present, |b, present| {
+			b.iter(|| reconstruct_v0(n_validators, present.iter().cloned()).unwrap())
+		});
+	}
+
+	group.finish();
+}
+
+fn reconstruct_systematic(c: &mut Criterion) {
+	let mut group = c.benchmark_group("reconstruct_systematic");
+	group.sample_size(10);
+
+	for (n_validators, size) in cases() {
+		let chunks = obtain_chunks_v0(n_validators, &available_data(size)).unwrap();
+
+		group.throughput(Throughput::Bytes(size as u64));
+		group.bench_with_input(id(n_validators, size), &chunks, |b, chunks| {
+			b.iter(|| reconstruct_from_systematic_chunks_v0(
+				n_validators,
+				chunks.iter().map(|chunk| &chunk[..]),
+			).unwrap())
+		});
+	}
+
+	group.finish();
+}
+
+criterion_group!(benches, encode, reconstruct, reconstruct_systematic);
+criterion_main!(benches);
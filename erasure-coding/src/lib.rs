@@ -24,6 +24,8 @@
 //! f is the maximum number of faulty validators in the system.
 //! The data is coded so any f+1 chunks can be used to reconstruct the full data.
 
+use std::collections::HashSet;
+
 use codec::{Encode, Decode};
 use reed_solomon::galois_16::{self, ReedSolomon};
 use primitives::v0::{self, Hash as H256, BlakeTwo256, HashT};
@@ -33,6 +35,9 @@ use trie::{EMPTY_PREFIX, MemoryDB, Trie, TrieMut, trie_types::{TrieDBMut, TrieDB
 
 use self::wrapped_shard::WrappedShard;
 
+pub use self::streaming::{ChunkEncoder, Reconstructor};
+
+mod streaming;
 mod wrapped_shard;
 
 // we are limited to the field order of GF(2^16), which is 65536
@@ -86,24 +91,6 @@ impl CodeParams {
 		needed_shard_len + needed_shard_len % 2
 	}
 
-	fn make_shards_for(&self, payload: &[u8]) -> Vec<WrappedShard> {
-		let shard_len = self.shard_len(payload.len());
-		let mut shards = vec![
-			WrappedShard::new(vec![0; shard_len]);
-			self.data_shards + self.parity_shards
-		];
-
-		for (data_chunk, blank_shard) in payload.chunks(shard_len).zip(&mut shards) {
-			// fill the empty shards with the corresponding piece of the payload,
-			// zero-padded to fit in the shards.
-			let len = std::cmp::min(shard_len, data_chunk.len());
-			let blank_shard: &mut [u8] = blank_shard.as_mut();
-			blank_shard[..len].copy_from_slice(&data_chunk[..len]);
-		}
-
-		shards
-	}
-
 	// make a reed-solomon instance.
 	fn make_encoder(&self) -> ReedSolomon {
 		ReedSolomon::new(self.data_shards, self.parity_shards)
@@ -124,6 +111,14 @@ fn code_params(n_validators: usize) -> Result<CodeParams, Error> {
 	})
 }
 
+/// The number of chunks needed to reconstruct a payload erasure-coded for the given number of
+/// validators. The chunks with indices below this number hold the payload itself.
+///
+/// Works only up to 65536 validators, and `n_validators` must be non-zero.
+pub fn systematic_recovery_threshold(n_validators: usize) -> Result<usize, Error> {
+	code_params(n_validators).map(|params| params.data_shards)
+}
+
 /// Obtain erasure-coded chunks for v0 `AvailableData`, one for each validator.
 ///
 /// Works only up to 65536 validators, and `n_validators` must be non-zero.
@@ -149,16 +144,7 @@ fn obtain_chunks<T: Encode>(n_validators: usize, data: &T)
 	-> Result<Vec<Vec<u8>>, Error>
 {
 	let params = code_params(n_validators)?;
-	let encoded = data.encode();
-
-	if encoded.is_empty() {
-		return Err(Error::BadPayload);
-	}
-
-	let mut shards = params.make_shards_for(&encoded[..]);
-
-	params.make_encoder().encode(&mut shards[..])
-		.expect("Payload non-empty, shard sizes are uniform, and validator numbers checked; qed");
+	let shards = streaming::encode_shards(&params, data)?;
 
 	Ok(shards.into_iter().map(|w| w.into_inner()).collect())
 }
@@ -201,47 +187,76 @@ pub fn reconstruct_v1<'a, I: 'a>(n_validators: usize, chunks: I)
 fn reconstruct<'a, I: 'a, T: Decode>(n_validators: usize, chunks: I) -> Result<T, Error>
 	where I: IntoIterator<Item=(&'a [u8], usize)>
 {
-	let params = code_params(n_validators)?;
-	let mut shards: Vec<Option<WrappedShard>> = vec![None; n_validators];
-	let mut shard_len = None;
+	let mut reconstructor = Reconstructor::new(n_validators)?;
 	for (chunk_data, chunk_idx) in chunks.into_iter().take(n_validators) {
-		if chunk_idx >= n_validators {
-			return Err(Error::ChunkIndexOutOfBounds(chunk_idx, n_validators));
-		}
+		reconstructor.add_chunk(chunk_idx, chunk_data)?;
+	}
 
-		let shard_len = shard_len.get_or_insert_with(|| chunk_data.len());
+	reconstructor.reconstruct()
+}
 
-		if *shard_len % 2 != 0 {
-			return Err(Error::UnevenLength);
-		}
+/// Reconstruct the v0 available data from the systematic chunks, without decoding.
+///
+/// Provide an iterator containing the data of the chunks with indices from 0 up to
+/// [`systematic_recovery_threshold`], in order. Any further chunks are ignored.
+///
+/// Works only up to 65536 validators, and `n_validators` must be non-zero.
+pub fn reconstruct_from_systematic_chunks_v0<'a, I: 'a>(n_validators: usize, chunks: I)
+	-> Result<v0::AvailableData, Error>
+	where I: IntoIterator<Item=&'a [u8]>
+{
+	reconstruct_from_systematic_chunks(n_validators, chunks)
+}
 
-		if *shard_len != chunk_data.len() || *shard_len == 0 {
-			return Err(Error::NonUniformChunks);
-		}
+/// Reconstruct the v1 available data from the systematic chunks, without decoding.
+///
+/// Provide an iterator containing the data of the chunks with indices from 0 up to
+/// [`systematic_recovery_threshold`], in order. Any further chunks are ignored.
+///
+/// Works only up to 65536 validators, and `n_validators` must be non-zero.
+pub fn reconstruct_from_systematic_chunks_v1<'a, I: 'a>(n_validators: usize, chunks: I)
+	-> Result<v1::AvailableData, Error>
+	where I: IntoIterator<Item=&'a [u8]>
+{
+	reconstruct_from_systematic_chunks(n_validators, chunks)
+}
+
+/// Reconstruct decodable data from the systematic chunks.
+///
+/// The systematic chunks are the data shards, so the payload is their concatenation.
+///
+/// Works only up to 65536 validators, and `n_validators` must be non-zero.
+fn reconstruct_from_systematic_chunks<'a, I: 'a, T: Decode>(n_validators: usize, chunks: I)
+	-> Result<T, Error>
+	where I: IntoIterator<Item=&'a [u8]>
+{
+	let params = code_params(n_validators)?;
+	let chunks: Vec<&[u8]> = chunks.into_iter().take(params.data_shards).collect();
 
-		shards[chunk_idx] = Some(WrappedShard::new(chunk_data.to_vec()));
+	if chunks.len() < params.data_shards {
+		return Err(Error::NotEnoughChunks);
 	}
 
-	if let Err(e) = params.make_encoder().reconstruct(&mut shards[..]) {
-		match e {
-			reed_solomon::Error::TooFewShardsPresent => Err(Error::NotEnoughChunks)?,
-			reed_solomon::Error::InvalidShardFlags => Err(Error::WrongValidatorCount)?,
-			reed_solomon::Error::TooManyShards => Err(Error::TooManyChunks)?,
-			reed_solomon::Error::EmptyShard => panic!("chunks are all non-empty; this is checked above; qed"),
-			reed_solomon::Error::IncorrectShardSize => panic!("chunks are all same len; this is checked above; qed"),
-			_ => panic!("reed_solomon encoder returns no more variants for this function; qed"),
-		}
+	let shard_len = chunks[0].len();
+	if shard_len % 2 != 0 {
+		return Err(Error::UnevenLength);
 	}
 
-	// lazily decode from the data shards.
+	if shard_len == 0 || chunks.iter().any(|chunk| chunk.len() != shard_len) {
+		return Err(Error::NonUniformChunks);
+	}
+
+	decode_from_shards(shard_len * params.data_shards, chunks.into_iter())
+}
+
+// lazily decode from the data shards, which must hold `len` bytes in total.
+fn decode_from_shards<'a, T: Decode>(len: usize, shards: impl Iterator<Item=&'a [u8]>)
+	-> Result<T, Error>
+{
 	Decode::decode(&mut ShardInput {
-		remaining_len: shard_len.map(|s| s * params.data_shards).unwrap_or(0),
+		remaining_len: len,
 		cur_shard: None,
-		shards: shards.iter()
-			.map(|x| x.as_ref())
-			.take(params.data_shards)
-			.map(|x| x.expect("all data shards have been recovered; qed"))
-			.map(|x| x.as_ref()),
+		shards,
 	}).or_else(|_| Err(Error::BadPayload))
 }
 
@@ -257,33 +272,23 @@ pub struct Branches<'a, I> {
 impl<'a, I: AsRef<[u8]>> Branches<'a, I> {
 	/// Get the trie root.
 	pub fn root(&self) -> H256 { self.root.clone() }
+
+	/// Get a compact proof of the chunks with the given indices.
+	pub fn compact_proof(&self, indices: &[usize]) -> Result<CompactProof, Error> {
+		compact_proof(&self.trie_storage, &self.root, self.chunks.len(), indices)
+	}
 }
 
 impl<'a, I: AsRef<[u8]>> Iterator for Branches<'a, I> {
 	type Item = (Vec<Vec<u8>>, &'a [u8]);
 
 	fn next(&mut self) -> Option<Self::Item> {
-		use trie::Recorder;
-
-		let trie = TrieDB::new(&self.trie_storage, &self.root)
-			.expect("`Branches` is only created with a valid memorydb that contains all nodes for the trie with given root; qed");
-
-		let mut recorder = Recorder::new();
-		let res = (self.current_pos as u32).using_encoded(|s|
-			trie.get_with(s, &mut recorder)
-		);
+		let nodes = branch_proof(&self.trie_storage, &self.root, self.current_pos)?;
+		let chunk = self.chunks.get(self.current_pos)
+			.expect("there is a one-to-one mapping of chunks to valid merkle branches; qed");
 
-		match res.expect("all nodes in trie present; qed") {
-			Some(_) => {
-				let nodes = recorder.drain().into_iter().map(|r| r.data).collect();
-				let chunk = self.chunks.get(self.current_pos)
-					.expect("there is a one-to-one mapping of chunks to valid merkle branches; qed");
-
-				self.current_pos += 1;
-				Some((nodes, chunk.as_ref()))
-			}
-			None => None,
-		}
+		self.current_pos += 1;
+		Some((nodes, chunk.as_ref()))
 	}
 }
 
@@ -292,13 +297,24 @@ impl<'a, I: AsRef<[u8]>> Iterator for Branches<'a, I> {
 pub fn branches<'a, I: 'a>(chunks: &'a [I]) -> Branches<'a, I>
 	where I: AsRef<[u8]>,
 {
+	let (trie_storage, root) = chunk_trie(chunks);
+
+	Branches {
+		trie_storage,
+		root,
+		chunks: chunks,
+		current_pos: 0,
+	}
+}
+
+// construct a trie mapping each chunk's index to its hash.
+fn chunk_trie<I: AsRef<[u8]>>(chunks: &[I]) -> (MemoryDB<Blake2Hasher>, H256) {
 	let mut trie_storage: MemoryDB<Blake2Hasher> = MemoryDB::default();
 	let mut root = H256::default();
 
-	// construct trie mapping each chunk's index to its hash.
 	{
 		let mut trie = TrieDBMut::new(&mut trie_storage, &mut root);
-		for (i, chunk) in chunks.as_ref().iter().enumerate() {
+		for (i, chunk) in chunks.iter().enumerate() {
 			(i as u32).using_encoded(|encoded_index| {
 				let chunk_hash = BlakeTwo256::hash(chunk.as_ref());
 				trie.insert(encoded_index, chunk_hash.as_ref())
@@ -307,23 +323,117 @@ pub fn branches<'a, I: 'a>(chunks: &'a [I]) -> Branches<'a, I>
 		}
 	}
 
-	Branches {
-		trie_storage,
-		root,
-		chunks: chunks,
-		current_pos: 0,
+	(trie_storage, root)
+}
+
+// the merkle branch of the chunk with the given index, if there is such a chunk.
+fn branch_proof(
+	trie_storage: &MemoryDB<Blake2Hasher>,
+	root: &H256,
+	index: usize,
+) -> Option<Vec<Vec<u8>>> {
+	use trie::Recorder;
+
+	let trie = TrieDB::new(trie_storage, root)
+		.expect("only called with a valid memorydb that contains all nodes for the trie with given root; qed");
+
+	let mut recorder = Recorder::new();
+	let res = (index as u32).using_encoded(|s|
+		trie.get_with(s, &mut recorder)
+	);
+
+	res.expect("all nodes in trie present; qed")
+		.map(|_| recorder.drain().into_iter().map(|r| r.data).collect())
+}
+
+// a compact proof of the chunks with the given indices, if they all exist.
+fn compact_proof(
+	trie_storage: &MemoryDB<Blake2Hasher>,
+	root: &H256,
+	n_chunks: usize,
+	indices: &[usize],
+) -> Result<CompactProof, Error> {
+	let branches = indices.iter()
+		.map(|&index| branch_proof(trie_storage, root, index)
+			.ok_or(Error::ChunkIndexOutOfBounds(index, n_chunks))
+		)
+		.collect::<Result<Vec<_>, _>>()?;
+
+	Ok(CompactProof::from_branches(branches.iter().map(|branch| &branch[..])))
+}
+
+/// Merkle branches of several chunks, containing every trie node only once.
+///
+/// Nodes close to the root are part of the branches of many chunks, so a compact proof of
+/// several chunks is considerably smaller than their separate branches.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct CompactProof {
+	/// The trie nodes of all branches.
+	pub nodes: Vec<Vec<u8>>,
+}
+
+impl CompactProof {
+	/// Merge the given merkle branches, dropping nodes shared between them.
+	pub fn from_branches<'a>(branches: impl IntoIterator<Item=&'a [Vec<u8>]>) -> Self {
+		let mut seen = HashSet::new();
+		let nodes = branches.into_iter()
+			.flatten()
+			.filter(|node| seen.insert(*node))
+			.cloned()
+			.collect();
+
+		CompactProof { nodes }
 	}
 }
 
 /// Verify a merkle branch, yielding the chunk hash meant to be present at that
 /// index.
 pub fn branch_hash(root: &H256, branch_nodes: &[Vec<u8>], index: usize) -> Result<H256, Error> {
+	proven_hash(&proof_storage(branch_nodes), root, index)
+}
+
+/// Verify that the chunk with the given index belongs to the erasure-coding with the given root.
+///
+/// Any modification of the chunk or the merkle branch leads to `Error::InvalidBranchProof`.
+pub fn verify_chunk(root: &H256, index: usize, chunk: &[u8], proof: &[Vec<u8>]) -> Result<(), Error> {
+	if branch_hash(root, proof, index)? == BlakeTwo256::hash(chunk) {
+		Ok(())
+	} else {
+		Err(Error::InvalidBranchProof)
+	}
+}
+
+/// Verify that all given chunks, along with their indices, belong to the erasure-coding with the
+/// given root.
+///
+/// Any modification of the chunks or the proof leads to `Error::InvalidBranchProof`.
+pub fn verify_chunks<'a, I: 'a>(root: &H256, chunks: I, proof: &CompactProof) -> Result<(), Error>
+	where I: IntoIterator<Item=(usize, &'a [u8])>
+{
+	let trie_storage = proof_storage(&proof.nodes);
+
+	for (index, chunk) in chunks {
+		if proven_hash(&trie_storage, root, index)? != BlakeTwo256::hash(chunk) {
+			return Err(Error::InvalidBranchProof);
+		}
+	}
+
+	Ok(())
+}
+
+// the storage of a trie containing the given nodes.
+fn proof_storage(nodes: &[Vec<u8>]) -> MemoryDB<Blake2Hasher> {
 	let mut trie_storage: MemoryDB<Blake2Hasher> = MemoryDB::default();
-	for node in branch_nodes.iter() {
+	for node in nodes.iter() {
 		(&mut trie_storage as &mut trie::HashDB<_>).insert(EMPTY_PREFIX, node.as_slice());
 	}
 
-	let trie = TrieDB::new(&trie_storage, &root).map_err(|_| Error::InvalidBranchProof)?;
+	trie_storage
+}
+
+// the chunk hash proven to be present at the given index.
+fn proven_hash(trie_storage: &MemoryDB<Blake2Hasher>, root: &H256, index: usize) -> Result<H256, Error> {
+	let trie = TrieDB::new(trie_storage, root).map_err(|_| Error::InvalidBranchProof)?;
 	let res = (index as u32).using_encoded(|key|
 		trie.get_with(key, |raw_hash: &[u8]| H256::decode(&mut &raw_hash[..]))
 	);
@@ -389,6 +499,7 @@ impl<'a, I: Iterator<Item=&'a [u8]>> codec::Input for ShardInput<'a, I> {
 mod tests {
 	use super::*;
 	use primitives::v0::{AvailableData, BlockData, PoVBlock};
+	use quickcheck::TestResult;
 
 	#[test]
 	fn field_order_is_right_size() {
@@ -478,6 +589,37 @@ mod tests {
 		assert_eq!(reconstructed, available_data);
 	}
 
+	#[test]
+	fn systematic_round_trip_works() {
+		let available_data = AvailableData {
+			pov_block: PoVBlock {
+				block_data: BlockData((0..255).collect()),
+			},
+			omitted_validation: Default::default(),
+		};
+		let chunks = obtain_chunks(10, &available_data).unwrap();
+
+		assert_eq!(systematic_recovery_threshold(10), Ok(4));
+
+		let reconstructed = reconstruct_from_systematic_chunks_v0(
+			10,
+			chunks.iter().map(|chunk| &chunk[..]),
+		).unwrap();
+		assert_eq!(reconstructed, available_data);
+
+		assert_eq!(
+			reconstruct_from_systematic_chunks_v0(10, chunks[..3].iter().map(|chunk| &chunk[..])),
+			Err(Error::NotEnoughChunks),
+		);
+		assert_eq!(
+			reconstruct_from_systematic_chunks_v0(
+				10,
+				[&chunks[0][..], &chunks[1][..], &chunks[2][..], &chunks[3][2..]].iter().cloned(),
+			),
+			Err(Error::NonUniformChunks),
+		);
+	}
+
 	#[test]
 	fn construct_valid_branches() {
 		let pov_block = PoVBlock {
@@ -507,4 +649,147 @@ mod tests {
 			assert_eq!(branch_hash(&root, &proof, i).unwrap(), BlakeTwo256::hash(&chunks[i]));
 		}
 	}
+
+	// between 2 and 50 validators. The data of a single validator has no parity shards, which the
+	// Reed-Solomon codec does not support.
+	fn n_validators_of(n_validators: u8) -> usize {
+		n_validators as usize % 49 + 2
+	}
+
+	fn chunks_of(data: Vec<u8>, n_validators: usize) -> Vec<Vec<u8>> {
+		let available_data = AvailableData {
+			pov_block: PoVBlock { block_data: BlockData(data) },
+			omitted_validation: Default::default(),
+		};
+
+		obtain_chunks(n_validators, &available_data).unwrap()
+	}
+
+	// flip the bits of the byte at `pos` of any of the given byte strings, counted across all.
+	fn tamper(bytes: &mut [Vec<u8>], pos: usize, mask: u8) {
+		let total: usize = bytes.iter().map(|b| b.len()).sum();
+		let mut pos = pos % total;
+
+		for b in bytes.iter_mut() {
+			if pos < b.len() {
+				b[pos] ^= mask.max(1);
+				return;
+			}
+			pos -= b.len();
+		}
+	}
+
+	#[test]
+	fn verify_chunk_works() {
+		let chunks = chunks_of(vec![2; 256], 10);
+		let branches = branches(&chunks);
+		let root = branches.root();
+
+		for (i, (proof, chunk)) in branches.enumerate() {
+			assert_eq!(verify_chunk(&root, i, chunk, &proof), Ok(()));
+			assert_eq!(
+				verify_chunk(&root, (i + 1) % 10, chunk, &proof),
+				Err(Error::InvalidBranchProof),
+			);
+		}
+	}
+
+	#[test]
+	fn compact_proof_deduplicates_nodes() {
+		let chunks = chunks_of(vec![2; 256], 100);
+		let branches = branches(&chunks);
+		let root = branches.root();
+		let indices: Vec<usize> = (0..100).step_by(3).collect();
+
+		let proof = branches.compact_proof(&indices).unwrap();
+		let separate: usize = branches.take(100)
+			.enumerate()
+			.filter(|(i, _)| indices.contains(i))
+			.map(|(_, (proof, _))| proof.len())
+			.sum();
+
+		assert!(proof.nodes.len() < separate);
+		assert_eq!(
+			verify_chunks(&root, indices.iter().map(|&i| (i, &chunks[i][..])), &proof),
+			Ok(()),
+		);
+		// chunks which are not part of the proof are not verified.
+		assert!(verify_chunks(&root, vec![(1, &chunks[1][..])], &proof).is_err());
+	}
+
+	#[test]
+	fn compact_proof_of_missing_chunk_fails() {
+		let chunks = chunks_of(vec![2; 256], 10);
+
+		assert_eq!(
+			branches(&chunks).compact_proof(&[3, 10]),
+			Err(Error::ChunkIndexOutOfBounds(10, 10)),
+		);
+	}
+
+	#[test]
+	fn tampered_chunk_is_detected() {
+		fn prop(data: Vec<u8>, n_validators: u8, index: usize, pos: usize, mask: u8) -> bool {
+			let n_validators = n_validators_of(n_validators);
+			let index = index % n_validators;
+			let chunks = chunks_of(data, n_validators);
+			let mut branches = branches(&chunks);
+			let root = branches.root();
+			let (proof, chunk) = branches.nth(index).unwrap();
+
+			let mut chunk = vec![chunk.to_vec()];
+			tamper(&mut chunk, pos, mask);
+
+			verify_chunk(&root, index, &chunk[0], &proof) == Err(Error::InvalidBranchProof)
+		}
+
+		quickcheck::quickcheck(prop as fn(Vec<u8>, u8, usize, usize, u8) -> bool);
+	}
+
+	#[test]
+	fn tampered_branch_is_detected() {
+		fn prop(data: Vec<u8>, n_validators: u8, index: usize, pos: usize, mask: u8) -> bool {
+			let n_validators = n_validators_of(n_validators);
+			let index = index % n_validators;
+			let chunks = chunks_of(data, n_validators);
+			let mut branches = branches(&chunks);
+			let root = branches.root();
+			let (mut proof, chunk) = branches.nth(index).unwrap();
+
+			tamper(&mut proof, pos, mask);
+
+			verify_chunk(&root, index, chunk, &proof) == Err(Error::InvalidBranchProof)
+		}
+
+		quickcheck::quickcheck(prop as fn(Vec<u8>, u8, usize, usize, u8) -> bool);
+	}
+
+	#[test]
+	fn tampered_compact_proof_is_detected() {
+		fn prop(data: Vec<u8>, n_validators: u8, indices: Vec<usize>, pos: usize, mask: u8) -> TestResult {
+			if indices.is_empty() {
+				return TestResult::discard();
+			}
+
+			let n_validators = n_validators_of(n_validators);
+			let indices: Vec<usize> = indices.into_iter().map(|i| i % n_validators).collect();
+			let chunks = chunks_of(data, n_validators);
+			let branches = branches(&chunks);
+			let root = branches.root();
+			let mut proof = branches.compact_proof(&indices).unwrap();
+			let present = || indices.iter().map(|&i| (i, &chunks[i][..]));
+
+			if verify_chunks(&root, present(), &proof) != Ok(()) {
+				return TestResult::failed();
+			}
+
+			tamper(&mut proof.nodes, pos, mask);
+
+			TestResult::from_bool(
+				verify_chunks(&root, present(), &proof) == Err(Error::InvalidBranchProof)
+			)
+		}
+
+		quickcheck::quickcheck(prop as fn(Vec<u8>, u8, Vec<usize>, usize, u8) -> TestResult);
+	}
 }
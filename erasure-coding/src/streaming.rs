@@ -0,0 +1,364 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Incremental erasure coding of large payloads.
+//!
+//! A [`ChunkEncoder`] encodes a payload once and then hands out single chunks along with their
+//! Merkle proofs on request. A [`Reconstructor`] collects chunks one at a time as they arrive and
+//! tells when enough of them are present to reconstruct the payload.
+
+use codec::{Encode, Decode};
+use primitives::v0::{self, Hash as H256, ErasureChunk};
+use primitives::v1;
+use sp_core::Blake2Hasher;
+use trie::MemoryDB;
+
+use super::{
+	CodeParams, CompactProof, Error, WrappedShard, branch_proof, chunk_trie, code_params,
+	compact_proof, decode_from_shards,
+};
+
+/// Erasure-coded chunks of a payload, handed out one at a time along with their Merkle proofs.
+///
+/// The payload is encoded directly into the data shards, so no separate copy of its encoding is
+/// kept.
+pub struct ChunkEncoder {
+	shards: Vec<WrappedShard>,
+	trie_storage: MemoryDB<Blake2Hasher>,
+	root: H256,
+}
+
+impl ChunkEncoder {
+	/// Erasure-code v0 `AvailableData` for the given number of validators.
+	///
+	/// Works only up to 65536 validators, and `n_validators` must be non-zero.
+	pub fn new_v0(n_validators: usize, data: &v0::AvailableData) -> Result<Self, Error> {
+		Self::new(n_validators, data)
+	}
+
+	/// Erasure-code v1 `AvailableData` for the given number of validators.
+	///
+	/// Works only up to 65536 validators, and `n_validators` must be non-zero.
+	pub fn new_v1(n_validators: usize, data: &v1::AvailableData) -> Result<Self, Error> {
+		Self::new(n_validators, data)
+	}
+
+	fn new<T: Encode>(n_validators: usize, data: &T) -> Result<Self, Error> {
+		let shards = encode_shards(&code_params(n_validators)?, data)?;
+		let (trie_storage, root) = chunk_trie(&shards);
+
+		Ok(ChunkEncoder { shards, trie_storage, root })
+	}
+
+	/// The root of the Merkle tree of all chunks.
+	pub fn root(&self) -> H256 {
+		self.root
+	}
+
+	/// The number of chunks, i.e. the number of validators.
+	pub fn n_chunks(&self) -> usize {
+		self.shards.len()
+	}
+
+	/// Get the chunk with the given index along with its Merkle proof.
+	pub fn chunk(&self, index: usize) -> Result<ErasureChunk, Error> {
+		let shard = self.shards.get(index)
+			.ok_or(Error::ChunkIndexOutOfBounds(index, self.shards.len()))?;
+
+		Ok(ErasureChunk {
+			chunk: AsRef::<[u8]>::as_ref(shard).to_vec(),
+			index: index as u32,
+			proof: branch_proof(&self.trie_storage, &self.root, index)
+				.expect("there is a one-to-one mapping of chunks to valid merkle branches; qed"),
+		})
+	}
+
+	/// Get a compact proof of the chunks with the given indices.
+	pub fn compact_proof(&self, indices: &[usize]) -> Result<CompactProof, Error> {
+		compact_proof(&self.trie_storage, &self.root, self.shards.len(), indices)
+	}
+
+	/// Get all chunks, without their proofs.
+	pub fn into_chunks(self) -> Vec<Vec<u8>> {
+		self.shards.into_iter().map(|shard| shard.into_inner()).collect()
+	}
+}
+
+/// Encode the given data into shards and compute the parity shards.
+pub(crate) fn encode_shards<T: Encode>(
+	params: &CodeParams,
+	data: &T,
+) -> Result<Vec<WrappedShard>, Error> {
+	let encoded_len = data.encoded_size();
+	if encoded_len == 0 {
+		return Err(Error::BadPayload);
+	}
+
+	let shard_len = params.shard_len(encoded_len);
+	let mut shards = vec![
+		WrappedShard::new(vec![0; shard_len]);
+		params.data_shards + params.parity_shards
+	];
+
+	data.encode_to(&mut ShardOutput {
+		shards: &mut shards[..params.data_shards],
+		shard_len,
+		written: 0,
+	});
+
+	params.make_encoder().encode(&mut shards[..])
+		.expect("Payload non-empty, shard sizes are uniform, and validator numbers checked; qed");
+
+	Ok(shards)
+}
+
+// output for `codec` which writes data across the data shards.
+struct ShardOutput<'a> {
+	shards: &'a mut [WrappedShard],
+	shard_len: usize,
+	written: usize,
+}
+
+impl<'a> codec::Output for ShardOutput<'a> {
+	fn write(&mut self, mut bytes: &[u8]) {
+		while !bytes.is_empty() {
+			let shard_index = self.written / self.shard_len;
+			let in_shard = self.written % self.shard_len;
+			let shard: &mut [u8] = self.shards[shard_index].as_mut();
+
+			let write_len = std::cmp::min(bytes.len(), self.shard_len - in_shard);
+			shard[in_shard..][..write_len].copy_from_slice(&bytes[..write_len]);
+
+			bytes = &bytes[write_len..];
+			self.written += write_len;
+		}
+	}
+}
+
+/// Collects chunks one at a time until the payload they were produced from can be
+/// reconstructed.
+pub struct Reconstructor {
+	params: CodeParams,
+	shards: Vec<Option<WrappedShard>>,
+	shard_len: Option<usize>,
+	received: usize,
+}
+
+impl Reconstructor {
+	/// Create a reconstructor for chunks produced for the given number of validators.
+	///
+	/// Works only up to 65536 validators, and `n_validators` must be non-zero.
+	pub fn new(n_validators: usize) -> Result<Self, Error> {
+		Ok(Reconstructor {
+			params: code_params(n_validators)?,
+			shards: vec![None; n_validators],
+			shard_len: None,
+			received: 0,
+		})
+	}
+
+	/// Add the chunk with the given index. Returns whether the payload can be reconstructed.
+	///
+	/// Adding a chunk with an index which is present already replaces it.
+	pub fn add_chunk(&mut self, index: usize, chunk: &[u8]) -> Result<bool, Error> {
+		let n_validators = self.shards.len();
+		if index >= n_validators {
+			return Err(Error::ChunkIndexOutOfBounds(index, n_validators));
+		}
+
+		let shard_len = self.shard_len.unwrap_or_else(|| chunk.len());
+
+		if shard_len % 2 != 0 {
+			return Err(Error::UnevenLength);
+		}
+
+		if shard_len != chunk.len() || shard_len == 0 {
+			return Err(Error::NonUniformChunks);
+		}
+
+		self.shard_len = Some(shard_len);
+
+		if self.shards[index].replace(WrappedShard::new(chunk.to_vec())).is_none() {
+			self.received += 1;
+		}
+
+		Ok(self.can_reconstruct())
+	}
+
+	/// Whether enough chunks are present to reconstruct the payload.
+	pub fn can_reconstruct(&self) -> bool {
+		self.received >= self.params.data_shards
+	}
+
+	/// The number of distinct chunks present.
+	pub fn received(&self) -> usize {
+		self.received
+	}
+
+	/// The number of distinct chunks needed to reconstruct the payload.
+	pub fn required(&self) -> usize {
+		self.params.data_shards
+	}
+
+	/// Reconstruct v0 `AvailableData` from the chunks present.
+	pub fn reconstruct_v0(&mut self) -> Result<v0::AvailableData, Error> {
+		self.reconstruct()
+	}
+
+	/// Reconstruct v1 `AvailableData` from the chunks present.
+	pub fn reconstruct_v1(&mut self) -> Result<v1::AvailableData, Error> {
+		self.reconstruct()
+	}
+
+	/// Reconstruct decodable data from the chunks present.
+	///
+	/// If all systematic chunks are present, the payload is taken from them without decoding.
+	pub(crate) fn reconstruct<T: Decode>(&mut self) -> Result<T, Error> {
+		let data_shards = self.params.data_shards;
+
+		let systematic = self.shards[..data_shards].iter().all(Option::is_some);
+		if !systematic {
+			if let Err(e) = self.params.make_encoder().reconstruct_data(&mut self.shards[..]) {
+				match e {
+					reed_solomon::Error::TooFewShardsPresent => Err(Error::NotEnoughChunks)?,
+					reed_solomon::Error::InvalidShardFlags => Err(Error::WrongValidatorCount)?,
+					reed_solomon::Error::TooManyShards => Err(Error::TooManyChunks)?,
+					reed_solomon::Error::EmptyShard => panic!("chunks are all non-empty; this is checked when adding them; qed"),
+					reed_solomon::Error::IncorrectShardSize => panic!("chunks are all same len; this is checked when adding them; qed"),
+					_ => panic!("reed_solomon encoder returns no more variants for this function; qed"),
+				}
+			}
+		}
+
+		decode_from_shards(
+			self.shard_len.map(|s| s * data_shards).unwrap_or(0),
+			self.shards.iter()
+				.map(|x| x.as_ref())
+				.take(data_shards)
+				.map(|x| x.expect("all data shards have been recovered; qed"))
+				.map(|x| x.as_ref()),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{branch_hash, obtain_chunks_v0, reconstruct_v0};
+	use primitives::v0::{AvailableData, BlakeTwo256, BlockData, HashT, PoVBlock};
+
+	fn available_data(len: usize) -> AvailableData {
+		AvailableData {
+			pov_block: PoVBlock {
+				block_data: BlockData((0..len).map(|i| i as u8).collect()),
+			},
+			omitted_validation: Default::default(),
+		}
+	}
+
+	#[test]
+	fn chunks_match_batch_encoding() {
+		let data = available_data(1000);
+		let encoder = ChunkEncoder::new_v0(10, &data).unwrap();
+		let chunks = obtain_chunks_v0(10, &data).unwrap();
+
+		assert_eq!(encoder.n_chunks(), 10);
+		assert_eq!(encoder.root(), crate::branches(&chunks).root());
+		for (index, chunk) in chunks.iter().enumerate() {
+			assert_eq!(&encoder.chunk(index).unwrap().chunk, chunk);
+		}
+		assert_eq!(encoder.into_chunks(), chunks);
+	}
+
+	#[test]
+	fn chunk_proofs_verify() {
+		let encoder = ChunkEncoder::new_v0(10, &available_data(1000)).unwrap();
+		let root = encoder.root();
+
+		for index in 0..10 {
+			let chunk = encoder.chunk(index).unwrap();
+			assert_eq!(chunk.index as usize, index);
+			assert_eq!(
+				branch_hash(&root, &chunk.proof, index).unwrap(),
+				BlakeTwo256::hash(&chunk.chunk),
+			);
+		}
+
+		assert_eq!(encoder.chunk(10).unwrap_err(), Error::ChunkIndexOutOfBounds(10, 10));
+	}
+
+	#[test]
+	fn reconstructs_once_enough_chunks_are_added() {
+		let data = available_data(1000);
+		let encoder = ChunkEncoder::new_v0(10, &data).unwrap();
+		let mut reconstructor = Reconstructor::new(10).unwrap();
+
+		assert_eq!(reconstructor.required(), 4);
+		assert!(!reconstructor.add_chunk(9, &encoder.chunk(9).unwrap().chunk).unwrap());
+		assert!(!reconstructor.add_chunk(2, &encoder.chunk(2).unwrap().chunk).unwrap());
+		// adding a chunk again does not count.
+		assert!(!reconstructor.add_chunk(2, &encoder.chunk(2).unwrap().chunk).unwrap());
+		assert_eq!(reconstructor.reconstruct_v0().unwrap_err(), Error::NotEnoughChunks);
+		assert!(!reconstructor.add_chunk(5, &encoder.chunk(5).unwrap().chunk).unwrap());
+		assert!(reconstructor.add_chunk(7, &encoder.chunk(7).unwrap().chunk).unwrap());
+		assert_eq!(reconstructor.received(), 4);
+
+		assert_eq!(reconstructor.reconstruct_v0().unwrap(), data);
+	}
+
+	#[test]
+	fn reconstructs_from_systematic_chunks() {
+		let data = available_data(1000);
+		let encoder = ChunkEncoder::new_v0(10, &data).unwrap();
+		let mut reconstructor = Reconstructor::new(10).unwrap();
+
+		for index in 0..4 {
+			reconstructor.add_chunk(index, &encoder.chunk(index).unwrap().chunk).unwrap();
+		}
+
+		assert_eq!(reconstructor.reconstruct_v0().unwrap(), data);
+	}
+
+	#[test]
+	fn rejects_malformed_chunks() {
+		let mut reconstructor = Reconstructor::new(10).unwrap();
+
+		assert_eq!(reconstructor.add_chunk(10, &[0; 4]), Err(Error::ChunkIndexOutOfBounds(10, 10)));
+		assert_eq!(reconstructor.add_chunk(0, &[0; 4]), Ok(false));
+		assert_eq!(reconstructor.add_chunk(1, &[0; 6]), Err(Error::NonUniformChunks));
+		assert_eq!(Reconstructor::new(10).unwrap().add_chunk(0, &[0; 3]), Err(Error::UnevenLength));
+		assert_eq!(Reconstructor::new(10).unwrap().add_chunk(0, &[]), Err(Error::NonUniformChunks));
+	}
+
+	#[test]
+	fn matches_batch_reconstruction() {
+		let data = available_data(5000);
+		let chunks = obtain_chunks_v0(100, &data).unwrap();
+		let mut reconstructor = Reconstructor::new(100).unwrap();
+
+		for index in (0..100).rev().step_by(2) {
+			if reconstructor.add_chunk(index, &chunks[index]).unwrap() {
+				break;
+			}
+		}
+
+		assert_eq!(reconstructor.received(), 34);
+		assert_eq!(
+			reconstructor.reconstruct_v0().unwrap(),
+			reconstruct_v0(100, (0..100).rev().step_by(2).map(|i| (&chunks[i][..], i))).unwrap(),
+		);
+	}
+}
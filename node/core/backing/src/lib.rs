@@ -95,6 +95,8 @@ struct CandidateBackingJob {
 	seconded: Option<Hash>,
 	/// We have already reported misbehaviors for these validators.
 	reported_misbehavior_for: HashSet<ValidatorIndex>,
+	/// We have already sent these candidates to the provisioner as backed.
+	backed: HashSet<Hash>,
 	table: Table<TableContext>,
 	table_context: TableContext,
 }
@@ -394,6 +396,29 @@ impl CandidateBackingJob {
 		Ok(())
 	}
 
+	/// Send the candidate to the provisioner once it has gathered enough votes to be backed.
+	async fn issue_new_backed_candidate(&mut self, candidate_hash: Hash) -> Result<(), Error> {
+		if self.backed.contains(&candidate_hash) ||
+			!self.table.candidate_includable(&candidate_hash, &self.table_context)
+		{
+			return Ok(());
+		}
+
+		let backed = self.get_backed()
+			.into_iter()
+			.find(|NewBackedCandidate(backed)| backed.candidate.hash() == candidate_hash);
+
+		if let Some(NewBackedCandidate(backed)) = backed {
+			self.backed.insert(candidate_hash);
+
+			self.send_to_provisioner(ProvisionerMessage::ProvisionableData(
+				ProvisionableData::BackedCandidate(backed),
+			)).await?;
+		}
+
+		Ok(())
+	}
+
 	/// Import a statement into the statement table and return the summary of the import.
 	async fn import_statement(
 		&mut self,
@@ -403,6 +428,10 @@ impl CandidateBackingJob {
 
 		let summary = self.table.import_statement(&self.table_context, stmt);
 
+		if let Some(ref summary) = summary {
+			self.issue_new_backed_candidate(summary.candidate).await?;
+		}
+
 		self.issue_new_misbehaviors().await?;
 
 		return Ok(summary);
@@ -611,13 +640,12 @@ impl CandidateBackingJob {
 			omitted_validation,
 		};
 
-		let chunks = erasure_coding::obtain_chunks_v1(
+		let encoder = erasure_coding::ChunkEncoder::new_v1(
 			self.table_context.validators.len(),
 			&available_data,
 		)?;
 
-		let branches = erasure_coding::branches(chunks.as_ref());
-		let erasure_root = branches.root();
+		let erasure_root = encoder.root();
 
 		let commitments = CandidateCommitments {
 			fees: outputs.fees,
@@ -632,13 +660,8 @@ impl CandidateBackingJob {
 			Err(e) => return Ok(Err(e)),
 		};
 
-		for (index, (proof, chunk)) in branches.enumerate() {
-			let chunk = ErasureChunk {
-				chunk: chunk.to_vec(),
-				index: index as u32,
-				proof,
-			};
-
+		for index in 0..encoder.n_chunks() {
+			let chunk = encoder.chunk(index)?;
 			self.store_chunk(index as ValidatorIndex, chunk).await?;
 		}
 
@@ -712,6 +735,7 @@ impl util::JobTrait for CandidateBackingJob {
 				issued_statements: HashSet::new(),
 				seconded: None,
 				reported_misbehavior_for: HashSet::new(),
+				backed: HashSet::new(),
 				table: Table::default(),
 				table_context,
 			};
@@ -877,6 +901,7 @@ mod tests {
 				max_code_size: 1000,
 				max_head_data_size: 1000,
 				block_number: Default::default(),
+				max_validation_fuel: 0,
 			};
 
 			Self {
@@ -1318,6 +1343,16 @@ mod tests {
 
 			virtual_overseer.send(FromOverseer::Communication{ msg: statement }).await;
 
+			// The candidate has reached the quorum of its group and is passed on to be included.
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::Provisioner(
+					ProvisionerMessage::ProvisionableData(
+						ProvisionableData::BackedCandidate(backed),
+					)
+				) if backed.candidate == candidate_a
+			);
+
 			let statement = CandidateBackingMessage::Statement(test_state.relay_parent, signed_c.clone());
 
 			virtual_overseer.send(FromOverseer::Communication{ msg: statement }).await;
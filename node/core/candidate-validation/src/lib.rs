@@ -0,0 +1,490 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Implements the Candidate Validation subsystem.
+//!
+//! This handles requests of other subsystems to validate candidates, either with validation data
+//! provided by the requester or with the validation data found in the state of the relay-parent
+//! of the candidate. Candidates are executed in the validation pool of the node if one is given,
+//! and metered with the `max_validation_fuel` of the global validation schedule.
+
+use std::sync::Arc;
+
+use futures::{channel::oneshot, prelude::*};
+use polkadot_node_primitives::{ValidationOutputs, ValidationResult};
+use polkadot_parachain::{
+	primitives::ValidationParams,
+	wasm_executor::{self, ExecutionMode, ValidationPool},
+};
+use polkadot_primitives::v1::{
+	CandidateDescriptor, Hash, OccupiedCoreAssumption, OmittedValidationData, PoV, ValidationCode,
+};
+use polkadot_subsystem::{
+	FromOverseer, OverseerSignal, SpawnedSubsystem, Subsystem, SubsystemContext, SubsystemError,
+	SubsystemResult,
+	messages::{
+		AllMessages, CandidateValidationMessage, RuntimeApiMessage, RuntimeApiRequest,
+		ValidationFailed,
+	},
+};
+
+#[derive(Debug, derive_more::From)]
+enum Error {
+	#[from]
+	Subsystem(SubsystemError),
+	#[from]
+	Oneshot(oneshot::Canceled),
+}
+
+/// The candidate validation subsystem.
+pub struct CandidateValidationSubsystem {
+	validation_pool: Option<ValidationPool>,
+}
+
+impl CandidateValidationSubsystem {
+	/// Create a new instance of the `CandidateValidationSubsystem`, which executes candidates
+	/// with the given validation pool, or in-process if there is none.
+	pub fn new(validation_pool: Option<ValidationPool>) -> Self {
+		CandidateValidationSubsystem { validation_pool }
+	}
+}
+
+impl<C> Subsystem<C> for CandidateValidationSubsystem
+	where C: SubsystemContext<Message = CandidateValidationMessage>
+{
+	fn start(self, ctx: C) -> SpawnedSubsystem {
+		SpawnedSubsystem {
+			name: "candidate-validation-subsystem",
+			future: run(ctx, self.validation_pool).map(|_| ()).boxed(),
+		}
+	}
+}
+
+type ResponseSender = oneshot::Sender<Result<ValidationResult, ValidationFailed>>;
+
+async fn run(
+	mut ctx: impl SubsystemContext<Message = CandidateValidationMessage>,
+	validation_pool: Option<ValidationPool>,
+) -> SubsystemResult<()> {
+	loop {
+		match ctx.recv().await? {
+			FromOverseer::Signal(OverseerSignal::StartWork(_)) => {}
+			FromOverseer::Signal(OverseerSignal::StopWork(_)) => {}
+			FromOverseer::Signal(OverseerSignal::Conclude) => return Ok(()),
+			FromOverseer::Communication { msg } => match msg {
+				CandidateValidationMessage::ValidateFromChainState(descriptor, pov, tx) => {
+					match request_validation_data(&mut ctx, &descriptor).await {
+						Ok(Some((omitted_validation, validation_code))) => spawn_validate(
+							&mut ctx,
+							validation_pool.clone(),
+							omitted_validation,
+							validation_code,
+							descriptor,
+							pov,
+							tx,
+						).await?,
+						Ok(None) => {
+							let _ = tx.send(Err(ValidationFailed));
+						}
+						// the runtime API gave no answer at this relay-parent.
+						Err(Error::Oneshot(_)) => {
+							log::debug!(
+								"Runtime API request dropped at {}, failing validation",
+								descriptor.relay_parent,
+							);
+							let _ = tx.send(Err(ValidationFailed));
+						}
+						Err(Error::Subsystem(e)) => return Err(e),
+					}
+				}
+				CandidateValidationMessage::ValidateFromExhaustive(
+					omitted_validation,
+					validation_code,
+					descriptor,
+					pov,
+					tx,
+				) => spawn_validate(
+					&mut ctx,
+					validation_pool.clone(),
+					omitted_validation,
+					validation_code,
+					descriptor,
+					pov,
+					tx,
+				).await?,
+			}
+		}
+	}
+}
+
+async fn request_from_runtime<T>(
+	ctx: &mut impl SubsystemContext<Message = CandidateValidationMessage>,
+	relay_parent: Hash,
+	request: impl FnOnce(oneshot::Sender<T>) -> RuntimeApiRequest,
+) -> Result<T, Error> {
+	let (tx, rx) = oneshot::channel();
+	ctx.send_message(AllMessages::RuntimeApi(
+		RuntimeApiMessage::Request(relay_parent, request(tx)),
+	)).await?;
+
+	Ok(rx.await?)
+}
+
+/// Request the data needed to validate the candidate from the state of its relay-parent.
+///
+/// Returns `None` if the para is not registered or already occupies a core.
+async fn request_validation_data(
+	ctx: &mut impl SubsystemContext<Message = CandidateValidationMessage>,
+	descriptor: &CandidateDescriptor,
+) -> Result<Option<(OmittedValidationData, ValidationCode)>, Error> {
+	let relay_parent = descriptor.relay_parent;
+	let para_id = descriptor.para_id;
+
+	let global_validation = request_from_runtime(
+		ctx,
+		relay_parent,
+		RuntimeApiRequest::GlobalValidationSchedule,
+	).await?;
+
+	let local_validation = request_from_runtime(
+		ctx,
+		relay_parent,
+		|tx| RuntimeApiRequest::LocalValidationData(para_id, OccupiedCoreAssumption::Free, tx),
+	).await?;
+
+	let local_validation = match local_validation {
+		Some(local_validation) => local_validation,
+		None => return Ok(None),
+	};
+
+	let validation_code = request_from_runtime(
+		ctx,
+		relay_parent,
+		|tx| RuntimeApiRequest::ValidationCode(para_id, global_validation.block_number, None, tx),
+	).await?;
+
+	Ok(Some((OmittedValidationData { global_validation, local_validation }, validation_code)))
+}
+
+/// Validate the candidate in a child task, as execution blocks for up to the execution timeout.
+async fn spawn_validate(
+	ctx: &mut impl SubsystemContext<Message = CandidateValidationMessage>,
+	validation_pool: Option<ValidationPool>,
+	omitted_validation: OmittedValidationData,
+	validation_code: ValidationCode,
+	descriptor: CandidateDescriptor,
+	pov: Arc<PoV>,
+	tx: ResponseSender,
+) -> SubsystemResult<()> {
+	ctx.spawn("validate-candidate", async move {
+		let res = validate_candidate_exhaustive(
+			validation_pool.as_ref(),
+			omitted_validation,
+			validation_code,
+			descriptor,
+			pov,
+		);
+
+		let _ = tx.send(res);
+	}.boxed()).await
+}
+
+/// Validate the candidate with the given validation data and code.
+///
+/// Only failures of the node itself are reported as errors, while misbehaviour of the
+/// validation code yields `ValidationResult::Invalid`.
+fn validate_candidate_exhaustive(
+	validation_pool: Option<&ValidationPool>,
+	omitted_validation: OmittedValidationData,
+	validation_code: ValidationCode,
+	descriptor: CandidateDescriptor,
+	pov: Arc<PoV>,
+) -> Result<ValidationResult, ValidationFailed> {
+	if pov.hash() != descriptor.pov_hash || descriptor.check_collator_signature().is_err() {
+		return Ok(ValidationResult::Invalid);
+	}
+
+	let OmittedValidationData { global_validation, local_validation } = omitted_validation;
+
+	let params = ValidationParams {
+		parent_head: local_validation.parent_head.clone(),
+		block_data: pov.block_data.clone(),
+		max_code_size: global_validation.max_code_size,
+		max_head_data_size: global_validation.max_head_data_size,
+		relay_chain_height: global_validation.block_number,
+		code_upgrade_allowed: local_validation.code_upgrade_allowed,
+	};
+
+	let execution_mode = validation_pool
+		.map(ExecutionMode::Remote)
+		.unwrap_or(ExecutionMode::Local {
+			max_code_size: global_validation.max_code_size as _,
+		});
+
+	// a fuel budget of zero means that validation is not metered.
+	let res = match global_validation.max_validation_fuel {
+		0 => wasm_executor::validate_candidate(&validation_code.0, params, execution_mode),
+		max_fuel => wasm_executor::validate_candidate_metered(
+			&validation_code.0,
+			params,
+			max_fuel,
+			execution_mode,
+		),
+	};
+
+	match res {
+		Ok(result) => {
+			if result.head_data.0.len() > global_validation.max_head_data_size as _ {
+				return Ok(ValidationResult::Invalid);
+			}
+
+			Ok(ValidationResult::Valid(ValidationOutputs {
+				head_data: result.head_data,
+				global_validation_schedule: global_validation,
+				local_validation_data: local_validation,
+				upward_messages: result.upward_messages,
+				fees: 0,
+				new_validation_code: result.new_validation_code,
+			}))
+		}
+		Err(e @ wasm_executor::Error::WorkerUnavailable) |
+		Err(e @ wasm_executor::Error::WorkerUnresponsive) |
+		Err(e @ wasm_executor::Error::Cancelled) |
+		Err(e @ wasm_executor::Error::Protocol(_)) |
+		Err(e @ wasm_executor::Error::Io(_)) |
+		Err(e @ wasm_executor::Error::System(_)) |
+		Err(e @ wasm_executor::Error::External(_)) => {
+			log::warn!("Failed to validate candidate of para {:?}: {}", descriptor.para_id, e);
+			Err(ValidationFailed)
+		}
+		Err(e) => {
+			log::debug!("Candidate of para {:?} is invalid: {}", descriptor.para_id, e);
+			Ok(ValidationResult::Invalid)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assert_matches::assert_matches;
+	use futures::executor;
+	use polkadot_primitives::v1::{
+		BlockData, CollatorPair, GlobalValidationSchedule, HeadData, Id as ParaId,
+		LocalValidationData, collator_signature_payload,
+	};
+	use sp_core::Pair;
+
+	const HALT_CODE: &[u8] = ::halt::WASM_BINARY;
+
+	struct TestState {
+		para_id: ParaId,
+		relay_parent: Hash,
+		global_validation: GlobalValidationSchedule,
+		local_validation: LocalValidationData,
+		pov: Arc<PoV>,
+	}
+
+	impl Default for TestState {
+		fn default() -> Self {
+			let global_validation = GlobalValidationSchedule {
+				max_code_size: 1024 * 1024,
+				max_head_data_size: 1024,
+				block_number: 5,
+				max_validation_fuel: 1_000_000,
+			};
+
+			let local_validation = LocalValidationData {
+				parent_head: HeadData(vec![1, 2, 3]),
+				balance: 0,
+				validation_code_hash: Default::default(),
+				code_upgrade_allowed: None,
+			};
+
+			Self {
+				para_id: ParaId::from(1),
+				relay_parent: [5; 32].into(),
+				global_validation,
+				local_validation,
+				pov: Arc::new(PoV { block_data: BlockData(vec![4, 5, 6]) }),
+			}
+		}
+	}
+
+	impl TestState {
+		fn omitted_validation(&self) -> OmittedValidationData {
+			OmittedValidationData {
+				global_validation: self.global_validation.clone(),
+				local_validation: self.local_validation.clone(),
+			}
+		}
+
+		// A descriptor of a candidate with the PoV of the test state, signed by a collator.
+		fn descriptor(&self) -> CandidateDescriptor {
+			let collator = CollatorPair::generate().0;
+			let pov_hash = self.pov.hash();
+			let payload = collator_signature_payload(&self.relay_parent, &self.para_id, &pov_hash);
+
+			CandidateDescriptor {
+				para_id: self.para_id,
+				relay_parent: self.relay_parent,
+				collator: collator.public(),
+				signature: collator.sign(&payload[..]),
+				pov_hash,
+			}
+		}
+	}
+
+	type VirtualOverseer = subsystem_test::TestSubsystemContextHandle<CandidateValidationMessage>;
+
+	fn test_harness<T: Future<Output=()>>(test: impl FnOnce(VirtualOverseer) -> T) {
+		let pool = sp_core::testing::SpawnBlockingExecutor::new();
+
+		let (context, virtual_overseer) = subsystem_test::make_subsystem_context(pool.clone());
+
+		let subsystem = run(context, None);
+		let test_fut = test(virtual_overseer);
+
+		futures::pin_mut!(test_fut);
+		futures::pin_mut!(subsystem);
+
+		executor::block_on(future::select(test_fut, subsystem));
+	}
+
+	#[test]
+	fn metered_validation_stops_code_which_never_halts() {
+		let test_state = TestState::default();
+
+		let res = validate_candidate_exhaustive(
+			None,
+			test_state.omitted_validation(),
+			ValidationCode(HALT_CODE.to_vec()),
+			test_state.descriptor(),
+			test_state.pov.clone(),
+		);
+
+		assert_matches!(res, Ok(ValidationResult::Invalid));
+	}
+
+	#[test]
+	fn candidate_with_wrong_pov_is_invalid() {
+		let test_state = TestState::default();
+		let mut descriptor = test_state.descriptor();
+		descriptor.pov_hash = [1; 32].into();
+
+		let res = validate_candidate_exhaustive(
+			None,
+			test_state.omitted_validation(),
+			ValidationCode(HALT_CODE.to_vec()),
+			descriptor,
+			test_state.pov.clone(),
+		);
+
+		assert_matches!(res, Ok(ValidationResult::Invalid));
+	}
+
+	#[test]
+	fn validates_with_data_from_chain_state() {
+		let test_state = TestState::default();
+
+		test_harness(|mut virtual_overseer| async move {
+			let (tx, rx) = oneshot::channel();
+			virtual_overseer.send(FromOverseer::Communication {
+				msg: CandidateValidationMessage::ValidateFromChainState(
+					test_state.descriptor(),
+					test_state.pov.clone(),
+					tx,
+				),
+			}).await;
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					parent,
+					RuntimeApiRequest::GlobalValidationSchedule(tx),
+				)) if parent == test_state.relay_parent => {
+					tx.send(test_state.global_validation.clone()).unwrap();
+				}
+			);
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					parent,
+					RuntimeApiRequest::LocalValidationData(para_id, OccupiedCoreAssumption::Free, tx),
+				)) if parent == test_state.relay_parent && para_id == test_state.para_id => {
+					tx.send(Some(test_state.local_validation.clone())).unwrap();
+				}
+			);
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					parent,
+					RuntimeApiRequest::ValidationCode(para_id, block_number, None, tx),
+				)) if parent == test_state.relay_parent && para_id == test_state.para_id => {
+					assert_eq!(block_number, test_state.global_validation.block_number);
+					tx.send(ValidationCode(HALT_CODE.to_vec())).unwrap();
+				}
+			);
+
+			// the code never halts, so it runs out of the fuel of the global validation schedule.
+			assert_matches!(rx.await.unwrap(), Ok(ValidationResult::Invalid));
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+	}
+
+	#[test]
+	fn fails_when_the_para_occupies_a_core() {
+		let test_state = TestState::default();
+
+		test_harness(|mut virtual_overseer| async move {
+			let (tx, rx) = oneshot::channel();
+			virtual_overseer.send(FromOverseer::Communication {
+				msg: CandidateValidationMessage::ValidateFromChainState(
+					test_state.descriptor(),
+					test_state.pov.clone(),
+					tx,
+				),
+			}).await;
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::GlobalValidationSchedule(tx),
+				)) => {
+					tx.send(test_state.global_validation.clone()).unwrap();
+				}
+			);
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::LocalValidationData(_, _, tx),
+				)) => {
+					tx.send(None).unwrap();
+				}
+			);
+
+			assert_matches!(rx.await.unwrap(), Err(ValidationFailed));
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+	}
+}
@@ -0,0 +1,646 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Implements the PVF checker subsystem.
+//!
+//! While the code upgrade of a para is being pre-checked, the validators of the current session
+//! vote on whether the new validation code compiles. This subsystem votes on behalf of the local
+//! validator: on each new relay-parent, it compiles the code of every pending pre-check it has
+//! not yet voted on in the session and submits a signed statement through the runtime API.
+//!
+//! Once a code upgrade is scheduled, the subsystem also prepares the new validation code, so that
+//! the first candidates validated with it do not pay for its compilation.
+
+use std::collections::{HashMap, HashSet};
+
+use futures::{channel::oneshot, prelude::*};
+use keystore::KeyStorePtr;
+use polkadot_parachain::wasm_executor::{self, ExecutionMode, ValidationPool};
+use polkadot_primitives::v1::{
+	Hash, Id as ParaId, PvfCheckStatement, SessionIndex, ValidatorId, ValidatorIndex, ValidatorPair,
+};
+use polkadot_subsystem::{
+	FromOverseer, OverseerSignal, SpawnedSubsystem, Subsystem, SubsystemContext, SubsystemError,
+	SubsystemResult,
+	messages::{AllMessages, PvfCheckerMessage, RuntimeApiMessage, RuntimeApiRequest},
+	util::signing_key,
+};
+use sp_core::Pair;
+
+#[derive(Debug, derive_more::From)]
+enum Error {
+	#[from]
+	Subsystem(SubsystemError),
+	#[from]
+	Oneshot(oneshot::Canceled),
+}
+
+/// The PVF checker subsystem.
+pub struct PvfCheckerSubsystem {
+	keystore: KeyStorePtr,
+	max_code_size: usize,
+	validation_pool: Option<ValidationPool>,
+}
+
+impl PvfCheckerSubsystem {
+	/// Create a new instance of the `PvfCheckerSubsystem`, which signs statements with the
+	/// validator key found in the given keystore and rejects code larger than `max_code_size`
+	/// bytes.
+	///
+	/// The code of scheduled upgrades is prepared with the given validation pool, or in-process
+	/// if there is none.
+	pub fn new(
+		keystore: KeyStorePtr,
+		max_code_size: usize,
+		validation_pool: Option<ValidationPool>,
+	) -> Self {
+		PvfCheckerSubsystem { keystore, max_code_size, validation_pool }
+	}
+}
+
+impl<C> Subsystem<C> for PvfCheckerSubsystem
+	where C: SubsystemContext<Message = PvfCheckerMessage>
+{
+	fn start(self, ctx: C) -> SpawnedSubsystem {
+		SpawnedSubsystem {
+			name: "pvf-checker-subsystem",
+			future: run(ctx, self.keystore, self.max_code_size, self.validation_pool)
+				.map(|_| ())
+				.boxed(),
+		}
+	}
+}
+
+#[derive(Default)]
+struct State {
+	/// The session of the relay-parent we last worked on.
+	session_index: SessionIndex,
+	/// The pre-checks we have voted on in that session, by para and code hash.
+	voted: HashSet<(ParaId, Hash)>,
+	/// Whether the code of a pending pre-check compiles, by code hash.
+	///
+	/// Votes are discarded on session changes, so this spares compiling the code again.
+	judgements: HashMap<Hash, bool>,
+}
+
+async fn run(
+	mut ctx: impl SubsystemContext<Message = PvfCheckerMessage>,
+	keystore: KeyStorePtr,
+	max_code_size: usize,
+	validation_pool: Option<ValidationPool>,
+) -> SubsystemResult<()> {
+	let mut state = State::default();
+
+	loop {
+		match ctx.recv().await? {
+			FromOverseer::Signal(OverseerSignal::StartWork(relay_parent)) => {
+				let res = handle_start_work(
+					&mut state,
+					&mut ctx,
+					&keystore,
+					max_code_size,
+					validation_pool.as_ref(),
+					relay_parent,
+				).await;
+
+				match res {
+					Ok(()) => {}
+					// the runtime API gave no answer at this relay-parent, so try again at the
+					// next one.
+					Err(Error::Oneshot(_)) => log::debug!(
+						"Runtime API request dropped at {}, skipping pre-checks",
+						relay_parent,
+					),
+					Err(Error::Subsystem(e)) => return Err(e),
+				}
+			}
+			FromOverseer::Signal(OverseerSignal::StopWork(_)) => {}
+			FromOverseer::Signal(OverseerSignal::Conclude) => return Ok(()),
+			FromOverseer::Communication { msg } => match msg {},
+		}
+	}
+}
+
+async fn request_from_runtime<T>(
+	ctx: &mut impl SubsystemContext<Message = PvfCheckerMessage>,
+	relay_parent: Hash,
+	request: impl FnOnce(oneshot::Sender<T>) -> RuntimeApiRequest,
+) -> Result<T, Error> {
+	let (tx, rx) = oneshot::channel();
+	ctx.send_message(AllMessages::RuntimeApi(
+		RuntimeApiMessage::Request(relay_parent, request(tx)),
+	)).await?;
+
+	Ok(rx.await?)
+}
+
+/// Find the key and index of the local validator among the given validators, if any.
+fn local_validator(
+	validators: &[ValidatorId],
+	keystore: &KeyStorePtr,
+) -> Option<(ValidatorPair, ValidatorIndex)> {
+	let key = signing_key(validators, keystore)?;
+	let index = validators.iter().position(|v| v == &key.public())?;
+
+	Some((key, index as ValidatorIndex))
+}
+
+async fn handle_start_work(
+	state: &mut State,
+	ctx: &mut impl SubsystemContext<Message = PvfCheckerMessage>,
+	keystore: &KeyStorePtr,
+	max_code_size: usize,
+	validation_pool: Option<&ValidationPool>,
+	relay_parent: Hash,
+) -> Result<(), Error> {
+	let validators = request_from_runtime(ctx, relay_parent, RuntimeApiRequest::Validators).await?;
+	let signing_context = request_from_runtime(
+		ctx,
+		relay_parent,
+		RuntimeApiRequest::SigningContext,
+	).await?;
+
+	// only validators of the session vote on pre-checks.
+	let (key, validator_index) = match local_validator(&validators, keystore) {
+		Some(validator) => validator,
+		None => return Ok(()),
+	};
+
+	let upgrades = request_from_runtime(
+		ctx,
+		relay_parent,
+		RuntimeApiRequest::ScheduledCodeUpgrades,
+	).await?;
+
+	// prepare the code for the fuel budget candidates are currently validated with.
+	let max_fuel = if upgrades.is_empty() {
+		None
+	} else {
+		let schedule = request_from_runtime(
+			ctx,
+			relay_parent,
+			RuntimeApiRequest::GlobalValidationSchedule,
+		).await?;
+
+		// a fuel budget of zero means that validation is not metered.
+		Some(schedule.max_validation_fuel).filter(|fuel| *fuel != 0)
+	};
+
+	for (para_id, code) in upgrades {
+		let mode = match validation_pool {
+			Some(pool) => ExecutionMode::Remote(pool),
+			None => ExecutionMode::Local { max_code_size },
+		};
+
+		if let Err(e) = wasm_executor::prepare_artifact(&code.0, max_fuel, mode) {
+			log::warn!("Failed to prepare the new validation code of para {:?}: {:?}", para_id, e);
+		}
+	}
+
+	// votes are discarded on session changes, so vote again on all pre-checks.
+	if signing_context.session_index != state.session_index {
+		state.session_index = signing_context.session_index;
+		state.voted.clear();
+	}
+
+	let pending = request_from_runtime(
+		ctx,
+		relay_parent,
+		RuntimeApiRequest::PendingPvfChecks,
+	).await?;
+	state.judgements.retain(|code_hash, _| pending.iter().any(|(_, h)| h == code_hash));
+
+	for (para_id, code_hash) in pending {
+		if state.voted.contains(&(para_id, code_hash)) {
+			continue;
+		}
+
+		let accept = match state.judgements.get(&code_hash) {
+			Some(accept) => *accept,
+			None => {
+				let code = request_from_runtime(
+					ctx,
+					relay_parent,
+					|tx| RuntimeApiRequest::PendingPvfCheckCode(para_id, tx),
+				).await?;
+
+				let code = match code {
+					Some(code) => code,
+					None => {
+						log::warn!("Missing code of pending pre-check for para {:?}", para_id);
+						continue;
+					}
+				};
+
+				let accept = wasm_executor::precheck_code(&code.0, max_code_size).is_ok();
+				state.judgements.insert(code_hash, accept);
+				accept
+			}
+		};
+
+		let statement = PvfCheckStatement {
+			accept,
+			code_hash,
+			para_id,
+			session_index: state.session_index,
+			validator_index,
+		};
+		let signature = key.sign(&statement.signing_payload()[..]);
+
+		ctx.send_message(AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+			relay_parent,
+			RuntimeApiRequest::SubmitPvfCheckStatement(statement, signature),
+		))).await?;
+
+		state.voted.insert((para_id, code_hash));
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assert_matches::assert_matches;
+	use futures::executor;
+	use polkadot_parachain::wasm_executor::{
+		DiskArtifacts, ValidationPoolConfig, DEFAULT_MAX_DISK_SIZE, artifact_id,
+	};
+	use polkadot_primitives::v1::{GlobalValidationSchedule, SigningContext, ValidationCode};
+	use sp_keyring::Sr25519Keyring;
+
+	// The smallest valid wasm module: just the magic number and the version.
+	const EMPTY_MODULE: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+	struct TestState {
+		keystore: KeyStorePtr,
+		validator_public: Vec<ValidatorId>,
+		signing_context: SigningContext,
+		pending: Vec<(ParaId, Hash, ValidationCode)>,
+		scheduled: Vec<(ParaId, ValidationCode)>,
+		max_validation_fuel: u64,
+		max_code_size: usize,
+	}
+
+	impl Default for TestState {
+		fn default() -> Self {
+			let validators = vec![
+				Sr25519Keyring::Alice,
+				Sr25519Keyring::Bob,
+				Sr25519Keyring::Charlie,
+			];
+
+			let keystore = keystore::Store::new_in_memory();
+			// Make sure `Bob` key is in the keystore, so this mocked node will be a validator.
+			keystore.write().insert_ephemeral_from_seed::<ValidatorPair>(&validators[1].to_seed())
+				.expect("Insert key into keystore");
+
+			let signing_context = SigningContext {
+				session_index: 1,
+				parent_hash: [1; 32].into(),
+			};
+
+			let pending = vec![
+				(ParaId::from(1), [2; 32].into(), ValidationCode(EMPTY_MODULE.to_vec())),
+				(ParaId::from(2), [3; 32].into(), ValidationCode(vec![1, 2, 3])),
+			];
+
+			Self {
+				keystore,
+				validator_public: validators.iter().map(|v| v.public().into()).collect(),
+				signing_context,
+				pending,
+				scheduled: Vec::new(),
+				max_validation_fuel: 0,
+				max_code_size: 1024,
+			}
+		}
+	}
+
+	type VirtualOverseer = subsystem_test::TestSubsystemContextHandle<PvfCheckerMessage>;
+
+	fn test_harness<T: Future<Output=()>>(
+		keystore: KeyStorePtr,
+		max_code_size: usize,
+		validation_pool: Option<ValidationPool>,
+		test: impl FnOnce(VirtualOverseer) -> T,
+	) {
+		let pool = sp_core::testing::SpawnBlockingExecutor::new();
+
+		let (context, virtual_overseer) = subsystem_test::make_subsystem_context(pool.clone());
+
+		let subsystem = run(context, keystore, max_code_size, validation_pool);
+		let test_fut = test(virtual_overseer);
+
+		futures::pin_mut!(test_fut);
+		futures::pin_mut!(subsystem);
+
+		executor::block_on(future::select(test_fut, subsystem));
+	}
+
+	// Starts work on the given relay-parent and answers the requests for the validators, the
+	// signing context, the scheduled code upgrades and the pending pre-checks.
+	async fn start_work(
+		virtual_overseer: &mut VirtualOverseer,
+		test_state: &TestState,
+		relay_parent: Hash,
+	) {
+		virtual_overseer.send(FromOverseer::Signal(OverseerSignal::StartWork(relay_parent))).await;
+
+		assert_matches!(
+			virtual_overseer.recv().await,
+			AllMessages::RuntimeApi(
+				RuntimeApiMessage::Request(parent, RuntimeApiRequest::Validators(tx))
+			) if parent == relay_parent => {
+				tx.send(test_state.validator_public.clone()).unwrap();
+			}
+		);
+
+		assert_matches!(
+			virtual_overseer.recv().await,
+			AllMessages::RuntimeApi(
+				RuntimeApiMessage::Request(parent, RuntimeApiRequest::SigningContext(tx))
+			) if parent == relay_parent => {
+				tx.send(test_state.signing_context.clone()).unwrap();
+			}
+		);
+
+		assert_matches!(
+			virtual_overseer.recv().await,
+			AllMessages::RuntimeApi(
+				RuntimeApiMessage::Request(parent, RuntimeApiRequest::ScheduledCodeUpgrades(tx))
+			) if parent == relay_parent => {
+				tx.send(test_state.scheduled.clone()).unwrap();
+			}
+		);
+
+		if !test_state.scheduled.is_empty() {
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(
+					RuntimeApiMessage::Request(
+						parent,
+						RuntimeApiRequest::GlobalValidationSchedule(tx),
+					)
+				) if parent == relay_parent => {
+					tx.send(GlobalValidationSchedule {
+						max_validation_fuel: test_state.max_validation_fuel,
+						..Default::default()
+					}).unwrap();
+				}
+			);
+		}
+
+		assert_matches!(
+			virtual_overseer.recv().await,
+			AllMessages::RuntimeApi(
+				RuntimeApiMessage::Request(parent, RuntimeApiRequest::PendingPvfChecks(tx))
+			) if parent == relay_parent => {
+				let pending = test_state.pending.iter()
+					.map(|(para_id, code_hash, _)| (*para_id, *code_hash))
+					.collect();
+				tx.send(pending).unwrap();
+			}
+		);
+	}
+
+	#[test]
+	fn compiles_pending_code_and_submits_statements() {
+		let test_state = TestState::default();
+
+		let keystore = test_state.keystore.clone();
+		test_harness(keystore, test_state.max_code_size, None, |mut virtual_overseer| async move {
+			let relay_parent = Hash::from([5; 32]);
+			start_work(&mut virtual_overseer, &test_state, relay_parent).await;
+
+			for (para_id, code_hash, code) in test_state.pending.iter().cloned() {
+				assert_matches!(
+					virtual_overseer.recv().await,
+					AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+						parent,
+						RuntimeApiRequest::PendingPvfCheckCode(id, tx),
+					)) if parent == relay_parent && id == para_id => {
+						tx.send(Some(code.clone())).unwrap();
+					}
+				);
+
+				assert_matches!(
+					virtual_overseer.recv().await,
+					AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+						parent,
+						RuntimeApiRequest::SubmitPvfCheckStatement(statement, signature),
+					)) if parent == relay_parent => {
+						assert_eq!(statement, PvfCheckStatement {
+							// only the empty module compiles.
+							accept: code.0 == EMPTY_MODULE.to_vec(),
+							code_hash,
+							para_id,
+							session_index: 1,
+							validator_index: 1,
+						});
+						assert!(ValidatorPair::verify(
+							&signature,
+							&statement.signing_payload()[..],
+							&test_state.validator_public[1],
+						));
+					}
+				);
+			}
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+	}
+
+	#[test]
+	fn votes_once_per_session() {
+		let mut test_state = TestState::default();
+		test_state.pending.truncate(1);
+		let (para_id, code_hash, code) = test_state.pending[0].clone();
+
+		let keystore = test_state.keystore.clone();
+		test_harness(keystore, test_state.max_code_size, None, |mut virtual_overseer| async move {
+			start_work(&mut virtual_overseer, &test_state, Hash::from([5; 32])).await;
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::PendingPvfCheckCode(id, tx),
+				)) if id == para_id => {
+					tx.send(Some(code)).unwrap();
+				}
+			);
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::SubmitPvfCheckStatement(statement, _),
+				)) if statement.session_index == 1
+			);
+
+			// nothing new to vote on in the same session.
+			start_work(&mut virtual_overseer, &test_state, Hash::from([6; 32])).await;
+
+			// the votes are discarded in the next session, so vote again without compiling the
+			// code again.
+			test_state.signing_context.session_index = 2;
+			start_work(&mut virtual_overseer, &test_state, Hash::from([7; 32])).await;
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::SubmitPvfCheckStatement(statement, _),
+				)) => {
+					assert_eq!(statement.session_index, 2);
+					assert_eq!(statement.code_hash, code_hash);
+					assert!(statement.accept);
+				}
+			);
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+	}
+
+	#[test]
+	fn rejects_code_above_the_size_limit() {
+		let mut test_state = TestState::default();
+		test_state.pending.truncate(1);
+		test_state.max_code_size = EMPTY_MODULE.len() - 1;
+		let (para_id, code_hash, code) = test_state.pending[0].clone();
+
+		let keystore = test_state.keystore.clone();
+		test_harness(keystore, test_state.max_code_size, None, |mut virtual_overseer| async move {
+			start_work(&mut virtual_overseer, &test_state, Hash::from([5; 32])).await;
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::PendingPvfCheckCode(id, tx),
+				)) if id == para_id => {
+					tx.send(Some(code)).unwrap();
+				}
+			);
+
+			// the empty module compiles, but is too large.
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::SubmitPvfCheckStatement(statement, _),
+				)) => {
+					assert_eq!(statement.code_hash, code_hash);
+					assert!(!statement.accept);
+				}
+			);
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+	}
+
+	#[test]
+	fn prepares_code_of_scheduled_upgrades() {
+		let mut test_state = TestState::default();
+		test_state.pending.clear();
+		test_state.scheduled = vec![
+			(ParaId::from(1), ValidationCode(EMPTY_MODULE.to_vec())),
+			(ParaId::from(2), ValidationCode(vec![1, 2, 3])),
+		];
+
+		let dir = tempfile::tempdir().unwrap();
+		let pool = ValidationPool::new(ValidationPoolConfig {
+			num_workers: 1,
+			artifacts_path: Some(dir.path().to_path_buf()),
+			..Default::default()
+		});
+
+		let keystore = test_state.keystore.clone();
+		let max_code_size = test_state.max_code_size;
+		test_harness(keystore, max_code_size, Some(pool), |mut virtual_overseer| async move {
+			start_work(&mut virtual_overseer, &test_state, Hash::from([5; 32])).await;
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+
+		// only code which compiles is stored for the validation workers.
+		let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), DEFAULT_MAX_DISK_SIZE).unwrap();
+		assert!(artifacts.contains(&artifact_id(&EMPTY_MODULE), None));
+		assert!(!artifacts.contains(&artifact_id(&[1, 2, 3]), None));
+	}
+
+	#[test]
+	fn keeps_running_when_the_runtime_api_does_not_answer() {
+		let mut test_state = TestState::default();
+		test_state.pending.clear();
+
+		let keystore = test_state.keystore.clone();
+		test_harness(keystore, test_state.max_code_size, None, |mut virtual_overseer| async move {
+			virtual_overseer.send(FromOverseer::Signal(
+				OverseerSignal::StartWork(Hash::from([5; 32]))
+			)).await;
+
+			// drop the request without answering.
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::Validators(_),
+				))
+			);
+
+			start_work(&mut virtual_overseer, &test_state, Hash::from([6; 32])).await;
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+	}
+
+	#[test]
+	fn does_not_vote_when_not_a_validator() {
+		let mut test_state = TestState::default();
+		test_state.validator_public.remove(1);
+
+		let keystore = test_state.keystore.clone();
+		test_harness(keystore, test_state.max_code_size, None, |mut virtual_overseer| async move {
+			virtual_overseer.send(FromOverseer::Signal(
+				OverseerSignal::StartWork(Hash::from([5; 32]))
+			)).await;
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::Validators(tx),
+				)) => {
+					tx.send(test_state.validator_public.clone()).unwrap();
+				}
+			);
+
+			assert_matches!(
+				virtual_overseer.recv().await,
+				AllMessages::RuntimeApi(RuntimeApiMessage::Request(
+					_,
+					RuntimeApiRequest::SigningContext(tx),
+				)) => {
+					tx.send(test_state.signing_context.clone()).unwrap();
+				}
+			);
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		});
+	}
+}
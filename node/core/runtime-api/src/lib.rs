@@ -19,4 +19,372 @@
 //! This provides a clean, ownerless wrapper around the parachain-related runtime APIs. This crate
 //! can also be used to cache responses from heavy runtime APIs.
 //!
-//! TODO: https://github.com/paritytech/polkadot/issues/1419 implement this.
+//! Requests are answered with the v1 `ParachainHost` runtime API. As it shares its name with the
+//! v0 API that the live runtimes implement, it cannot be required of the client at compile time.
+//! The runtime API is called by name instead, and requests which the runtime at the relay-parent
+//! cannot answer are dropped, so that the requester sees its response channel canceled.
+
+use std::sync::Arc;
+
+use futures::{channel::oneshot, prelude::*};
+use parity_scale_codec::{Decode, Encode};
+use polkadot_primitives::v1::{
+	AssignmentKind, Block, CoreAssignment, CoreIndex, CoreOccupied, CoreState,
+	GroupRotationInfo, Hash, LocalValidationData, OccupiedCoreAssumption, SigningContext,
+	ValidatorIndex,
+};
+use polkadot_subsystem::{
+	FromOverseer, OverseerSignal, SpawnedSubsystem, Subsystem, SubsystemContext, SubsystemResult,
+	messages::{RuntimeApiMessage, RuntimeApiRequest, SchedulerRoster},
+};
+use sc_client_api::{CallExecutor, ExecutionStrategy, ExecutorProvider};
+use primitives::ExecutionContext;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+/// An error of a call to the runtime API.
+#[derive(Debug, derive_more::From)]
+pub enum Error {
+	/// The call failed, e.g. because the runtime does not implement the function.
+	#[from]
+	Client(sp_blockchain::Error),
+	/// The result of the call could not be decoded.
+	#[from]
+	Decode(parity_scale_codec::Error),
+}
+
+/// Call the function of the v1 `ParachainHost` runtime API with the given name and arguments
+/// against the post-state of the block `at`, and decode its result.
+///
+/// The call is made in an offchain context, so that the runtime can submit transactions to the
+/// transaction pool registered with the client.
+pub fn call_runtime_api<B, Client, R>(
+	client: &Client,
+	at: Hash,
+	function: &str,
+	args: impl Encode,
+) -> Result<R, Error> where
+	B: BlockT<Hash = Hash>,
+	Client: ExecutorProvider<B>,
+	R: Decode,
+{
+	let at = BlockId::Hash(at);
+	let context = ExecutionContext::OffchainCall(None);
+	let (_, extensions) = client.execution_extensions()
+		.manager_and_extensions::<sp_blockchain::Error, ()>(&at, context);
+
+	let encoded = client.executor().call(
+		&at,
+		&format!("ParachainHost_{}", function),
+		&args.encode(),
+		ExecutionStrategy::NativeElseWasm,
+		Some(extensions),
+	)?;
+
+	Ok(R::decode(&mut &encoded[..])?)
+}
+
+/// The runtime API subsystem.
+pub struct RuntimeApiSubsystem<Client> {
+	client: Arc<Client>,
+}
+
+impl<Client> RuntimeApiSubsystem<Client> {
+	/// Create a new instance of the `RuntimeApiSubsystem`, which answers requests with the runtime
+	/// of the given client.
+	pub fn new(client: Arc<Client>) -> Self {
+		RuntimeApiSubsystem { client }
+	}
+}
+
+impl<Client, C> Subsystem<C> for RuntimeApiSubsystem<Client> where
+	Client: ExecutorProvider<Block> + Send + Sync + 'static,
+	C: SubsystemContext<Message = RuntimeApiMessage>,
+{
+	fn start(self, ctx: C) -> SpawnedSubsystem {
+		SpawnedSubsystem {
+			name: "runtime-api-subsystem",
+			future: run::<Block, _>(ctx, self.client).map(|_| ()).boxed(),
+		}
+	}
+}
+
+async fn run<B, Client>(
+	mut ctx: impl SubsystemContext<Message = RuntimeApiMessage>,
+	client: Arc<Client>,
+) -> SubsystemResult<()> where
+	B: BlockT<Hash = Hash>,
+	Client: ExecutorProvider<B>,
+{
+	loop {
+		match ctx.recv().await? {
+			FromOverseer::Signal(OverseerSignal::StartWork(_)) => {}
+			FromOverseer::Signal(OverseerSignal::StopWork(_)) => {}
+			FromOverseer::Signal(OverseerSignal::Conclude) => return Ok(()),
+			FromOverseer::Communication { msg } => match msg {
+				RuntimeApiMessage::Request(relay_parent, request) =>
+					make_runtime_api_request::<B, _>(&*client, relay_parent, request),
+			}
+		}
+	}
+}
+
+/// Answer the request with the post-state of the relay-parent, or drop it if the runtime cannot
+/// answer it.
+fn make_runtime_api_request<B, Client>(
+	client: &Client,
+	relay_parent: Hash,
+	request: RuntimeApiRequest,
+) where
+	B: BlockT<Hash = Hash>,
+	Client: ExecutorProvider<B>,
+{
+	macro_rules! query {
+		($function:ident($($arg:expr),*)) => {
+			call_runtime_api(client, relay_parent, stringify!($function), ($($arg,)*))
+		}
+	}
+
+	match request {
+		RuntimeApiRequest::Validators(tx) =>
+			answer(relay_parent, "validators", query!(validators()).map(Some), tx),
+		RuntimeApiRequest::ValidatorGroups(tx) => {
+			let groups: Result<(_, GroupRotationInfo), _> = query!(validator_groups());
+			let roster = groups.and_then(|(groups, rotation_info)| {
+				let cores = query!(availability_cores())?;
+				Ok(Some(scheduler_roster(groups, rotation_info, cores)))
+			});
+
+			answer(relay_parent, "validator groups", roster, tx)
+		}
+		RuntimeApiRequest::SigningContext(tx) => {
+			let signing_context = query!(session_index_for_child()).map(|session_index| {
+				Some(SigningContext { session_index, parent_hash: relay_parent })
+			});
+
+			answer(relay_parent, "signing context", signing_context, tx)
+		}
+		RuntimeApiRequest::ValidationCode(para_id, _, None, tx) => {
+			// the code of the para in the post-state of the relay-parent is the code which
+			// candidates in its context are executed with.
+			let code = query!(validation_code(para_id, OccupiedCoreAssumption::Free));
+
+			answer(relay_parent, "validation code", code, tx)
+		}
+		RuntimeApiRequest::ValidationCode(para_id, _, Some(_), _) => log::debug!(
+			"The code of para {:?} at an intermediate parablock is not known at {}",
+			para_id,
+			relay_parent,
+		),
+		RuntimeApiRequest::GlobalValidationSchedule(tx) => {
+			let schedule = query!(global_validation_schedule()).map(Some);
+
+			answer(relay_parent, "global validation schedule", schedule, tx)
+		}
+		RuntimeApiRequest::LocalValidationData(para_id, assumption, tx) => {
+			let data = query!(local_validation_data(para_id, assumption)).map(Some);
+
+			answer(relay_parent, "local validation data", data, tx)
+		}
+		RuntimeApiRequest::HeadData(para_id, tx) => {
+			// the head of the para in the post-state of the relay-parent, which a candidate
+			// pending availability has not updated yet.
+			let head = query!(local_validation_data(para_id, OccupiedCoreAssumption::TimedOut))
+				.map(|data: Option<LocalValidationData>| data.map(|data| data.parent_head));
+
+			answer(relay_parent, "head data", head, tx)
+		}
+		RuntimeApiRequest::OldestUnapprovedInclusion(tx) => {
+			let number = query!(oldest_unapproved_inclusion()).map(Some);
+
+			answer(relay_parent, "oldest unapproved inclusion", number, tx)
+		}
+		RuntimeApiRequest::OldestInvalidInclusion(tx) => {
+			let number = query!(oldest_invalid_inclusion()).map(Some);
+
+			answer(relay_parent, "oldest invalid inclusion", number, tx)
+		}
+		RuntimeApiRequest::OldestUnavailableInclusion(tx) => {
+			let number = query!(oldest_unavailable_inclusion()).map(Some);
+
+			answer(relay_parent, "oldest unavailable inclusion", number, tx)
+		}
+		RuntimeApiRequest::PendingPvfChecks(tx) => {
+			let pending = query!(pending_pvf_checks()).map(Some);
+
+			answer(relay_parent, "pending pre-checks", pending, tx)
+		}
+		RuntimeApiRequest::PendingPvfCheckCode(para_id, tx) => {
+			let code = query!(pending_pvf_check_code(para_id)).map(Some);
+
+			answer(relay_parent, "pending pre-check code", code, tx)
+		}
+		RuntimeApiRequest::ScheduledCodeUpgrades(tx) => {
+			let upgrades = query!(scheduled_code_upgrades()).map(Some);
+
+			answer(relay_parent, "scheduled code upgrades", upgrades, tx)
+		}
+		RuntimeApiRequest::SubmitPvfCheckStatement(statement, signature) => {
+			let submitted: Result<(), _> = query!(submit_pvf_check_statement(statement, signature));
+
+			if let Err(e) = submitted {
+				log::warn!("Failed to submit a pre-check statement at {}: {:?}", relay_parent, e);
+			}
+		}
+	}
+}
+
+/// Send the response of the runtime to the requester, or drop the response channel if the runtime
+/// gave none.
+fn answer<T>(
+	relay_parent: Hash,
+	what: &str,
+	response: Result<Option<T>, Error>,
+	tx: oneshot::Sender<T>,
+) {
+	match response {
+		Ok(Some(response)) => {
+			let _ = tx.send(response);
+		}
+		Ok(None) => log::debug!("No {} known at {}", what, relay_parent),
+		Err(e) => log::debug!("Failed to request the {} at {}: {:?}", what, relay_parent, e),
+	}
+}
+
+/// Build the scheduler roster out of the validator groups and the availability cores.
+///
+/// The runtime does not expose which parathread claim occupies a core, so every occupied core is
+/// reported as occupied by a parachain.
+fn scheduler_roster(
+	validator_groups: Vec<Vec<ValidatorIndex>>,
+	rotation_info: GroupRotationInfo,
+	cores: Vec<CoreState>,
+) -> SchedulerRoster {
+	let n_cores = cores.len();
+	let mut scheduled = Vec::new();
+	let mut upcoming = Vec::new();
+	let mut availability_cores = Vec::with_capacity(n_cores);
+
+	for (i, core) in cores.into_iter().enumerate() {
+		let core_index = CoreIndex(i as u32);
+
+		match core {
+			CoreState::Occupied(occupied) => {
+				upcoming.extend(occupied.next_up_on_available.map(|next| next.para_id));
+				availability_cores.push(Some(CoreOccupied::Parachain));
+			}
+			CoreState::Scheduled(core) => {
+				let kind = match core.collator {
+					Some(collator) => AssignmentKind::Parathread(collator, 0),
+					None => AssignmentKind::Parachain,
+				};
+
+				scheduled.push(CoreAssignment {
+					core: core_index,
+					para_id: core.para_id,
+					kind,
+					group_idx: rotation_info.group_for_core(core_index, n_cores),
+				});
+				availability_cores.push(None);
+			}
+			CoreState::Free => availability_cores.push(None),
+		}
+	}
+
+	SchedulerRoster { validator_groups, scheduled, upcoming, availability_cores }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assert_matches::assert_matches;
+	use futures::executor;
+	use polkadot_primitives::v1::{GroupIndex, Id as ParaId, OccupiedCore, ScheduledCore};
+	use sp_blockchain::HeaderBackend;
+
+	#[test]
+	fn scheduler_roster_is_built_from_cores() {
+		let collator = Default::default();
+		let rotation_info = GroupRotationInfo {
+			session_start_block: 10,
+			group_rotation_frequency: 5,
+			now: 15,
+		};
+		let cores = vec![
+			CoreState::Scheduled(ScheduledCore { para_id: ParaId::from(1), collator: None }),
+			CoreState::Occupied(OccupiedCore {
+				para_id: ParaId::from(2),
+				next_up_on_available: Some(ScheduledCore {
+					para_id: ParaId::from(3),
+					collator: None,
+				}),
+				occupied_since: 12,
+				time_out_at: 20,
+				next_up_on_time_out: None,
+				availability: Default::default(),
+				group_responsible: GroupIndex(1),
+			}),
+			CoreState::Scheduled(ScheduledCore {
+				para_id: ParaId::from(4),
+				collator: Some(collator),
+			}),
+			CoreState::Free,
+		];
+
+		let groups = vec![vec![0], vec![1], vec![2], vec![3]];
+		let roster = scheduler_roster(groups.clone(), rotation_info, cores);
+
+		assert_eq!(roster.validator_groups, groups);
+		// the groups have rotated once since the start of the session.
+		assert_eq!(
+			roster.scheduled.iter()
+				.map(|a| (a.core.0, a.para_id, a.kind.clone(), a.group_idx.0))
+				.collect::<Vec<_>>(),
+			vec![
+				(0, ParaId::from(1), AssignmentKind::Parachain, 1),
+				(2, ParaId::from(4), AssignmentKind::Parathread(Default::default(), 0), 3),
+			],
+		);
+		assert_eq!(roster.upcoming, vec![ParaId::from(3)]);
+		assert_eq!(
+			roster.availability_cores,
+			vec![None, Some(CoreOccupied::Parachain), None, None],
+		);
+	}
+
+	#[test]
+	fn requests_are_answered_by_the_runtime() {
+		// the test runtime implements the v0 `ParachainHost` API, which shares the function
+		// listing the validators with the v1 API but knows nothing about inclusions.
+		let client = Arc::new(polkadot_test_runtime_client::new());
+		let relay_parent = client.info().genesis_hash;
+
+		let pool = primitives::testing::SpawnBlockingExecutor::new();
+		let (ctx, mut virtual_overseer) = subsystem_test::make_subsystem_context(pool);
+
+		let subsystem = run(ctx, client);
+		let test_fut = async move {
+			let (tx, rx) = oneshot::channel();
+			virtual_overseer.send(FromOverseer::Communication {
+				msg: RuntimeApiMessage::Request(relay_parent, RuntimeApiRequest::Validators(tx)),
+			}).await;
+			assert_matches!(rx.await, Ok(_));
+
+			let (tx, rx) = oneshot::channel();
+			virtual_overseer.send(FromOverseer::Communication {
+				msg: RuntimeApiMessage::Request(
+					relay_parent,
+					RuntimeApiRequest::OldestInvalidInclusion(tx),
+				),
+			}).await;
+			assert_matches!(rx.await, Err(oneshot::Canceled));
+
+			virtual_overseer.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		};
+
+		futures::pin_mut!(test_fut);
+		futures::pin_mut!(subsystem);
+
+		let (result, _) = executor::block_on(future::join(subsystem, test_fut));
+		assert!(result.is_ok());
+	}
+}
@@ -153,6 +153,7 @@ fn main() {
 			runtime_api: DummySubsystem,
 			availability_store: DummySubsystem,
 			network_bridge: DummySubsystem,
+			pvf_checker: DummySubsystem,
 		};
 		let (overseer, _handler) = Overseer::new(
 			vec![],
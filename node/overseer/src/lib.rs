@@ -79,7 +79,7 @@ use polkadot_subsystem::messages::{
 	CandidateSelectionMessage, StatementDistributionMessage,
 	AvailabilityDistributionMessage, BitfieldDistributionMessage,
 	ProvisionerMessage, PoVDistributionMessage, RuntimeApiMessage,
-	AvailabilityStoreMessage, NetworkBridgeMessage, AllMessages,
+	AvailabilityStoreMessage, NetworkBridgeMessage, PvfCheckerMessage, AllMessages,
 };
 pub use polkadot_subsystem::{
 	Subsystem, SubsystemContext, OverseerSignal, FromOverseer, SubsystemError, SubsystemResult,
@@ -357,6 +357,9 @@ pub struct Overseer<S: SpawnNamed> {
 	/// A network bridge subsystem.
 	network_bridge_subsystem: OverseenSubsystem<NetworkBridgeMessage>,
 
+	/// A PVF checker subsystem.
+	pvf_checker_subsystem: OverseenSubsystem<PvfCheckerMessage>,
+
 
 	/// Spawner to spawn tasks to.
 	s: S,
@@ -390,7 +393,7 @@ pub struct Overseer<S: SpawnNamed> {
 ///
 /// [`Subsystem`]: trait.Subsystem.html
 /// [`DummySubsystem`]: struct.DummySubsystem.html
-pub struct AllSubsystems<CV, CB, CS, SD, AD, BD, P, PoVD, RA, AS, NB> {
+pub struct AllSubsystems<CV, CB, CS, SD, AD, BD, P, PoVD, RA, AS, NB, PC> {
 	/// A candidate validation subsystem.
 	pub candidate_validation: CV,
 	/// A candidate backing subsystem.
@@ -413,6 +416,8 @@ pub struct AllSubsystems<CV, CB, CS, SD, AD, BD, P, PoVD, RA, AS, NB> {
 	pub availability_store: AS,
 	/// A network bridge subsystem.
 	pub network_bridge: NB,
+	/// A PVF checker subsystem.
+	pub pvf_checker: PC,
 }
 
 impl<S> Overseer<S>
@@ -493,6 +498,7 @@ where
 	///     runtime_api: DummySubsystem,
 	///     availability_store: DummySubsystem,
 	///     network_bridge: DummySubsystem,
+	///     pvf_checker: DummySubsystem,
 	/// };
 	/// let (overseer, _handler) = Overseer::new(
 	///     vec![],
@@ -513,9 +519,9 @@ where
 	/// #
 	/// # }); }
 	/// ```
-	pub fn new<CV, CB, CS, SD, AD, BD, P, PoVD, RA, AS, NB>(
+	pub fn new<CV, CB, CS, SD, AD, BD, P, PoVD, RA, AS, NB, PC>(
 		leaves: impl IntoIterator<Item = BlockInfo>,
-		all_subsystems: AllSubsystems<CV, CB, CS, SD, AD, BD, P, PoVD, RA, AS, NB>,
+		all_subsystems: AllSubsystems<CV, CB, CS, SD, AD, BD, P, PoVD, RA, AS, NB, PC>,
 		mut s: S,
 	) -> SubsystemResult<(Self, OverseerHandler)>
 	where
@@ -530,6 +536,7 @@ where
 		RA: Subsystem<OverseerSubsystemContext<RuntimeApiMessage>> + Send,
 		AS: Subsystem<OverseerSubsystemContext<AvailabilityStoreMessage>> + Send,
 		NB: Subsystem<OverseerSubsystemContext<NetworkBridgeMessage>> + Send,
+		PC: Subsystem<OverseerSubsystemContext<PvfCheckerMessage>> + Send,
 	{
 		let (events_tx, events_rx) = mpsc::channel(CHANNEL_CAPACITY);
 
@@ -617,6 +624,13 @@ where
 			all_subsystems.network_bridge,
 		)?;
 
+		let pvf_checker_subsystem = spawn(
+			&mut s,
+			&mut running_subsystems,
+			&mut running_subsystems_rx,
+			all_subsystems.pvf_checker,
+		)?;
+
 		let active_leaves = HashSet::new();
 
 		let leaves = leaves
@@ -636,6 +650,7 @@ where
 			runtime_api_subsystem,
 			availability_store_subsystem,
 			network_bridge_subsystem,
+			pvf_checker_subsystem,
 			s,
 			running_subsystems,
 			running_subsystems_rx,
@@ -693,6 +708,10 @@ where
 			let _ = s.tx.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
 		}
 
+		if let Some(ref mut s) = self.pvf_checker_subsystem.instance {
+			let _ = s.tx.send(FromOverseer::Signal(OverseerSignal::Conclude)).await;
+		}
+
 		let mut stop_delay = Delay::new(Duration::from_secs(STOP_DELAY)).fuse();
 
 		loop {
@@ -833,6 +852,10 @@ where
 		}
 
 		if let Some(ref mut s) = self.network_bridge_subsystem.instance {
+			s.tx.send(FromOverseer::Signal(signal.clone())).await?;
+		}
+
+		if let Some(ref mut s) = self.pvf_checker_subsystem.instance {
 			s.tx.send(FromOverseer::Signal(signal)).await?;
 		}
 
@@ -896,6 +919,11 @@ where
 					let _ = s.tx.send(FromOverseer::Communication { msg }).await;
 				}
 			}
+			AllMessages::PvfChecker(msg) => {
+				if let Some(ref mut s) = self.pvf_checker_subsystem.instance {
+					let _ = s.tx.send(FromOverseer::Communication { msg }).await;
+				}
+			}
 		}
 	}
 
@@ -1056,6 +1084,7 @@ mod tests {
 				runtime_api: DummySubsystem,
 				availability_store: DummySubsystem,
 				network_bridge: DummySubsystem,
+				pvf_checker: DummySubsystem,
 			};
 			let (overseer, mut handler) = Overseer::new(
 				vec![],
@@ -1118,6 +1147,7 @@ mod tests {
 				runtime_api: DummySubsystem,
 				availability_store: DummySubsystem,
 				network_bridge: DummySubsystem,
+				pvf_checker: DummySubsystem,
 			};
 			let (overseer, _handle) = Overseer::new(
 				vec![],
@@ -1233,6 +1263,7 @@ mod tests {
 				runtime_api: DummySubsystem,
 				availability_store: DummySubsystem,
 				network_bridge: DummySubsystem,
+				pvf_checker: DummySubsystem,
 			};
 			let (overseer, mut handler) = Overseer::new(
 				vec![first_block],
@@ -1329,6 +1360,7 @@ mod tests {
 				runtime_api: DummySubsystem,
 				availability_store: DummySubsystem,
 				network_bridge: DummySubsystem,
+				pvf_checker: DummySubsystem,
 			};
 			// start with two forks of different height.
 			let (overseer, mut handler) = Overseer::new(
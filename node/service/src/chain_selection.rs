@@ -0,0 +1,335 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Parachain-aware chain selection.
+//!
+//! Relay chain blocks which include parachain candidates that have been found to be invalid, or
+//! which backed candidates that never became available, should never be built upon, and blocks
+//! which include candidates that have not yet been approved should not be finalized. Both of these
+//! facts are known to the runtime, which is queried through the client.
+
+use std::sync::Arc;
+
+use polkadot_node_core_runtime_api::call_runtime_api;
+use polkadot_primitives::v1::{BlockNumber, Hash, Header};
+use consensus_common::{Error as ConsensusError, SelectChain};
+use sc_client_api::ExecutorProvider;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::generic::BlockId;
+use sp_runtime::traits::{Block as BlockT, Header as _, NumberFor};
+
+/// Call a runtime API function returning a block number at the given block.
+///
+/// Returns `None` if the runtime does not know of any such block or cannot answer the call.
+fn request_block_number<Block, C>(
+	client: &C,
+	at: Hash,
+	function: &str,
+) -> Option<BlockNumber> where
+	Block: BlockT<Hash = Hash>,
+	C: ExecutorProvider<Block>,
+{
+	call_runtime_api(client, at, function, ()).unwrap_or_else(|e| {
+		log::debug!("Runtime API call {} failed at {}: {:?}", function, at, e);
+		None
+	})
+}
+
+/// Walk backwards from `header` until the ancestor at height `number` is found.
+///
+/// Returns `None` if `number` is above `header` or some ancestor is unknown to the backend.
+fn find_ancestor<Block, B>(
+	backend: &B,
+	header: &Header,
+	number: BlockNumber,
+) -> Option<Header> where
+	Block: BlockT<Hash = Hash, Header = Header>,
+	B: HeaderBackend<Block>,
+{
+	let mut current = header.clone();
+
+	loop {
+		if current.number < number {
+			return None;
+		}
+
+		if current.number == number {
+			return Some(current);
+		}
+
+		current = backend.header(BlockId::Hash(current.parent_hash)).ok()??;
+	}
+}
+
+/// Pick the block to build on given the best block and the oldest blocks in its chain which
+/// included an invalid candidate or backed a candidate that never became available.
+fn best_chain_before<Block, B>(
+	backend: &B,
+	best: Header,
+	oldest_invalid: Option<BlockNumber>,
+	oldest_unavailable: Option<BlockNumber>,
+) -> Result<Header, ConsensusError> where
+	Block: BlockT<Hash = Hash, Header = Header>,
+	B: HeaderBackend<Block>,
+{
+	let oldest_reverted = match (oldest_invalid, oldest_unavailable) {
+		(Some(invalid), Some(unavailable)) => Some(std::cmp::min(invalid, unavailable)),
+		(invalid, unavailable) => invalid.or(unavailable),
+	};
+
+	match oldest_reverted {
+		Some(number) if number > 0 && number <= best.number => {
+			find_ancestor::<Block, _>(backend, &best, number - 1)
+				.ok_or_else(|| ConsensusError::ChainLookup(format!(
+					"Missing ancestor #{} of best block {}", number - 1, best.hash(),
+				)))
+		}
+		_ => Ok(best),
+	}
+}
+
+/// Restrict a vote for `current_target` to blocks below the oldest block in its chain which
+/// included a candidate that has not yet been approved.
+fn restrict_vote_before<Block, B>(
+	backend: &B,
+	base: &Header,
+	current_target: &Header,
+	oldest_unapproved: Option<BlockNumber>,
+) -> Option<(Hash, BlockNumber)> where
+	Block: BlockT<Hash = Hash, Header = Header>,
+	B: HeaderBackend<Block>,
+{
+	let oldest_unapproved = oldest_unapproved?;
+
+	// nothing above the base is approved, so keep voting for the base.
+	if oldest_unapproved <= base.number {
+		return Some((base.hash(), base.number));
+	}
+
+	if oldest_unapproved > current_target.number {
+		return None;
+	}
+
+	find_ancestor::<Block, _>(backend, current_target, oldest_unapproved - 1)
+		.map(|header| (header.hash(), header.number))
+}
+
+/// A [`SelectChain`] which wraps another one and avoids building on top of relay chain blocks
+/// which included candidates that were found to be invalid or backed candidates that never
+/// became available.
+///
+/// If the best block of the inner chain selection has such a block in its ancestry, the parent of
+/// the oldest such block is returned instead.
+pub struct SelectRelayChain<S, C> {
+	inner: S,
+	client: Arc<C>,
+}
+
+impl<S, C> SelectRelayChain<S, C> {
+	/// Create a new `SelectRelayChain` wrapping `inner`.
+	pub fn new(inner: S, client: Arc<C>) -> Self {
+		SelectRelayChain {
+			inner,
+			client,
+		}
+	}
+}
+
+impl<S: Clone, C> Clone for SelectRelayChain<S, C> {
+	fn clone(&self) -> Self {
+		SelectRelayChain {
+			inner: self.inner.clone(),
+			client: self.client.clone(),
+		}
+	}
+}
+
+impl<Block, S, C> SelectChain<Block> for SelectRelayChain<S, C> where
+	Block: BlockT<Hash = Hash, Header = Header>,
+	S: SelectChain<Block>,
+	C: HeaderBackend<Block> + ExecutorProvider<Block> + Send + Sync,
+{
+	fn leaves(&self) -> Result<Vec<Hash>, ConsensusError> {
+		self.inner.leaves()
+	}
+
+	fn best_chain(&self) -> Result<Header, ConsensusError> {
+		let best = self.inner.best_chain()?;
+
+		let oldest_invalid =
+			request_block_number(&*self.client, best.hash(), "oldest_invalid_inclusion");
+		let oldest_unavailable =
+			request_block_number(&*self.client, best.hash(), "oldest_unavailable_inclusion");
+
+		best_chain_before::<Block, _>(&*self.client, best, oldest_invalid, oldest_unavailable)
+	}
+
+	fn finality_target(
+		&self,
+		target_hash: Hash,
+		maybe_max_number: Option<NumberFor<Block>>,
+	) -> Result<Option<Hash>, ConsensusError> {
+		self.inner.finality_target(target_hash, maybe_max_number)
+	}
+}
+
+/// A GRANDPA voting rule which restricts votes to blocks below the oldest relay chain block
+/// including a candidate which has not yet been approved.
+pub(crate) struct ApprovedCandidatesOnly<C>(pub(crate) Arc<C>);
+
+impl<Block, B, C> grandpa::VotingRule<Block, B> for ApprovedCandidatesOnly<C> where
+	Block: BlockT<Hash = Hash, Header = Header>,
+	B: HeaderBackend<Block>,
+	C: ExecutorProvider<Block> + Send + Sync,
+{
+	fn restrict_vote(
+		&self,
+		backend: &B,
+		base: &Header,
+		_best_target: &Header,
+		current_target: &Header,
+	) -> Option<(Hash, BlockNumber)> {
+		let oldest_unapproved =
+			request_block_number(&*self.0, current_target.hash(), "oldest_unapproved_inclusion");
+
+		restrict_vote_before::<Block, _>(backend, base, current_target, oldest_unapproved)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use grandpa::VotingRule;
+	use polkadot_test_runtime_client::prelude::*;
+	use polkadot_test_runtime_client::sp_consensus::BlockOrigin;
+	use polkadot_test_runtime_client::runtime::Block;
+	use sc_consensus::LongestChain;
+	use sc_block_builder::BlockBuilderProvider;
+
+	fn client_with_blocks(n: u32) -> (Arc<TestClient>, LongestChain<Backend, Block>) {
+		let (client, longest_chain) = TestClientBuilder::new().build_with_longest_chain();
+		let client = Arc::new(client);
+
+		{
+			let mut client = client.clone();
+			for i in 0..n {
+				let mut builder = client.new_block(Default::default()).unwrap();
+
+				for extrinsic in polkadot_test_runtime_client::needed_extrinsics(vec![], i) {
+					builder.push(extrinsic).unwrap()
+				}
+
+				let block = builder.build().unwrap().block;
+				client.import(BlockOrigin::Own, block).unwrap();
+			}
+		}
+
+		assert_eq!(client.info().best_number, n);
+		(client, longest_chain)
+	}
+
+	fn get_header(client: &TestClient, n: BlockNumber) -> Header {
+		client.header(&BlockId::Number(n)).unwrap().unwrap()
+	}
+
+	fn restrict_vote(
+		client: &TestClient,
+		base: BlockNumber,
+		current_target: BlockNumber,
+		oldest_unapproved: Option<BlockNumber>,
+	) -> Option<(Hash, BlockNumber)> {
+		restrict_vote_before::<Block, _>(
+			client,
+			&get_header(client, base),
+			&get_header(client, current_target),
+			oldest_unapproved,
+		)
+	}
+
+	fn best_chain(
+		client: &TestClient,
+		oldest_invalid: Option<BlockNumber>,
+		oldest_unavailable: Option<BlockNumber>,
+	) -> Header {
+		best_chain_before::<Block, _>(
+			client,
+			get_header(client, 20),
+			oldest_invalid,
+			oldest_unavailable,
+		).unwrap()
+	}
+
+	#[test]
+	fn votes_are_restricted_to_approved_candidates() {
+		let (client, _) = client_with_blocks(20);
+
+		// everything is approved, votes are not restricted.
+		assert_eq!(restrict_vote(&client, 0, 20, None), None);
+
+		// block #15 included an unapproved candidate, votes are limited to its parent.
+		let parent = get_header(&client, 14);
+		assert_eq!(restrict_vote(&client, 10, 20, Some(15)), Some((parent.hash(), parent.number)));
+
+		// the target is below the unapproved block, the vote is not restricted.
+		assert_eq!(restrict_vote(&client, 10, 12, Some(15)), None);
+
+		// the base itself is not approved yet, keep voting for the base.
+		let base = get_header(&client, 16);
+		assert_eq!(restrict_vote(&client, 16, 20, Some(15)), Some((base.hash(), base.number)));
+	}
+
+	#[test]
+	fn best_chain_avoids_invalid_and_unavailable_inclusions() {
+		let (client, _) = client_with_blocks(20);
+
+		// nothing invalid or unavailable, the best block is kept.
+		assert_eq!(best_chain(&client, None, None), get_header(&client, 20));
+
+		// block #12 included an invalid candidate, build on its parent instead.
+		assert_eq!(best_chain(&client, Some(12), None), get_header(&client, 11));
+
+		// block #14 backed a candidate which timed out, build on its parent instead.
+		assert_eq!(best_chain(&client, None, Some(14)), get_header(&client, 13));
+
+		// the oldest of the invalid and unavailable inclusions is avoided.
+		assert_eq!(best_chain(&client, Some(16), Some(14)), get_header(&client, 13));
+		assert_eq!(best_chain(&client, Some(12), Some(14)), get_header(&client, 11));
+	}
+
+	#[test]
+	fn runtime_without_inclusion_tracking_is_not_restricted() {
+		let _ = env_logger::try_init();
+
+		// the test runtime does not track the inclusions of candidates, so the runtime API calls
+		// fail and neither the chain selection nor the votes are restricted.
+		let (client, longest_chain) = client_with_blocks(20);
+
+		let select_chain = SelectRelayChain::new(longest_chain.clone(), client.clone());
+		assert_eq!(select_chain.best_chain().unwrap(), get_header(&client, 20));
+		assert_eq!(select_chain.leaves().unwrap(), longest_chain.leaves().unwrap());
+
+		let voting_rule = ApprovedCandidatesOnly(client.clone());
+		assert_eq!(
+			voting_rule.restrict_vote(
+				&*client,
+				&get_header(&client, 10),
+				&get_header(&client, 20),
+				&get_header(&client, 20),
+			),
+			None,
+		);
+	}
+}
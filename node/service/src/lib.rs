@@ -17,6 +17,7 @@
 //! Polkadot service. Specialized wrapper over substrate service.
 
 pub mod chain_spec;
+mod chain_selection;
 mod grandpa_support;
 mod client;
 
@@ -32,6 +33,14 @@ use sp_blockchain::HeaderBackend;
 use polkadot_overseer::{self as overseer, AllSubsystems, BlockInfo, Overseer, OverseerHandler};
 use polkadot_subsystem::DummySubsystem;
 use polkadot_node_core_proposer::ProposerFactory;
+use polkadot_node_core_backing::CandidateBackingSubsystem;
+use polkadot_node_core_candidate_validation::CandidateValidationSubsystem;
+use polkadot_node_core_pvf_checker::PvfCheckerSubsystem;
+use polkadot_node_core_runtime_api::RuntimeApiSubsystem;
+use polkadot_parachain::wasm_executor::ValidationPool;
+use polkadot_network_bridge::{self as network_bridge, NetworkBridge};
+use polkadot_pov_distribution::PoVDistribution;
+use polkadot_statement_distribution::StatementDistribution;
 use sp_trie::PrefixedMemoryDB;
 use sp_core::traits::SpawnNamed;
 pub use service::{
@@ -56,6 +65,8 @@ pub use kusama_runtime;
 pub use westend_runtime;
 use prometheus_endpoint::Registry;
 pub use self::client::PolkadotClient;
+pub use self::chain_selection::SelectRelayChain;
+pub use polkadot_parachain::wasm_executor::ValidationPoolConfig;
 
 native_executor_instance!(
 	pub PolkadotExecutor,
@@ -91,6 +102,7 @@ pub trait RuntimeApiCollection<Extrinsic: codec::Codec + Send + Sync + 'static>:
 	+ sp_offchain::OffchainWorkerApi<Block>
 	+ sp_session::SessionKeys<Block>
 	+ authority_discovery_primitives::AuthorityDiscoveryApi<Block>
+	+ polkadot_runtime_common::claims::ClaimsApi<Block, AccountId>
 where
 	Extrinsic: RuntimeExtrinsic,
 	<Self as sp_api::ApiExt<Block>>::StateBackend: sp_api::StateBackend<BlakeTwo256>,
@@ -109,7 +121,8 @@ where
 	+ sp_api::Metadata<Block>
 	+ sp_offchain::OffchainWorkerApi<Block>
 	+ sp_session::SessionKeys<Block>
-	+ authority_discovery_primitives::AuthorityDiscoveryApi<Block>,
+	+ authority_discovery_primitives::AuthorityDiscoveryApi<Block>
+	+ polkadot_runtime_common::claims::ClaimsApi<Block, AccountId>,
 	Extrinsic: RuntimeExtrinsic,
 	<Self as sp_api::ApiExt<Block>>::StateBackend: sp_api::StateBackend<BlakeTwo256>,
 {}
@@ -270,22 +283,39 @@ macro_rules! new_full_start {
 	}}
 }
 
-fn real_overseer<S: SpawnNamed>(
+/// Instantiate the overseer with all subsystems which have an implementation. The remaining
+/// slots are filled with `DummySubsystem`s.
+///
+/// The network service must have the notifications protocol of the network bridge registered.
+fn real_overseer<S, N, C>(
 	leaves: impl IntoIterator<Item = BlockInfo>,
+	client: Arc<C>,
+	network: N,
+	keystore: sc_keystore::KeyStorePtr,
+	validation_pool_config: ValidationPoolConfig,
 	s: S,
-) -> Result<(Overseer<S>, OverseerHandler), ServiceError> {
+) -> Result<(Overseer<S>, OverseerHandler), ServiceError>
+where
+	S: SpawnNamed + Clone + Unpin + Send + 'static,
+	N: network_bridge::Network,
+	C: sc_client_api::ExecutorProvider<Block> + Send + Sync + 'static,
+{
+	let max_code_size = validation_pool_config.max_code_size;
+	let validation_pool = ValidationPool::new(validation_pool_config);
+
 	let all_subsystems = AllSubsystems {
-		candidate_validation: DummySubsystem,
-		candidate_backing: DummySubsystem,
+		candidate_validation: CandidateValidationSubsystem::new(Some(validation_pool.clone())),
+		candidate_backing: CandidateBackingSubsystem::new(s.clone(), keystore.clone()),
 		candidate_selection: DummySubsystem,
-		statement_distribution: DummySubsystem,
+		statement_distribution: StatementDistribution,
 		availability_distribution: DummySubsystem,
 		bitfield_distribution: DummySubsystem,
 		provisioner: DummySubsystem,
-		pov_distribution: DummySubsystem,
-		runtime_api: DummySubsystem,
+		pov_distribution: PoVDistribution,
+		runtime_api: RuntimeApiSubsystem::new(client),
 		availability_store: DummySubsystem,
-		network_bridge: DummySubsystem,
+		network_bridge: NetworkBridge::new(network),
+		pvf_checker: PvfCheckerSubsystem::new(keystore, max_code_size, Some(validation_pool)),
 	};
 	Overseer::new(
 		leaves,
@@ -302,6 +332,7 @@ macro_rules! new_full {
 		$collating_for:expr,
 		$authority_discovery_disabled:expr,
 		$grandpa_pause:expr,
+		$validation_pool_config:expr,
 		$runtime:ty,
 		$dispatch:ty,
 	) => {{
@@ -315,6 +346,8 @@ macro_rules! new_full {
 		let disable_grandpa = $config.disable_grandpa;
 		let name = $config.network.node_name.clone();
 
+		$config.network.notifications_protocols.push(network_bridge::notifications_protocol_info());
+
 		let (builder, mut import_setup, inherent_data_providers, mut rpc_setup) =
 			new_full_start!($config, $runtime, $dispatch);
 
@@ -352,7 +385,14 @@ macro_rules! new_full {
 			})
 			.collect();
 
-		let (overseer, handler) = real_overseer(leaves, spawner)?;
+		let (overseer, handler) = real_overseer(
+			leaves,
+			client.clone(),
+			network.clone(),
+			keystore.clone(),
+			$validation_pool_config,
+			spawner,
+		)?;
 		let handler_clone = handler.clone();
 
 		task_manager.spawn_essential_handle().spawn_blocking("overseer", Box::pin(async move {
@@ -376,14 +416,17 @@ macro_rules! new_full {
 		}));
 
 		if role.is_authority() {
-			let select_chain = select_chain.ok_or(ServiceError::SelectChainRequired)?;
+			let select_chain = SelectRelayChain::new(
+				select_chain.ok_or(ServiceError::SelectChainRequired)?,
+				client.clone(),
+			);
 			let can_author_with =
 				consensus_common::CanAuthorWithNativeVersion::new(client.executor().clone());
 
 			let proposer = ProposerFactory::new(
 				client.clone(),
 				transaction_pool,
-				handler_clone,
+				handler_clone.clone(),
 			);
 
 			let babe_config = babe::BabeParams {
@@ -430,6 +473,11 @@ macro_rules! new_full {
 			// provide better guarantees of block and vote data availability than
 			// the observer.
 
+			// never vote for blocks which include candidates that have not been
+			// approved yet.
+			let voting_rules = grandpa::VotingRulesBuilder::default()
+				.add(chain_selection::ApprovedCandidatesOnly(client.clone()));
+
 			// add a custom voting rule to temporarily stop voting for new blocks
 			// after the given pause block is finalized and restarting after the
 			// given delay.
@@ -440,13 +488,11 @@ macro_rules! new_full {
 						delay,
 					);
 
-					grandpa::VotingRulesBuilder::default()
+					voting_rules
 						.add(grandpa_support::PauseAfterBlockFor(block, delay))
 						.build()
 				},
-				None =>
-					grandpa::VotingRulesBuilder::default()
-						.build(),
+				None => voting_rules.build(),
 			};
 
 			let grandpa_config = grandpa::GrandpaParams {
@@ -604,6 +650,7 @@ pub fn polkadot_new_full(
 	_authority_discovery_disabled: bool,
 	_slot_duration: u64,
 	grandpa_pause: Option<(u32, u32)>,
+	validation_pool_config: ValidationPoolConfig,
 )
 	-> Result<(
 		TaskManager,
@@ -620,6 +667,7 @@ pub fn polkadot_new_full(
 		collating_for,
 		authority_discovery_disabled,
 		grandpa_pause,
+		validation_pool_config,
 		polkadot_runtime::RuntimeApi,
 		PolkadotExecutor,
 	);
@@ -636,6 +684,7 @@ pub fn kusama_new_full(
 	_authority_discovery_disabled: bool,
 	_slot_duration: u64,
 	grandpa_pause: Option<(u32, u32)>,
+	validation_pool_config: ValidationPoolConfig,
 ) -> Result<(
 		TaskManager,
 		Arc<impl PolkadotClient<
@@ -652,6 +701,7 @@ pub fn kusama_new_full(
 		collating_for,
 		authority_discovery_disabled,
 		grandpa_pause,
+		validation_pool_config,
 		kusama_runtime::RuntimeApi,
 		KusamaExecutor,
 	);
@@ -668,6 +718,7 @@ pub fn westend_new_full(
 	_authority_discovery_disabled: bool,
 	_slot_duration: u64,
 	grandpa_pause: Option<(u32, u32)>,
+	validation_pool_config: ValidationPoolConfig,
 )
 	-> Result<(
 		TaskManager,
@@ -684,6 +735,7 @@ pub fn westend_new_full(
 		collating_for,
 		authority_discovery_disabled,
 		grandpa_pause,
+		validation_pool_config,
 		westend_runtime::RuntimeApi,
 		WestendExecutor,
 	);
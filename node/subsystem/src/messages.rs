@@ -29,7 +29,8 @@ use polkadot_primitives::v1::{
 	CandidateReceipt, PoV, ErasureChunk, BackedCandidate, Id as ParaId,
 	SignedAvailabilityBitfield, SigningContext, ValidatorId, ValidationCode, ValidatorIndex,
 	CoreAssignment, CoreOccupied, HeadData, CandidateDescriptor,
-	ValidatorSignature, OmittedValidationData,
+	ValidatorSignature, OmittedValidationData, PvfCheckStatement, GlobalValidationSchedule,
+	LocalValidationData, OccupiedCoreAssumption,
 };
 use polkadot_node_primitives::{
 	MisbehaviorReport, SignedFullStatement, View, ProtocolId, ValidationResult,
@@ -270,8 +271,35 @@ pub enum RuntimeApiRequest {
 	/// an optional block number representing an intermediate parablock executed in the context of
 	/// that block.
 	ValidationCode(ParaId, BlockNumber, Option<BlockNumber>, oneshot::Sender<ValidationCode>),
+	/// Get the global validation schedule at the state of a given block.
+	GlobalValidationSchedule(oneshot::Sender<GlobalValidationSchedule>),
+	/// Get the local validation data for a specific para, with the given occupied core assumption.
+	LocalValidationData(
+		ParaId,
+		OccupiedCoreAssumption,
+		oneshot::Sender<Option<LocalValidationData>>,
+	),
 	/// Get head data for a specific para.
 	HeadData(ParaId, oneshot::Sender<HeadData>),
+	/// Get the number of the oldest block in the chain which included a candidate that has not
+	/// yet been approved, if any.
+	OldestUnapprovedInclusion(oneshot::Sender<Option<BlockNumber>>),
+	/// Get the number of the oldest block in the chain which included a candidate that was found
+	/// to be invalid, if any.
+	OldestInvalidInclusion(oneshot::Sender<Option<BlockNumber>>),
+	/// Get the number of the oldest block in the chain which backed a candidate that then timed
+	/// out while pending availability, if any.
+	OldestUnavailableInclusion(oneshot::Sender<Option<BlockNumber>>),
+	/// Get the paras with a code upgrade which is being pre-checked by validators, along with the
+	/// hash of the new validation code.
+	PendingPvfChecks(oneshot::Sender<Vec<(ParaId, Hash)>>),
+	/// Get the new validation code of a para whose code upgrade is being pre-checked, if any.
+	PendingPvfCheckCode(ParaId, oneshot::Sender<Option<ValidationCode>>),
+	/// Get the paras whose code upgrade was scheduled within the block, along with the new
+	/// validation code.
+	ScheduledCodeUpgrades(oneshot::Sender<Vec<(ParaId, ValidationCode)>>),
+	/// Submit a signed statement on a pending pre-check as an unsigned transaction.
+	SubmitPvfCheckStatement(PvfCheckStatement, ValidatorSignature),
 }
 
 /// A message to the Runtime API subsystem.
@@ -290,6 +318,19 @@ impl RuntimeApiMessage {
 	}
 }
 
+/// Messages received by the PVF checker subsystem.
+///
+/// The PVF checker acts on overseer signals only, so no messages are defined yet.
+#[derive(Debug)]
+pub enum PvfCheckerMessage {}
+
+impl PvfCheckerMessage {
+	/// If the current variant contains the relay parent hash, return it.
+	pub fn relay_parent(&self) -> Option<Hash> {
+		match *self {}
+	}
+}
+
 /// Statement distribution message.
 #[derive(Debug)]
 pub enum StatementDistributionMessage {
@@ -408,4 +449,6 @@ pub enum AllMessages {
 	AvailabilityStore(AvailabilityStoreMessage),
 	/// Message for the network bridge subsystem.
 	NetworkBridge(NetworkBridgeMessage),
+	/// Message for the PVF checker subsystem.
+	PvfChecker(PvfCheckerMessage),
 }
@@ -0,0 +1,352 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Spins up a network of test nodes, each running an overseer with the real backing, statement
+//! distribution, PoV distribution and network bridge subsystems on top of the node's network
+//! service, and checks that they back a candidate.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use futures::{channel::mpsc, future, pin_mut, select, FutureExt as _, StreamExt as _};
+use polkadot_network_bridge::{self as network_bridge, NetworkBridge, POLKADOT_ENGINE_ID};
+use polkadot_node_core_backing::CandidateBackingSubsystem;
+use polkadot_node_primitives::{ValidationOutputs, ValidationResult};
+use polkadot_overseer::{AllSubsystems, BlockInfo, Overseer, OverseerHandler};
+use polkadot_pov_distribution::PoVDistribution;
+use polkadot_primitives::v1::{
+	AssignmentKind, AvailableData, BackedCandidate, BlockData, CandidateCommitments,
+	CandidateDescriptor, CandidateReceipt, CoreAssignment, CoreIndex, GlobalValidationSchedule,
+	GroupIndex, Hash, HeadData, Id as ParaId, LocalValidationData, OmittedValidationData, PoV,
+	SigningContext, ValidatorId, ValidatorPair,
+};
+use polkadot_statement_distribution::StatementDistribution;
+use polkadot_subsystem::{
+	messages::{
+		AllMessages, CandidateBackingMessage, CandidateValidationMessage, PoVDistributionMessage,
+		ProvisionableData, ProvisionerMessage, RuntimeApiMessage, RuntimeApiRequest,
+		SchedulerRoster,
+	},
+	DummySubsystem, FromOverseer, OverseerSignal, SpawnedSubsystem, Subsystem, SubsystemContext,
+};
+use polkadot_test_service::*;
+use sc_network::{Event as NetworkEvent, NetworkService, PeerId};
+use service::{config::MultiaddrWithPeerId, TaskExecutor, TaskManager};
+use sp_blockchain::HeaderBackend;
+use sp_keyring::Sr25519Keyring;
+use tokio::{time::delay_for as sleep, task::spawn};
+
+static INTEGRATION_TEST_ALLOWED_TIME: Option<&str> = option_env!("INTEGRATION_TEST_ALLOWED_TIME");
+
+/// The validators of the test network. The first two form the backing group of `PARA_ID`.
+const VALIDATORS: [Sr25519Keyring; 3] = [
+	Sr25519Keyring::Alice,
+	Sr25519Keyring::Bob,
+	Sr25519Keyring::Charlie,
+];
+
+const PARA_ID: u32 = 1;
+
+/// A runtime API subsystem which answers the requests made while backing a candidate.
+///
+/// No runtime implements the v1 `ParachainHost` API yet, so the test runtime cannot schedule the
+/// para.
+struct TestRuntimeApi {
+	validators: Vec<ValidatorId>,
+	roster: SchedulerRoster,
+}
+
+impl<C> Subsystem<C> for TestRuntimeApi
+	where C: SubsystemContext<Message=RuntimeApiMessage>
+{
+	fn start(self, mut ctx: C) -> SpawnedSubsystem {
+		SpawnedSubsystem {
+			name: "test-runtime-api",
+			future: Box::pin(async move {
+				loop {
+					match ctx.recv().await {
+						Ok(FromOverseer::Communication {
+							msg: RuntimeApiMessage::Request(relay_parent, request),
+						}) => match request {
+							RuntimeApiRequest::Validators(tx) => {
+								let _ = tx.send(self.validators.clone());
+							}
+							RuntimeApiRequest::ValidatorGroups(tx) => {
+								let _ = tx.send(self.roster.clone());
+							}
+							RuntimeApiRequest::SigningContext(tx) => {
+								let _ = tx.send(SigningContext {
+									session_index: 1,
+									parent_hash: relay_parent,
+								});
+							}
+							_ => (),
+						},
+						Ok(FromOverseer::Signal(OverseerSignal::Conclude)) => return,
+						Err(_) => return,
+						_ => (),
+					}
+				}
+			}),
+		}
+	}
+}
+
+/// A candidate validation subsystem which judges every candidate valid.
+struct TestCandidateValidation;
+
+impl<C> Subsystem<C> for TestCandidateValidation
+	where C: SubsystemContext<Message=CandidateValidationMessage>
+{
+	fn start(self, mut ctx: C) -> SpawnedSubsystem {
+		SpawnedSubsystem {
+			name: "test-candidate-validation",
+			future: Box::pin(async move {
+				loop {
+					match ctx.recv().await {
+						Ok(FromOverseer::Communication {
+							msg: CandidateValidationMessage::ValidateFromChainState(_, _, tx),
+						}) => {
+							let _ = tx.send(Ok(ValidationResult::Valid(validation_outputs())));
+						}
+						Ok(FromOverseer::Signal(OverseerSignal::Conclude)) => return,
+						Err(_) => return,
+						_ => (),
+					}
+				}
+			}),
+		}
+	}
+}
+
+/// A provisioner subsystem which passes on the candidates backed by its node.
+struct TestProvisioner(mpsc::UnboundedSender<BackedCandidate>);
+
+impl<C> Subsystem<C> for TestProvisioner
+	where C: SubsystemContext<Message=ProvisionerMessage>
+{
+	fn start(self, mut ctx: C) -> SpawnedSubsystem {
+		SpawnedSubsystem {
+			name: "test-provisioner",
+			future: Box::pin(async move {
+				loop {
+					match ctx.recv().await {
+						Ok(FromOverseer::Communication {
+							msg: ProvisionerMessage::ProvisionableData(
+								ProvisionableData::BackedCandidate(backed),
+							),
+						}) => {
+							let _ = self.0.unbounded_send(backed);
+						}
+						Ok(FromOverseer::Signal(OverseerSignal::Conclude)) => return,
+						Err(_) => return,
+						_ => (),
+					}
+				}
+			}),
+		}
+	}
+}
+
+fn validation_outputs() -> ValidationOutputs {
+	ValidationOutputs {
+		head_data: HeadData(vec![1, 2, 3]),
+		global_validation_schedule: GlobalValidationSchedule::default(),
+		local_validation_data: LocalValidationData::default(),
+		upward_messages: Vec::new(),
+		fees: Default::default(),
+		new_validation_code: None,
+	}
+}
+
+fn roster() -> SchedulerRoster {
+	SchedulerRoster {
+		validator_groups: vec![vec![0, 1], vec![2]],
+		scheduled: vec![
+			CoreAssignment {
+				core: CoreIndex::from(0),
+				para_id: PARA_ID.into(),
+				kind: AssignmentKind::Parachain,
+				group_idx: GroupIndex::from(0),
+			},
+			CoreAssignment {
+				core: CoreIndex::from(1),
+				para_id: (PARA_ID + 1).into(),
+				kind: AssignmentKind::Parachain,
+				group_idx: GroupIndex::from(1),
+			},
+		],
+		upcoming: vec![],
+		availability_cores: vec![],
+	}
+}
+
+/// A test node running an overseer on top of its network service.
+struct OverseerNode {
+	task_manager: TaskManager,
+	network: Arc<NetworkService<polkadot_primitives::v1::Block, Hash>>,
+	handler: OverseerHandler,
+	backed: mpsc::UnboundedReceiver<BackedCandidate>,
+	addr: MultiaddrWithPeerId,
+	genesis_hash: Hash,
+}
+
+/// Start a test node for the given validator and an overseer working on its genesis block.
+fn run_overseer_node(
+	task_executor: TaskExecutor,
+	validator: Sr25519Keyring,
+	boot_nodes: Vec<MultiaddrWithPeerId>,
+) -> OverseerNode {
+	let mut config = node_config(|| {}, task_executor, validator, boot_nodes);
+	config.network.notifications_protocols.push(network_bridge::notifications_protocol_info());
+
+	let multiaddr = config.network.listen_addresses[0].clone();
+	let (task_manager, client, _, network, _) =
+		polkadot_test_new_full(config, None, None, true, 6000)
+			.expect("could not create Polkadot test service");
+
+	let addr = MultiaddrWithPeerId { multiaddr, peer_id: network.local_peer_id().clone() };
+	let genesis = BlockInfo {
+		hash: client.info().genesis_hash,
+		parent_hash: Default::default(),
+		number: 0,
+	};
+
+	let keystore = keystore::Store::new_in_memory();
+	keystore.write().insert_ephemeral_from_seed::<ValidatorPair>(&validator.to_seed())
+		.expect("Insert key into keystore");
+
+	let spawner = task_manager.spawn_handle();
+	let (backed_tx, backed) = mpsc::unbounded();
+	let all_subsystems = AllSubsystems {
+		candidate_validation: TestCandidateValidation,
+		candidate_backing: CandidateBackingSubsystem::new(spawner.clone(), keystore),
+		candidate_selection: DummySubsystem,
+		statement_distribution: StatementDistribution,
+		availability_distribution: DummySubsystem,
+		bitfield_distribution: DummySubsystem,
+		provisioner: TestProvisioner(backed_tx),
+		pov_distribution: PoVDistribution,
+		runtime_api: TestRuntimeApi {
+			validators: VALIDATORS.iter().map(|v| v.public().into()).collect(),
+			roster: roster(),
+		},
+		availability_store: DummySubsystem,
+		network_bridge: NetworkBridge::new(network.clone()),
+		pvf_checker: DummySubsystem,
+	};
+	let (overseer, handler) = Overseer::new(vec![genesis.clone()], all_subsystems, spawner.clone())
+		.expect("could not create the overseer");
+
+	spawner.spawn("overseer", overseer.run().map(|_| ()));
+
+	OverseerNode { task_manager, network, handler, backed, addr, genesis_hash: genesis.hash }
+}
+
+/// Wait until the network bridge protocol is open to all of the given peers.
+async fn wait_for_peers(node: &OverseerNode, peers: &[&OverseerNode]) {
+	let mut missing: HashSet<PeerId> = peers.iter()
+		.map(|peer| peer.network.local_peer_id().clone())
+		.collect();
+	let mut events = node.network.event_stream("back-candidate-test");
+
+	while !missing.is_empty() {
+		match events.next().await {
+			Some(NetworkEvent::NotificationStreamOpened { remote, engine_id, .. })
+				if engine_id == POLKADOT_ENGINE_ID => { missing.remove(&remote); }
+			Some(_) => {}
+			None => panic!("the network service stopped"),
+		}
+	}
+}
+
+#[tokio::test]
+async fn test_network_backs_a_candidate() {
+	let task_executor: TaskExecutor = (move |fut, _| {
+		spawn(fut);
+	})
+	.into();
+	let mut alice = run_overseer_node(task_executor.clone(), VALIDATORS[0], Vec::new());
+	let bob = run_overseer_node(task_executor.clone(), VALIDATORS[1], vec![alice.addr.clone()]);
+	let charlie = run_overseer_node(task_executor, VALIDATORS[2], vec![alice.addr.clone()]);
+
+	let t1 = sleep(Duration::from_secs(
+		INTEGRATION_TEST_ALLOWED_TIME
+			.and_then(|x| x.parse().ok())
+			.unwrap_or(600),
+	))
+	.fuse();
+	let t2 = async {
+		// The nodes gossip statements only once they know of each other.
+		future::join(
+			wait_for_peers(&alice, &[&bob, &charlie]),
+			wait_for_peers(&bob, &[&alice]),
+		).await;
+
+		let relay_parent = alice.genesis_hash;
+		let pov = PoV { block_data: BlockData(vec![42, 43, 44]) };
+		let descriptor = CandidateDescriptor {
+			para_id: ParaId::from(PARA_ID),
+			relay_parent,
+			pov_hash: pov.hash(),
+			..Default::default()
+		};
+
+		let outputs = validation_outputs();
+		let available_data = AvailableData {
+			omitted_validation: OmittedValidationData {
+				global_validation: outputs.global_validation_schedule,
+				local_validation: outputs.local_validation_data,
+			},
+			pov: pov.clone(),
+		};
+		let chunks = erasure_coding::obtain_chunks_v1(VALIDATORS.len(), &available_data).unwrap();
+		let commitments = CandidateCommitments {
+			head_data: outputs.head_data,
+			erasure_root: erasure_coding::branches(&chunks).root(),
+			..Default::default()
+		};
+		let candidate = CandidateReceipt {
+			descriptor: descriptor.clone(),
+			commitments_hash: commitments.hash(),
+		};
+
+		// Alice seconds the candidate; Bob has to fetch the PoV over the network to validate it.
+		alice.handler.send_msg(AllMessages::PoVDistribution(
+			PoVDistributionMessage::DistributePoV(relay_parent, descriptor, Arc::new(pov.clone())),
+		)).await.unwrap();
+		alice.handler.send_msg(AllMessages::CandidateBacking(
+			CandidateBackingMessage::Second(relay_parent, candidate.clone(), pov),
+		)).await.unwrap();
+
+		let backed = alice.backed.next().await.expect("the provisioner of Alice stopped");
+		assert_eq!(backed.candidate.hash(), candidate.hash());
+		assert_eq!(backed.validity_votes.len(), 2);
+	}
+	.fuse();
+
+	{
+		pin_mut!(t1, t2);
+
+		select! {
+			_ = t1 => panic!("the test took too long, maybe no candidate has been backed"),
+			_ = t2 => {},
+		}
+	}
+
+	for mut node in vec![alice, bob, charlie] {
+		node.task_manager.terminate();
+	}
+}
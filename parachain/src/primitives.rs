@@ -31,6 +31,9 @@ use sp_core::bytes;
 /// Block number type used by the relay chain.
 pub use polkadot_core_primitives::BlockNumber as RelayChainBlockNumber;
 
+/// The maximum size of validation code the validation host is able to execute, in bytes.
+pub const MAX_CODE_MEM: usize = 16 * 1024 * 1024; // 16 MiB
+
 /// Parachain head data included in the chain.
 #[derive(PartialEq, Eq, Clone, PartialOrd, Ord, Encode, Decode, RuntimeDebug)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, Default))]
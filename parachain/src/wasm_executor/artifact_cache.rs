@@ -0,0 +1,365 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Content-addressed caches of prepared parachain validation code.
+//!
+//! Compiling validation code dominates the time it takes to validate a candidate, so prepared
+//! artifacts are kept around, keyed by the hash of the code they were prepared from.
+//!
+//! Preparing validation code checks that it compiles and, if its execution is metered, instruments
+//! it with the fuel budget. The prepared code is then compiled into a module of the interpreter.
+//!
+//! [`ArtifactCache`] holds compiled modules in memory and is used by every process executing
+//! validation code, i.e. the node itself in local execution mode and each validation worker.
+//! [`DiskArtifacts`] holds prepared code on disk, which is kept across restarts and sent to the
+//! validation workers. Modules of the interpreter cannot be serialized, so each worker still
+//! compiles the prepared code it receives into its own [`ArtifactCache`], but neither checks nor
+//! instruments it again.
+
+use std::{
+	borrow::Cow, collections::HashMap, fs, hash::Hash, io, path::{Path, PathBuf}, sync::Arc,
+};
+use parking_lot::Mutex;
+use sc_executor_common::wasm_runtime::WasmModule;
+use sp_wasm_interface::HostFunctions as _;
+use log::{debug, warn};
+use super::{Error, HostFunctions, metering, precheck_code};
+
+/// Identifies an artifact: the blake2-256 hash of the validation code it was prepared from.
+pub type ArtifactId = [u8; 32];
+
+/// The default maximum total size in bytes of the prepared code of artifacts held in memory.
+pub const DEFAULT_MAX_MEMORY_SIZE: usize = 256 * 1024 * 1024; // 256 MiB
+
+/// The default maximum total size in bytes of prepared code stored on disk.
+pub const DEFAULT_MAX_DISK_SIZE: u64 = 1024 * 1024 * 1024; // 1 GiB
+
+/// The number of wasm heap pages available to validation code.
+const HEAP_PAGES: u64 = 1024;
+
+/// Compute the identifier of the artifact prepared from the given validation code.
+pub fn artifact_id(validation_code: &[u8]) -> ArtifactId {
+	sp_core::hashing::blake2_256(validation_code)
+}
+
+/// Identifies prepared code and the module compiled from it: the artifact it was prepared from
+/// and the fuel the code was instrumented with, if any.
+type ModuleKey = (ArtifactId, Option<u64>);
+
+/// Prepare the given validation code to be compiled: if `max_fuel` is given, the code is
+/// instrumented to execute at most that many instructions.
+pub fn prepare_code(validation_code: &[u8], max_fuel: Option<u64>) -> Result<Cow<[u8]>, Error> {
+	match max_fuel {
+		Some(fuel) => Ok(Cow::Owned(metering::instrument(validation_code, fuel)?)),
+		None => Ok(Cow::Borrowed(validation_code)),
+	}
+}
+
+/// Bookkeeping shared by both caches: size and recency of each artifact.
+struct Lru<K> {
+	entries: HashMap<K, (u64, u64)>,
+	total_size: u64,
+	tick: u64,
+}
+
+impl<K: Copy + Eq + Hash> Lru<K> {
+	fn new() -> Self {
+		Lru { entries: HashMap::new(), total_size: 0, tick: 0 }
+	}
+
+	fn contains(&self, id: &K) -> bool {
+		self.entries.contains_key(id)
+	}
+
+	/// Mark the artifact as used most recently, inserting it with the given size if unknown.
+	fn touch(&mut self, id: K, size: u64) {
+		self.tick += 1;
+		let tick = self.tick;
+		let total_size = &mut self.total_size;
+		self.entries.entry(id)
+			.and_modify(|entry| entry.1 = tick)
+			.or_insert_with(|| {
+				*total_size += size;
+				(size, tick)
+			});
+	}
+
+	/// Evict least recently used artifacts until the total size is at most `max_size`, sparing
+	/// `keep`. Returns the evicted artifacts.
+	fn evict(&mut self, max_size: u64, keep: &K) -> Vec<K> {
+		let mut evicted = Vec::new();
+
+		while self.total_size > max_size {
+			let oldest = self.entries.iter()
+				.filter(|(id, _)| *id != keep)
+				.min_by_key(|(_, (_, tick))| *tick)
+				.map(|(id, _)| *id);
+
+			match oldest {
+				Some(id) => {
+					if let Some((size, _)) = self.entries.remove(&id) {
+						self.total_size -= size;
+					}
+					evicted.push(id);
+				}
+				None => break,
+			}
+		}
+
+		evicted
+	}
+}
+
+/// An in-memory cache of compiled validation code with least-recently-used eviction.
+///
+/// Metered code is cached per fuel budget, as the budget is part of the instrumented code. The
+/// size of an artifact is accounted as the size of its prepared code.
+pub struct ArtifactCache {
+	max_size: usize,
+	inner: Mutex<(Lru<ModuleKey>, HashMap<ModuleKey, Arc<dyn WasmModule>>)>,
+}
+
+impl ArtifactCache {
+	/// Create a new cache holding artifacts compiled from at most `max_size` bytes of prepared
+	/// code.
+	///
+	/// The most recently used artifact is always kept, even if it exceeds the limit on its own.
+	pub fn new(max_size: usize) -> Self {
+		ArtifactCache {
+			max_size,
+			inner: Mutex::new((Lru::new(), HashMap::new())),
+		}
+	}
+
+	/// Get the compiled module for the given validation code, preparing, compiling and caching it
+	/// if it is not in the cache yet.
+	///
+	/// If `max_fuel` is given, the module is compiled from the code instrumented to execute at
+	/// most that many instructions. The code is only instrumented if the module is not cached.
+	pub fn get_or_prepare(
+		&self,
+		validation_code: &[u8],
+		max_fuel: Option<u64>,
+	) -> Result<Arc<dyn WasmModule>, Error> {
+		let id = artifact_id(validation_code);
+
+		if let Some(module) = self.get(&id, max_fuel) {
+			return Ok(module);
+		}
+
+		let prepared = prepare_code(validation_code, max_fuel)?;
+		self.compile(id, max_fuel, &prepared)
+	}
+
+	/// Get the compiled module of the given artifact, compiling and caching it from its prepared
+	/// code if it is not in the cache yet.
+	///
+	/// The prepared code is only loaded if the module is not cached.
+	pub fn get_or_compile(
+		&self,
+		id: &ArtifactId,
+		max_fuel: Option<u64>,
+		load_prepared: impl FnOnce() -> Result<Vec<u8>, Error>,
+	) -> Result<Arc<dyn WasmModule>, Error> {
+		if let Some(module) = self.get(id, max_fuel) {
+			return Ok(module);
+		}
+
+		self.compile(*id, max_fuel, &load_prepared()?)
+	}
+
+	fn compile(
+		&self,
+		id: ArtifactId,
+		max_fuel: Option<u64>,
+		prepared: &[u8],
+	) -> Result<Arc<dyn WasmModule>, Error> {
+		let key = (id, max_fuel);
+
+		// compile without holding the lock, so that other code can be served meanwhile.
+		let module: Arc<dyn WasmModule> = Arc::new(sc_executor_wasmi::create_runtime(
+			prepared,
+			HEAP_PAGES,
+			HostFunctions::host_functions(),
+			true,
+		).map_err(|e| Error::InvalidCode(format!("{:?}", e)))?);
+
+		let mut inner = self.inner.lock();
+		let (ref mut lru, ref mut modules) = *inner;
+		lru.touch(key, prepared.len() as u64);
+		let module = modules.entry(key).or_insert(module).clone();
+		for evicted in lru.evict(self.max_size as u64, &key) {
+			debug!("Evicting artifact {:?} from memory", evicted);
+			modules.remove(&evicted);
+		}
+
+		Ok(module)
+	}
+
+	/// Get the compiled module with the given identifier and fuel budget, if it is in the cache.
+	pub fn get(&self, id: &ArtifactId, max_fuel: Option<u64>) -> Option<Arc<dyn WasmModule>> {
+		let key = (*id, max_fuel);
+		let mut inner = self.inner.lock();
+		let (ref mut lru, ref modules) = *inner;
+		let module = modules.get(&key)?.clone();
+		lru.touch(key, 0);
+		Some(module)
+	}
+
+	/// Whether the artifact with the given identifier and fuel budget is in the cache.
+	pub fn contains(&self, id: &ArtifactId, max_fuel: Option<u64>) -> bool {
+		self.inner.lock().0.contains(&(*id, max_fuel))
+	}
+
+	/// The total size of the prepared code of all cached artifacts.
+	pub fn size(&self) -> usize {
+		self.inner.lock().0.total_size as usize
+	}
+}
+
+/// Prepared validation code, stored on disk under its artifact identifier and fuel budget with
+/// least-recently-used eviction.
+pub struct DiskArtifacts {
+	path: PathBuf,
+	max_size: u64,
+	lru: Mutex<Lru<ModuleKey>>,
+}
+
+impl DiskArtifacts {
+	/// Open the artifact directory at `path`, creating it if necessary.
+	///
+	/// Artifacts already present are picked up, ordered by their modification time.
+	pub fn new(path: PathBuf, max_size: u64) -> io::Result<Self> {
+		fs::create_dir_all(&path)?;
+
+		let mut existing = Vec::new();
+		for entry in fs::read_dir(&path)? {
+			let entry = entry?;
+			let key = match parse_artifact_name(&entry.file_name().to_string_lossy()) {
+				Some(key) => key,
+				None => continue,
+			};
+			let metadata = entry.metadata()?;
+			existing.push((metadata.modified()?, key, metadata.len()));
+		}
+		existing.sort();
+
+		let mut lru = Lru::new();
+		for (_, key, size) in existing {
+			lru.touch(key, size);
+		}
+
+		let artifacts = DiskArtifacts { path, max_size, lru: Mutex::new(lru) };
+		artifacts.evict(&([0; 32], None));
+
+		Ok(artifacts)
+	}
+
+	/// The path under which the code of the given artifact prepared for the given fuel budget is
+	/// stored.
+	pub fn artifact_path(&self, id: &ArtifactId, max_fuel: Option<u64>) -> PathBuf {
+		match max_fuel {
+			Some(fuel) => self.path.join(format!("{}-{}", hex(id), fuel)),
+			None => self.path.join(hex(id)),
+		}
+	}
+
+	/// Prepare the given validation code for the given fuel budget and store it, returning the
+	/// path of the prepared code.
+	///
+	/// Code is checked to be at most `max_code_size` bytes and to compile before it is prepared
+	/// for the first time.
+	pub fn store(
+		&self,
+		validation_code: &[u8],
+		max_code_size: usize,
+		max_fuel: Option<u64>,
+	) -> Result<PathBuf, Error> {
+		let key = (artifact_id(validation_code), max_fuel);
+		let path = self.artifact_path(&key.0, max_fuel);
+
+		if self.lru.lock().contains(&key) && path.exists() {
+			self.lru.lock().touch(key, 0);
+			return Ok(path);
+		}
+
+		precheck_code(validation_code, max_code_size)?;
+		let prepared = prepare_code(validation_code, max_fuel)?;
+		write_atomically(&path, &prepared)?;
+
+		self.lru.lock().touch(key, prepared.len() as u64);
+		self.evict(&key);
+
+		Ok(path)
+	}
+
+	/// Load the prepared code of the given artifact for the given fuel budget.
+	pub fn load(&self, id: &ArtifactId, max_fuel: Option<u64>) -> Option<Vec<u8>> {
+		let code = fs::read(self.artifact_path(id, max_fuel)).ok()?;
+		self.lru.lock().touch((*id, max_fuel), code.len() as u64);
+		Some(code)
+	}
+
+	/// Whether the code of the given artifact is stored prepared for the given fuel budget.
+	pub fn contains(&self, id: &ArtifactId, max_fuel: Option<u64>) -> bool {
+		self.lru.lock().contains(&(*id, max_fuel))
+	}
+
+	fn evict(&self, keep: &ModuleKey) {
+		let evicted = self.lru.lock().evict(self.max_size, keep);
+		for (id, max_fuel) in evicted {
+			debug!("Evicting artifact {:?} prepared for {:?} fuel from disk", id, max_fuel);
+			if let Err(e) = fs::remove_file(self.artifact_path(&id, max_fuel)) {
+				warn!("Failed to remove artifact {:?}: {:?}", id, e);
+			}
+		}
+	}
+}
+
+/// Write to a temporary file first so that workers never observe partially written artifacts.
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+	let tmp_path = path.with_extension("tmp");
+	fs::write(&tmp_path, data)?;
+	fs::rename(&tmp_path, path)
+}
+
+fn hex(id: &ArtifactId) -> String {
+	id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse the name of a file storing prepared code: the artifact identifier in hex, followed by
+/// the fuel budget if the code is metered.
+fn parse_artifact_name(name: &str) -> Option<ModuleKey> {
+	let (id, max_fuel) = match name.find('-') {
+		Some(i) => (&name[..i], Some(name[i + 1..].parse().ok()?)),
+		None => (name, None),
+	};
+
+	Some((parse_artifact_id(id)?, max_fuel))
+}
+
+fn parse_artifact_id(name: &str) -> Option<ArtifactId> {
+	if name.len() != 64 {
+		return None;
+	}
+
+	let mut id = [0u8; 32];
+	for (i, byte) in id.iter_mut().enumerate() {
+		*byte = u8::from_str_radix(name.get(i * 2..i * 2 + 2)?, 16).ok()?;
+	}
+
+	Some(id)
+}
@@ -0,0 +1,256 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Deterministic metering of the execution of validation code.
+//!
+//! Validation code is instrumented to keep track of the fuel it has left in a global. Every
+//! instruction costs one unit of fuel, which is charged at the start of each straight-line
+//! sequence of instructions. Once a sequence costs more than the fuel left, the code signals
+//! this to the host and traps, so that every validator arrives at the same outcome regardless
+//! of how fast it executes the code.
+
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use parity_wasm::{builder, elements::{
+	self, BlockType, GlobalEntry, GlobalType, ImportCountType, InitExpr,
+	Instruction, Internal, Module, Section, ValueType,
+}};
+use sp_externalities::ExternalitiesExt;
+use super::Error;
+
+/// The module of the import signaling that the fuel ran out.
+const OUT_OF_FUEL_MODULE: &str = "env";
+
+/// The name of the import signaling that the fuel ran out, matching [`pvf_metering::out_of_fuel`].
+const OUT_OF_FUEL_FUNCTION: &str = "ext_pvf_metering_out_of_fuel_version_1";
+
+sp_externalities::decl_extension! {
+	/// Set once the executed validation code ran out of fuel.
+	pub struct OutOfFuelExt(Arc<AtomicBool>);
+}
+
+/// Host functions used by instrumented validation code.
+#[sp_runtime_interface::runtime_interface]
+pub trait PvfMetering {
+	/// Signal that the executing validation code ran out of fuel. The code traps right after.
+	fn out_of_fuel(&mut self) {
+		if let Some(ext) = self.extension::<OutOfFuelExt>() {
+			ext.0.store(true, Ordering::SeqCst);
+		}
+	}
+}
+
+/// Instrument the given validation code to execute at most `fuel` instructions.
+pub fn instrument(validation_code: &[u8], fuel: u64) -> Result<Vec<u8>, Error> {
+	let module: Module = elements::deserialize_buffer(validation_code)
+		.map_err(|e| Error::InvalidCode(e.to_string()))?;
+
+	let module = inject_out_of_fuel_import(module);
+	// the import is the last imported function.
+	let out_of_fuel = module.import_count(ImportCountType::Function) as u32 - 1;
+
+	let mut module = builder::from_module(module)
+		.with_global(GlobalEntry::new(
+			GlobalType::new(ValueType::I64, true),
+			InitExpr::new(vec![
+				Instruction::I64Const(fuel.min(i64::max_value() as u64) as i64),
+				Instruction::End,
+			]),
+		))
+		.build();
+	let fuel_global = module.import_count(ImportCountType::Global) as u32
+		+ module.global_section().map_or(0, |s| s.entries().len() as u32)
+		- 1;
+
+	if let Some(code) = module.code_section_mut() {
+		for body in code.bodies_mut() {
+			let instructions = body.code_mut().elements_mut();
+			*instructions = meter(instructions, fuel_global, out_of_fuel);
+		}
+	}
+
+	elements::serialize(module).map_err(|e| Error::InvalidCode(e.to_string()))
+}
+
+/// Import the function signaling that the fuel ran out, shifting the indices of all functions
+/// defined by the module by one.
+fn inject_out_of_fuel_import(module: Module) -> Module {
+	let mut module_builder = builder::from_module(module);
+	let signature = module_builder.push_signature(
+		builder::signature().build_sig(),
+	);
+	module_builder.push_import(
+		builder::import()
+			.module(OUT_OF_FUEL_MODULE)
+			.field(OUT_OF_FUEL_FUNCTION)
+			.external()
+			.func(signature)
+			.build(),
+	);
+	let mut module = module_builder.build();
+
+	let imported = module.import_count(ImportCountType::Function) as u32;
+	let shift = |index: &mut u32| if *index >= imported - 1 { *index += 1 };
+
+	for section in module.sections_mut() {
+		match section {
+			Section::Code(code) => for body in code.bodies_mut() {
+				for instruction in body.code_mut().elements_mut() {
+					if let Instruction::Call(index) = instruction {
+						shift(index);
+					}
+				}
+			},
+			Section::Export(exports) => for export in exports.entries_mut() {
+				if let Internal::Function(index) = export.internal_mut() {
+					shift(index);
+				}
+			},
+			Section::Element(elements) => for segment in elements.entries_mut() {
+				for index in segment.members_mut() {
+					shift(index);
+				}
+			},
+			Section::Start(index) => shift(index),
+			_ => {},
+		}
+	}
+
+	module
+}
+
+/// Whether the instruction ends a straight-line sequence of instructions.
+fn ends_sequence(instruction: &Instruction) -> bool {
+	match instruction {
+		Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) | Instruction::Else |
+		Instruction::End | Instruction::Br(_) | Instruction::BrIf(_) | Instruction::BrTable(_) |
+		Instruction::Return | Instruction::Unreachable => true,
+		_ => false,
+	}
+}
+
+/// Charge for every straight-line sequence of the given function body before it is executed.
+fn meter(instructions: &[Instruction], fuel_global: u32, out_of_fuel: u32) -> Vec<Instruction> {
+	let mut metered = Vec::with_capacity(instructions.len() * 2);
+	let mut start = 0;
+
+	while start < instructions.len() {
+		let end = instructions[start..].iter()
+			.position(ends_sequence)
+			.map_or(instructions.len(), |position| start + position + 1);
+
+		charge(&mut metered, (end - start) as i64, fuel_global, out_of_fuel);
+		metered.extend_from_slice(&instructions[start..end]);
+		start = end;
+	}
+
+	metered
+}
+
+fn charge(metered: &mut Vec<Instruction>, cost: i64, fuel_global: u32, out_of_fuel: u32) {
+	metered.extend_from_slice(&[
+		Instruction::GetGlobal(fuel_global),
+		Instruction::I64Const(cost),
+		Instruction::I64LtU,
+		Instruction::If(BlockType::NoResult),
+		Instruction::Call(out_of_fuel),
+		Instruction::Unreachable,
+		Instruction::End,
+		Instruction::GetGlobal(fuel_global),
+		Instruction::I64Const(cost),
+		Instruction::I64Sub,
+		Instruction::SetGlobal(fuel_global),
+	]);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn instructions(module: &Module, function: usize) -> &[Instruction] {
+		module.code_section().unwrap().bodies()[function].code().elements()
+	}
+
+	fn test_module() -> Module {
+		builder::module()
+			.import()
+				.module("env")
+				.field("memory")
+				.external()
+				.memory(1, None)
+				.build()
+			.function()
+				.signature().build()
+				.body()
+					.with_instructions(elements::Instructions::new(vec![
+						Instruction::Call(1),
+						Instruction::End,
+					]))
+					.build()
+				.build()
+			.function()
+				.signature().build()
+				.body()
+					.with_instructions(elements::Instructions::new(vec![
+						Instruction::Loop(BlockType::NoResult),
+						Instruction::Nop,
+						Instruction::Br(0),
+						Instruction::End,
+						Instruction::End,
+					]))
+					.build()
+				.build()
+			.export()
+				.field("validate_block")
+				.internal().func(0)
+				.build()
+			.build()
+	}
+
+	#[test]
+	fn defined_functions_are_shifted_by_the_import() {
+		let code = elements::serialize(test_module()).unwrap();
+		let module: Module = elements::deserialize_buffer(&instrument(&code, 100).unwrap()).unwrap();
+
+		assert_eq!(module.import_count(ImportCountType::Function), 1);
+		match module.export_section().unwrap().entries()[0].internal() {
+			Internal::Function(index) => assert_eq!(*index, 1),
+			_ => panic!("export is not a function"),
+		}
+		assert!(instructions(&module, 0).contains(&Instruction::Call(2)));
+	}
+
+	#[test]
+	fn every_sequence_is_charged() {
+		let code = elements::serialize(test_module()).unwrap();
+		let module: Module = elements::deserialize_buffer(&instrument(&code, 100).unwrap()).unwrap();
+		let charges = |function| instructions(&module, function).iter()
+			.filter(|instruction| **instruction == Instruction::I64Sub)
+			.count();
+
+		// the body of the first function is a single sequence.
+		assert_eq!(charges(0), 1);
+		// `loop`, the loop body up to `br`, the `end` of the loop, and the `end` of the function.
+		assert_eq!(charges(1), 4);
+	}
+
+	#[test]
+	fn invalid_code_is_rejected() {
+		match instrument(&[0, 1, 2, 3], 100) {
+			Err(Error::InvalidCode(_)) => {},
+			r => panic!("{:?}", r),
+		}
+	}
+}
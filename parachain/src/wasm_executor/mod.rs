@@ -21,39 +21,128 @@
 //! a WASM VM for re-execution of a parachain candidate.
 
 use std::any::{TypeId, Any};
-use crate::primitives::{ValidationParams, ValidationResult};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::primitives::{ValidationParams, ValidationResult, MAX_CODE_MEM};
 use codec::{Decode, Encode};
 use sp_core::storage::ChildInfo;
-use sp_core::traits::CallInWasm;
 use sp_externalities::Extensions;
-use sp_wasm_interface::HostFunctions as _;
-
-#[cfg(not(any(target_os = "android", target_os = "unknown")))]
-pub use validation_host::{run_worker, ValidationPool, EXECUTION_TIMEOUT_SEC};
-
+use sc_executor_common::wasm_runtime::WasmModule;
+
+#[cfg(all(unix, not(target_os = "android")))]
+pub use validation_host::{run_worker, ValidationPool};
+pub use artifact_cache::{
+	ArtifactCache, ArtifactId, DiskArtifacts, artifact_id, prepare_code,
+	DEFAULT_MAX_MEMORY_SIZE, DEFAULT_MAX_DISK_SIZE,
+};
+pub use sandbox::{SandboxConfig, DEFAULT_MAX_ADDRESS_SPACE};
+#[cfg(unix)]
+pub use sandbox::enable_sandbox;
+
+mod artifact_cache;
+mod metering;
+mod sandbox;
 mod validation_host;
 
+lazy_static::lazy_static! {
+	/// Compiled validation code of this process.
+	static ref ARTIFACTS: ArtifactCache = ArtifactCache::new(DEFAULT_MAX_MEMORY_SIZE);
+}
+
 // maximum memory in bytes
 const MAX_RUNTIME_MEM: usize = 1024 * 1024 * 1024; // 1 GiB
-const MAX_CODE_MEM: usize = 16 * 1024 * 1024; // 16 MiB
 
-/// A stub validation-pool defined when compiling for Android or WASM.
-#[cfg(any(target_os = "android", target_os = "unknown"))]
+/// Default execution timeout in seconds.
+///
+/// See [`ValidationPoolConfig::execution_timeout`] for how it relates to the fuel budget.
+#[cfg(debug_assertions)]
+pub const EXECUTION_TIMEOUT_SEC: u64 =  30;
+
+/// Default execution timeout in seconds.
+///
+/// See [`ValidationPoolConfig::execution_timeout`] for how it relates to the fuel budget.
+#[cfg(not(debug_assertions))]
+pub const EXECUTION_TIMEOUT_SEC: u64 =  5;
+
+/// The default number of validation workers.
+pub const DEFAULT_NUM_WORKERS: usize = 8;
+
+/// Configuration of a [`ValidationPool`].
+#[derive(Debug, Clone)]
+pub struct ValidationPoolConfig {
+	/// The number of validation workers, i.e. the number of candidates which can be validated
+	/// in parallel.
+	pub num_workers: usize,
+	/// The time a worker is given to validate a single candidate before it is cancelled.
+	///
+	/// This is only a backstop against workers stalling outside of metered code, e.g. in host
+	/// functions or while compiling. The work a candidate may do is bounded by its fuel budget,
+	/// which is the same on every machine, whereas a timeout depends on the speed of the
+	/// validator. It must therefore be set well above the time it takes to exhaust any fuel
+	/// budget the runtime may configure, so that valid candidates never time out.
+	pub execution_timeout: Duration,
+	/// The interval at which a busy worker is checked to be alive. A worker which does not
+	/// answer within one interval is killed.
+	pub heartbeat_interval: Duration,
+	/// The maximum size of validation code, in bytes.
+	pub max_code_size: usize,
+	/// The maximum size of the encoded validation parameters, in bytes.
+	pub max_params_size: usize,
+	/// Whether a worker which has exited or was killed after a timeout is started again
+	/// for the next candidate.
+	///
+	/// If disabled, validation on a host whose worker is gone fails with
+	/// [`Error::WorkerUnavailable`].
+	pub respawn_workers: bool,
+	/// The directory in which prepared validation code is stored for the workers. If not set, the
+	/// code is copied to the worker and prepared by it for every candidate.
+	pub artifacts_path: Option<PathBuf>,
+	/// The maximum total size of the prepared code stored in `artifacts_path`, in bytes.
+	pub max_artifacts_size: u64,
+	/// The restrictions workers apply to themselves before executing any validation code.
+	pub sandbox: SandboxConfig,
+	/// The arguments to start the current executable as a validation worker with, followed by
+	/// the path of the socket to connect to. Defaults to the `validation-worker` subcommand.
+	pub worker_args: Option<Vec<String>>,
+}
+
+impl Default for ValidationPoolConfig {
+	fn default() -> Self {
+		ValidationPoolConfig {
+			num_workers: DEFAULT_NUM_WORKERS,
+			execution_timeout: Duration::from_secs(EXECUTION_TIMEOUT_SEC),
+			heartbeat_interval: Duration::from_secs(1),
+			max_code_size: MAX_CODE_MEM,
+			max_params_size: MAX_RUNTIME_MEM,
+			respawn_workers: true,
+			artifacts_path: None,
+			max_artifacts_size: DEFAULT_MAX_DISK_SIZE,
+			sandbox: Default::default(),
+			worker_args: None,
+		}
+	}
+}
+
+/// A stub validation-pool defined when compiling for Android, WASM or other platforms without
+/// Unix sockets.
+#[cfg(any(not(unix), target_os = "android"))]
 #[derive(Clone)]
 pub struct ValidationPool {
-	_inner: (), // private field means not publicly-instantiable
+	max_code_size: usize, // private field means not publicly-instantiable
 }
 
-#[cfg(any(target_os = "android", target_os = "unknown"))]
+#[cfg(any(not(unix), target_os = "android"))]
 impl ValidationPool {
 	/// Create a new `ValidationPool`.
-	pub fn new() -> Self {
-		ValidationPool { _inner: () }
+	pub fn new(config: ValidationPoolConfig) -> Self {
+		ValidationPool { max_code_size: config.max_code_size }
 	}
 }
 
-/// A stub function defined when compiling for Android or WASM.
-#[cfg(any(target_os = "android", target_os = "unknown"))]
+/// A stub function defined when compiling for Android, WASM or other platforms without Unix
+/// sockets.
+#[cfg(any(not(unix), target_os = "android"))]
 pub fn run_worker(_: &str) -> Result<(), String> {
 	Err("Cannot run validation worker on this platform".to_string())
 }
@@ -62,8 +151,12 @@ pub fn run_worker(_: &str) -> Result<(), String> {
 ///
 /// > Note: When compiling for WASM, the `Remote` variants are not available.
 pub enum ExecutionMode<'a> {
-	/// Execute in-process. The execution can not be interrupted or aborted.
-	Local,
+	/// Execute in-process, rejecting validation code larger than `max_code_size` bytes. The
+	/// execution can not be interrupted or aborted.
+	Local {
+		/// The maximum size of validation code, in bytes.
+		max_code_size: usize,
+	},
 	/// Remote execution in a spawned process.
 	Remote(&'a ValidationPool),
 	/// Remote execution in a spawned test runner.
@@ -77,26 +170,44 @@ pub enum Error {
 	#[display(fmt = "WASM executor error: {:?}", _0)]
 	WasmExecutor(sc_executor::error::Error),
 	/// Call data is too large.
-	#[display(fmt = "Validation parameters are {} bytes, max allowed is {}", _0, MAX_RUNTIME_MEM)]
+	#[display(fmt = "Validation parameters are {} bytes, max allowed is {}", _0, _1)]
 	#[from(ignore)]
-	ParamsTooLarge(usize),
+	ParamsTooLarge(usize, usize),
 	/// Code size it too large.
-	#[display(fmt = "WASM code is {} bytes, max allowed is {}", _0, MAX_CODE_MEM)]
-	CodeTooLarge(usize),
+	#[display(fmt = "WASM code is {} bytes, max allowed is {}", _0, _1)]
+	#[from(ignore)]
+	CodeTooLarge(usize, usize),
 	/// Bad return data or type.
 	#[display(fmt = "Validation function returned invalid data.")]
 	BadReturn,
 	#[display(fmt = "Validation function timeout.")]
 	Timeout,
+	/// The validation function exhausted its execution budget.
+	#[display(fmt = "Validation function ran out of fuel.")]
+	OutOfFuel,
+	/// The validation worker is gone and respawning workers is disabled.
+	#[display(fmt = "Validation worker is not running and respawning is disabled.")]
+	WorkerUnavailable,
+	/// The validation worker did not answer a heartbeat in time.
+	#[display(fmt = "Validation worker stopped responding.")]
+	WorkerUnresponsive,
+	/// Validation of the candidate was cancelled.
+	#[display(fmt = "Validation was cancelled.")]
+	Cancelled,
+	/// The validation worker sent a malformed message.
+	#[display(fmt = "Validation worker protocol error: {}", _0)]
+	#[from(ignore)]
+	Protocol(String),
 	#[display(fmt = "IO error: {}", _0)]
 	Io(std::io::Error),
 	#[display(fmt = "System error: {}", _0)]
 	System(Box<dyn std::error::Error + Send>),
 	#[display(fmt = "WASM worker error: {}", _0)]
 	External(String),
-	#[display(fmt = "Shared memory error: {}", _0)]
-	#[cfg(not(any(target_os = "android", target_os = "unknown")))]
-	SharedMem(shared_memory::SharedMemError),
+	/// Validation code failed to compile.
+	#[display(fmt = "Invalid validation code: {}", _0)]
+	#[from(ignore)]
+	InvalidCode(String),
 }
 
 impl std::error::Error for Error {
@@ -105,8 +216,6 @@ impl std::error::Error for Error {
 			Error::WasmExecutor(ref err) => Some(err),
 			Error::Io(ref err) => Some(err),
 			Error::System(ref err) => Some(&**err),
-			#[cfg(not(any(target_os = "android", target_os = "unknown")))]
-			Error::SharedMem(ref err) => Some(err),
 			_ => None,
 		}
 	}
@@ -115,29 +224,57 @@ impl std::error::Error for Error {
 /// Validate a candidate under the given validation code.
 ///
 /// This will fail if the validation code is not a proper parachain validation module.
+///
+/// The execution is not metered, so it is only bounded by the execution timeout in remote
+/// execution mode. Use [`validate_candidate_metered`] to make the outcome independent of the
+/// speed of the machine.
 pub fn validate_candidate(
 	validation_code: &[u8],
 	params: ValidationParams,
 	options: ExecutionMode<'_>,
+) -> Result<ValidationResult, Error> {
+	validate_candidate_with_fuel(validation_code, params, None, options)
+}
+
+/// Validate a candidate under the given validation code, executing at most `max_fuel`
+/// instructions of it.
+///
+/// Validation fails with [`Error::OutOfFuel`] once the budget is exhausted, which happens at the
+/// same point on every machine. The execution timeout still applies in remote execution mode.
+pub fn validate_candidate_metered(
+	validation_code: &[u8],
+	params: ValidationParams,
+	max_fuel: u64,
+	options: ExecutionMode<'_>,
+) -> Result<ValidationResult, Error> {
+	validate_candidate_with_fuel(validation_code, params, Some(max_fuel), options)
+}
+
+fn validate_candidate_with_fuel(
+	validation_code: &[u8],
+	params: ValidationParams,
+	max_fuel: Option<u64>,
+	options: ExecutionMode<'_>,
 ) -> Result<ValidationResult, Error> {
 	match options {
-		ExecutionMode::Local => {
-			validate_candidate_internal(validation_code, &params.encode())
+		ExecutionMode::Local { max_code_size } => {
+			check_code_size(validation_code, max_code_size)?;
+			validate_candidate_internal(validation_code, &params.encode(), max_fuel)
 		},
-		#[cfg(not(any(target_os = "android", target_os = "unknown")))]
+		#[cfg(all(unix, not(target_os = "android")))]
 		ExecutionMode::Remote(pool) => {
-			pool.validate_candidate(validation_code, params, false)
+			pool.validate_candidate(validation_code, params, max_fuel, false)
 		},
-		#[cfg(not(any(target_os = "android", target_os = "unknown")))]
+		#[cfg(all(unix, not(target_os = "android")))]
 		ExecutionMode::RemoteTest(pool) => {
-			pool.validate_candidate(validation_code, params, true)
+			pool.validate_candidate(validation_code, params, max_fuel, true)
 		},
-		#[cfg(any(target_os = "android", target_os = "unknown"))]
+		#[cfg(any(not(unix), target_os = "android"))]
 		ExecutionMode::Remote(pool) =>
 			Err(Error::System(Box::<dyn std::error::Error + Send + Sync>::from(
 				"Remote validator not available".to_string()
 			) as Box<_>)),
-		#[cfg(any(target_os = "android", target_os = "unknown"))]
+		#[cfg(any(not(unix), target_os = "android"))]
 		ExecutionMode::RemoteTest(pool) =>
 			Err(Error::System(Box::<dyn std::error::Error + Send + Sync>::from(
 				"Remote validator not available".to_string()
@@ -145,36 +282,103 @@ pub fn validate_candidate(
 	}
 }
 
+/// Prepare the given validation code ahead of validating candidates with it, e.g. once a code
+/// upgrade of a parachain has been scheduled. The code is instrumented with `max_fuel` if its
+/// execution is going to be metered.
+///
+/// In local execution mode the code is compiled and kept in memory. For remote execution the
+/// prepared code is stored in the artifact directory of the pool, if one is configured.
+pub fn prepare_artifact(
+	validation_code: &[u8],
+	max_fuel: Option<u64>,
+	options: ExecutionMode<'_>,
+) -> Result<ArtifactId, Error> {
+	match options {
+		ExecutionMode::Local { max_code_size } => {
+			check_code_size(validation_code, max_code_size)?;
+			ARTIFACTS.get_or_prepare(validation_code, max_fuel)?;
+			Ok(artifact_id(validation_code))
+		},
+		#[cfg(all(unix, not(target_os = "android")))]
+		ExecutionMode::Remote(pool) | ExecutionMode::RemoteTest(pool) => {
+			pool.prepare_artifact(validation_code, max_fuel)
+		},
+		#[cfg(any(not(unix), target_os = "android"))]
+		ExecutionMode::Remote(pool) | ExecutionMode::RemoteTest(pool) => {
+			precheck_code(validation_code, pool.max_code_size)?;
+			Ok(artifact_id(validation_code))
+		},
+	}
+}
+
+/// Check that the given validation code is at most `max_code_size` bytes and compiles, without
+/// executing it.
+///
+/// Validators use this to pre-check the new validation code of a parachain code upgrade before
+/// the upgrade is scheduled.
+pub fn precheck_code(validation_code: &[u8], max_code_size: usize) -> Result<(), Error> {
+	check_code_size(validation_code, max_code_size)?;
+
+	sc_executor::wasmi::Module::from_buffer(validation_code)
+		.map(|_| ())
+		.map_err(|e| Error::InvalidCode(e.to_string()))
+}
+
+fn check_code_size(validation_code: &[u8], max_code_size: usize) -> Result<(), Error> {
+	if validation_code.len() > max_code_size {
+		return Err(Error::CodeTooLarge(validation_code.len(), max_code_size));
+	}
+
+	Ok(())
+}
+
 /// The host functions provided by the wasm executor to the parachain wasm blob.
-type HostFunctions = sp_io::SubstrateHostFunctions;
+type HostFunctions = (sp_io::SubstrateHostFunctions, metering::pvf_metering::HostFunctions);
 
 /// Validate a candidate under the given validation code.
 ///
 /// This will fail if the validation code is not a proper parachain validation module.
+///
+/// The compiled code is cached, so validating further candidates with the same code does not
+/// compile it again. If `max_fuel` is given, the code is instrumented to execute at most that many
+/// instructions, and the instrumented code is cached per fuel budget.
 pub fn validate_candidate_internal(
 	validation_code: &[u8],
 	encoded_call_data: &[u8],
+	max_fuel: Option<u64>,
 ) -> Result<ValidationResult, Error> {
+	let module = ARTIFACTS.get_or_prepare(validation_code, max_fuel)?;
+
+	execute_module(&*module, encoded_call_data)
+}
+
+/// Validate a candidate with the given compiled validation code.
+fn execute_module(
+	module: &dyn WasmModule,
+	encoded_call_data: &[u8],
+) -> Result<ValidationResult, Error> {
+	let instance = module.new_instance()?;
+
+	let out_of_fuel = Arc::new(AtomicBool::new(false));
 	let mut extensions = Extensions::new();
 	extensions.register(sp_core::traits::TaskExecutorExt(sp_core::tasks::executor()));
+	extensions.register(metering::OutOfFuelExt(out_of_fuel.clone()));
 
 	let mut ext = ValidationExternalities(extensions);
 
-	let executor = sc_executor::WasmExecutor::new(
-		sc_executor::WasmExecutionMethod::Interpreted,
-		// TODO: Make sure we don't use more than 1GB: https://github.com/paritytech/polkadot/issues/699
-		Some(1024),
-		HostFunctions::host_functions(),
-		8
-	);
-	let res = executor.call_in_wasm(
-		validation_code,
-		None,
-		"validate_block",
-		encoded_call_data,
-		&mut ext,
-		sp_core::traits::MissingHostFunctions::Allow,
-	)?;
+	// TODO: Make sure we don't use more than 1GB: https://github.com/paritytech/polkadot/issues/699
+	let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+		sp_externalities::set_and_run_with_externalities(
+			&mut ext,
+			|| instance.call("validate_block".into(), encoded_call_data),
+		)
+	})).map_err(|_| Error::External("Validation function panicked".to_string()))?;
+
+	// the instrumented code traps right after signaling that it ran out of fuel.
+	if out_of_fuel.load(Ordering::SeqCst) {
+		return Err(Error::OutOfFuel);
+	}
+	let res = res?;
 
 	ValidationResult::decode(&mut &res[..]).map_err(|_| Error::BadReturn.into())
 }
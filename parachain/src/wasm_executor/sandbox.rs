@@ -0,0 +1,396 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Restriction of the privileges of validation workers.
+//!
+//! Validation workers execute untrusted code. Once a worker is connected to its host and before
+//! it executes any validation code, it limits the resources it may use, leaves the network
+//! namespace of the node and, on Linux, installs a seccomp filter which kills the worker as soon
+//! as it makes a system call it does not need for validating candidates.
+
+use std::time::Duration;
+use codec::{Decode, Encode};
+
+/// The default maximum size of the address space of a worker, in bytes.
+pub const DEFAULT_MAX_ADDRESS_SPACE: u64 = 8 * 1024 * 1024 * 1024; // 8 GiB
+
+/// Restrictions of the privileges of validation workers.
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct SandboxConfig {
+	/// Whether workers restrict their privileges at all.
+	pub enabled: bool,
+	/// The maximum size of the address space of a worker, in bytes.
+	pub max_address_space: Option<u64>,
+	/// The maximum CPU time a worker may spend on a single candidate, in seconds. A worker
+	/// exceeding it is killed. If not set, twice the execution timeout of the pool is used.
+	///
+	/// Like the execution timeout, this is a backstop rather than a bound on the work of a
+	/// candidate, and must be well above the time it takes to exhaust any fuel budget.
+	pub max_cpu_time_secs: Option<u64>,
+	/// Whether a worker which can not be moved into a new network namespace keeps running in the
+	/// network namespace of the node instead of failing to start. Only Linux has network
+	/// namespaces.
+	pub allow_shared_network: bool,
+}
+
+impl Default for SandboxConfig {
+	fn default() -> Self {
+		SandboxConfig {
+			enabled: true,
+			max_address_space: Some(DEFAULT_MAX_ADDRESS_SPACE),
+			max_cpu_time_secs: None,
+			allow_shared_network: false,
+		}
+	}
+}
+
+impl SandboxConfig {
+	/// Fill in the CPU time limit of workers of a pool with the given execution timeout, unless
+	/// it is configured explicitly.
+	pub(crate) fn with_execution_timeout(mut self, execution_timeout: Duration) -> Self {
+		// round up, the limit is enforced with a granularity of seconds.
+		let timeout_secs = execution_timeout.as_secs()
+			+ (execution_timeout.subsec_nanos() > 0) as u64;
+		self.max_cpu_time_secs.get_or_insert(timeout_secs * 2);
+		self
+	}
+}
+
+/// Restrict the privileges of the current process according to the given configuration.
+///
+/// Moving the process into a new network namespace requires the process to be single-threaded
+/// and unprivileged user namespaces to be available. If that fails, so does this function, unless
+/// a shared network is allowed by the configuration. Creating sockets is forbidden by the seccomp
+/// filter in any case.
+#[cfg(unix)]
+pub fn enable_sandbox(config: &SandboxConfig) -> Result<(), String> {
+	if !config.enabled {
+		return Ok(());
+	}
+
+	#[cfg(target_os = "linux")]
+	{
+		if let Err(e) = unshare_network() {
+			if !config.allow_shared_network {
+				return Err(e);
+			}
+			log::warn!("{}. Keeping the network namespace of the node", e);
+		}
+	}
+
+	if let Some(max_address_space) = config.max_address_space {
+		limits::set(libc::RLIMIT_AS, max_address_space, true)?;
+	}
+	// on Linux, the host sets the CPU time limit before each candidate.
+	#[cfg(not(target_os = "linux"))]
+	renew_cpu_time_limit(config)?;
+
+	#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+	seccomp::install()?;
+	#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+	log::warn!("System calls of validation workers are not restricted on this platform");
+
+	Ok(())
+}
+
+/// Allow the worker with the given process id to spend the configured CPU time on its next
+/// candidate, on top of the time it has spent so far.
+///
+/// Only the soft limit is raised, as raising the hard limit requires privileges. The limit is
+/// renewed by the host, so that the seccomp filter of the worker can forbid changing limits.
+#[cfg(target_os = "linux")]
+pub fn renew_worker_cpu_time_limit(pid: u32, config: &SandboxConfig) -> Result<(), String> {
+	let max_cpu_time = match config.max_cpu_time_secs {
+		Some(max_cpu_time) if config.enabled => max_cpu_time,
+		_ => return Ok(()),
+	};
+
+	let used = limits::cpu_time_used(pid)?;
+	limits::set_for(pid, libc::RLIMIT_CPU, used + max_cpu_time)
+}
+
+/// Allow the current process to spend the configured CPU time on a single candidate, on top of
+/// the time it has spent so far.
+///
+/// Workers renew their own limit on platforms without a seccomp filter.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn renew_cpu_time_limit(config: &SandboxConfig) -> Result<(), String> {
+	let max_cpu_time = match config.max_cpu_time_secs {
+		Some(max_cpu_time) if config.enabled => max_cpu_time,
+		_ => return Ok(()),
+	};
+
+	let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+	if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+		return Err(limits::last_os_error("getrusage"));
+	}
+	// round up, the limit is enforced with a granularity of seconds.
+	let used = usage.ru_utime.tv_sec as u64 + usage.ru_stime.tv_sec as u64 + 1;
+
+	limits::set(libc::RLIMIT_CPU, used + max_cpu_time, false)
+}
+
+#[cfg(target_os = "linux")]
+fn unshare_network() -> Result<(), String> {
+	if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+		return Err(limits::last_os_error("unshare"));
+	}
+
+	Ok(())
+}
+
+#[cfg(unix)]
+mod limits {
+	#[cfg(all(target_os = "linux", target_env = "gnu"))]
+	type Resource = libc::__rlimit_resource_t;
+	#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+	type Resource = libc::c_int;
+
+	pub(super) fn last_os_error(call: &str) -> String {
+		format!("{} failed: {}", call, std::io::Error::last_os_error())
+	}
+
+	/// The soft limit to set for the given value, which can not exceed the hard limit.
+	fn soft_limit(value: u64, hard_limit: libc::rlim_t) -> libc::rlim_t {
+		let value = value as libc::rlim_t;
+		if hard_limit == libc::RLIM_INFINITY {
+			value
+		} else {
+			value.min(hard_limit)
+		}
+	}
+
+	/// Set the soft limit of the given resource, and the hard limit as well if `hard` is set.
+	pub(super) fn set(resource: Resource, value: u64, hard: bool) -> Result<(), String> {
+		let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+		if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+			return Err(last_os_error("getrlimit"));
+		}
+
+		limit.rlim_cur = soft_limit(value, limit.rlim_max);
+		if hard {
+			limit.rlim_max = limit.rlim_cur;
+		}
+
+		if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+			return Err(last_os_error("setrlimit"));
+		}
+
+		Ok(())
+	}
+
+	/// Set the soft limit of the given resource of the process with the given id.
+	#[cfg(target_os = "linux")]
+	pub(super) fn set_for(pid: u32, resource: Resource, value: u64) -> Result<(), String> {
+		let pid = pid as libc::pid_t;
+		let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+		if unsafe { libc::prlimit(pid, resource, std::ptr::null(), &mut limit) } != 0 {
+			return Err(last_os_error("prlimit"));
+		}
+
+		limit.rlim_cur = soft_limit(value, limit.rlim_max);
+		if unsafe { libc::prlimit(pid, resource, &limit, std::ptr::null_mut()) } != 0 {
+			return Err(last_os_error("prlimit"));
+		}
+
+		Ok(())
+	}
+
+	/// The CPU time the process with the given id has spent so far, in seconds rounded up.
+	#[cfg(target_os = "linux")]
+	pub(super) fn cpu_time_used(pid: u32) -> Result<u64, String> {
+		let path = format!("/proc/{}/stat", pid);
+		let stat = std::fs::read_to_string(&path)
+			.map_err(|e| format!("Reading {} failed: {}", path, e))?;
+
+		// the fields of interest follow the command name, which may contain spaces.
+		let fields: Vec<&str> = stat.rsplit(')').next().unwrap_or("").split_whitespace().collect();
+		// `utime` and `stime` are the 14th and 15th fields, counting from the process id.
+		let ticks = |index: usize| fields.get(index - 3)
+			.and_then(|field| field.parse::<u64>().ok())
+			.ok_or_else(|| format!("Malformed {}", path));
+		let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+
+		Ok((ticks(14)? + ticks(15)?) / ticks_per_sec + 1)
+	}
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod seccomp {
+	use super::limits::last_os_error;
+
+	// see `linux/filter.h`, `linux/bpf_common.h` and `linux/seccomp.h`.
+	/// `BPF_LD | BPF_W | BPF_ABS`
+	const BPF_LD_W_ABS: u16 = 0x20;
+	/// `BPF_JMP | BPF_JEQ | BPF_K`
+	const BPF_JEQ_K: u16 = 0x15;
+	/// `BPF_RET | BPF_K`
+	const BPF_RET_K: u16 = 0x06;
+
+	const SECCOMP_SET_MODE_FILTER: libc::c_ulong = 1;
+	const SECCOMP_FILTER_FLAG_TSYNC: libc::c_ulong = 1;
+	const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+	const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+	const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+	const AUDIT_ARCH_X86_64: u32 = 0xc000_003e;
+
+	// offsets in `struct seccomp_data`.
+	const NR_OFFSET: u32 = 0;
+	const ARCH_OFFSET: u32 = 4;
+	/// The lower and upper half of the third argument of the system call.
+	const ARG2_LOW_OFFSET: u32 = 32;
+	const ARG2_HIGH_OFFSET: u32 = 36;
+
+	// not defined by all versions of `libc`.
+	const SYS_RSEQ: libc::c_long = 334;
+	const SYS_CLONE3: libc::c_long = 435;
+
+	/// The system calls a worker needs to talk to its host and execute validation code. Files
+	/// can neither be opened nor inspected by path, the host sends the code over the connection.
+	const ALLOWED: &[libc::c_long] = &[
+		// IO on the host connection.
+		libc::SYS_read,
+		libc::SYS_write,
+		libc::SYS_readv,
+		libc::SYS_writev,
+		libc::SYS_recvfrom,
+		libc::SYS_sendto,
+		libc::SYS_recvmsg,
+		libc::SYS_sendmsg,
+		libc::SYS_poll,
+		libc::SYS_close,
+		libc::SYS_fstat,
+		libc::SYS_lseek,
+		// memory.
+		libc::SYS_mmap,
+		libc::SYS_munmap,
+		libc::SYS_mremap,
+		libc::SYS_mprotect,
+		libc::SYS_madvise,
+		libc::SYS_brk,
+		// threads and synchronization.
+		libc::SYS_clone,
+		libc::SYS_futex,
+		libc::SYS_set_robust_list,
+		SYS_RSEQ,
+		libc::SYS_sched_yield,
+		libc::SYS_sched_getaffinity,
+		libc::SYS_prctl,
+		libc::SYS_gettid,
+		libc::SYS_getpid,
+		libc::SYS_tgkill,
+		// signals.
+		libc::SYS_rt_sigaction,
+		libc::SYS_rt_sigprocmask,
+		libc::SYS_rt_sigreturn,
+		libc::SYS_sigaltstack,
+		// time and randomness.
+		libc::SYS_clock_gettime,
+		libc::SYS_gettimeofday,
+		libc::SYS_nanosleep,
+		libc::SYS_clock_nanosleep,
+		libc::SYS_getrandom,
+		// resource limits, which can only be read. See `install` for `prlimit64`.
+		libc::SYS_getrusage,
+		libc::SYS_getrlimit,
+		// exiting.
+		libc::SYS_exit,
+		libc::SYS_exit_group,
+	];
+
+	fn statement(code: u16, k: u32) -> libc::sock_filter {
+		libc::sock_filter { code, jt: 0, jf: 0, k }
+	}
+
+	fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+		libc::sock_filter { code, jt, jf, k }
+	}
+
+	/// Install a filter killing the process on any system call which is not allowed, in all
+	/// threads of the process.
+	pub(super) fn install() -> Result<(), String> {
+		let mut filter = vec![
+			statement(BPF_LD_W_ABS, ARCH_OFFSET),
+			jump(BPF_JEQ_K, AUDIT_ARCH_X86_64, 1, 0),
+			statement(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+			statement(BPF_LD_W_ABS, NR_OFFSET),
+			// make the C library fall back to `clone`.
+			jump(BPF_JEQ_K, SYS_CLONE3 as u32, 0, 1),
+			statement(BPF_RET_K, SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+		];
+		for syscall in ALLOWED {
+			filter.push(jump(BPF_JEQ_K, *syscall as u32, 0, 1));
+			filter.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW));
+		}
+		// the C library reads limits with `prlimit64`, so allow it without a new limit, i.e.
+		// with a null third argument. Limits are renewed by the host instead.
+		filter.extend(vec![
+			jump(BPF_JEQ_K, libc::SYS_prlimit64 as u32, 0, 5),
+			statement(BPF_LD_W_ABS, ARG2_LOW_OFFSET),
+			jump(BPF_JEQ_K, 0, 0, 3),
+			statement(BPF_LD_W_ABS, ARG2_HIGH_OFFSET),
+			jump(BPF_JEQ_K, 0, 0, 1),
+			statement(BPF_RET_K, SECCOMP_RET_ALLOW),
+		]);
+		filter.push(statement(BPF_RET_K, SECCOMP_RET_KILL_PROCESS));
+
+		let program = libc::sock_fprog {
+			len: filter.len() as libc::c_ushort,
+			filter: filter.as_mut_ptr(),
+		};
+
+		if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+			return Err(last_os_error("prctl"));
+		}
+
+		let result = unsafe {
+			libc::syscall(
+				libc::SYS_seccomp,
+				SECCOMP_SET_MODE_FILTER,
+				SECCOMP_FILTER_FLAG_TSYNC,
+				&program as *const libc::sock_fprog,
+			)
+		};
+		if result != 0 {
+			return Err(last_os_error("seccomp"));
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cpu_time_limit_defaults_to_twice_the_execution_timeout() {
+		let config = SandboxConfig::default().with_execution_timeout(Duration::from_millis(2500));
+		assert_eq!(config.max_cpu_time_secs, Some(6));
+
+		let config = SandboxConfig { max_cpu_time_secs: Some(1), ..Default::default() }
+			.with_execution_timeout(Duration::from_secs(10));
+		assert_eq!(config.max_cpu_time_secs, Some(1));
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn cpu_time_of_a_process_is_read() {
+		assert!(limits::cpu_time_used(std::process::id()).unwrap() >= 1);
+		assert!(limits::cpu_time_used(u32::max_value()).is_err());
+	}
+}
@@ -14,49 +14,101 @@
 // You should have received a copy of the GNU General Public License
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
 
-#![cfg(not(any(target_os = "android", target_os = "unknown")))]
-
-use std::{process, env, sync::Arc, sync::atomic};
+#![cfg(all(unix, not(target_os = "android")))]
+
+//! Validation of candidates in separate worker processes.
+//!
+//! The host and each of its workers talk over a Unix socket using length-prefixed,
+//! SCALE-encoded frames. The host sends candidates to validate, heartbeats and cancellations;
+//! the worker answers with results, structured errors and heartbeat replies.
+
+use std::{
+	env, fs, io::{self, Read, Write}, process, sync::{Arc, atomic}, thread,
+	os::unix::{fs::DirBuilderExt, net::{UnixListener, UnixStream}, process::ExitStatusExt},
+	path::PathBuf,
+	time::{Duration, Instant},
+};
 use codec::{Decode, Encode};
 use crate::primitives::{ValidationParams, ValidationResult};
-use super::{validate_candidate_internal, Error};
-use super::{MAX_CODE_MEM, MAX_RUNTIME_MEM};
-use shared_memory::{SharedMem, SharedMemConf, EventState, WriteLockable, EventWait, EventSet};
+use super::{validate_candidate_internal, Error, ValidationPoolConfig};
+use super::{ARTIFACTS, ArtifactId, DiskArtifacts, artifact_id, execute_module, precheck_code};
+use super::sandbox::{SandboxConfig, enable_sandbox};
+#[cfg(target_os = "linux")]
+use super::sandbox::renew_worker_cpu_time_limit;
+#[cfg(not(target_os = "linux"))]
+use super::sandbox::renew_cpu_time_limit;
 use parking_lot::Mutex;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 
 const WORKER_ARGS_TEST: &[&'static str] = &["--nocapture", "validation_worker"];
 /// CLI Argument to start in validation worker mode.
 const WORKER_ARG: &'static str = "validation-worker";
 const WORKER_ARGS: &[&'static str] = &[WORKER_ARG];
 
-/// Execution timeout in seconds;
-#[cfg(debug_assertions)]
-pub const EXECUTION_TIMEOUT_SEC: u64 =  30;
+/// Name of the socket a worker connects to, within a directory private to the worker.
+const WORKER_SOCKET_NAME: &'static str = "worker.sock";
 
-#[cfg(not(debug_assertions))]
-pub const EXECUTION_TIMEOUT_SEC: u64 =  5;
+/// The time a worker is given to acknowledge a cancellation before it is killed.
+const CANCELLATION_GRACE: Duration = Duration::from_millis(500);
 
-enum Event {
-	CandidateReady = 0,
-	ResultReady = 1,
-	WorkerReady = 2,
-}
+/// Overhead of a frame on top of the code and parameters it carries.
+const FRAME_OVERHEAD: usize = 1024;
+
+/// Used to give the socket directories of the workers of this process distinct paths.
+static NEXT_SOCKET_ID: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
 
 /// A pool of hosts.
 #[derive(Clone)]
 pub struct ValidationPool {
 	hosts: Arc<Vec<Mutex<ValidationHost>>>,
+	artifacts: Option<Arc<DiskArtifacts>>,
+	max_code_size: usize,
 }
 
-const DEFAULT_NUM_HOSTS: usize = 8;
-
 impl ValidationPool {
-	/// Creates a validation pool with the default configuration.
-	pub fn new() -> ValidationPool {
+	/// Creates a validation pool with the given configuration.
+	///
+	/// At least one validation host is always created.
+	pub fn new(config: ValidationPoolConfig) -> ValidationPool {
+		let num_workers = config.num_workers.max(1);
+		let artifacts = config.artifacts_path.clone().and_then(|path| {
+			match DiskArtifacts::new(path.clone(), config.max_artifacts_size) {
+				Ok(artifacts) => Some(Arc::new(artifacts)),
+				Err(e) => {
+					warn!("Failed to open artifact directory {:?}: {:?}", path, e);
+					None
+				}
+			}
+		});
+
 		ValidationPool {
-			hosts: Arc::new((0..DEFAULT_NUM_HOSTS).map(|_| Default::default()).collect()),
+			hosts: Arc::new(
+				(0..num_workers)
+					.map(|_| Mutex::new(ValidationHost::new(config.clone(), artifacts.clone())))
+					.collect()
+			),
+			artifacts,
+			max_code_size: config.max_code_size,
+		}
+	}
+
+	/// Prepare the given validation code for the given fuel budget ahead of validating candidates
+	/// with it, storing it in the artifact directory of the pool if one is configured.
+	///
+	/// This fails if the code is larger than the configured maximum code size or does not compile.
+	pub fn prepare_artifact(
+		&self,
+		validation_code: &[u8],
+		max_fuel: Option<u64>,
+	) -> Result<ArtifactId, Error> {
+		match self.artifacts {
+			Some(ref artifacts) => {
+				artifacts.store(validation_code, self.max_code_size, max_fuel)?;
+			}
+			None => precheck_code(validation_code, self.max_code_size)?,
 		}
+
+		Ok(artifact_id(validation_code))
 	}
 
 	/// Validate a candidate under the given validation code using the next
@@ -67,164 +119,475 @@ impl ValidationPool {
 		&self,
 		validation_code: &[u8],
 		params: ValidationParams,
+		max_fuel: Option<u64>,
 		test_mode: bool,
 	) -> Result<ValidationResult, Error> {
 		for host in self.hosts.iter() {
 			if let Some(mut host) = host.try_lock() {
-				return host.validate_candidate(validation_code, params, test_mode);
+				return host.validate_candidate(validation_code, params, max_fuel, test_mode);
 			}
 		}
 
 		// all workers are busy, just wait for the first one
-		self.hosts[0].lock().validate_candidate(validation_code, params, test_mode)
+		self.hosts[0].lock().validate_candidate(validation_code, params, max_fuel, test_mode)
 	}
 }
 
-/// Validation worker process entry point. Runs a loop waiting for candidates to validate
-/// and sends back results via shared memory.
-pub fn run_worker(mem_id: &str) -> Result<(), String> {
-	let mut memory = match SharedMem::open(mem_id) {
-		Ok(memory) => memory,
-		Err(e) => {
-			debug!("{} Error opening shared memory: {:?}", process::id(), e);
-			return Err(format!("Error opening shared memory: {:?}", e));
-		}
+/// Validation worker process entry point. Connects to the host at the given socket path,
+/// restricts its own privileges as configured by the host and validates the candidates it sends
+/// until the host goes away.
+pub fn run_worker(socket_path: &str) -> Result<(), String> {
+	let stream = UnixStream::connect(socket_path)
+		.map_err(|e| format!("{} Error connecting to {}: {:?}", process::id(), socket_path, e))?;
+	let writer = stream.try_clone()
+		.map_err(|e| format!("{} Error cloning socket: {:?}", process::id(), e))?;
+	let mut reader = FrameReader::new(stream, u32::max_value() as usize);
+
+	let sandbox = match reader.read::<ToWorker>() {
+		Ok(Some(ToWorker::Configure(sandbox))) => sandbox,
+		Ok(message) => return Err(format!("{} Expected configuration, got {:?}", process::id(), message)),
+		Err(e) => return Err(format!("{} Error reading configuration: {:?}", process::id(), e)),
 	};
+	enable_sandbox(&sandbox)
+		.map_err(|e| format!("{} Error enabling sandbox: {}", process::id(), e))?;
+
+	let writer = Arc::new(Mutex::new(writer));
+	// The candidate currently being validated, if any.
+	let in_flight = Arc::new(Mutex::new(None));
+
+	let (job_tx, job_rx) = std::sync::mpsc::channel::<(u64, Code, Vec<u8>, Option<u64>)>();
+	{
+		let writer = writer.clone();
+		let in_flight = in_flight.clone();
+		thread::spawn(move || {
+			for (id, code, params, max_fuel) in job_rx {
+				debug!("{} Processing candidate {}", process::id(), id);
+				#[cfg(not(target_os = "linux"))]
+				{
+					if let Err(e) = renew_cpu_time_limit(&sandbox) {
+						warn!("{} Error renewing CPU time limit: {}", process::id(), e);
+						return;
+					}
+				}
+				let result = execute(code, &params, max_fuel);
+				debug!("{} Candidate {} validated: {:?}", process::id(), id, result);
+
+				let mut writer = writer.lock();
+				// the candidate may have been cancelled meanwhile.
+				if in_flight.lock().take() == Some(id) {
+					if let Err(e) = write_frame(&mut *writer, &FromWorker::Result { id, result }) {
+						debug!("{} Error sending result: {:?}", process::id(), e);
+						return;
+					}
+				}
+			}
+		});
+	}
 
-	let exit = Arc::new(atomic::AtomicBool::new(false));
-	// spawn parent monitor thread
-	let watch_exit = exit.clone();
-	std::thread::spawn(move || {
-		use std::io::Read;
-		let mut in_data = Vec::new();
-		// pipe terminates when parent process exits
-		std::io::stdin().read_to_end(&mut in_data).ok();
-		debug!("{} Parent process is dead. Exiting", process::id());
-		exit.store(true, atomic::Ordering::Relaxed);
-	});
-
-	memory.set(Event::WorkerReady as usize, EventState::Signaled)
-		.map_err(|e| format!("{} Error setting shared event: {:?}", process::id(), e))?;
+	write_frame(&mut *writer.lock(), &FromWorker::Ready)
+		.map_err(|e| format!("{} Error signaling readiness: {:?}", process::id(), e))?;
 
 	loop {
-		if watch_exit.load(atomic::Ordering::Relaxed) {
-			break;
-		}
-
-		debug!("{} Waiting for candidate", process::id());
-		match memory.wait(Event::CandidateReady as usize, shared_memory::Timeout::Sec(3)) {
+		let message = match reader.read::<ToWorker>() {
+			Ok(Some(message)) => message,
+			Ok(None) => continue,
 			Err(e) => {
-				// Timeout
-				trace!("{} Timeout waiting for candidate: {:?}", process::id(), e);
-				continue;
+				// the socket is closed when the host exits.
+				debug!("{} Host is gone: {:?}. Exiting", process::id(), e);
+				return Ok(());
+			}
+		};
+
+		match message {
+			ToWorker::Configure(_) => {
+				return Err(format!("{} Worker is configured already", process::id()));
+			}
+			ToWorker::Validate { id, code, params, max_fuel } => {
+				*in_flight.lock() = Some(id);
+				job_tx.send((id, code, params, max_fuel))
+					.map_err(|_| format!("{} Execution thread is gone", process::id()))?;
+			}
+			ToWorker::Ping(nonce) => {
+				trace!("{} Heartbeat {}", process::id(), nonce);
+				write_frame(&mut *writer.lock(), &FromWorker::Pong(nonce))
+					.map_err(|e| format!("{} Error answering heartbeat: {:?}", process::id(), e))?;
 			}
-			Ok(()) => {}
-		}
-
-		{
-			debug!("{} Processing candidate", process::id());
-			// we have candidate data
-			let mut slice = memory.wlock_as_slice(0)
-				.map_err(|e| format!("Error locking shared memory: {:?}", e))?;
-
-			let result = {
-				let data: &mut[u8] = &mut **slice;
-				let (header_buf, rest) = data.split_at_mut(1024);
-				let mut header_buf: &[u8] = header_buf;
-				let header = ValidationHeader::decode(&mut header_buf)
-					.map_err(|_| format!("Error decoding validation request."))?;
-				debug!("{} Candidate header: {:?}", process::id(), header);
-				let (code, rest) = rest.split_at_mut(MAX_CODE_MEM);
-				let (code, _) = code.split_at_mut(header.code_size as usize);
-				let (call_data, _) = rest.split_at_mut(MAX_RUNTIME_MEM);
-				let (call_data, _) = call_data.split_at_mut(header.params_size as usize);
-
-				let result = validate_candidate_internal(code, call_data);
-				debug!("{} Candidate validated: {:?}", process::id(), result);
-
-				match result {
-					Ok(r) => ValidationResultHeader::Ok(r),
-					Err(e) => ValidationResultHeader::Error(e.to_string()),
+			ToWorker::Cancel(id) => {
+				let mut writer = writer.lock();
+				if in_flight.lock().take() == Some(id) {
+					// the execution can not be interrupted, so acknowledge and exit.
+					debug!("{} Candidate {} cancelled. Exiting", process::id(), id);
+					let _ = write_frame(
+						&mut *writer,
+						&FromWorker::Result { id, result: Err(WorkerError::Cancelled) },
+					);
+					process::exit(0);
 				}
-			};
-			let mut data: &mut[u8] = &mut **slice;
-			result.encode_to(&mut data);
+			}
 		}
-		debug!("{} Signaling result", process::id());
-		memory.set(Event::ResultReady as usize, EventState::Signaled)
-			.map_err(|e| format!("Error setting shared event: {:?}", e))?;
 	}
-	Ok(())
 }
 
-/// Params header in shared memory. All offsets should be aligned to WASM page size.
+fn execute(
+	code: Code,
+	params: &[u8],
+	max_fuel: Option<u64>,
+) -> Result<ValidationResult, WorkerError> {
+	let result = match code {
+		Code::Inline(code) => validate_candidate_internal(&code, params, max_fuel),
+		Code::Prepared { id, code } => ARTIFACTS
+			.get_or_compile(&id, max_fuel, move || Ok(code))
+			.and_then(|module| execute_module(&*module, params)),
+	};
+
+	result.map_err(Into::into)
+}
+
+/// The validation code of a candidate.
 #[derive(Encode, Decode, Debug)]
-struct ValidationHeader {
-	code_size: u64,
-	params_size: u64,
+enum Code {
+	/// The code itself.
+	Inline(Vec<u8>),
+	/// The code of the given artifact, prepared for the fuel budget of the candidate. The worker
+	/// compiles it unless it has done so already.
+	Prepared { id: ArtifactId, code: Vec<u8> },
 }
 
+/// Messages sent by the host to a worker.
 #[derive(Encode, Decode, Debug)]
-pub enum ValidationResultHeader {
-	Ok(ValidationResult),
-	Error(String),
+enum ToWorker {
+	/// The restrictions the worker applies to itself. This is the first message sent to a
+	/// worker, which answers with `FromWorker::Ready` once they are in place.
+	Configure(SandboxConfig),
+	/// Validate a candidate with the given encoded parameters, metering the execution if a
+	/// budget is given.
+	Validate { id: u64, code: Code, params: Vec<u8>, max_fuel: Option<u64> },
+	/// Heartbeat, answered with `FromWorker::Pong` carrying the same nonce.
+	Ping(u64),
+	/// Cancel validation of the given candidate.
+	Cancel(u64),
 }
 
-unsafe impl Send for ValidationHost {}
+/// Messages sent by a worker to the host.
+#[derive(Encode, Decode, Debug)]
+enum FromWorker {
+	/// The worker is ready to validate candidates.
+	Ready,
+	/// Answer to a heartbeat.
+	Pong(u64),
+	/// The outcome of validating a candidate.
+	Result { id: u64, result: Result<ValidationResult, WorkerError> },
+}
 
-#[derive(Default)]
-struct ValidationHost {
-	worker: Option<process::Child>,
-	memory: Option<SharedMem>,
-	id: u32,
+/// Errors reported by a worker, mapped onto [`Error`] by the host.
+#[derive(Encode, Decode, Debug, PartialEq)]
+enum WorkerError {
+	InvalidCode(String),
+	BadReturn,
+	Cancelled,
+	OutOfFuel,
+	Io(String),
+	Executor(String),
+	Other(String),
+}
+
+impl From<Error> for WorkerError {
+	fn from(e: Error) -> Self {
+		match e {
+			Error::InvalidCode(message) => WorkerError::InvalidCode(message),
+			Error::BadReturn => WorkerError::BadReturn,
+			Error::Cancelled => WorkerError::Cancelled,
+			Error::OutOfFuel => WorkerError::OutOfFuel,
+			Error::Io(e) => WorkerError::Io(e.to_string()),
+			Error::WasmExecutor(e) => WorkerError::Executor(format!("{:?}", e)),
+			e => WorkerError::Other(e.to_string()),
+		}
+	}
+}
+
+impl From<WorkerError> for Error {
+	fn from(e: WorkerError) -> Self {
+		match e {
+			WorkerError::InvalidCode(message) => Error::InvalidCode(message),
+			WorkerError::BadReturn => Error::BadReturn,
+			WorkerError::Cancelled => Error::Cancelled,
+			WorkerError::OutOfFuel => Error::OutOfFuel,
+			WorkerError::Io(message)
+				| WorkerError::Executor(message)
+				| WorkerError::Other(message) => Error::External(message),
+		}
+	}
+}
+
+/// Write a message as a frame: its length as a little-endian `u32`, followed by its encoding.
+fn write_frame<T: Encode>(stream: &mut impl Write, message: &T) -> io::Result<()> {
+	let data = message.encode();
+	if data.len() > u32::max_value() as usize {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Message too large"));
+	}
+
+	stream.write_all(&(data.len() as u32).to_le_bytes())?;
+	stream.write_all(&data)?;
+	stream.flush()
+}
+
+/// Reads frames from a stream, buffering partially received ones across read timeouts.
+struct FrameReader {
+	stream: UnixStream,
+	buf: Vec<u8>,
+	max_frame_size: usize,
+}
+
+impl FrameReader {
+	fn new(stream: UnixStream, max_frame_size: usize) -> Self {
+		FrameReader { stream, buf: Vec::new(), max_frame_size }
+	}
+
+	/// Read the next message. Returns `None` if the read timeout of the stream elapsed before a
+	/// complete frame was received.
+	fn read<T: Decode>(&mut self) -> Result<Option<T>, Error> {
+		let mut chunk = [0u8; 64 * 1024];
+
+		loop {
+			if let Some(frame) = self.take_frame()? {
+				return T::decode(&mut &frame[..])
+					.map(Some)
+					.map_err(|e| Error::Protocol(format!("Malformed message: {:?}", e)));
+			}
+
+			match self.stream.read(&mut chunk) {
+				Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+				Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+					|| e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+				Err(e) => return Err(e.into()),
+			}
+		}
+	}
+
+	fn take_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+		if self.buf.len() < 4 {
+			return Ok(None);
+		}
+
+		let len = u32::from_le_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]]) as usize;
+		if len > self.max_frame_size {
+			return Err(Error::Protocol(
+				format!("Frame of {} bytes exceeds the maximum of {}", len, self.max_frame_size),
+			));
+		}
+
+		if self.buf.len() < 4 + len {
+			return Ok(None);
+		}
+
+		let frame = self.buf[4..4 + len].to_vec();
+		self.buf.drain(..4 + len);
+		Ok(Some(frame))
+	}
+
+	/// Read the next message, waiting at most until `deadline`.
+	fn read_until<T: Decode>(&mut self, deadline: Instant) -> Result<Option<T>, Error> {
+		loop {
+			let now = Instant::now();
+			if now >= deadline {
+				return Ok(None);
+			}
+
+			self.stream.set_read_timeout(Some(deadline - now))?;
+			if let Some(message) = self.read()? {
+				return Ok(Some(message));
+			}
+		}
+	}
+}
+
+/// A running worker process and the connection to it.
+struct Worker {
+	child: process::Child,
+	writer: UnixStream,
+	reader: FrameReader,
+	next_id: u64,
+}
+
+impl Worker {
+	fn send(&mut self, message: &ToWorker) -> Result<(), Error> {
+		let result = write_frame(&mut self.writer, message).map_err(Error::from);
+		self.check_exit(result)
+	}
+
+	fn receive(&mut self, deadline: Instant) -> Result<Option<FromWorker>, Error> {
+		let result = self.reader.read_until(deadline);
+		self.check_exit(result)
+	}
+
+	/// Report the exit of the worker if the given result failed because the connection to it
+	/// was closed.
+	fn check_exit<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+		match result {
+			Err(Error::Io(ref e)) if is_disconnect(e) => Err(self.exit_error()),
+			result => result,
+		}
+	}
+
+	fn exit_error(&mut self) -> Error {
+		let deadline = Instant::now() + CANCELLATION_GRACE;
+		loop {
+			match self.child.try_wait() {
+				Ok(Some(status)) => return match status.signal() {
+					Some(signal) => Error::External(
+						format!("Validation worker was killed by signal {}", signal),
+					),
+					None => Error::External(format!("Validation worker exited: {}", status)),
+				},
+				Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+				Ok(None) => return Error::External(
+					"Validation worker closed its connection".to_string(),
+				),
+				Err(e) => return e.into(),
+			}
+		}
+	}
+}
+
+fn is_disconnect(e: &io::Error) -> bool {
+	match e.kind() {
+		io::ErrorKind::UnexpectedEof
+			| io::ErrorKind::BrokenPipe
+			| io::ErrorKind::ConnectionReset => true,
+		_ => false,
+	}
 }
 
-impl Drop for ValidationHost {
+impl Drop for Worker {
 	fn drop(&mut self) {
-		if let Some(ref mut worker) = &mut self.worker {
-			worker.kill().ok();
+		self.child.kill().ok();
+		self.child.wait().ok();
+	}
+}
+
+/// Accept a connection on the given listener, waiting at most until `deadline`.
+fn accept_until(listener: &UnixListener, deadline: Instant) -> Result<UnixStream, Error> {
+	listener.set_nonblocking(true)?;
+
+	loop {
+		match listener.accept() {
+			Ok((stream, _)) => {
+				stream.set_nonblocking(false)?;
+				return Ok(stream);
+			}
+			Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+				if Instant::now() >= deadline {
+					return Err(Error::Timeout);
+				}
+				thread::sleep(Duration::from_millis(10));
+			}
+			Err(e) => return Err(e.into()),
 		}
 	}
 }
 
+struct ValidationHost {
+	worker: Option<Worker>,
+	id: u32,
+	/// Whether a worker has ever been started by this host.
+	spawned: bool,
+	next_nonce: u64,
+	config: ValidationPoolConfig,
+	artifacts: Option<Arc<DiskArtifacts>>,
+}
+
 impl ValidationHost {
-	fn create_memory() -> Result<SharedMem, Error> {
-		let mem_size = MAX_RUNTIME_MEM + MAX_CODE_MEM + 1024;
-		let mem_config = SharedMemConf::default()
-			.set_size(mem_size)
-			.add_lock(shared_memory::LockType::Mutex, 0, mem_size)?
-			.add_event(shared_memory::EventType::Auto)?  // Event::CandidateReady
-			.add_event(shared_memory::EventType::Auto)?  // Event::ResultReady
-			.add_event(shared_memory::EventType::Auto)?; // Event::WorkerReady
+	fn new(mut config: ValidationPoolConfig, artifacts: Option<Arc<DiskArtifacts>>) -> Self {
+		config.sandbox = config.sandbox.with_execution_timeout(config.execution_timeout);
+
+		ValidationHost {
+			worker: None,
+			id: 0,
+			spawned: false,
+			next_nonce: 0,
+			config,
+			artifacts,
+		}
+	}
 
-		Ok(mem_config.create()?)
+	fn max_frame_size(&self) -> usize {
+		self.config.max_code_size + self.config.max_params_size + FRAME_OVERHEAD
 	}
 
 	fn start_worker(&mut self, test_mode: bool) -> Result<(), Error> {
 		if let Some(ref mut worker) = self.worker {
 			// Check if still alive
-			if let Ok(None) = worker.try_wait() {
+			if let Ok(None) = worker.child.try_wait() {
 				// Still running
 				return Ok(());
 			}
 		}
-		let memory = Self::create_memory()?;
+		self.worker = None;
+		if self.spawned && !self.config.respawn_workers {
+			return Err(Error::WorkerUnavailable);
+		}
+
+		let socket_dir = socket_dir()?;
+		let socket_path = socket_dir.join(WORKER_SOCKET_NAME);
+		let listener = match UnixListener::bind(&socket_path) {
+			Ok(listener) => listener,
+			Err(e) => {
+				let _ = fs::remove_dir_all(&socket_dir);
+				return Err(e.into());
+			}
+		};
+
 		let self_path = env::current_exe()?;
 		debug!("Starting worker at {:?}", self_path);
-		let mut args = if test_mode { WORKER_ARGS_TEST.to_vec() } else { WORKER_ARGS.to_vec() };
-		args.push(memory.get_os_path());
-		let worker = process::Command::new(self_path)
+		let mut args: Vec<String> = match self.config.worker_args {
+			Some(ref args) => args.clone(),
+			None if test_mode => WORKER_ARGS_TEST.iter().map(|arg| arg.to_string()).collect(),
+			None => WORKER_ARGS.iter().map(|arg| arg.to_string()).collect(),
+		};
+		args.push(socket_path.to_string_lossy().into_owned());
+		let child = process::Command::new(self_path)
 			.args(args)
-			.stdin(process::Stdio::piped())
-			.spawn()?;
-		self.id = worker.id();
-		self.worker = Some(worker);
+			.spawn();
+		let child = match child {
+			Ok(child) => child,
+			Err(e) => {
+				let _ = fs::remove_dir_all(&socket_dir);
+				return Err(e.into());
+			}
+		};
+		self.id = child.id();
+		self.spawned = true;
+
+		let deadline = Instant::now() + self.config.execution_timeout;
+		let accepted = accept_until(&listener, deadline);
+		let _ = fs::remove_dir_all(&socket_dir);
 
-		memory.wait(
-			Event::WorkerReady as usize,
-			shared_memory::Timeout::Sec(EXECUTION_TIMEOUT_SEC as usize),
-		)?;
-		self.memory = Some(memory);
+		let stream = match accepted {
+			Ok(stream) => stream,
+			Err(e) => {
+				let mut child = child;
+				child.kill().ok();
+				child.wait().ok();
+				return Err(e);
+			}
+		};
+		let mut worker = Worker {
+			child,
+			writer: stream.try_clone()?,
+			reader: FrameReader::new(stream, self.max_frame_size()),
+			next_id: 0,
+		};
+
+		worker.send(&ToWorker::Configure(self.config.sandbox.clone()))?;
+		match worker.receive(deadline)? {
+			Some(FromWorker::Ready) => {}
+			Some(message) => return Err(Error::Protocol(format!("Unexpected message: {:?}", message))),
+			None => return Err(Error::Timeout),
+		}
+
+		self.worker = Some(worker);
 		Ok(())
 	}
 
@@ -235,65 +598,253 @@ impl ValidationHost {
 		&mut self,
 		validation_code: &[u8],
 		params: ValidationParams,
+		max_fuel: Option<u64>,
 		test_mode: bool,
 	) -> Result<ValidationResult, Error> {
-		if validation_code.len() > MAX_CODE_MEM {
-			return Err(Error::CodeTooLarge(validation_code.len()));
+		let max_code_size = self.config.max_code_size;
+		let max_params_size = self.config.max_params_size;
+		if validation_code.len() > max_code_size {
+			return Err(Error::CodeTooLarge(validation_code.len(), max_code_size));
+		}
+		let encoded_params = params.encode();
+		if encoded_params.len() > max_params_size {
+			return Err(Error::ParamsTooLarge(encoded_params.len(), max_params_size));
 		}
+		// Workers can not open files, so prepared code which is stored on disk is sent to them.
+		let code = match self.artifacts {
+			Some(ref artifacts) => {
+				let id = artifact_id(validation_code);
+				artifacts.store(validation_code, max_code_size, max_fuel)?;
+				match artifacts.load(&id, max_fuel) {
+					Some(code) => Code::Prepared { id, code },
+					None => Code::Inline(validation_code.to_vec()),
+				}
+			}
+			None => Code::Inline(validation_code.to_vec()),
+		};
 		// First, check if need to spawn the child process
 		self.start_worker(test_mode)?;
-		let memory = self.memory.as_mut()
-			.expect("memory is always `Some` after `start_worker` completes successfully");
-		{
-			// Put data in shared mem
-			let data: &mut[u8] = &mut **memory.wlock_as_slice(0)?;
-			let (mut header_buf, rest) = data.split_at_mut(1024);
-			let (code, rest) = rest.split_at_mut(MAX_CODE_MEM);
-			let (code, _) = code.split_at_mut(validation_code.len());
-			let (call_data, _) = rest.split_at_mut(MAX_RUNTIME_MEM);
-			code[..validation_code.len()].copy_from_slice(validation_code);
-			let encoded_params = params.encode();
-			if encoded_params.len() >= MAX_RUNTIME_MEM {
-				return Err(Error::ParamsTooLarge(MAX_RUNTIME_MEM));
-			}
-			call_data[..encoded_params.len()].copy_from_slice(&encoded_params);
 
-			let header = ValidationHeader {
-				code_size: validation_code.len() as u64,
-				params_size: encoded_params.len() as u64,
-			};
-
-			header.encode_to(&mut header_buf);
+		let result = self.run_candidate(code, encoded_params, max_fuel);
+		match result {
+			Ok(result) => result,
+			Err(e) => {
+				// the worker is in an unknown state, don't reuse it.
+				self.worker = None;
+				Err(e)
+			}
 		}
+	}
 
-		debug!("{} Signaling candidate", self.id);
-		memory.set(Event::CandidateReady as usize, EventState::Signaled)?;
-
-		debug!("{} Waiting for results", self.id);
-		match memory.wait(Event::ResultReady as usize, shared_memory::Timeout::Sec(EXECUTION_TIMEOUT_SEC as usize)) {
-			Err(e) => {
-				debug!("Worker timeout: {:?}", e);
-				if let Some(mut worker) = self.worker.take() {
-					worker.kill().ok();
+	/// Send a candidate to the worker and wait for its outcome.
+	///
+	/// The outer error is returned if the worker can not be used any longer.
+	fn run_candidate(
+		&mut self,
+		code: Code,
+		params: Vec<u8>,
+		max_fuel: Option<u64>,
+	) -> Result<Result<ValidationResult, Error>, Error> {
+		let execution_timeout = self.config.execution_timeout;
+		let heartbeat_interval = self.config.heartbeat_interval.max(Duration::from_millis(1));
+		let host_id = self.id;
+		let next_nonce = &mut self.next_nonce;
+		let worker = self.worker.as_mut()
+			.expect("worker is always `Some` after `start_worker` completes successfully");
+
+		#[cfg(target_os = "linux")]
+		renew_worker_cpu_time_limit(worker.child.id(), &self.config.sandbox)
+			.map_err(Error::External)?;
+
+		let id = worker.next_id;
+		worker.next_id += 1;
+
+		debug!("{} Sending candidate {}", host_id, id);
+		worker.send(&ToWorker::Validate { id, code, params, max_fuel })?;
+
+		// the fuel budget bounds the work of the candidate, the deadline only guards against a
+		// worker stalling outside of metered code and is configured well above any budget.
+		let deadline = Instant::now() + execution_timeout;
+		let mut pending_ping = None;
+		loop {
+			let heartbeat_deadline = std::cmp::min(Instant::now() + heartbeat_interval, deadline);
+			match worker.receive(heartbeat_deadline)? {
+				Some(FromWorker::Result { id: result_id, result }) if result_id == id => {
+					debug!("{} Received result of candidate {}: {:?}", host_id, id, result);
+					return Ok(result.map_err(Into::into));
+				}
+				Some(FromWorker::Pong(nonce)) => {
+					if pending_ping == Some(nonce) {
+						pending_ping = None;
+					}
+				}
+				Some(message) => trace!("{} Ignoring message {:?}", host_id, message),
+				None if Instant::now() >= deadline => {
+					debug!("{} Worker timeout, cancelling candidate {}", host_id, id);
+					Self::cancel(worker, id);
+					return Err(Error::Timeout);
+				}
+				None => {
+					if pending_ping.is_some() {
+						debug!("{} Worker did not answer heartbeat", host_id);
+						return Err(Error::WorkerUnresponsive);
+					}
+
+					let nonce = *next_nonce;
+					*next_nonce += 1;
+					worker.send(&ToWorker::Ping(nonce))?;
+					pending_ping = Some(nonce);
 				}
-				return Err(Error::Timeout.into());
 			}
-			Ok(()) => {}
-		}
-
-		{
-			debug!("{} Reading results", self.id);
-			let data: &[u8] = &**memory.wlock_as_slice(0)?;
-			let (header_buf, _) = data.split_at(1024);
-			let mut header_buf: &[u8] = header_buf;
-			let header = ValidationResultHeader::decode(&mut header_buf).unwrap();
-			match header {
-				ValidationResultHeader::Ok(result) => Ok(result),
-				ValidationResultHeader::Error(message) => {
-					debug!("{} Validation error: {}", self.id, message);
-					Err(Error::External(message).into())
+		}
+	}
+
+	/// Ask the worker to cancel the given candidate and give it some time to acknowledge.
+	fn cancel(worker: &mut Worker, id: u64) {
+		if write_frame(&mut worker.writer, &ToWorker::Cancel(id)).is_err() {
+			return;
+		}
+
+		let deadline = Instant::now() + CANCELLATION_GRACE;
+		while let Ok(Some(message)) = worker.reader.read_until::<FromWorker>(deadline) {
+			if let FromWorker::Result { id: result_id, .. } = message {
+				if result_id == id {
+					break;
 				}
 			}
 		}
 	}
 }
+
+/// Create a fresh directory for the socket of a worker.
+///
+/// Only the current user may access the directory, so that no other user can connect to the
+/// socket in place of the worker. Creating it fails if something else was created at its path
+/// meanwhile.
+fn socket_dir() -> io::Result<PathBuf> {
+	let dir = env::temp_dir().join(format!(
+		"polkadot-pvf-{}-{}",
+		process::id(),
+		NEXT_SOCKET_ID.fetch_add(1, atomic::Ordering::Relaxed),
+	));
+	// left over by an earlier process with the same id.
+	let _ = fs::remove_dir_all(&dir);
+	fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+	Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn socket_pair(max_frame_size: usize) -> (UnixStream, FrameReader) {
+		let (a, b) = UnixStream::pair().unwrap();
+		(a, FrameReader::new(b, max_frame_size))
+	}
+
+	#[test]
+	fn socket_dirs_are_private() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let first = socket_dir().unwrap();
+		let second = socket_dir().unwrap();
+		assert_ne!(first, second);
+		assert_eq!(fs::metadata(&first).unwrap().permissions().mode() & 0o777, 0o700);
+
+		fs::remove_dir(first).unwrap();
+		fs::remove_dir(second).unwrap();
+	}
+
+	#[test]
+	fn frames_roundtrip() {
+		let (mut writer, mut reader) = socket_pair(1024);
+
+		write_frame(&mut writer, &ToWorker::Ping(7)).unwrap();
+		write_frame(&mut writer, &ToWorker::Cancel(3)).unwrap();
+
+		match reader.read::<ToWorker>().unwrap() {
+			Some(ToWorker::Ping(7)) => {},
+			r => panic!("{:?}", r),
+		}
+		match reader.read::<ToWorker>().unwrap() {
+			Some(ToWorker::Cancel(3)) => {},
+			r => panic!("{:?}", r),
+		}
+	}
+
+	#[test]
+	fn partial_frames_are_buffered_across_timeouts() {
+		let (mut writer, mut reader) = socket_pair(1024);
+		let data = FromWorker::Pong(42).encode();
+
+		writer.write_all(&(data.len() as u32).to_le_bytes()).unwrap();
+		writer.write_all(&data[..1]).unwrap();
+		let deadline = Instant::now() + Duration::from_millis(50);
+		assert!(reader.read_until::<FromWorker>(deadline).unwrap().is_none());
+
+		writer.write_all(&data[1..]).unwrap();
+		let deadline = Instant::now() + Duration::from_secs(1);
+		match reader.read_until::<FromWorker>(deadline).unwrap() {
+			Some(FromWorker::Pong(42)) => {},
+			r => panic!("{:?}", r),
+		}
+	}
+
+	#[test]
+	fn frames_above_maximum_size_are_rejected() {
+		let (mut writer, mut reader) = socket_pair(16);
+
+		write_frame(&mut writer, &ToWorker::Validate {
+			id: 0,
+			code: Code::Inline(vec![0; 32]),
+			params: Vec::new(),
+			max_fuel: None,
+		}).unwrap();
+
+		match reader.read::<ToWorker>() {
+			Err(Error::Protocol(_)) => {},
+			r => panic!("{:?}", r),
+		}
+	}
+
+	#[test]
+	fn closed_connection_is_reported() {
+		let (writer, mut reader) = socket_pair(1024);
+		drop(writer);
+
+		match reader.read::<ToWorker>() {
+			Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {},
+			r => panic!("{:?}", r),
+		}
+	}
+
+	#[test]
+	fn worker_errors_map_onto_executor_errors() {
+		let roundtrip = |e: Error| -> Error {
+			let encoded = WorkerError::from(e).encode();
+			WorkerError::decode(&mut &encoded[..]).unwrap().into()
+		};
+
+		match roundtrip(Error::InvalidCode("bad".into())) {
+			Error::InvalidCode(ref message) if message == "bad" => {},
+			e => panic!("{:?}", e),
+		}
+		match roundtrip(Error::BadReturn) {
+			Error::BadReturn => {},
+			e => panic!("{:?}", e),
+		}
+		match roundtrip(Error::Cancelled) {
+			Error::Cancelled => {},
+			e => panic!("{:?}", e),
+		}
+		match roundtrip(Error::OutOfFuel) {
+			Error::OutOfFuel => {},
+			e => panic!("{:?}", e),
+		}
+		match roundtrip(Error::Timeout) {
+			Error::External(_) => {},
+			e => panic!("{:?}", e),
+		}
+	}
+}
@@ -54,39 +54,58 @@ fn hash_head(head: &HeadData) -> [u8; 32] {
 	tiny_keccak::keccak256(head.encode().as_slice())
 }
 
-#[test]
-pub fn execute_good_on_parent() {
-	let parent_head = HeadData {
+fn genesis_head() -> HeadData {
+	HeadData {
 		number: 0,
 		parent_hash: [0; 32],
 		post_state: hash_state(0),
-	};
+	}
+}
 
+/// The parameters to validate a good block on top of the genesis head of the adder parachain.
+pub fn good_on_parent_params() -> ValidationParams {
 	let block_data = BlockData {
 		state: 0,
 		add: 512,
 	};
 
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	ValidationParams {
+		parent_head: GenericHeadData(genesis_head().encode()),
+		block_data: GenericBlockData(block_data.encode()),
+		max_code_size: 1024,
+		max_head_data_size: 1024,
+		relay_chain_height: 1,
+		code_upgrade_allowed: None,
+	}
+}
+
+/// Validate a good block on top of the genesis head of the adder parachain with the given pool,
+/// checking the resulting head if validation succeeds.
+pub fn validate_good_on_parent(
+	pool: &parachain::wasm_executor::ValidationPool,
+) -> Result<(), parachain::wasm_executor::Error> {
+	let parent_head = genesis_head();
 
 	let ret = parachain::wasm_executor::validate_candidate(
 		TEST_CODE,
-		ValidationParams {
-			parent_head: GenericHeadData(parent_head.encode()),
-			block_data: GenericBlockData(block_data.encode()),
-			max_code_size: 1024,
-			max_head_data_size: 1024,
-			relay_chain_height: 1,
-			code_upgrade_allowed: None,
-		},
-		parachain::wasm_executor::ExecutionMode::RemoteTest(&pool),
-	).unwrap();
+		good_on_parent_params(),
+		parachain::wasm_executor::ExecutionMode::RemoteTest(pool),
+	)?;
 
 	let new_head = HeadData::decode(&mut &ret.head_data.0[..]).unwrap();
 
 	assert_eq!(new_head.number, 1);
 	assert_eq!(new_head.parent_hash, hash_head(&parent_head));
 	assert_eq!(new_head.post_state, hash_state(512));
+
+	Ok(())
+}
+
+#[test]
+pub fn execute_good_on_parent() {
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
+
+	validate_good_on_parent(&pool).unwrap();
 }
 
 #[test]
@@ -94,7 +113,7 @@ fn execute_good_chain_on_parent() {
 	let mut number = 0;
 	let mut parent_hash = [0; 32];
 	let mut last_state = 0;
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	for add in 0..10 {
 		let parent_head = HeadData {
@@ -135,7 +154,7 @@ fn execute_good_chain_on_parent() {
 
 #[test]
 fn execute_bad_on_parent() {
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	let parent_head = HeadData {
 		number: 0,
@@ -0,0 +1,236 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Caching of prepared validation code.
+
+use crate::adder;
+use parachain::primitives::MAX_CODE_MEM;
+use parachain::wasm_executor::{
+	self, ArtifactCache, DiskArtifacts, Error, ExecutionMode, ValidationPool, ValidationPoolConfig,
+	artifact_id, prepare_code,
+};
+use std::sync::Arc;
+
+const ADDER_CODE: &[u8] = ::adder::WASM_BINARY;
+const HALT_CODE: &[u8] = ::halt::WASM_BINARY;
+const CODE_UPGRADER_CODE: &[u8] = ::code_upgrader::WASM_BINARY;
+
+#[test]
+fn memory_cache_prepares_code_once() {
+	let cache = ArtifactCache::new(wasm_executor::DEFAULT_MAX_MEMORY_SIZE);
+
+	let first = cache.get_or_prepare(ADDER_CODE, None).unwrap();
+	let second = cache.get_or_prepare(ADDER_CODE, None).unwrap();
+
+	assert!(Arc::ptr_eq(&first, &second));
+	assert!(cache.contains(&artifact_id(ADDER_CODE), None));
+	assert_eq!(cache.size(), ADDER_CODE.len());
+}
+
+#[test]
+fn memory_cache_prepares_metered_code_per_fuel_budget() {
+	let cache = ArtifactCache::new(wasm_executor::DEFAULT_MAX_MEMORY_SIZE);
+	let id = artifact_id(ADDER_CODE);
+
+	let first = cache.get_or_prepare(ADDER_CODE, Some(1_000_000)).unwrap();
+	let second = cache.get_or_prepare(ADDER_CODE, Some(1_000_000)).unwrap();
+	assert!(Arc::ptr_eq(&first, &second));
+
+	let unmetered = cache.get_or_prepare(ADDER_CODE, None).unwrap();
+	assert!(!Arc::ptr_eq(&first, &unmetered));
+
+	assert!(cache.contains(&id, Some(1_000_000)));
+	assert!(cache.contains(&id, None));
+	assert!(!cache.contains(&id, Some(2_000_000)));
+
+	let metered_size = prepare_code(ADDER_CODE, Some(1_000_000)).unwrap().len();
+	assert!(metered_size > ADDER_CODE.len());
+	assert_eq!(cache.size(), ADDER_CODE.len() + metered_size);
+}
+
+#[test]
+fn memory_cache_compiles_prepared_code_once() {
+	let cache = ArtifactCache::new(wasm_executor::DEFAULT_MAX_MEMORY_SIZE);
+	let id = artifact_id(ADDER_CODE);
+	let prepared = prepare_code(ADDER_CODE, Some(1_000_000)).unwrap().into_owned();
+
+	let first = cache.get_or_compile(&id, Some(1_000_000), || Ok(prepared.clone())).unwrap();
+	let second = cache.get_or_compile(&id, Some(1_000_000), || panic!("loaded twice")).unwrap();
+	assert!(Arc::ptr_eq(&first, &second));
+
+	// the module compiled from prepared code is the one of the validation code it was prepared
+	// from.
+	let prepared_here = cache.get_or_prepare(ADDER_CODE, Some(1_000_000)).unwrap();
+	assert!(Arc::ptr_eq(&first, &prepared_here));
+	assert_eq!(cache.size(), prepared.len());
+}
+
+#[test]
+fn memory_cache_evicts_least_recently_used() {
+	let cache = ArtifactCache::new(ADDER_CODE.len() + HALT_CODE.len() + CODE_UPGRADER_CODE.len() - 1);
+
+	cache.get_or_prepare(ADDER_CODE, None).unwrap();
+	cache.get_or_prepare(HALT_CODE, None).unwrap();
+	assert!(cache.get(&artifact_id(ADDER_CODE), None).is_some());
+
+	cache.get_or_prepare(CODE_UPGRADER_CODE, None).unwrap();
+
+	assert!(cache.contains(&artifact_id(ADDER_CODE), None));
+	assert!(!cache.contains(&artifact_id(HALT_CODE), None));
+	assert!(cache.contains(&artifact_id(CODE_UPGRADER_CODE), None));
+	assert_eq!(cache.size(), ADDER_CODE.len() + CODE_UPGRADER_CODE.len());
+}
+
+#[test]
+fn memory_cache_keeps_most_recent_artifact_above_limit() {
+	let cache = ArtifactCache::new(0);
+
+	cache.get_or_prepare(ADDER_CODE, None).unwrap();
+	cache.get_or_prepare(HALT_CODE, None).unwrap();
+
+	assert!(!cache.contains(&artifact_id(ADDER_CODE), None));
+	assert!(cache.contains(&artifact_id(HALT_CODE), None));
+}
+
+#[test]
+fn invalid_code_is_not_cached() {
+	let cache = ArtifactCache::new(wasm_executor::DEFAULT_MAX_MEMORY_SIZE);
+
+	match cache.get_or_prepare(&[0, 1, 2, 3], None) {
+		Err(wasm_executor::Error::InvalidCode(_)) => {},
+		r => panic!("{:?}", r.map(|_| ())),
+	}
+	assert_eq!(cache.size(), 0);
+}
+
+#[test]
+fn disk_artifacts_are_stored_and_reopened() {
+	let dir = tempfile::tempdir().unwrap();
+	let id = artifact_id(ADDER_CODE);
+
+	{
+		let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), u64::max_value()).unwrap();
+		let path = artifacts.store(ADDER_CODE, MAX_CODE_MEM, None).unwrap();
+
+		assert_eq!(path, artifacts.artifact_path(&id, None));
+		assert_eq!(std::fs::read(&path).unwrap(), ADDER_CODE);
+		assert_eq!(artifacts.load(&id, None).unwrap(), ADDER_CODE);
+
+		assert!(artifacts.store(&[0, 1, 2, 3], MAX_CODE_MEM, None).is_err());
+		assert!(!artifacts.contains(&artifact_id(&[0, 1, 2, 3]), None));
+	}
+
+	let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), u64::max_value()).unwrap();
+	assert!(artifacts.contains(&id, None));
+	assert_eq!(artifacts.load(&id, None).unwrap(), ADDER_CODE);
+}
+
+#[test]
+fn disk_artifacts_persist_instrumented_code() {
+	let dir = tempfile::tempdir().unwrap();
+	let id = artifact_id(ADDER_CODE);
+	let instrumented = prepare_code(ADDER_CODE, Some(1_000_000)).unwrap().into_owned();
+
+	{
+		let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), u64::max_value()).unwrap();
+		let path = artifacts.store(ADDER_CODE, MAX_CODE_MEM, Some(1_000_000)).unwrap();
+
+		assert_eq!(path, artifacts.artifact_path(&id, Some(1_000_000)));
+		assert_ne!(path, artifacts.artifact_path(&id, None));
+		assert_eq!(std::fs::read(&path).unwrap(), instrumented);
+		assert!(!artifacts.contains(&id, None));
+	}
+
+	// the instrumented code is picked up again after a restart.
+	let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), u64::max_value()).unwrap();
+	assert!(artifacts.contains(&id, Some(1_000_000)));
+	assert!(!artifacts.contains(&id, Some(2_000_000)));
+	assert_eq!(artifacts.load(&id, Some(1_000_000)).unwrap(), instrumented);
+}
+
+#[test]
+fn disk_artifacts_evict_least_recently_used() {
+	let dir = tempfile::tempdir().unwrap();
+	let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), ADDER_CODE.len() as u64).unwrap();
+
+	let adder_path = artifacts.store(ADDER_CODE, MAX_CODE_MEM, None).unwrap();
+	let halt_path = artifacts.store(HALT_CODE, MAX_CODE_MEM, None).unwrap();
+
+	assert!(!adder_path.exists());
+	assert!(halt_path.exists());
+	assert!(!artifacts.contains(&artifact_id(ADDER_CODE), None));
+	assert!(artifacts.contains(&artifact_id(HALT_CODE), None));
+}
+
+#[test]
+fn pool_stores_artifacts_on_disk() {
+	let dir = tempfile::tempdir().unwrap();
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		num_workers: 1,
+		artifacts_path: Some(dir.path().to_path_buf()),
+		..Default::default()
+	});
+
+	let mode = ExecutionMode::RemoteTest(&pool);
+	let id = wasm_executor::prepare_artifact(HALT_CODE, Some(1_000_000), mode).unwrap();
+	assert_eq!(id, artifact_id(HALT_CODE));
+
+	// workers are sent the prepared code stored on disk.
+	adder::validate_good_on_parent(&pool).unwrap();
+	adder::validate_good_on_parent(&pool).unwrap();
+
+	let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), u64::max_value()).unwrap();
+	assert!(artifacts.contains(&artifact_id(ADDER_CODE), None));
+	assert!(artifacts.contains(&artifact_id(HALT_CODE), Some(1_000_000)));
+	assert!(!artifacts.contains(&artifact_id(HALT_CODE), None));
+}
+
+#[test]
+fn prepare_artifact_rejects_invalid_code() {
+	let mode = || ExecutionMode::Local { max_code_size: MAX_CODE_MEM };
+
+	assert!(wasm_executor::prepare_artifact(&[0, 1, 2, 3], None, mode()).is_err());
+	assert_eq!(
+		wasm_executor::prepare_artifact(ADDER_CODE, None, mode()).unwrap(),
+		artifact_id(ADDER_CODE),
+	);
+}
+
+#[test]
+fn oversized_code_is_not_prepared() {
+	let max_code_size = ADDER_CODE.len() - 1;
+
+	let mode = ExecutionMode::Local { max_code_size };
+	match wasm_executor::prepare_artifact(ADDER_CODE, None, mode) {
+		Err(Error::CodeTooLarge(size, max)) if size == ADDER_CODE.len() && max == max_code_size => {},
+		r => panic!("{:?}", r),
+	}
+
+	let dir = tempfile::tempdir().unwrap();
+	let artifacts = DiskArtifacts::new(dir.path().to_path_buf(), u64::max_value()).unwrap();
+	assert!(artifacts.store(ADDER_CODE, max_code_size, None).is_err());
+	assert!(!artifacts.contains(&artifact_id(ADDER_CODE), None));
+
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		num_workers: 1,
+		max_code_size,
+		artifacts_path: Some(dir.path().to_path_buf()),
+		..Default::default()
+	});
+	let mode = ExecutionMode::RemoteTest(&pool);
+	assert!(wasm_executor::prepare_artifact(ADDER_CODE, None, mode).is_err());
+	assert!(!artifacts.contains(&artifact_id(ADDER_CODE), None));
+}
@@ -28,7 +28,7 @@ const TEST_CODE: &[u8] = code_upgrader::WASM_BINARY;
 
 #[test]
 pub fn execute_good_no_upgrade() {
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	let parent_head = HeadData {
 		number: 0,
@@ -64,7 +64,7 @@ pub fn execute_good_no_upgrade() {
 
 #[test]
 pub fn execute_good_with_upgrade() {
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	let parent_head = HeadData {
 		number: 0,
@@ -107,7 +107,7 @@ pub fn execute_good_with_upgrade() {
 #[test]
 #[should_panic]
 pub fn code_upgrade_not_allowed() {
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	let parent_head = HeadData {
 		number: 0,
@@ -136,7 +136,7 @@ pub fn code_upgrade_not_allowed() {
 
 #[test]
 pub fn applies_code_upgrade_after_delay() {
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	let (new_head, state) = {
 		let parent_head = HeadData {
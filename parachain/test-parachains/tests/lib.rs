@@ -15,7 +15,10 @@
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
 
 mod adder;
+mod artifact_cache;
 mod code_upgrader;
+mod metering;
+mod sandbox;
 mod wasm_executor;
 
 use parachain::wasm_executor::run_worker;
@@ -25,7 +28,32 @@ use parachain::wasm_executor::run_worker;
 // It then passes "validation_worker" on CLI effectivly making rust test executor to run this single test.
 #[test]
 fn validation_worker() {
-	if let Some(id) = std::env::args().find(|a| a.starts_with("/shmem_rs_")) {
-		run_worker(&id).unwrap()
+	if let Some(socket_path) = std::env::args().find(|a| a.ends_with(".sock")) {
+		run_worker(&socket_path).unwrap()
+	}
+}
+
+// Entry point for a worker which makes a forbidden system call right after enabling its sandbox,
+// started with the arguments in `sandbox::VIOLATING_WORKER_ARGS`.
+#[test]
+fn sandbox_violating_worker() {
+	if let Some(socket_path) = std::env::args().find(|a| a.ends_with(".sock")) {
+		let _stream = std::os::unix::net::UnixStream::connect(&socket_path).unwrap();
+		parachain::wasm_executor::enable_sandbox(&Default::default()).unwrap();
+		// creating sockets is forbidden.
+		let _ = std::net::UdpSocket::bind("127.0.0.1:0");
+	}
+}
+
+// Entry point for a worker which opens a file right after enabling its sandbox, started with the
+// arguments in `sandbox::FILE_OPENING_WORKER_ARGS`.
+#[test]
+fn sandbox_file_opening_worker() {
+	if let Some(socket_path) = std::env::args().find(|a| a.ends_with(".sock")) {
+		let _stream = std::os::unix::net::UnixStream::connect(&socket_path).unwrap();
+		let path = std::env::current_exe().unwrap();
+		parachain::wasm_executor::enable_sandbox(&Default::default()).unwrap();
+		// opening files is forbidden.
+		let _ = std::fs::File::open(&path);
 	}
 }
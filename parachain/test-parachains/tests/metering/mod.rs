@@ -0,0 +1,148 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Deterministic metering of validation.
+
+use crate::adder;
+use parachain::{
+	primitives::{BlockData, ValidationParams, MAX_CODE_MEM},
+	wasm_executor::{
+		Error, ExecutionMode, ValidationPool, ValidationPoolConfig, validate_candidate_metered,
+	},
+};
+use std::time::Duration;
+
+// Code that exposes `validate_block` and loops infinitely
+const INFINITE_LOOP_CODE: &[u8] = halt::WASM_BINARY;
+
+const FUEL: u64 = 1_000_000;
+
+fn halt_params() -> ValidationParams {
+	ValidationParams {
+		block_data: BlockData(Vec::new()),
+		parent_head: Default::default(),
+		max_code_size: 1024,
+		max_head_data_size: 1024,
+		relay_chain_height: 1,
+		code_upgrade_allowed: None,
+	}
+}
+
+fn pool_with_timeout(execution_timeout: Duration) -> ValidationPool {
+	ValidationPool::new(ValidationPoolConfig {
+		num_workers: 1,
+		execution_timeout,
+		..Default::default()
+	})
+}
+
+#[test]
+fn infinite_loop_runs_out_of_fuel_locally() {
+	let local = ExecutionMode::Local { max_code_size: MAX_CODE_MEM };
+	match validate_candidate_metered(INFINITE_LOOP_CODE, halt_params(), FUEL, local) {
+		Err(Error::OutOfFuel) => {},
+		r => panic!("{:?}", r),
+	}
+}
+
+#[test]
+fn infinite_loop_runs_out_of_fuel_before_timeout() {
+	let pool = pool_with_timeout(Duration::from_secs(60));
+
+	match validate_candidate_metered(
+		INFINITE_LOOP_CODE,
+		halt_params(),
+		FUEL,
+		ExecutionMode::RemoteTest(&pool),
+	) {
+		Err(Error::OutOfFuel) => {},
+		r => panic!("{:?}", r),
+	}
+
+	// the worker survives running out of fuel.
+	adder::validate_good_on_parent(&pool).unwrap();
+}
+
+#[test]
+fn out_of_fuel_is_distinct_from_timeout() {
+	let pool = pool_with_timeout(Duration::from_secs(1));
+
+	match validate_candidate_metered(
+		INFINITE_LOOP_CODE,
+		halt_params(),
+		u64::max_value(),
+		ExecutionMode::RemoteTest(&pool),
+	) {
+		Err(Error::Timeout) => {},
+		r => panic!("{:?}", r),
+	}
+}
+
+#[test]
+fn good_block_validates_with_enough_fuel() {
+	let pool = pool_with_timeout(Duration::from_secs(60));
+
+	validate_candidate_metered(
+		::adder::WASM_BINARY,
+		adder::good_on_parent_params(),
+		FUEL,
+		ExecutionMode::RemoteTest(&pool),
+	).unwrap();
+}
+
+// Validators differ in speed, but also in how they execute candidates. Every budget must lead
+// to the same outcome however the candidate is executed.
+#[test]
+fn outcome_does_not_depend_on_execution() {
+	let fast = pool_with_timeout(Duration::from_secs(60));
+	let slow = ValidationPool::new(ValidationPoolConfig {
+		num_workers: 4,
+		execution_timeout: Duration::from_secs(120),
+		heartbeat_interval: Duration::from_millis(10),
+		..Default::default()
+	});
+
+	let mut outcomes = Vec::new();
+	for fuel in (0..8).map(|exp| 10u64.pow(exp)) {
+		let local = validate_candidate_metered(
+			::adder::WASM_BINARY,
+			adder::good_on_parent_params(),
+			fuel,
+			ExecutionMode::Local { max_code_size: MAX_CODE_MEM },
+		).map(|result| result.head_data);
+
+		for pool in &[&fast, &slow] {
+			let remote = validate_candidate_metered(
+				::adder::WASM_BINARY,
+				adder::good_on_parent_params(),
+				fuel,
+				ExecutionMode::RemoteTest(*pool),
+			).map(|result| result.head_data);
+
+			match (&local, &remote) {
+				(Ok(local), Ok(remote)) => assert_eq!(local, remote),
+				(Err(Error::OutOfFuel), Err(Error::OutOfFuel)) => {},
+				r => panic!("fuel {}: {:?}", fuel, r),
+			}
+		}
+
+		outcomes.push(local.is_ok());
+	}
+
+	// small budgets are exhausted, large ones are not.
+	assert!(!outcomes[0]);
+	assert!(outcomes[outcomes.len() - 1]);
+}
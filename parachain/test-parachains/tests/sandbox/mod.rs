@@ -0,0 +1,108 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Restriction of the privileges of validation workers.
+
+use crate::adder;
+use parachain::{
+	primitives::{BlockData, ValidationParams},
+	wasm_executor::{Error, ExecutionMode, SandboxConfig, ValidationPool, ValidationPoolConfig},
+};
+use std::time::Duration;
+
+/// Arguments starting the test binary as a worker which violates its sandbox.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const VIOLATING_WORKER_ARGS: &[&str] = &["--nocapture", "sandbox_violating_worker"];
+
+/// Arguments starting the test binary as a worker which opens a file in its sandbox.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const FILE_OPENING_WORKER_ARGS: &[&str] = &["--nocapture", "sandbox_file_opening_worker"];
+
+#[test]
+fn sandboxed_worker_validates() {
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		num_workers: 1,
+		sandbox: SandboxConfig::default(),
+		..Default::default()
+	});
+
+	adder::validate_good_on_parent(&pool).unwrap();
+	// the worker is reused after validating a candidate in its sandbox.
+	adder::validate_good_on_parent(&pool).unwrap();
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn worker_making_forbidden_syscall_is_killed() {
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		num_workers: 1,
+		worker_args: Some(VIOLATING_WORKER_ARGS.iter().map(|arg| arg.to_string()).collect()),
+		..Default::default()
+	});
+
+	match adder::validate_good_on_parent(&pool) {
+		Err(Error::External(ref message)) if message.contains("signal") => {},
+		r => panic!("{:?}", r),
+	}
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn worker_opening_files_is_killed() {
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		num_workers: 1,
+		worker_args: Some(FILE_OPENING_WORKER_ARGS.iter().map(|arg| arg.to_string()).collect()),
+		..Default::default()
+	});
+
+	match adder::validate_good_on_parent(&pool) {
+		Err(Error::External(ref message)) if message.contains("signal") => {},
+		r => panic!("{:?}", r),
+	}
+}
+
+#[test]
+fn worker_exceeding_cpu_time_is_killed() {
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		num_workers: 1,
+		execution_timeout: Duration::from_secs(60),
+		sandbox: SandboxConfig {
+			max_cpu_time_secs: Some(1),
+			..Default::default()
+		},
+		..Default::default()
+	});
+
+	let result = parachain::wasm_executor::validate_candidate(
+		halt::WASM_BINARY,
+		ValidationParams {
+			block_data: BlockData(Vec::new()),
+			parent_head: Default::default(),
+			max_code_size: 1024,
+			max_head_data_size: 1024,
+			relay_chain_height: 1,
+			code_upgrade_allowed: None,
+		},
+		ExecutionMode::RemoteTest(&pool),
+	);
+	match result {
+		Err(Error::External(ref message)) if message.contains("signal") => {},
+		r => panic!("{:?}", r),
+	}
+
+	// a new worker is started for the next candidate.
+	adder::validate_good_on_parent(&pool).unwrap();
+}
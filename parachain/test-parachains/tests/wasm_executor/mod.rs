@@ -18,16 +18,17 @@
 
 use crate::adder;
 use parachain::{
-	primitives::{BlockData, ValidationParams},
-	wasm_executor::EXECUTION_TIMEOUT_SEC,
+	primitives::{BlockData, ValidationParams, MAX_CODE_MEM},
+	wasm_executor::{EXECUTION_TIMEOUT_SEC, ValidationPool, ValidationPoolConfig},
 };
+use std::time::{Duration, Instant};
 
 // Code that exposes `validate_block` and loops infinitely
 const INFINITE_LOOP_CODE: &[u8] = halt::WASM_BINARY;
 
 #[test]
 fn terminates_on_timeout() {
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	let result = parachain::wasm_executor::validate_candidate(
 		INFINITE_LOOP_CODE,
@@ -52,7 +53,7 @@ fn terminates_on_timeout() {
 
 #[test]
 fn parallel_execution() {
-	let pool = parachain::wasm_executor::ValidationPool::new();
+	let pool = parachain::wasm_executor::ValidationPool::new(Default::default());
 
 	let start = std::time::Instant::now();
 
@@ -89,3 +90,127 @@ fn parallel_execution() {
 		< std::time::Duration::from_secs(EXECUTION_TIMEOUT_SEC * 2)
 	);
 }
+
+#[test]
+fn precheck_accepts_valid_code() {
+	assert!(parachain::wasm_executor::precheck_code(::adder::WASM_BINARY, MAX_CODE_MEM).is_ok());
+	assert!(parachain::wasm_executor::precheck_code(INFINITE_LOOP_CODE, MAX_CODE_MEM).is_ok());
+}
+
+#[test]
+fn precheck_rejects_invalid_code() {
+	match parachain::wasm_executor::precheck_code(&[0, 1, 2, 3], MAX_CODE_MEM) {
+		Err(parachain::wasm_executor::Error::InvalidCode(_)) => {},
+		r => panic!("{:?}", r),
+	}
+}
+
+#[test]
+fn precheck_rejects_oversized_code() {
+	let code = ::adder::WASM_BINARY;
+	match parachain::wasm_executor::precheck_code(code, code.len() - 1) {
+		Err(parachain::wasm_executor::Error::CodeTooLarge(size, _)) if size == code.len() => {},
+		r => panic!("{:?}", r),
+	}
+}
+
+fn validate_infinite_loop(pool: &ValidationPool) -> Result<(), parachain::wasm_executor::Error> {
+	parachain::wasm_executor::validate_candidate(
+		INFINITE_LOOP_CODE,
+		ValidationParams {
+			block_data: BlockData(Vec::new()),
+			parent_head: Default::default(),
+			max_code_size: 1024,
+			max_head_data_size: 1024,
+			relay_chain_height: 1,
+			code_upgrade_allowed: None,
+		},
+		parachain::wasm_executor::ExecutionMode::RemoteTest(pool),
+	).map(|_| ())
+}
+
+fn single_worker_config(execution_timeout: Duration) -> ValidationPoolConfig {
+	ValidationPoolConfig {
+		num_workers: 1,
+		execution_timeout,
+		..Default::default()
+	}
+}
+
+#[test]
+fn terminates_on_configured_timeout() {
+	let timeout = Duration::from_secs(3);
+	let pool = ValidationPool::new(single_worker_config(timeout));
+
+	let start = Instant::now();
+	match validate_infinite_loop(&pool) {
+		Err(parachain::wasm_executor::Error::Timeout) => {},
+		r => panic!("{:?}", r),
+	}
+
+	// the worker startup is bounded by the same timeout.
+	let elapsed = Instant::now().duration_since(start);
+	assert!(elapsed >= timeout);
+	assert!(elapsed < timeout * 3);
+}
+
+#[test]
+fn respawns_worker_after_timeout() {
+	let pool = ValidationPool::new(single_worker_config(Duration::from_secs(3)));
+
+	match validate_infinite_loop(&pool) {
+		Err(parachain::wasm_executor::Error::Timeout) => {},
+		r => panic!("{:?}", r),
+	}
+
+	// the only worker was killed and is started again.
+	adder::validate_good_on_parent(&pool).unwrap();
+}
+
+#[test]
+fn does_not_respawn_worker_if_disabled() {
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		respawn_workers: false,
+		..single_worker_config(Duration::from_secs(3))
+	});
+
+	adder::validate_good_on_parent(&pool).unwrap();
+
+	match validate_infinite_loop(&pool) {
+		Err(parachain::wasm_executor::Error::Timeout) => {},
+		r => panic!("{:?}", r),
+	}
+
+	match adder::validate_good_on_parent(&pool) {
+		Err(parachain::wasm_executor::Error::WorkerUnavailable) => {},
+		r => panic!("{:?}", r),
+	}
+}
+
+#[test]
+fn rejects_code_above_configured_size() {
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		max_code_size: 1024,
+		..Default::default()
+	});
+
+	match adder::validate_good_on_parent(&pool) {
+		Err(parachain::wasm_executor::Error::CodeTooLarge(size, 1024)) => {
+			assert_eq!(size, ::adder::WASM_BINARY.len());
+		},
+		r => panic!("{:?}", r),
+	}
+}
+
+#[test]
+fn rejects_params_above_configured_size() {
+	let pool = ValidationPool::new(ValidationPoolConfig {
+		max_params_size: 16,
+		..Default::default()
+	});
+
+	match adder::validate_good_on_parent(&pool) {
+		Err(parachain::wasm_executor::Error::ParamsTooLarge(_, 16)) => {},
+		r => panic!("{:?}", r),
+	}
+}
@@ -924,6 +924,8 @@ pub enum ValidityError {
 	NoPermission = 2,
 	/// An invalid statement was made for a claim.
 	InvalidStatement = 3,
+	/// The native signature is invalid.
+	InvalidNativeSignature = 4,
 }
 
 impl From<ValidityError> for u8 {
@@ -20,6 +20,9 @@ use sp_std::prelude::*;
 use parity_scale_codec::{Encode, Decode};
 use bitvec::vec::BitVec;
 
+#[cfg(feature = "std")]
+use serde::{Serialize, Deserialize};
+
 use primitives::RuntimeDebug;
 use runtime_primitives::traits::AppVerify;
 use inherents::InherentIdentifier;
@@ -37,7 +40,7 @@ pub use polkadot_core_primitives::v1::{
 // Export some polkadot-parachain primitives
 pub use polkadot_parachain::primitives::{
 	Id, ParachainDispatchOrigin, LOWEST_USER_ID, UpwardMessage, HeadData, BlockData,
-	ValidationCode,
+	ValidationCode, MAX_CODE_MEM,
 };
 
 // Export some basic parachain primitives from v0.
@@ -239,6 +242,9 @@ pub struct GlobalValidationSchedule<N = BlockNumber> {
 	pub max_head_data_size: u32,
 	/// The relay-chain block number this is in the context of.
 	pub block_number: N,
+	/// The maximum number of instructions a candidate may execute during validation. If zero,
+	/// validation is not metered.
+	pub max_validation_fuel: u64,
 }
 
 /// Commitments made in a `CandidateReceipt`. Many of these are outputs of validation.
@@ -621,6 +627,182 @@ pub enum CandidateEvent<H = Hash> {
 	CandidateTimedOut(CandidateReceipt<H>, HeadData),
 }
 
+/// A validator's approval of a candidate, issued after a secondary check of the candidate
+/// in the acceptance period.
+#[derive(Clone, Encode, Decode, RuntimeDebug)]
+pub struct ApprovalVote(pub Hash);
+
+impl ApprovalVote {
+	/// Yields the payload to be signed by the approving validator. Approvals are only valid
+	/// for the session in which the candidate was included.
+	pub fn signing_payload(&self, session_index: SessionIndex) -> Vec<u8> {
+		const MAGIC: [u8; 4] = *b"APPR";
+
+		(MAGIC, &self.0, session_index).encode()
+	}
+}
+
+/// Compute the delay tranche in which a validator is assigned to check a candidate.
+///
+/// The assignment is derived from the assignment seed recorded when the candidate was included,
+/// so validators cannot choose their assignments. The seed is a hash of the session randomness and
+/// the parent of the including block, not a VRF output, so assignments can be computed by anyone
+/// as soon as that parent is known, before the candidate is included.
+///
+/// Tranche `t` becomes eligible to approve `t` blocks after inclusion. If `n_delay_tranches` is
+/// `0` or `1`, every validator is assigned to tranche `0`.
+pub fn approval_delay_tranche(
+	assignment_seed: &[u8; 32],
+	candidate_hash: &Hash,
+	validator_index: ValidatorIndex,
+	n_delay_tranches: u32,
+) -> u32 {
+	const MAGIC: [u8; 4] = *b"TRNC";
+
+	if n_delay_tranches <= 1 { return 0 }
+
+	let hash = BlakeTwo256::hash_of(&(MAGIC, assignment_seed, candidate_hash, validator_index));
+	let mut tranche_bytes = [0u8; 4];
+	tranche_bytes.copy_from_slice(&hash.as_ref()[..4]);
+
+	u32::from_le_bytes(tranche_bytes) % n_delay_tranches
+}
+
+/// A statement about the validity of an included candidate, issued by a validator during a dispute.
+#[derive(Clone, Copy, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+pub enum DisputeStatement {
+	/// The candidate is valid.
+	#[codec(index = "0")]
+	Valid,
+	/// The candidate is invalid.
+	#[codec(index = "1")]
+	Invalid,
+}
+
+impl DisputeStatement {
+	/// Yields the payload to be signed by the validator issuing the statement. Statements are only
+	/// valid for the session in which the candidate was included.
+	pub fn signing_payload(&self, candidate_hash: &Hash, session_index: SessionIndex) -> Vec<u8> {
+		const MAGIC: [u8; 4] = *b"DISP";
+
+		(MAGIC, self, candidate_hash, session_index).encode()
+	}
+}
+
+/// A dispute statement signed by a validator.
+#[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+pub struct SignedDisputeStatement {
+	/// The statement.
+	pub statement: DisputeStatement,
+	/// The index of the validator in the validator set of the candidate's session.
+	pub validator_index: ValidatorIndex,
+	/// The signature of the validator on the statement's signing payload.
+	pub signature: ValidatorSignature,
+}
+
+/// A statement by a validator on whether the new validation code of a para compiles, issued
+/// while a code upgrade is being pre-checked.
+#[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+pub struct PvfCheckStatement {
+	/// Whether the validator was able to compile the code.
+	pub accept: bool,
+	/// The hash of the validation code being checked.
+	pub code_hash: Hash,
+	/// The para the code upgrade is for.
+	pub para_id: Id,
+	/// The session the statement is issued in. Statements are only valid for this session.
+	pub session_index: SessionIndex,
+	/// The index of the validator in the validator set of the session.
+	pub validator_index: ValidatorIndex,
+}
+
+impl PvfCheckStatement {
+	/// Yields the payload to be signed by the validator issuing the statement.
+	pub fn signing_payload(&self) -> Vec<u8> {
+		const MAGIC: [u8; 4] = *b"VCPC";
+
+		(MAGIC, self).encode()
+	}
+}
+
+/// A candidate included in this fork of the relay chain which has not yet been approved
+/// by secondary checkers.
+#[derive(Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(PartialEq, Debug))]
+pub struct PendingApproval<N = BlockNumber> {
+	/// The hash of the candidate receipt.
+	pub candidate_hash: Hash,
+	/// The ID of the para the candidate is for.
+	pub para_id: Id,
+	/// The session the candidate was included in. Only validators of this session may approve it.
+	pub session_index: SessionIndex,
+	/// The relay-chain block number the candidate was included at.
+	pub included_at: N,
+	/// The seed used to compute approval assignments for the candidate.
+	pub assignment_seed: [u8; 32],
+	/// The number of approvals gathered so far.
+	pub approvals: u32,
+	/// The number of approvals needed for the candidate to be approved.
+	pub needed_approvals: u32,
+	/// The number of delay tranches approval assignments are spread over.
+	pub n_delay_tranches: u32,
+}
+
+/// All configuration of the runtime with respect to parachains and parathreads.
+#[derive(Clone, Encode, Decode, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub struct HostConfiguration<N = BlockNumber> {
+	/// The minimum frequency at which parachains can update their validation code.
+	pub validation_upgrade_frequency: N,
+	/// The delay, in blocks, before a validation upgrade is applied.
+	pub validation_upgrade_delay: N,
+	/// The acceptance period, in blocks. This is the amount of blocks after availability that validators
+	/// and fishermen have to perform secondary checks or issue reports.
+	pub acceptance_period: N,
+	/// The maximum validation code size, in bytes.
+	pub max_code_size: u32,
+	/// The maximum head-data size, in bytes.
+	pub max_head_data_size: u32,
+	/// The amount of execution cores to dedicate to parathread execution.
+	pub parathread_cores: u32,
+	/// The number of retries that a parathread author has to submit their block.
+	pub parathread_retries: u32,
+	/// How often parachain groups should be rotated across parachains. Must be non-zero.
+	pub parachain_rotation_frequency: N,
+	/// The availability period, in blocks, for parachains. This is the amount of blocks
+	/// after inclusion that validators have to make the block available and signal its availability to
+	/// the chain. Must be at least 1.
+	pub chain_availability_period: N,
+	/// The availability period, in blocks, for parathreads. Same as the `chain_availability_period`,
+	/// but a differing timeout due to differing requirements. Must be at least 1.
+	pub thread_availability_period: N,
+	/// The amount of blocks ahead to schedule parachains and parathreads.
+	pub scheduling_lookahead: u32,
+	/// The number of approval votes needed for an included candidate to be approved. Must be
+	/// non-zero.
+	pub needed_approvals: u32,
+	/// The number of delay tranches approval checkers are assigned to. Tranche `t` may approve
+	/// a candidate `t` blocks after its inclusion. Must be at least 1.
+	pub n_delay_tranches: u32,
+	/// The number of sessions a code upgrade may spend being pre-checked by validators before it
+	/// is rejected. If zero, code upgrades are scheduled without pre-checking. Otherwise, must be
+	/// at least 2.
+	pub pvf_checking_ttl: u32,
+	/// The maximum number of instructions a candidate may execute during validation. Must be
+	/// non-zero.
+	pub max_validation_fuel: u64,
+}
+
+/// The active host configuration along with the configuration pending for the next session, if any.
+#[derive(Clone, Encode, Decode, PartialEq)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub struct HostConfigurationInfo<N = BlockNumber> {
+	/// The configuration of the current session.
+	pub active: HostConfiguration<N>,
+	/// The configuration which will be activated at the start of the given session, if any.
+	pub pending: Option<(SessionIndex, HostConfiguration<N>)>,
+}
+
 sp_api::decl_runtime_apis! {
 	/// The API for querying the state of parachains on-chain.
 	pub trait ParachainHost<H: Decode, N: Decode> {
@@ -660,6 +842,11 @@ sp_api::decl_runtime_apis! {
 		fn validation_code(para_id: Id, assumption: OccupiedCoreAssumption)
 			-> Option<ValidationCode>;
 
+		/// Get the validation code from its hash, if it is still stored on-chain.
+		///
+		/// Both current and past code of paras can be fetched this way.
+		fn validation_code_by_hash(hash: Hash) -> Option<ValidationCode>;
+
 		/// Get the receipt of a candidate pending availability. This returns `Some` for any paras
 		/// assigned to occupied cores in `availability_cores` and `None` otherwise.
 		fn candidate_pending_availability(para_id: Id) -> Option<CommittedCandidateReceipt<H>>;
@@ -669,6 +856,51 @@ sp_api::decl_runtime_apis! {
 		// initialization.
 		#[skip_initialize_block]
 		fn candidate_events() -> Vec<CandidateEvent<H>>;
+
+		/// Get all candidates included in this fork of the relay chain which are still awaiting
+		/// approval by secondary checkers.
+		fn pending_approvals() -> Vec<PendingApproval<N>>;
+
+		/// Get the number of the oldest relay-chain block which included a candidate that has not
+		/// yet been approved, if any.
+		///
+		/// Neither this block nor any of its descendants should be finalized until the candidate
+		/// has been approved.
+		fn oldest_unapproved_inclusion() -> Option<N>;
+
+		/// Get the number of the oldest relay-chain block which included a candidate that was
+		/// found to be invalid, if any.
+		///
+		/// Neither this block nor any of its descendants should be built upon or finalized.
+		fn oldest_invalid_inclusion() -> Option<N>;
+
+		/// Get the number of the oldest relay-chain block which backed a candidate that then timed
+		/// out while pending availability, if any.
+		///
+		/// Neither this block nor any of its descendants should be built upon.
+		fn oldest_unavailable_inclusion() -> Option<N>;
+
+		/// Get the active host configuration and the configuration pending for the next
+		/// session, if any.
+		fn host_configuration() -> HostConfigurationInfo<N>;
+
+		/// Get the paras with a code upgrade which is being pre-checked by validators, along with
+		/// the hash of the new validation code.
+		fn pending_pvf_checks() -> Vec<(Id, Hash)>;
+
+		/// Get the new validation code of a para whose code upgrade is being pre-checked, if any.
+		fn pending_pvf_check_code(para_id: Id) -> Option<ValidationCode>;
+
+		/// Get the paras whose code upgrade was scheduled within a block, along with the new
+		/// validation code.
+		// NOTE: this needs to skip block initialization as events are wiped within block
+		// initialization.
+		#[skip_initialize_block]
+		fn scheduled_code_upgrades() -> Vec<(Id, ValidationCode)>;
+
+		/// Submit a validator's signed statement on a pending pre-check as an unsigned
+		/// transaction.
+		fn submit_pvf_check_statement(statement: PvfCheckStatement, signature: ValidatorSignature);
 	}
 }
 
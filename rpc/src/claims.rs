@@ -0,0 +1,97 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! RPCs for preparing claims of DOTs.
+//!
+//! These require the runtime to implement the `ClaimsApi` runtime API.
+
+use std::sync::Arc;
+
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use polkadot_primitives::v0::{AccountId, Block, BlockId, Hash};
+use polkadot_runtime_common::claims::{ClaimsApi as ClaimsRuntimeApi, StatementKind};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+
+/// Error code for errors returned by the runtime API.
+const RUNTIME_ERROR: i64 = 1;
+/// Error code for chains whose runtime has no claims.
+const NO_CLAIMS_ERROR: i64 = 2;
+
+/// Claims RPC methods.
+#[rpc]
+pub trait ClaimsApi<BlockHash, AccountId> {
+	/// Get the exact payload a claimant must sign to have its claim paid out to `dest`, given
+	/// the statement kind its claim requires, at the given block or at the best block if none is
+	/// given.
+	///
+	/// Native claimants sign the payload as is, while Ethereum claimants sign it as an Ethereum
+	/// signed message, e.g. with `personal_sign`.
+	#[rpc(name = "claims_payload")]
+	fn claim_payload(
+		&self,
+		dest: AccountId,
+		statement: Option<StatementKind>,
+		at: Option<BlockHash>,
+	) -> Result<Bytes>;
+}
+
+/// An implementation of the claims RPC methods.
+pub struct Claims<C> {
+	client: Arc<C>,
+}
+
+impl<C> Claims<C> {
+	/// Create a new `Claims` RPC handler backed by the given client.
+	pub fn new(client: Arc<C>) -> Self {
+		Claims { client }
+	}
+}
+
+impl<C> ClaimsApi<Hash, AccountId> for Claims<C> where
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+	C::Api: ClaimsRuntimeApi<Block, AccountId>,
+{
+	fn claim_payload(
+		&self,
+		dest: AccountId,
+		statement: Option<StatementKind>,
+		at: Option<Hash>,
+	) -> Result<Bytes> {
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+		let payload = self.client.runtime_api()
+			.claim_payload(&at, dest, statement)
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(RUNTIME_ERROR),
+				message: "Unable to query the claim payload.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})?;
+
+		// Runtimes without claims return an empty payload.
+		if payload.is_empty() {
+			return Err(RpcError {
+				code: ErrorCode::ServerError(NO_CLAIMS_ERROR),
+				message: "This chain has no claims.".into(),
+				data: None,
+			});
+		}
+
+		Ok(payload.into())
+	}
+}
@@ -26,11 +26,14 @@ use txpool_api::TransactionPool;
 use sp_blockchain::{HeaderBackend, HeaderMetadata, Error as BlockChainError};
 use sp_consensus::SelectChain;
 use sp_consensus_babe::BabeApi;
-use sc_client_api::light::{Fetcher, RemoteBlockchain};
+use sc_client_api::{ExecutorProvider, light::{Fetcher, RemoteBlockchain}};
 use sc_consensus_babe::Epoch;
 use sc_rpc::DenyUnsafe;
 use sp_block_builder::BlockBuilder;
 
+pub mod claims;
+pub mod parachains;
+
 /// A type representing all RPC extensions.
 pub type RpcExtension = jsonrpc_core::IoHandler<sc_rpc::Metadata>;
 
@@ -84,17 +87,21 @@ pub struct FullDeps<C, P, SC> {
 pub fn create_full<C, P, UE, SC>(deps: FullDeps<C, P, SC>) -> RpcExtension where
 	C: ProvideRuntimeApi<Block>,
 	C: HeaderBackend<Block> + HeaderMetadata<Block, Error=BlockChainError>,
+	C: ExecutorProvider<Block>,
 	C: Send + Sync + 'static,
 	C::Api: frame_rpc_system::AccountNonceApi<Block, AccountId, Nonce>,
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance, UE>,
 	C::Api: BabeApi<Block>,
 	C::Api: BlockBuilder<Block>,
+	C::Api: polkadot_runtime_common::claims::ClaimsApi<Block, AccountId>,
 	P: TransactionPool + Sync + Send + 'static,
 	UE: codec::Codec + Send + Sync + 'static,
 	SC: SelectChain<Block> + 'static,
 {
 	use frame_rpc_system::{FullSystem, SystemApi};
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
+	use claims::{Claims, ClaimsApi};
+	use parachains::{Parachains, ParachainsApi};
 	use sc_finality_grandpa_rpc::{GrandpaApi, GrandpaRpcHandler};
 	use sc_consensus_babe_rpc::BabeRpcHandler;
 
@@ -123,6 +130,12 @@ pub fn create_full<C, P, UE, SC>(deps: FullDeps<C, P, SC>) -> RpcExtension where
 	io.extend_with(
 		TransactionPaymentApi::to_delegate(TransactionPayment::new(client.clone()))
 	);
+	io.extend_with(
+		ClaimsApi::to_delegate(Claims::new(client.clone()))
+	);
+	io.extend_with(
+		ParachainsApi::to_delegate(Parachains::new(client.clone()))
+	);
 	io.extend_with(
 		sc_consensus_babe_rpc::BabeApi::to_delegate(
 			BabeRpcHandler::new(
@@ -0,0 +1,137 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! RPCs for querying the parachains state of the relay chain.
+//!
+//! These require the runtime to implement the v1 `ParachainHost` runtime API. As it shares its
+//! name with the v0 API that the live runtimes implement, it cannot be required of the client at
+//! compile time. The runtime API is called by name instead, and fails on runtimes which do not
+//! implement it.
+
+use std::sync::Arc;
+
+use codec::Decode;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use polkadot_primitives::v1::{Block, BlockId, BlockNumber, Hash, HostConfigurationInfo};
+use sc_client_api::{CallExecutor, ExecutionStrategy, ExecutorProvider};
+use sp_blockchain::HeaderBackend;
+
+/// Error code for errors returned by the runtime API.
+const RUNTIME_ERROR: i64 = 1;
+
+/// Parachains RPC methods.
+#[rpc]
+pub trait ParachainsApi<BlockHash, N> {
+	/// Get the active host configuration and the configuration pending for the next session, if
+	/// any, at the given block or at the best block if none is given.
+	#[rpc(name = "parachains_hostConfiguration")]
+	fn host_configuration(&self, at: Option<BlockHash>) -> Result<HostConfigurationInfo<N>>;
+}
+
+/// An implementation of the parachains RPC methods.
+pub struct Parachains<C> {
+	client: Arc<C>,
+}
+
+impl<C> Parachains<C> {
+	/// Create a new `Parachains` RPC handler backed by the given client.
+	pub fn new(client: Arc<C>) -> Self {
+		Parachains { client }
+	}
+}
+
+impl<C> ParachainsApi<Hash, BlockNumber> for Parachains<C> where
+	C: ExecutorProvider<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+{
+	fn host_configuration(&self, at: Option<Hash>) -> Result<HostConfigurationInfo<BlockNumber>> {
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+		let response = self.client.executor().call(
+			&at,
+			"ParachainHost_host_configuration",
+			&[],
+			ExecutionStrategy::NativeElseWasm,
+			None,
+		);
+
+		decode_response(response, "Unable to query the host configuration.")
+	}
+}
+
+// Decode the response of a runtime API call, or turn its failure into an RPC error.
+fn decode_response<R: Decode>(
+	response: std::result::Result<Vec<u8>, sp_blockchain::Error>,
+	message: &str,
+) -> Result<R> {
+	let runtime_error = |data: String| RpcError {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: message.into(),
+		data: Some(data.into()),
+	};
+
+	let encoded = response.map_err(|e| runtime_error(format!("{:?}", e)))?;
+	R::decode(&mut &encoded[..]).map_err(|e| runtime_error(format!("{:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use codec::Encode;
+	use polkadot_primitives::v1::HostConfiguration;
+
+	#[test]
+	fn host_configuration_is_decoded() {
+		let info = HostConfigurationInfo {
+			active: HostConfiguration {
+				max_code_size: 1024,
+				..Default::default()
+			},
+			pending: Some((5, HostConfiguration {
+				max_code_size: 2048,
+				..Default::default()
+			})),
+		};
+
+		let decoded: HostConfigurationInfo<BlockNumber> =
+			decode_response(Ok(info.encode()), "").unwrap();
+		assert_eq!(decoded, info);
+
+		let error = decode_response::<HostConfigurationInfo<BlockNumber>>(Ok(vec![1]), "")
+			.unwrap_err();
+		assert_eq!(error.code, ErrorCode::ServerError(RUNTIME_ERROR));
+	}
+
+	#[test]
+	fn host_configuration_is_served_over_json_rpc() {
+		// the test runtime does not implement the v1 `ParachainHost` API.
+		let client = Arc::new(polkadot_test_runtime_client::new());
+
+		let mut io = jsonrpc_core::IoHandler::new();
+		io.extend_with(ParachainsApi::to_delegate(Parachains::new(client)));
+
+		let request = concat!(
+			r#"{"jsonrpc":"2.0","method":"parachains_hostConfiguration","#,
+			r#""params":[],"id":1}"#,
+		);
+		let response = io.handle_request_sync(request).expect("method is registered; qed");
+
+		let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+		assert_eq!(response["error"]["code"], RUNTIME_ERROR);
+		assert_eq!(response["error"]["message"], "Unable to query the host configuration.");
+	}
+}
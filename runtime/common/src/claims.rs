@@ -20,7 +20,8 @@ use sp_std::{prelude::*, fmt::Debug};
 use sp_io::{hashing::keccak_256, crypto::secp256k1_ecdsa_recover};
 use frame_support::{
 	decl_event, decl_storage, decl_module, decl_error, ensure, dispatch::IsSubType,
-	traits::{Currency, Get, VestingSchedule, EnsureOrigin}, weights::{Pays, DispatchClass}
+	traits::{Currency, Get, VestingSchedule, EnsureOrigin}, weights::{Pays, DispatchClass, Weight},
+	Parameter,
 };
 use system::{ensure_signed, ensure_root, ensure_none};
 use codec::{Encode, Decode};
@@ -29,7 +30,8 @@ use serde::{self, Serialize, Deserialize, Serializer, Deserializer};
 #[cfg(feature = "std")]
 use sp_runtime::traits::Zero;
 use sp_runtime::{
-	traits::{CheckedSub, SignedExtension, DispatchInfoOf}, RuntimeDebug, DispatchResult,
+	traits::{CheckedSub, SignedExtension, DispatchInfoOf, IdentifyAccount, Verify},
+	RuntimeDebug, DispatchResult, DispatchError,
 	transaction_validity::{
 		TransactionLongevity, TransactionValidity, ValidTransaction, InvalidTransaction,
 		TransactionSource, TransactionValidityError,
@@ -47,6 +49,13 @@ pub trait Trait: system::Trait {
 	type VestingSchedule: VestingSchedule<Self::AccountId, Moment=Self::BlockNumber>;
 	type Prefix: Get<&'static [u8]>;
 	type MoveClaimOrigin: EnsureOrigin<Self::Origin>;
+	/// The identity of a native claimant, which signs for its claim; e.g. `MultiSigner`.
+	type Signer: IdentifyAccount<AccountId = Self::AccountId>;
+	/// The signature of a native claimant over its claim payload; e.g. `MultiSignature`, which
+	/// covers sr25519 and ed25519 keys.
+	type Signature: Verify<Signer = Self::Signer> + Parameter;
+	/// The maximum number of claims in a single `claim_many` batch.
+	type MaxClaimsPerBatch: Get<u32>;
 }
 
 /// The kind of a statement an account needs to make for a claim to be valid.
@@ -61,7 +70,7 @@ pub enum StatementKind {
 
 impl StatementKind {
 	/// Convert this to the (English) statement it represents.
-	fn to_text(self) -> &'static [u8] {
+	pub fn to_text(self) -> &'static [u8] {
 		match self {
 			StatementKind::Regular =>
 				&b"I hereby agree to the terms of the statement whose SHA-256 multihash is \
@@ -112,6 +121,17 @@ impl<'de> Deserialize<'de> for EthereumAddress {
 	}
 }
 
+/// A claim pre-assigned to a native account, which signs for it instead of an Ethereum address.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct NativeClaim<Balance, BlockNumber> {
+	/// The amount to be claimed.
+	pub value: Balance,
+	/// The vesting schedule of the claim, if any; see `Vesting`.
+	pub vesting: Option<(Balance, Balance, BlockNumber)>,
+	/// The statement kind that must be signed, if any.
+	pub statement: Option<StatementKind>,
+}
+
 #[derive(Encode, Decode, Clone)]
 pub struct EcdsaSignature(pub [u8; 65]);
 
@@ -134,6 +154,10 @@ decl_event!(
 	{
 		/// Someone claimed some DOTs.
 		Claimed(AccountId, EthereumAddress, Balance),
+		/// Someone claimed some DOTs pre-assigned to a native account. \[dest, claimant, balance\]
+		NativeClaimed(AccountId, AccountId, Balance),
+		/// A batch of claims was processed, with the result of each item in order.
+		ClaimedMany(Vec<DispatchResult>),
 	}
 );
 
@@ -152,6 +176,12 @@ decl_error! {
 		InvalidStatement,
 		/// The account already has a vested balance.
 		VestedBalanceExists,
+		/// Invalid native signature.
+		InvalidNativeSignature,
+		/// A batch of claims must not be empty.
+		EmptyBatch,
+		/// A batch of claims must not contain more than `MaxClaimsPerBatch` claims.
+		BatchTooLarge,
 	}
 }
 
@@ -187,6 +217,11 @@ decl_storage! {
 				.filter_map(|(a, _, i, _)| Some((i.clone()?, a.clone())))
 				.collect::<Vec<_>>()
 		}): map hasher(identity) T::AccountId => Option<EthereumAddress>;
+
+		/// Claims pre-assigned to native accounts, which sign for them with their own key.
+		NativeClaims get(fn native_claims):
+			map hasher(blake2_128_concat) T::AccountId
+			=> Option<NativeClaim<BalanceOf<T>, T::BlockNumber>>;
 	}
 	add_extra_genesis {
 		config(claims): Vec<(EthereumAddress, BalanceOf<T>, Option<T::AccountId>, Option<StatementKind>)>;
@@ -200,6 +235,9 @@ decl_module! {
 		/// The Prefix that is used in signed Ethereum messages for this network
 		const Prefix: &[u8] = T::Prefix::get();
 
+		/// The maximum number of claims in a single `claim_many` batch.
+		const MaxClaimsPerBatch: u32 = T::MaxClaimsPerBatch::get();
+
 		/// Deposit one of this module's events by using the default implementation.
 		fn deposit_event() = default;
 
@@ -412,6 +450,108 @@ decl_module! {
 				if maybe_o.as_ref().map_or(false, |o| o == &old) { *maybe_o = Some(new) }
 			));
 		}
+
+		/// Make a batch of claims, e.g. on behalf of a custodian holding many Ethereum addresses.
+		///
+		/// The dispatch origin for this call must be _None_.
+		///
+		/// Unsigned Validation:
+		/// A call to `claim_many` is deemed valid if every item would be a valid `claim_attest`,
+		/// with an empty `statement` standing for a claim which needs no statement.
+		///
+		/// Parameters:
+		/// - `claims`: The destination account, signature and statement of each claim, as for
+		///   `claim_attest`. At most `MaxClaimsPerBatch`.
+		///
+		/// Items are processed independently: the result of each is reported in order in the
+		/// `ClaimedMany` event, and a failing item does not revert the others.
+		///
+		/// <weight>
+		/// The weight of one `claim_attest` per item, of which there are at most
+		/// `MaxClaimsPerBatch`.
+		/// </weight>
+		#[weight = (T::DbWeight::get().reads_writes(7, 7) + 270_000_000 + 190_000_000)
+			.saturating_mul(claims.len() as Weight)
+		]
+		fn claim_many(origin, claims: Vec<(T::AccountId, EcdsaSignature, Vec<u8>)>) {
+			ensure_none(origin)?;
+			ensure!(!claims.is_empty(), Error::<T>::EmptyBatch);
+			ensure!(
+				claims.len() <= T::MaxClaimsPerBatch::get() as usize,
+				Error::<T>::BatchTooLarge,
+			);
+
+			let results = claims.into_iter()
+				.map(|(dest, ethereum_signature, statement)| {
+					let signer = Self::checked_signer(&dest, &ethereum_signature, &statement)?;
+					Self::process_claim(signer, dest)
+				})
+				.collect();
+			Self::deposit_event(RawEvent::ClaimedMany(results));
+		}
+
+		/// Mint a new claim to collect DOTs, pre-assigned to a native account.
+		///
+		/// The dispatch origin for this call must be _Root_.
+		///
+		/// Parameters:
+		/// - `who`: The native account allowed to collect this claim, by signing for it.
+		/// - `value`: The number of DOTs that will be claimed.
+		/// - `vesting_schedule`: An optional vesting schedule for these DOTs.
+		/// - `statement`: The statement kind that must be signed, if any.
+		///
+		/// <weight>
+		/// The weight of this call is invariant over the input parameters.
+		/// - One storage mutate to increase the total claims available.
+		/// - One storage write to add a new claim.
+		/// </weight>
+		#[weight = T::DbWeight::get().reads_writes(1, 2) + 10_000_000]
+		fn mint_native_claim(origin,
+			who: T::AccountId,
+			value: BalanceOf<T>,
+			vesting_schedule: Option<(BalanceOf<T>, BalanceOf<T>, T::BlockNumber)>,
+			statement: Option<StatementKind>,
+		) {
+			ensure_root(origin)?;
+
+			<Total<T>>::mutate(|t| *t += value);
+			<NativeClaims<T>>::insert(who, NativeClaim { value, vesting: vesting_schedule, statement });
+		}
+
+		/// Make a claim pre-assigned to a native account, signed with its own key.
+		///
+		/// The dispatch origin for this call must be _None_.
+		///
+		/// Unsigned Validation:
+		/// A call to `claim_native` is deemed valid if `who` has a native claim and `signature`
+		/// is its signature of the payload given by `claim_payload` for `dest` and the claim's
+		/// statement:
+		///
+		/// > (configured prefix string)(address)(statement)
+		///
+		/// Parameters:
+		/// - `who`: The native account holding the claim.
+		/// - `dest`: The destination account to payout the claim.
+		/// - `signature`: The signature of `who` over the payload described above.
+		///
+		/// <weight>
+		/// The weight of this call is invariant over the input parameters.
+		/// - One signature verification.
+		/// - As `claim`, otherwise.
+		/// </weight>
+		#[weight = T::DbWeight::get().reads_writes(6, 5) + 270_000_000 + 190_000_000]
+		fn claim_native(origin, who: T::AccountId, dest: T::AccountId, signature: T::Signature) {
+			ensure_none(origin)?;
+
+			let claim = <NativeClaims<T>>::get(&who).ok_or(Error::<T>::SignerHasNoClaim)?;
+			let payload = Self::claim_payload(&dest, claim.statement);
+			ensure!(signature.verify(&payload[..], &who), Error::<T>::InvalidNativeSignature);
+
+			Self::payout(&dest, claim.value, claim.vesting)?;
+			<NativeClaims<T>>::remove(&who);
+
+			Self::deposit_event(RawEvent::NativeClaimed(dest, who, claim.value));
+		}
 	}
 }
 
@@ -453,29 +593,69 @@ impl<T: Trait> Module<T> {
 		Some(res)
 	}
 
-	fn process_claim(signer: EthereumAddress, dest: T::AccountId) -> DispatchResult {
-		let balance_due = <Claims<T>>::get(&signer)
-			.ok_or(Error::<T>::SignerHasNoClaim)?;
-
+	/// The exact payload a claimant signs to have its claim paid out to `dest`, given the
+	/// statement kind its claim requires: the configured prefix, the hex-encoded `dest` and the
+	/// statement text, if any.
+	///
+	/// Native claimants sign this payload as is, while Ethereum claimants sign it as an Ethereum
+	/// signed message.
+	pub fn claim_payload(dest: &T::AccountId, statement: Option<StatementKind>) -> Vec<u8> {
+		let mut payload = T::Prefix::get().to_vec();
+		payload.extend(dest.using_encoded(to_ascii_hex));
+		if let Some(s) = statement {
+			payload.extend_from_slice(s.to_text());
+		}
+		payload
+	}
+
+	// Recovers the signer of an attested claim to `dest`, checking that `statement` is exactly
+	// the one the claim requires; an empty `statement` stands for none.
+	fn checked_signer(
+		dest: &T::AccountId,
+		ethereum_signature: &EcdsaSignature,
+		statement: &[u8],
+	) -> Result<EthereumAddress, DispatchError> {
+		let data = dest.using_encoded(to_ascii_hex);
+		let signer = Self::eth_recover(ethereum_signature, &data, statement)
+			.ok_or(Error::<T>::InvalidEthereumSignature)?;
+		let expected = Signing::get(&signer).map_or(&[][..], |s| s.to_text());
+		ensure!(expected == statement, Error::<T>::InvalidStatement);
+		Ok(signer)
+	}
+
+	// Pays `balance_due` out of the pot to `dest`, along with its vesting schedule, if any.
+	fn payout(
+		dest: &T::AccountId,
+		balance_due: BalanceOf<T>,
+		vesting: Option<(BalanceOf<T>, BalanceOf<T>, T::BlockNumber)>,
+	) -> DispatchResult {
 		let new_total = Self::total().checked_sub(&balance_due).ok_or(Error::<T>::PotUnderflow)?;
 
-		let vesting = Vesting::<T>::get(&signer);
-		if vesting.is_some() && T::VestingSchedule::vesting_balance(&dest).is_some() {
+		if vesting.is_some() && T::VestingSchedule::vesting_balance(dest).is_some() {
 			return Err(Error::<T>::VestedBalanceExists.into())
 		}
 
 		// We first need to deposit the balance to ensure that the account exists.
-		CurrencyOf::<T>::deposit_creating(&dest, balance_due);
+		CurrencyOf::<T>::deposit_creating(dest, balance_due);
 
 		// Check if this claim should have a vesting schedule.
 		if let Some(vs) = vesting {
 			// This can only fail if the account already has a vesting schedule,
 			// but this is checked above.
-			T::VestingSchedule::add_vesting_schedule(&dest, vs.0, vs.1, vs.2)
+			T::VestingSchedule::add_vesting_schedule(dest, vs.0, vs.1, vs.2)
 				.expect("No other vesting schedule exists, as checked above; qed");
 		}
 
 		<Total<T>>::put(new_total);
+		Ok(())
+	}
+
+	fn process_claim(signer: EthereumAddress, dest: T::AccountId) -> DispatchResult {
+		let balance_due = <Claims<T>>::get(&signer)
+			.ok_or(Error::<T>::SignerHasNoClaim)?;
+
+		Self::payout(&dest, balance_due, Vesting::<T>::get(&signer))?;
+
 		<Claims<T>>::remove(&signer);
 		<Vesting<T>>::remove(&signer);
 		Signing::remove(&signer);
@@ -510,6 +690,51 @@ impl<T: Trait> sp_runtime::traits::ValidateUnsigned for Module<T> {
 				let data = account.using_encoded(to_ascii_hex);
 				(Self::eth_recover(&ethereum_signature, &data, &statement), Some(statement.as_slice()))
 			}
+			Call::claim_many(claims) => {
+				ensure!(!claims.is_empty(), InvalidTransaction::Call);
+				ensure!(
+					claims.len() <= T::MaxClaimsPerBatch::get() as usize,
+					InvalidTransaction::ExhaustsResources,
+				);
+				let mut provides = Vec::with_capacity(claims.len());
+				for (account, ethereum_signature, statement) in claims.iter() {
+					let signer = Self::checked_signer(account, ethereum_signature, statement)
+						.map_err(|e| InvalidTransaction::Custom(
+							if e == Error::<T>::InvalidStatement.into() {
+								ValidityError::InvalidStatement
+							} else {
+								ValidityError::InvalidEthereumSignature
+							}.into()
+						))?;
+					let e = InvalidTransaction::Custom(ValidityError::SignerHasNoClaim.into());
+					ensure!(<Claims<T>>::contains_key(&signer), e);
+					provides.push(("claims", signer).encode());
+				}
+
+				return Ok(ValidTransaction {
+					priority: PRIORITY,
+					requires: vec![],
+					provides,
+					longevity: TransactionLongevity::max_value(),
+					propagate: true,
+				})
+			}
+			Call::claim_native(who, dest, signature) => {
+				let e = InvalidTransaction::Custom(ValidityError::SignerHasNoClaim.into());
+				let claim = <NativeClaims<T>>::get(who).ok_or(e)?;
+
+				let payload = Self::claim_payload(dest, claim.statement);
+				let e = InvalidTransaction::Custom(ValidityError::InvalidNativeSignature.into());
+				ensure!(signature.verify(&payload[..], who), e);
+
+				return Ok(ValidTransaction {
+					priority: PRIORITY,
+					requires: vec![],
+					provides: vec![("native_claims", who).encode()],
+					longevity: TransactionLongevity::max_value(),
+					propagate: true,
+				})
+			}
 			_ => return Err(InvalidTransaction::Call.into()),
 		};
 
@@ -602,6 +827,17 @@ impl<T: Trait + Send + Sync> SignedExtension for PrevalidateAttests<T> where
 	}
 }
 
+sp_api::decl_runtime_apis! {
+	/// The API for querying what claimants need to sign.
+	pub trait ClaimsApi<AccountId> where AccountId: codec::Codec {
+		/// The exact payload to sign to have a claim paid out to `dest`, given the statement kind
+		/// the claim requires. See `Module::claim_payload`.
+		///
+		/// Runtimes without claims return an empty payload.
+		fn claim_payload(dest: AccountId, statement: Option<StatementKind>) -> Vec<u8>;
+	}
+}
+
 #[cfg(any(test, feature = "runtime-benchmarks"))]
 mod secp_utils {
 	use super::*;
@@ -636,7 +872,10 @@ mod tests {
 	use codec::Encode;
 	// The testing primitives are very useful for avoiding having to work with signatures
 	// or public keys. `u64` is used as the `AccountId` and no `Signature`s are required.
-	use sp_runtime::{Perbill, traits::{BlakeTwo256, IdentityLookup, Identity}, testing::Header};
+	use sp_runtime::{
+		Perbill, traits::{BlakeTwo256, IdentityLookup, Identity},
+		testing::{Header, TestSignature, UintAuthorityId},
+	};
 	use frame_support::{
 		impl_outer_origin, impl_outer_dispatch, assert_ok, assert_err, assert_noop, parameter_types,
 		ord_parameter_types, weights::{Pays, GetDispatchInfo}, traits::ExistenceRequirement,
@@ -718,6 +957,7 @@ mod tests {
 
 	parameter_types!{
 		pub Prefix: &'static [u8] = b"Pay RUSTs to the TEST account:";
+		pub const MaxClaimsPerBatch: u32 = 5;
 	}
 	ord_parameter_types! {
 		pub const Six: u64 = 6;
@@ -728,6 +968,9 @@ mod tests {
 		type VestingSchedule = Vesting;
 		type Prefix = Prefix;
 		type MoveClaimOrigin = system::EnsureSignedBy<Six, u64>;
+		type Signer = UintAuthorityId;
+		type Signature = TestSignature;
+		type MaxClaimsPerBatch = MaxClaimsPerBatch;
 	}
 	type System = system::Module<Test>;
 	type Balances = balances::Module<Test>;
@@ -1094,6 +1337,192 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn claim_payload_works() {
+		new_test_ext().execute_with(|| {
+			let mut expected = b"Pay RUSTs to the TEST account:".to_vec();
+			expected.extend(to_ascii_hex(&42u64.encode()));
+			assert_eq!(Claims::claim_payload(&42, None), expected);
+			expected.extend_from_slice(StatementKind::Saft.to_text());
+			assert_eq!(Claims::claim_payload(&42, Some(StatementKind::Saft)), expected);
+
+			// Ethereum claimants sign the payload as an Ethereum signed message.
+			let payload = Claims::claim_payload(&42, Some(StatementKind::Regular));
+			let signable = Claims::ethereum_signable_message(
+				&to_ascii_hex(&42u64.encode()),
+				StatementKind::Regular.to_text(),
+			);
+			assert!(signable.ends_with(&payload));
+		});
+	}
+
+	#[test]
+	fn native_claiming_works() {
+		new_test_ext().execute_with(|| {
+			assert_noop!(
+				Claims::mint_native_claim(Origin::signed(42), 7, 500, None, None),
+				BadOrigin,
+			);
+			assert_ok!(Claims::mint_native_claim(
+				Origin::root(), 7, 500, Some((50, 10, 1)), Some(StatementKind::Saft),
+			));
+			assert_eq!(Claims::total(), total_claims() + 500);
+
+			// The claimant must sign the payload for the right destination and statement.
+			let payload = Claims::claim_payload(&42, Some(StatementKind::Regular));
+			assert_noop!(
+				Claims::claim_native(Origin::none(), 7, 42, TestSignature(7, payload)),
+				Error::<Test>::InvalidNativeSignature,
+			);
+			let payload = Claims::claim_payload(&42, Some(StatementKind::Saft));
+			assert_noop!(
+				Claims::claim_native(Origin::none(), 7, 69, TestSignature(7, payload.clone())),
+				Error::<Test>::InvalidNativeSignature,
+			);
+			assert_noop!(
+				Claims::claim_native(Origin::none(), 8, 42, TestSignature(8, payload.clone())),
+				Error::<Test>::SignerHasNoClaim,
+			);
+
+			assert_ok!(Claims::claim_native(Origin::none(), 7, 42, TestSignature(7, payload.clone())));
+			assert_eq!(Balances::free_balance(&42), 500);
+			assert_eq!(Vesting::vesting_balance(&42), Some(50));
+			assert_eq!(Claims::total(), total_claims());
+			assert_eq!(Claims::native_claims(&7), None);
+
+			assert_noop!(
+				Claims::claim_native(Origin::none(), 7, 42, TestSignature(7, payload)),
+				Error::<Test>::SignerHasNoClaim,
+			);
+		});
+	}
+
+	#[test]
+	fn claim_many_works() {
+		new_test_ext().execute_with(|| {
+			assert_noop!(Claims::claim_many(Origin::none(), vec![]), Error::<Test>::EmptyBatch);
+			let item = (42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]);
+			let too_many = vec![item; MaxClaimsPerBatch::get() as usize + 1];
+			assert_noop!(
+				Claims::claim_many(Origin::none(), too_many),
+				Error::<Test>::BatchTooLarge,
+			);
+			assert_noop!(
+				Claims::claim_many(Origin::signed(42), vec![
+					(42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]),
+				]),
+				BadOrigin,
+			);
+
+			let regular = StatementKind::Regular.to_text();
+			assert_ok!(Claims::claim_many(Origin::none(), vec![
+				(42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]),
+				// Missing statement.
+				(43, sig::<Test>(&dave(), &43u64.encode(), &[][..]), vec![]),
+				(44, sig::<Test>(&dave(), &44u64.encode(), regular), regular.to_vec()),
+				// Already claimed.
+				(45, sig::<Test>(&alice(), &45u64.encode(), &[][..]), vec![]),
+				// No claim.
+				(46, sig::<Test>(&bob(), &46u64.encode(), &[][..]), vec![]),
+			]));
+
+			assert_eq!(Balances::free_balance(&42), 100);
+			assert_eq!(Balances::free_balance(&43), 0);
+			assert_eq!(Balances::free_balance(&44), 200);
+			assert_eq!(Balances::free_balance(&45), 0);
+			assert_eq!(Balances::free_balance(&46), 0);
+			assert_eq!(Claims::total(), total_claims() - 300);
+		});
+	}
+
+	#[test]
+	fn claim_many_weight_scales_with_batch() {
+		let item = (42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]);
+		let one = ClaimsCall::<Test>::claim_many(vec![item.clone()]).get_dispatch_info();
+		let three = ClaimsCall::<Test>::claim_many(vec![item.clone(), item.clone(), item]).get_dispatch_info();
+		assert_eq!(three.weight, one.weight * 3);
+	}
+
+	#[test]
+	fn validate_unsigned_batches_and_native_claims() {
+		use sp_runtime::traits::ValidateUnsigned;
+		let source = sp_runtime::transaction_validity::TransactionSource::External;
+
+		new_test_ext().execute_with(|| {
+			let regular = StatementKind::Regular.to_text();
+			let call = ClaimsCall::claim_many(vec![
+				(42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]),
+				(43, sig::<Test>(&dave(), &43u64.encode(), regular), regular.to_vec()),
+			]);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(source, &call),
+				Ok(ValidTransaction {
+					priority: 100,
+					requires: vec![],
+					provides: vec![("claims", eth(&alice())).encode(), ("claims", eth(&dave())).encode()],
+					longevity: TransactionLongevity::max_value(),
+					propagate: true,
+				})
+			);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(source, &ClaimsCall::claim_many(vec![])),
+				InvalidTransaction::Call.into(),
+			);
+			let item = (42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]);
+			let call = ClaimsCall::claim_many(vec![item; MaxClaimsPerBatch::get() as usize + 1]);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(source, &call),
+				InvalidTransaction::ExhaustsResources.into(),
+			);
+			let call = ClaimsCall::claim_many(vec![
+				(42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]),
+				(43, sig::<Test>(&dave(), &43u64.encode(), &[][..]), vec![]),
+			]);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(source, &call),
+				InvalidTransaction::Custom(ValidityError::InvalidStatement.into()).into(),
+			);
+			let call = ClaimsCall::claim_many(vec![
+				(42, sig::<Test>(&alice(), &42u64.encode(), &[][..]), vec![]),
+				(46, sig::<Test>(&bob(), &46u64.encode(), &[][..]), vec![]),
+			]);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(source, &call),
+				InvalidTransaction::Custom(ValidityError::SignerHasNoClaim.into()).into(),
+			);
+
+			assert_ok!(Claims::mint_native_claim(Origin::root(), 7, 500, None, None));
+			let payload = Claims::claim_payload(&42, None);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(
+					source,
+					&ClaimsCall::claim_native(7, 42, TestSignature(7, payload.clone())),
+				),
+				Ok(ValidTransaction {
+					priority: 100,
+					requires: vec![],
+					provides: vec![("native_claims", 7u64).encode()],
+					longevity: TransactionLongevity::max_value(),
+					propagate: true,
+				})
+			);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(
+					source,
+					&ClaimsCall::claim_native(7, 43, TestSignature(7, payload.clone())),
+				),
+				InvalidTransaction::Custom(ValidityError::InvalidNativeSignature.into()).into(),
+			);
+			assert_eq!(
+				<Module<Test>>::validate_unsigned(
+					source,
+					&ClaimsCall::claim_native(8, 42, TestSignature(8, payload)),
+				),
+				InvalidTransaction::Custom(ValidityError::SignerHasNoClaim.into()).into(),
+			);
+		});
+	}
+
 	#[test]
 	fn validate_unsigned_works() {
 		use sp_runtime::traits::ValidateUnsigned;
@@ -31,6 +31,14 @@
 //! a deposit must be paid of the amount `SubmissionDeposit`. Substantial resources are taken on
 //! the main trie in tracking a fund and this accounts for that.
 //!
+//! A contribution may carry a memo of at most `MaxMemoLength` bytes (e.g. an account on the
+//! parachain to which rewards should be paid), which is stored in the child-trie next to the
+//! contributed amount, and a referral code of at most `MaxReferralLength` bytes. The total amount
+//! contributed under each referral code is tallied in `Referrals` so that referrers may later be
+//! rewarded off-chain. The fund owner may limit the total that any single account contributes
+//! and may restrict contributions to accounts which they have placed on the fund's allow-list
+//! (using `set_contribution_policy`, `allow_contributors` and `disallow_contributors`).
+//!
 //! Funds may be set up during an auction period; their closing time is fixed at creation (as a
 //! block number) and if the fund is not successful by the closing time, then it will become *retired*.
 //! Funds may span multiple auctions, and even auctions that sell differing periods. However, for a
@@ -41,7 +49,9 @@
 //! Contributors may get a refund of their contributions from retired funds. After a period (`RetirementPeriod`)
 //! the fund may be dissolved entirely. At this point any non-refunded contributions are considered
 //! `orphaned` and are disposed of through the `OrphanedFunds` handler (which may e.g. place them
-//! into the treasury).
+//! into the treasury). So that no contributor need lose their funds through inaction, any account
+//! may call `refund` on an ended fund, which returns up to `RemoveKeysLimit` contributions at a
+//! time to their contributors; it may be called repeatedly until the whole fund is refunded.
 //!
 //! Funds may accept contributions at any point before their success or retirement. When a parachain
 //! slot auction enters its ending period, then parachains will each place a bid; the bid will be
@@ -53,6 +63,15 @@
 //! contains all expected functionality. However, this is not enforced and deploy data may happen
 //! at any point, even after a slot has been successfully won or, indeed, never.
 //!
+//! Rather than deploying a new parachain, a fund may instead be used to extend the lease of an
+//! existing parachain. The parachain must first authorise this (using the `authorize_extension`
+//! call from its own account), after which the fund owner may fix the parachain as the fund's
+//! target (using `fix_extension`). This must happen before any contributions are made, and a fund
+//! may have either deploy data or an extension target, never both. The fund then bids to renew
+//! the parachain, and if successful is marked as funding it through the `onboard` call. Should
+//! the parachain later swap its slot with another (through the registrar's `swap`), the fund
+//! follows the slot; for this the runtime must use this module as the registrar's `SwapAux`.
+//!
 //! Funds that are successful winners of a slot may have their slot claimed through the `onboard`
 //! call. This may only be done once and must be after the deploy data has been fixed. Successful
 //! funds remain tracked (in the `Funds` storage item and the associated child trie) as long as
@@ -64,13 +83,16 @@
 //! @WARNING: For funds to be returned, it is imperative that this module's account is provided as
 //! the offboarding account for the slot. In the case that a parachain supplemented these funds in
 //! order to win a later auction, then it is the parachain's duty to ensure that the right amount of
-//! funds ultimately end up in module's fund sub-account.
+//! funds ultimately end up in module's fund sub-account. Funds extending a lease are instead repaid
+//! what they paid for the extension by the slots module once the extension has ended, after which
+//! they may be retired even though the parachain carries on.
 
 use frame_support::{
 	decl_module, decl_storage, decl_event, decl_error, storage::child, ensure,
 	traits::{
 		Currency, Get, OnUnbalanced, WithdrawReason, ExistenceRequirement::AllowDeath
 	},
+	weights::Weight,
 };
 use system::ensure_signed;
 use sp_runtime::{ModuleId,
@@ -79,7 +101,7 @@ use sp_runtime::{ModuleId,
 use crate::slots;
 use codec::{Encode, Decode};
 use sp_std::vec::Vec;
-use primitives::v0::{Id as ParaId, HeadData};
+use primitives::v0::{Id as ParaId, HeadData, SwapAux};
 
 pub type BalanceOf<T> =
 	<<T as slots::Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
@@ -106,6 +128,18 @@ pub trait Trait: slots::Trait {
 
 	/// What to do with funds that were not withdrawn.
 	type OrphanedFunds: OnUnbalanced<NegativeImbalanceOf<Self>>;
+
+	/// The maximum length (in bytes) of the memo attached to a contribution.
+	type MaxMemoLength: Get<u8>;
+
+	/// The maximum length (in bytes) of a referral code.
+	type MaxReferralLength: Get<u8>;
+
+	/// The maximum number of contributions returned in a single call to `refund`.
+	type RemoveKeysLimit: Get<u32>;
+
+	/// The maximum number of accounts added to or removed from an allow-list in a single call.
+	type MaxAllowListChange: Get<u32>;
 }
 
 /// Simple index for identifying a fund.
@@ -145,6 +179,14 @@ pub struct FundInfo<AccountId, Balance, Hash, BlockNumber> {
 	end: BlockNumber,
 	/// A hard-cap on the amount that may be contributed.
 	cap: Balance,
+	/// A limit on the total amount that any single account may contribute, if any. Set by the
+	/// owner.
+	max_contribution: Option<Balance>,
+	/// Whether only accounts on this fund's allow-list may contribute. Set by the owner.
+	allow_list_only: bool,
+	/// The existing parachain whose lease this fund extends, if any. Once set it may not be reset.
+	/// A fund extending a lease has no deploy data.
+	extends: Option<ParaId>,
 	/// The most recent block that this had a contribution. Determines if we make a bid or not.
 	/// If this is `Never`, this fund has never received a contribution.
 	/// If this is `PreEnding(n)`, this fund received a contribution sometime in auction
@@ -179,6 +221,23 @@ decl_storage! {
 
 		/// The number of auctions that have entered into their ending period so far.
 		EndingsCount get(fn endings_count): slots::AuctionIndex;
+
+		/// The total amount contributed to a fund under each referral code.
+		Referrals get(fn referrals):
+			double_map hasher(twox_64_concat) FundIndex, hasher(blake2_128_concat) Vec<u8>
+			=> BalanceOf<T>;
+
+		/// The fund that each parachain has authorised to extend its lease.
+		ExtensionAuthorization get(fn extension_authorization):
+			map hasher(twox_64_concat) ParaId => Option<FundIndex>;
+
+		/// The funds which are funding, or which are targeting for an extension, each parachain.
+		ParaFunds get(fn para_funds): map hasher(twox_64_concat) ParaId => Vec<FundIndex>;
+
+		/// The accounts which are allowed to contribute to a fund while it is in allow-list mode.
+		AllowList get(fn allow_list):
+			double_map hasher(twox_64_concat) FundIndex, hasher(twox_64_concat) T::AccountId
+			=> bool;
 	}
 }
 
@@ -194,6 +253,24 @@ decl_event! {
 		Dissolved(FundIndex),
 		DeployDataFixed(FundIndex),
 		Onboarded(FundIndex, ParaId),
+		/// An account set the memo of its contribution to a fund. [who, fund_index, memo]
+		MemoUpdated(AccountId, FundIndex, Vec<u8>),
+		/// A contribution was made under a referral code. [who, fund_index, referral_code, amount]
+		Referred(AccountId, FundIndex, Vec<u8>, Balance),
+		/// The owner changed the contribution policy of a fund. [fund_index, max_contribution,
+		/// allow_list_only]
+		ContributionPolicySet(FundIndex, Option<Balance>, bool),
+		/// The owner changed the allow-list of a fund. [fund_index]
+		AllowListUpdated(FundIndex),
+		/// Some of the contributions to a fund were refunded; `refund` must be called again to
+		/// refund the rest. [fund_index]
+		PartiallyRefunded(FundIndex),
+		/// All of the contributions to a fund have been refunded. [fund_index]
+		AllRefunded(FundIndex),
+		/// A parachain authorised a fund to extend its lease. [para_id, fund_index]
+		ExtensionAuthorized(ParaId, FundIndex),
+		/// A fund was fixed to extend the lease of a parachain. [fund_index, para_id]
+		ExtensionFixed(FundIndex, ParaId),
 	}
 }
 
@@ -230,6 +307,8 @@ decl_error! {
 		ParaHasDeposit,
 		/// Funds have not yet been returned.
 		FundsNotReturned,
+		/// The lease periods funded by this extension fund have not yet ended.
+		ExtensionNotEnded,
 		/// Fund has not yet retired.
 		FundNotRetired,
 		/// The crowdfund has not yet ended.
@@ -240,6 +319,30 @@ decl_error! {
 		HasActiveParachain,
 		/// The retirement period has not ended.
 		InRetirementPeriod,
+		/// The memo is longer than `MaxMemoLength`.
+		MemoTooLarge,
+		/// The referral code is longer than `MaxReferralLength`.
+		ReferralCodeTooLarge,
+		/// The contribution would take this account's total over the fund's per-account limit.
+		ContributionLimitExceeded,
+		/// This fund only accepts contributions from accounts on its allow-list.
+		NotAllowListed,
+		/// The origin of this call must be a parachain.
+		NotParaOrigin,
+		/// The parachain has not authorised this fund to extend its lease.
+		ExtensionNotAuthorized,
+		/// This fund already has deployment data or an extension target.
+		ExistingTarget,
+		/// The extension target may only be fixed before any contributions are made.
+		HasContributions,
+		/// This fund extends the lease of an existing parachain and has no deployment data.
+		IsExtension,
+		/// The parachain is not the one whose lease this fund extends.
+		NotExtensionTarget,
+		/// This fund has not won an extension of the parachain's lease.
+		ExtensionNotWon,
+		/// More accounts were given than `MaxAllowListChange`.
+		TooManyAccounts,
 	}
 }
 
@@ -284,6 +387,9 @@ decl_module! {
 				raised: Zero::zero(),
 				end,
 				cap,
+				max_contribution: None,
+				allow_list_only: false,
+				extends: None,
 				last_contribution: LastContribution::Never,
 				first_slot,
 				last_slot,
@@ -296,11 +402,28 @@ decl_module! {
 		/// Contribute to a crowd sale. This will transfer some balance over to fund a parachain
 		/// slot. It will be withdrawable in two instances: the parachain becomes retired; or the
 		/// slot is unable to be purchased and the timeout expires.
+		///
+		/// - `memo`, if given, replaces the memo stored alongside this account's contribution.
+		/// - `referral`, if given, is the referral code under which this contribution is tallied.
 		#[weight = 0]
-		fn contribute(origin, #[compact] index: FundIndex, #[compact] value: BalanceOf<T>) {
+		fn contribute(origin,
+			#[compact] index: FundIndex,
+			#[compact] value: BalanceOf<T>,
+			memo: Option<Vec<u8>>,
+			referral: Option<Vec<u8>>,
+		) {
 			let who = ensure_signed(origin)?;
 
 			ensure!(value >= T::MinContribution::get(), Error::<T>::ContributionTooSmall);
+			if let Some(ref memo) = memo {
+				ensure!(memo.len() <= T::MaxMemoLength::get().into(), Error::<T>::MemoTooLarge);
+			}
+			if let Some(ref referral) = referral {
+				ensure!(
+					referral.len() <= T::MaxReferralLength::get().into(),
+					Error::<T>::ReferralCodeTooLarge,
+				);
+			}
 			let mut fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
 			fund.raised  = fund.raised.checked_add(&value).ok_or(Error::<T>::Overflow)?;
 			ensure!(fund.raised <= fund.cap, Error::<T>::CapExceeded);
@@ -309,11 +432,19 @@ decl_module! {
 			let now = <system::Module<T>>::block_number();
 			ensure!(fund.end > now, Error::<T>::ContributionPeriodOver);
 
+			ensure!(
+				!fund.allow_list_only || <AllowList<T>>::get(index, &who),
+				Error::<T>::NotAllowListed,
+			);
+			let (balance, old_memo) = Self::contribution_get(index, &who);
+			let balance = balance.checked_add(&value).ok_or(Error::<T>::Overflow)?;
+			if let Some(max_contribution) = fund.max_contribution {
+				ensure!(balance <= max_contribution, Error::<T>::ContributionLimitExceeded);
+			}
+
 			T::Currency::transfer(&who, &Self::fund_account_id(index), value, AllowDeath)?;
 
-			let balance = Self::contribution_get(index, &who);
-			let balance = balance.saturating_add(value);
-			Self::contribution_put(index, &who, &balance);
+			Self::contribution_put(index, &who, &balance, memo.as_ref().unwrap_or(&old_memo));
 
 			if <slots::Module<T>>::is_ending(now).is_some() {
 				match fund.last_contribution {
@@ -345,9 +476,88 @@ decl_module! {
 
 			<Funds<T>>::insert(index, &fund);
 
+			if let Some(referral) = referral {
+				<Referrals<T>>::mutate(index, &referral, |total| *total = total.saturating_add(value));
+				Self::deposit_event(RawEvent::Referred(who.clone(), index, referral, value));
+			}
+			if let Some(memo) = memo {
+				Self::deposit_event(RawEvent::MemoUpdated(who.clone(), index, memo));
+			}
 			Self::deposit_event(RawEvent::Contributed(who, index, value));
 		}
 
+		/// Set the contribution policy of a fund. This only affects future contributions.
+		///
+		/// - `origin` must be the fund owner.
+		/// - `max_contribution` is the limit on the total any single account may contribute, if
+		///   any.
+		/// - `allow_list_only` restricts contributions to accounts on the fund's allow-list.
+		#[weight = 0]
+		fn set_contribution_policy(origin,
+			#[compact] index: FundIndex,
+			max_contribution: Option<BalanceOf<T>>,
+			allow_list_only: bool,
+		) {
+			let who = ensure_signed(origin)?;
+
+			let mut fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
+			ensure!(fund.owner == who, Error::<T>::InvalidOrigin); // must be fund owner
+
+			fund.max_contribution = max_contribution;
+			fund.allow_list_only = allow_list_only;
+
+			<Funds<T>>::insert(index, &fund);
+
+			Self::deposit_event(
+				RawEvent::ContributionPolicySet(index, max_contribution, allow_list_only)
+			);
+		}
+
+		/// Add accounts to the allow-list of a fund.
+		///
+		/// - `origin` must be the fund owner.
+		/// - `accounts` may contain at most `MaxAllowListChange` accounts.
+		#[weight = T::DbWeight::get().reads_writes(1, 1 + accounts.len() as Weight)]
+		fn allow_contributors(origin, #[compact] index: FundIndex, accounts: Vec<T::AccountId>) {
+			let who = ensure_signed(origin)?;
+
+			ensure!(
+				accounts.len() <= T::MaxAllowListChange::get() as usize,
+				Error::<T>::TooManyAccounts,
+			);
+			let fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
+			ensure!(fund.owner == who, Error::<T>::InvalidOrigin); // must be fund owner
+
+			for account in accounts {
+				<AllowList<T>>::insert(index, account, true);
+			}
+
+			Self::deposit_event(RawEvent::AllowListUpdated(index));
+		}
+
+		/// Remove accounts from the allow-list of a fund. Existing contributions of these accounts
+		/// are not affected.
+		///
+		/// - `origin` must be the fund owner.
+		/// - `accounts` may contain at most `MaxAllowListChange` accounts.
+		#[weight = T::DbWeight::get().reads_writes(1, 1 + accounts.len() as Weight)]
+		fn disallow_contributors(origin, #[compact] index: FundIndex, accounts: Vec<T::AccountId>) {
+			let who = ensure_signed(origin)?;
+
+			ensure!(
+				accounts.len() <= T::MaxAllowListChange::get() as usize,
+				Error::<T>::TooManyAccounts,
+			);
+			let fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
+			ensure!(fund.owner == who, Error::<T>::InvalidOrigin); // must be fund owner
+
+			for account in accounts {
+				<AllowList<T>>::remove(index, account);
+			}
+
+			Self::deposit_event(RawEvent::AllowListUpdated(index));
+		}
+
 		/// Set the deploy data of the funded parachain if not already set. Once set, this cannot
 		/// be changed again.
 		///
@@ -367,6 +577,7 @@ decl_module! {
 			let mut fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
 			ensure!(fund.owner == who, Error::<T>::InvalidOrigin); // must be fund owner
 			ensure!(fund.deploy_data.is_none(), Error::<T>::ExistingDeployData);
+			ensure!(fund.extends.is_none(), Error::<T>::IsExtension);
 
 			fund.deploy_data = Some(DeployData { code_hash, code_size, initial_head_data });
 
@@ -375,9 +586,59 @@ decl_module! {
 			Self::deposit_event(RawEvent::DeployDataFixed(index));
 		}
 
+		/// Authorise a fund to extend the lease of a parachain. This replaces any previous
+		/// authorisation which has not yet been used.
+		///
+		/// - `origin` must be the parachain's account.
+		/// - `index` is the fund index that may target the parachain.
+		#[weight = 0]
+		fn authorize_extension(origin, #[compact] index: FundIndex) {
+			let who = ensure_signed(origin)?;
+			let para_id = <ParaId>::try_from_account(&who).ok_or(Error::<T>::NotParaOrigin)?;
+
+			ensure!(<Funds<T>>::contains_key(index), Error::<T>::InvalidFundIndex);
+			ExtensionAuthorization::insert(para_id, index);
+
+			Self::deposit_event(RawEvent::ExtensionAuthorized(para_id, index));
+		}
+
+		/// Set the fund to extend the lease of an existing parachain rather than deploying a new
+		/// one. Once set, this cannot be changed again.
+		///
+		/// - `origin` must be the fund owner.
+		/// - `index` is the fund index that `origin` owns and whose target will be set.
+		/// - `para_id` is the parachain, which must have authorised this fund using
+		///   `authorize_extension`.
+		#[weight = 0]
+		fn fix_extension(origin, #[compact] index: FundIndex, #[compact] para_id: ParaId) {
+			let who = ensure_signed(origin)?;
+
+			let mut fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
+			ensure!(fund.owner == who, Error::<T>::InvalidOrigin); // must be fund owner
+			ensure!(
+				fund.deploy_data.is_none() && fund.extends.is_none(),
+				Error::<T>::ExistingTarget,
+			);
+			// Contributors must know what they contribute to.
+			ensure!(fund.raised.is_zero(), Error::<T>::HasContributions);
+			ensure!(
+				ExtensionAuthorization::get(para_id) == Some(index),
+				Error::<T>::ExtensionNotAuthorized,
+			);
+
+			ExtensionAuthorization::remove(para_id);
+			fund.extends = Some(para_id);
+			ParaFunds::mutate(para_id, |funds| funds.push(index));
+
+			<Funds<T>>::insert(index, &fund);
+
+			Self::deposit_event(RawEvent::ExtensionFixed(index, para_id));
+		}
+
 		/// Complete onboarding process for a winning parachain fund. This can be called once by
 		/// any origin once a fund wins a slot and the fund has set its deploy data (using
-		/// `fix_deploy_data`).
+		/// `fix_deploy_data`), or once a fund extending the lease of an existing parachain has
+		/// won the extension.
 		///
 		/// - `index` is the fund index that `origin` owns and whose deploy data will be set.
 		/// - `para_id` is the parachain index that this fund won.
@@ -389,42 +650,76 @@ decl_module! {
 			let _ = ensure_signed(origin)?;
 
 			let mut fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
-			let DeployData { code_hash, code_size, initial_head_data }
-				= fund.clone().deploy_data.ok_or(Error::<T>::UnsetDeployData)?;
 			ensure!(fund.parachain.is_none(), Error::<T>::AlreadyOnboard);
-			fund.parachain = Some(para_id);
-
-			let fund_origin = system::RawOrigin::Signed(Self::fund_account_id(index)).into();
-			<slots::Module<T>>::fix_deploy_data(
-				fund_origin,
-				index,
-				para_id,
-				code_hash,
-				code_size,
-				initial_head_data,
-			)?;
+
+			if let Some(target) = fund.extends {
+				ensure!(target == para_id, Error::<T>::NotExtensionTarget);
+				// The fund must have won the extension in an auction, and the parachain must still
+				// hold its lease for the slots bid on.
+				let funder = Self::funder(index);
+				ensure!(
+					<slots::Module<T>>::extension_wins(&funder)
+						.map_or(false, |last| last >= fund.last_slot)
+					&& <slots::Module<T>>::last_lease_period(&para_id)
+						.map_or(false, |last| last >= fund.last_slot),
+					Error::<T>::ExtensionNotWon,
+				);
+				<slots::ExtensionWins<T>>::remove(&funder);
+				fund.parachain = Some(para_id);
+			} else {
+				let DeployData { code_hash, code_size, initial_head_data }
+					= fund.clone().deploy_data.ok_or(Error::<T>::UnsetDeployData)?;
+				fund.parachain = Some(para_id);
+
+				let fund_origin = system::RawOrigin::Signed(Self::fund_account_id(index)).into();
+				<slots::Module<T>>::fix_deploy_data(
+					fund_origin,
+					index,
+					para_id,
+					code_hash,
+					code_size,
+					initial_head_data,
+				)?;
+				ParaFunds::mutate(para_id, |funds| funds.push(index));
+			}
 
 			<Funds<T>>::insert(index, &fund);
 
 			Self::deposit_event(RawEvent::Onboarded(index, para_id));
 		}
 
-		/// Note that a successful fund has lost its parachain slot, and place it into retirement.
+		/// Note that a successful fund has lost its parachain slot, or that the lease periods it
+		/// extended a parachain's lease by have ended, and place it into retirement.
 		#[weight = 0]
 		fn begin_retirement(origin, #[compact] index: FundIndex) {
 			let _ = ensure_signed(origin)?;
 
 			let mut fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
 			let parachain_id = fund.parachain.take().ok_or(Error::<T>::NotParachain)?;
-			// No deposit information implies the parachain was off-boarded
-			ensure!(<slots::Module<T>>::deposits(parachain_id).len() == 0, Error::<T>::ParaHasDeposit);
+			if fund.extends.is_some() {
+				// The parachain may well hold a lease beyond the extension.
+				ensure!(
+					<slots::Module<T>>::lease_period_index() > fund.last_slot,
+					Error::<T>::ExtensionNotEnded,
+				);
+			} else {
+				// No deposit information implies the parachain was off-boarded
+				ensure!(
+					<slots::Module<T>>::deposits(parachain_id).len() == 0,
+					Error::<T>::ParaHasDeposit,
+				);
+			}
 			let account = Self::fund_account_id(index);
-			// Funds should be returned at the end of off-boarding
+			// Funds should be returned at the end of off-boarding, or, for an extension, by the
+			// slots module once the extension has ended
 			ensure!(T::Currency::free_balance(&account) >= fund.raised, Error::<T>::FundsNotReturned);
 
 			// This fund just ended. Withdrawal period begins.
 			let now = <system::Module<T>>::block_number();
 			fund.end = now;
+			if fund.extends.is_none() {
+				Self::remove_para_fund(parachain_id, index);
+			}
 
 			<Funds<T>>::insert(index, &fund);
 
@@ -443,7 +738,7 @@ decl_module! {
 			// `fund.end` can represent the end of a failed crowdsale or the beginning of retirement
 			ensure!(now >= fund.end, Error::<T>::FundNotEnded);
 
-			let balance = Self::contribution_get(index, &who);
+			let (balance, _) = Self::contribution_get(index, &who);
 			ensure!(balance > Zero::zero(), Error::<T>::NoContributions);
 
 			// Avoid using transfer to ensure we don't pay any fees.
@@ -460,6 +755,70 @@ decl_module! {
 			Self::deposit_event(RawEvent::Withdrew(who, index, balance));
 		}
 
+		/// Return the contributions of an unsuccessful or off-boarded fund to their contributors.
+		/// May be called by any account.
+		///
+		/// At most `RemoveKeysLimit` contributions are returned by each call; contributions are
+		/// removed from the fund's child-trie as they are returned, so subsequent calls carry on
+		/// where the last one stopped.
+		#[weight = T::DbWeight::get().reads_writes(
+			2 + 2 * T::RemoveKeysLimit::get() as Weight,
+			1 + 3 * T::RemoveKeysLimit::get() as Weight,
+		)]
+		fn refund(origin, #[compact] index: FundIndex) {
+			let _ = ensure_signed(origin)?;
+
+			let mut fund = Self::funds(index).ok_or(Error::<T>::InvalidFundIndex)?;
+			ensure!(fund.parachain.is_none(), Error::<T>::FundNotRetired);
+			let now = <system::Module<T>>::block_number();
+
+			// `fund.end` can represent the end of a failed crowdsale or the beginning of retirement
+			ensure!(now >= fund.end, Error::<T>::FundNotEnded);
+
+			let fund_account = Self::fund_account_id(index);
+			ensure!(T::Currency::free_balance(&fund_account) >= fund.raised, Error::<T>::FundsNotReturned);
+
+			let child_info = Self::id_from_index(index);
+			let transfer = WithdrawReason::Transfer.into();
+			let mut refunded = 0;
+			let all_refunded = loop {
+				if refunded >= T::RemoveKeysLimit::get() {
+					break false;
+				}
+				// Returned contributions are removed from the trie, so its first key is always the
+				// next contribution to return.
+				let key = match sp_io::default_child_storage::next_key(child_info.storage_key(), &[]) {
+					Some(key) => key,
+					None => break true,
+				};
+				refunded += 1;
+
+				let (balance, _) = child::get_or_default::<(BalanceOf<T>, Vec<u8>)>(&child_info, &key);
+				if let Ok(who) = T::AccountId::decode(&mut &key[..]) {
+					// Avoid using transfer to ensure we don't pay any fees. This should not fail
+					// as we checked the fund account's balance above, but if it does, we leave
+					// the contribution in place so it can be returned later.
+					let imbalance = T::Currency::withdraw(&fund_account, balance, transfer, AllowDeath);
+					let imbalance = match imbalance {
+						Ok(imbalance) => imbalance,
+						Err(_) => break false,
+					};
+					// The contributor's account is recreated if it has been reaped in the meantime.
+					T::Currency::resolve_creating(&who, imbalance);
+					fund.raised = fund.raised.saturating_sub(balance);
+				}
+				child::kill(&child_info, &key);
+			};
+
+			<Funds<T>>::insert(index, &fund);
+
+			if all_refunded {
+				Self::deposit_event(RawEvent::AllRefunded(index));
+			} else {
+				Self::deposit_event(RawEvent::PartiallyRefunded(index));
+			}
+		}
+
 		/// Remove a fund after either: it was unsuccessful and it timed out; or it was successful
 		/// but it has been retired from its parachain slot. This places any deposits that were not
 		/// withdrawn into the treasury.
@@ -486,6 +845,11 @@ decl_module! {
 			T::OrphanedFunds::on_unbalanced(imbalance);
 
 			Self::crowdfund_kill(index);
+			if let Some(para_id) = fund.extends {
+				Self::remove_para_fund(para_id, index);
+			}
+			<Referrals<T>>::remove_prefix(index);
+			<AllowList<T>>::remove_prefix(index);
 			<Funds<T>>::remove(index);
 
 			Self::deposit_event(RawEvent::Dissolved(index));
@@ -499,12 +863,11 @@ decl_module! {
 					EndingsCount::mutate(|c| *c += 1);
 				}
 				for (fund, index) in NewRaise::take().into_iter().filter_map(|i| Self::funds(i).map(|f| (f, i))) {
-					let bidder = slots::Bidder::New(slots::NewBidder {
-						who: Self::fund_account_id(index),
-						/// FundIndex and slots::SubId happen to be the same type (u32). If this
-						/// ever changes, then some sort of conversion will be needed here.
-						sub: index,
-					});
+					let funder = Self::funder(index);
+					let bidder = match fund.extends {
+						Some(para_id) => slots::Bidder::Extension(para_id, funder),
+						None => slots::Bidder::New(funder),
+					};
 
 					// Care needs to be taken by the crowdfund creator that this function will succeed given
 					// the crowdfunding configuration. We do some checks ahead of time in crowdfund `create`.
@@ -530,6 +893,16 @@ impl<T: Trait> Module<T> {
 		T::ModuleId::get().into_sub_account(index)
 	}
 
+	/// The bidder identity under which a fund bids in slot auctions.
+	fn funder(index: FundIndex) -> slots::NewBidder<T::AccountId> {
+		slots::NewBidder {
+			who: Self::fund_account_id(index),
+			// FundIndex and slots::SubId happen to be the same type (u32). If this ever changes,
+			// then some sort of conversion will be needed here.
+			sub: index,
+		}
+	}
+
 	pub fn id_from_index(index: FundIndex) -> child::ChildInfo {
 		let mut buf = Vec::new();
 		buf.extend_from_slice(b"crowdfund");
@@ -537,12 +910,18 @@ impl<T: Trait> Module<T> {
 		child::ChildInfo::new_default(T::Hashing::hash(&buf[..]).as_ref())
 	}
 
-	pub fn contribution_put(index: FundIndex, who: &T::AccountId, balance: &BalanceOf<T>) {
-		who.using_encoded(|b| child::put(&Self::id_from_index(index), b, balance));
+	pub fn contribution_put(
+		index: FundIndex,
+		who: &T::AccountId,
+		balance: &BalanceOf<T>,
+		memo: &[u8],
+	) {
+		who.using_encoded(|b| child::put(&Self::id_from_index(index), b, &(balance, memo)));
 	}
 
-	pub fn contribution_get(index: FundIndex, who: &T::AccountId) -> BalanceOf<T> {
-		who.using_encoded(|b| child::get_or_default::<BalanceOf<T>>(
+	/// The amount contributed by `who` to the fund `index`, together with the memo they attached.
+	pub fn contribution_get(index: FundIndex, who: &T::AccountId) -> (BalanceOf<T>, Vec<u8>) {
+		who.using_encoded(|b| child::get_or_default::<(BalanceOf<T>, Vec<u8>)>(
 			&Self::id_from_index(index),
 			b,
 		))
@@ -555,6 +934,32 @@ impl<T: Trait> Module<T> {
 	pub fn crowdfund_kill(index: FundIndex) {
 		child::kill_storage(&Self::id_from_index(index));
 	}
+
+	fn remove_para_fund(para_id: ParaId, index: FundIndex) {
+		ParaFunds::mutate(para_id, |funds| funds.retain(|i| *i != index));
+	}
+}
+
+/// Funds follow the slot of the parachain that they fund, or whose lease they extend, when it is
+/// swapped with another. Everything else is left to `slots`.
+impl<T: Trait> SwapAux for Module<T> {
+	fn ensure_can_swap(one: ParaId, other: ParaId) -> Result<(), &'static str> {
+		<slots::Module<T>>::ensure_can_swap(one, other)
+	}
+
+	fn on_swap(one: ParaId, other: ParaId) -> Result<(), &'static str> {
+		<slots::Module<T>>::on_swap(one, other)?;
+
+		let swapped = |id: ParaId| if id == one { other } else if id == other { one } else { id };
+		for index in ParaFunds::get(one).into_iter().chain(ParaFunds::get(other)) {
+			<Funds<T>>::mutate(index, |fund| if let Some(fund) = fund {
+				fund.parachain = fund.parachain.map(swapped);
+				fund.extends = fund.extends.map(swapped);
+			});
+		}
+		ParaFunds::swap(one, other);
+		Ok(())
+	}
 }
 
 #[cfg(test)]
@@ -732,6 +1137,7 @@ mod tests {
 	parameter_types!{
 		pub const LeasePeriod: u64 = 10;
 		pub const EndingPeriod: u64 = 3;
+		pub const RandomnessRevealDelay: u64 = 0;
 	}
 	impl slots::Trait for Test {
 		type Event = ();
@@ -740,12 +1146,17 @@ mod tests {
 		type LeasePeriod = LeasePeriod;
 		type EndingPeriod = EndingPeriod;
 		type Randomness = RandomnessCollectiveFlip;
+		type RandomnessRevealDelay = RandomnessRevealDelay;
 	}
 	parameter_types! {
 		pub const SubmissionDeposit: u64 = 1;
 		pub const MinContribution: u64 = 10;
 		pub const RetirementPeriod: u64 = 5;
 		pub const CrowdfundModuleId: ModuleId = ModuleId(*b"py/cfund");
+		pub const MaxMemoLength: u8 = 32;
+		pub const MaxReferralLength: u8 = 16;
+		pub const RemoveKeysLimit: u32 = 2;
+		pub const MaxAllowListChange: u32 = 3;
 	}
 	impl Trait for Test {
 		type Event = ();
@@ -754,6 +1165,10 @@ mod tests {
 		type RetirementPeriod = RetirementPeriod;
 		type OrphanedFunds = Treasury;
 		type ModuleId = CrowdfundModuleId;
+		type MaxMemoLength = MaxMemoLength;
+		type MaxReferralLength = MaxReferralLength;
+		type RemoveKeysLimit = RemoveKeysLimit;
+		type MaxAllowListChange = MaxAllowListChange;
 	}
 
 	type System = system::Module<Test>;
@@ -799,7 +1214,7 @@ mod tests {
 			assert_eq!(Crowdfund::funds(0), None);
 			let empty: Vec<FundIndex> = Vec::new();
 			assert_eq!(Crowdfund::new_raise(), empty);
-			assert_eq!(Crowdfund::contribution_get(0, &1), 0);
+			assert_eq!(Crowdfund::contribution_get(0, &1).0, 0);
 			assert_eq!(Crowdfund::endings_count(), 0);
 		});
 	}
@@ -819,6 +1234,9 @@ mod tests {
 				// 5 blocks length + 3 block ending period + 1 starting block
 				end: 9,
 				cap: 1000,
+				max_contribution: None,
+				allow_list_only: false,
+				extends: None,
 				last_contribution: LastContribution::Never,
 				first_slot: 1,
 				last_slot: 4,
@@ -865,14 +1283,14 @@ mod tests {
 			assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 1);
 
 			// No contributions yet
-			assert_eq!(Crowdfund::contribution_get(0, &1), 0);
+			assert_eq!(Crowdfund::contribution_get(0, &1).0, 0);
 
 			// User 1 contributes to their own crowdfund
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None, None));
 			// User 1 has spent some funds to do this, transfer fees **are** taken
 			assert_eq!(Balances::free_balance(1), 950);
 			// Contributions are stored in the trie
-			assert_eq!(Crowdfund::contribution_get(0, &1), 49);
+			assert_eq!(Crowdfund::contribution_get(0, &1).0, 49);
 			// Contributions appear in free balance of crowdfund
 			assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 50);
 			// Crowdfund is added to NewRaise
@@ -890,22 +1308,112 @@ mod tests {
 	fn contribute_handles_basic_errors() {
 		new_test_ext().execute_with(|| {
 			// Cannot contribute to non-existing fund
-			assert_noop!(Crowdfund::contribute(Origin::signed(1), 0, 49), Error::<Test>::InvalidFundIndex);
+			assert_noop!(Crowdfund::contribute(Origin::signed(1), 0, 49, None, None), Error::<Test>::InvalidFundIndex);
 			// Cannot contribute below minimum contribution
-			assert_noop!(Crowdfund::contribute(Origin::signed(1), 0, 9), Error::<Test>::ContributionTooSmall);
+			assert_noop!(Crowdfund::contribute(Origin::signed(1), 0, 9, None, None), Error::<Test>::ContributionTooSmall);
 
 			// Set up a crowdfund
 			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 101));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 101, None, None));
 
 			// Cannot contribute past the limit
-			assert_noop!(Crowdfund::contribute(Origin::signed(2), 0, 900), Error::<Test>::CapExceeded);
+			assert_noop!(Crowdfund::contribute(Origin::signed(2), 0, 900, None, None), Error::<Test>::CapExceeded);
 
 			// Move past end date
 			run_to_block(10);
 
 			// Cannot contribute to ended fund
-			assert_noop!(Crowdfund::contribute(Origin::signed(1), 0, 49), Error::<Test>::ContributionPeriodOver);
+			assert_noop!(Crowdfund::contribute(Origin::signed(1), 0, 49, None, None), Error::<Test>::ContributionPeriodOver);
+		});
+	}
+
+	#[test]
+	fn contribute_with_memo_and_referral_works() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
+
+			let memo = Some(b"para-2".to_vec());
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 50, memo, Some(b"ref".to_vec())));
+			assert_eq!(Crowdfund::contribution_get(0, &2), (50, b"para-2".to_vec()));
+			assert_eq!(Crowdfund::referrals(0, b"ref".to_vec()), 50);
+
+			// Memo is kept if not given again, and replaced if it is
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 20, None, Some(b"ref".to_vec())));
+			assert_eq!(Crowdfund::contribution_get(0, &2), (70, b"para-2".to_vec()));
+			let memo = Some(b"para-2b".to_vec());
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 10, memo, None));
+			assert_eq!(Crowdfund::contribution_get(0, &2), (80, b"para-2b".to_vec()));
+
+			// Referrals are aggregated across contributors
+			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 30, None, Some(b"ref".to_vec())));
+			assert_eq!(Crowdfund::referrals(0, b"ref".to_vec()), 100);
+			assert_eq!(Crowdfund::funds(0).unwrap().raised, 110);
+
+			// Memo and referral code lengths are bounded
+			assert_noop!(
+				Crowdfund::contribute(Origin::signed(2), 0, 10, Some(vec![0; 33]), None),
+				Error::<Test>::MemoTooLarge
+			);
+			assert_noop!(
+				Crowdfund::contribute(Origin::signed(2), 0, 10, None, Some(vec![0; 17])),
+				Error::<Test>::ReferralCodeTooLarge
+			);
+
+			// Referrals are cleaned up when the fund is dissolved
+			run_to_block(15);
+			assert_ok!(Crowdfund::dissolve(Origin::signed(1), 0));
+			assert_eq!(Crowdfund::referrals(0, b"ref".to_vec()), 0);
+		});
+	}
+
+	#[test]
+	fn contribution_policy_works() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
+
+			// Only the owner may set the policy
+			assert_noop!(
+				Crowdfund::set_contribution_policy(Origin::signed(2), 0, Some(100), true),
+				Error::<Test>::InvalidOrigin
+			);
+			assert_noop!(
+				Crowdfund::allow_contributors(Origin::signed(2), 0, vec![2]),
+				Error::<Test>::InvalidOrigin
+			);
+			assert_ok!(Crowdfund::set_contribution_policy(Origin::signed(1), 0, Some(100), true));
+
+			// Account 2 is not allowed until it is placed on the allow-list
+			assert_noop!(
+				Crowdfund::contribute(Origin::signed(2), 0, 50, None, None),
+				Error::<Test>::NotAllowListed
+			);
+			assert_noop!(
+				Crowdfund::allow_contributors(Origin::signed(1), 0, vec![2, 3, 4, 5]),
+				Error::<Test>::TooManyAccounts
+			);
+			assert_ok!(Crowdfund::allow_contributors(Origin::signed(1), 0, vec![2, 3]));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 60, None, None));
+
+			// The per-account limit applies to the total contributed by an account
+			assert_noop!(
+				Crowdfund::contribute(Origin::signed(2), 0, 50, None, None),
+				Error::<Test>::ContributionLimitExceeded
+			);
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 40, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 100, None, None));
+
+			// Removing from the allow-list stops further contributions only
+			assert_ok!(Crowdfund::disallow_contributors(Origin::signed(1), 0, vec![3]));
+			assert_noop!(
+				Crowdfund::contribute(Origin::signed(3), 0, 10, None, None),
+				Error::<Test>::NotAllowListed
+			);
+			assert_eq!(Crowdfund::contribution_get(0, &3).0, 100);
+
+			// Lifting the policy opens the fund to everyone again
+			assert_ok!(Crowdfund::set_contribution_policy(Origin::signed(1), 0, None, false));
+			assert_ok!(Crowdfund::contribute(Origin::signed(4), 0, 500, None, None));
+			assert_eq!(Crowdfund::funds(0).unwrap().raised, 700);
 		});
 	}
 
@@ -1004,7 +1512,7 @@ mod tests {
 			));
 
 			// Fund crowdfund
-			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000, None, None));
 
 			run_to_block(10);
 
@@ -1022,6 +1530,132 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn extension_works() {
+		use frame_support::{StorageValue, StorageMap};
+
+		new_test_ext().execute_with(|| {
+			// Parachain 0 already holds a lease for the current lease period
+			let para_id = ParaId::from(0);
+			let para_account: u64 = para_id.into_account();
+			<slots::ManagedIds>::put(vec![para_id]);
+			<slots::Deposits<Test>>::insert(para_id, vec![1]);
+
+			// Set up a crowdfund
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
+			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
+
+			// The fund can only target the parachain once authorised by it
+			assert_noop!(
+				Crowdfund::fix_extension(Origin::signed(1), 0, para_id),
+				Error::<Test>::ExtensionNotAuthorized
+			);
+			assert_noop!(
+				Crowdfund::authorize_extension(Origin::signed(1), 0),
+				Error::<Test>::NotParaOrigin
+			);
+			assert_ok!(Crowdfund::authorize_extension(Origin::signed(para_account), 0));
+			assert_noop!(
+				Crowdfund::fix_extension(Origin::signed(2), 0, para_id),
+				Error::<Test>::InvalidOrigin
+			);
+			assert_ok!(Crowdfund::fix_extension(Origin::signed(1), 0, para_id));
+			assert_eq!(Crowdfund::funds(0).unwrap().extends, Some(para_id));
+			assert_eq!(Crowdfund::para_funds(para_id), vec![0]);
+			assert_eq!(Crowdfund::extension_authorization(para_id), None);
+
+			// A fund extending a lease doesn't deploy anything
+			assert_noop!(
+				Crowdfund::fix_deploy_data(
+					Origin::signed(1),
+					0,
+					<Test as system::Trait>::Hash::default(),
+					0,
+					vec![0].into(),
+				),
+				Error::<Test>::IsExtension
+			);
+
+			// Fund crowdfund
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 100, None, None));
+			assert_noop!(Crowdfund::onboard(Origin::signed(1), 0, para_id), Error::<Test>::ExtensionNotWon);
+
+			run_to_block(10);
+
+			// The fund paid for the extension, less the deposit the parachain already held
+			assert_eq!(Balances::total_balance(&Crowdfund::fund_account_id(0)), 2);
+			assert_eq!(Slots::deposits(para_id), vec![100; 4]);
+
+			assert_noop!(Crowdfund::onboard(Origin::signed(1), 0, 1.into()), Error::<Test>::NotExtensionTarget);
+			assert_eq!(Slots::extension_wins(Crowdfund::funder(0)), Some(4));
+			assert_ok!(Crowdfund::onboard(Origin::signed(1), 0, para_id));
+			assert_eq!(Crowdfund::funds(0).unwrap().parachain, Some(para_id));
+			assert_eq!(Slots::extension_wins(Crowdfund::funder(0)), None);
+
+			// The fund follows the slot when the parachain is swapped
+			assert_ok!(Crowdfund::ensure_can_swap(para_id, 1.into()));
+			assert_ok!(Crowdfund::on_swap(para_id, 1.into()));
+			let fund = Crowdfund::funds(0).unwrap();
+			assert_eq!(fund.parachain, Some(1.into()));
+			assert_eq!(fund.extends, Some(1.into()));
+			assert_eq!(Crowdfund::para_funds(ParaId::from(1)), vec![0]);
+			assert!(Crowdfund::para_funds(para_id).is_empty());
+			assert_eq!(Slots::deposits(ParaId::from(1)), vec![100; 4]);
+		});
+	}
+
+	#[test]
+	fn extension_fund_is_repaid_and_retires() {
+		use frame_support::{StorageValue, StorageMap};
+
+		new_test_ext().execute_with(|| {
+			// Parachain 0 already holds a lease for the current lease period
+			let para_id = ParaId::from(0);
+			let para_account: u64 = para_id.into_account();
+			PARACHAINS.with(|p| p.borrow_mut().insert(0, (vec![0].into(), vec![0].into())));
+			<slots::ManagedIds>::put(vec![para_id]);
+			<slots::Deposits<Test>>::insert(para_id, vec![1]);
+			<slots::Offboarding<Test>>::insert(para_id, 4);
+
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
+			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
+			assert_ok!(Crowdfund::authorize_extension(Origin::signed(para_account), 0));
+			assert_ok!(Crowdfund::fix_extension(Origin::signed(1), 0, para_id));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 100, None, None));
+
+			run_to_block(10);
+
+			// The fund paid for the extension, less the deposit the parachain already held
+			let fund_account = Crowdfund::fund_account_id(0);
+			assert_eq!(Balances::free_balance(&fund_account), 2);
+			assert_eq!(Slots::extension_funders(para_id), vec![(fund_account, 4, 99)]);
+			assert_ok!(Crowdfund::onboard(Origin::signed(1), 0, para_id));
+
+			run_to_block(49);
+			assert_noop!(
+				Crowdfund::begin_retirement(Origin::signed(1), 0),
+				Error::<Test>::ExtensionNotEnded,
+			);
+
+			// The extension ends with the parachain's lease; the fund is repaid first, and the
+			// deposit the parachain held before goes to its off-boarding account
+			run_to_block(50);
+			assert_eq!(Balances::free_balance(&fund_account), 101);
+			assert_eq!(Balances::free_balance(4), 4001);
+			assert!(Slots::extension_funders(para_id).is_empty());
+			assert!(Slots::deposits(para_id).is_empty());
+
+			assert_ok!(Crowdfund::begin_retirement(Origin::signed(1), 0));
+			assert_eq!(Crowdfund::funds(0).unwrap().parachain, None);
+
+			// Contributors can withdraw their contributions
+			assert_eq!(Balances::free_balance(2), 1900);
+			assert_ok!(Crowdfund::withdraw(Origin::signed(2), 0));
+			assert_eq!(Balances::free_balance(2), 2000);
+			assert_eq!(Balances::free_balance(&fund_account), 1);
+		});
+	}
+
 	#[test]
 	fn onboard_handles_basic_errors() {
 		new_test_ext().execute_with(|| {
@@ -1031,7 +1665,7 @@ mod tests {
 			assert_eq!(Balances::free_balance(1), 999);
 
 			// Fund crowdfund
-			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000, None, None));
 
 			run_to_block(10);
 
@@ -1078,7 +1712,7 @@ mod tests {
 			));
 
 			// Fund crowdfund
-			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000, None, None));
 
 			run_to_block(10);
 
@@ -1121,7 +1755,7 @@ mod tests {
 			));
 
 			// Fund crowdfund
-			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 1000, None, None));
 
 			run_to_block(10);
 
@@ -1155,9 +1789,9 @@ mod tests {
 			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
 			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
 			// Transfer fee is taken here
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None, None));
 
 			// Skip all the way to the end
 			run_to_block(50);
@@ -1181,7 +1815,7 @@ mod tests {
 			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
 			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
 			// Transfer fee is taken here
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None, None));
 			assert_eq!(Balances::free_balance(1), 950);
 
 			run_to_block(5);
@@ -1198,6 +1832,47 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn refund_works() {
+		new_test_ext().execute_with(|| {
+			// Set up a crowdfund
+			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
+			// Transfer fee is taken here
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None, None));
+
+			// Cannot refund before the fund has ended
+			assert_noop!(Crowdfund::refund(Origin::signed(4), 0), Error::<Test>::FundNotEnded);
+			assert_noop!(Crowdfund::refund(Origin::signed(4), 1), Error::<Test>::InvalidFundIndex);
+
+			// Skip all the way to the end
+			run_to_block(50);
+
+			// Anyone can refund; only `RemoveKeysLimit` contributions are returned at a time
+			assert_ok!(Crowdfund::refund(Origin::signed(4), 0));
+			let remaining: Vec<u64> = [1u64, 2, 3].iter()
+				.map(|who| Crowdfund::contribution_get(0, who).0)
+				.filter(|balance| *balance > 0)
+				.collect();
+			assert_eq!(remaining.len(), 1);
+			assert_eq!(Crowdfund::funds(0).unwrap().raised, remaining[0]);
+
+			// A second call finishes the job
+			assert_ok!(Crowdfund::refund(Origin::signed(4), 0));
+			assert_eq!(Crowdfund::funds(0).unwrap().raised, 0);
+			assert_eq!(Balances::free_balance(1), 999);
+			assert_eq!(Balances::free_balance(2), 2000);
+			assert_eq!(Balances::free_balance(3), 3000);
+			assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 1);
+
+			// Further calls are harmless, and the fund may be dissolved without orphaning anything
+			assert_ok!(Crowdfund::refund(Origin::signed(4), 0));
+			assert_ok!(Crowdfund::dissolve(Origin::signed(1), 0));
+			assert_eq!(Balances::free_balance(1), 1000);
+		});
+	}
+
 	#[test]
 	fn dissolve_works() {
 		new_test_ext().execute_with(|| {
@@ -1205,9 +1880,9 @@ mod tests {
 			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
 			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
 			// Transfer fee is taken here
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None, None));
 
 			// Skip all the way to the end
 			run_to_block(50);
@@ -1228,7 +1903,7 @@ mod tests {
 			assert_eq!(Balances::free_balance(Treasury::account_id()), 600);
 
 			// Storage trie is removed
-			assert_eq!(Crowdfund::contribution_get(0,&0), 0);
+			assert_eq!(Crowdfund::contribution_get(0,&0).0, 0);
 			// Fund storage is removed
 			assert_eq!(Crowdfund::funds(0), None);
 
@@ -1242,9 +1917,9 @@ mod tests {
 			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
 			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
 			// Transfer fee is taken here
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None, None));
 
 			// Cannot dissolve an invalid fund index
 			assert_noop!(Crowdfund::dissolve(Origin::signed(1), 1), Error::<Test>::InvalidFundIndex);
@@ -1274,7 +1949,7 @@ mod tests {
 			// Create a crowdfund before an auction is created
 			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 9));
 			// Users can already contribute
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None, None));
 			// Fund added to NewRaise
 			assert_eq!(Crowdfund::new_raise(), vec![0]);
 
@@ -1311,15 +1986,15 @@ mod tests {
 	fn fund_across_multiple_auctions_works() {
 		new_test_ext().execute_with(|| {
 			// Create an auction
-			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 2));
 			// Create two competing crowdfunds, with end dates across multiple auctions
 			// Each crowdfund is competing for the same slots, so only one can win
-			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 1, 4, 30));
-			assert_ok!(Crowdfund::create(Origin::signed(2), 1000, 1, 4, 30));
+			assert_ok!(Crowdfund::create(Origin::signed(1), 1000, 2, 5, 30));
+			assert_ok!(Crowdfund::create(Origin::signed(2), 1000, 2, 5, 30));
 
 			// Contribute to all, but more money to 0, less to 1
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 300));
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 1, 200));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 300, None, None));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 1, 200, None, None));
 
 			// Add deploy data to all
 			assert_ok!(Crowdfund::fix_deploy_data(
@@ -1348,10 +2023,10 @@ mod tests {
 			// This parachain is managed by Slots
 			assert_eq!(Slots::managed_ids(), vec![0.into()]);
 
-			// Create a second auction
-			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
+			// Create a second auction for the same lease periods
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 2));
 			// Contribute to existing funds add to NewRaise
-			assert_ok!(Crowdfund::contribute(Origin::signed(1), 1, 10));
+			assert_ok!(Crowdfund::contribute(Origin::signed(1), 1, 10, None, None));
 
 			// End the current auction, fund 1 wins!
 			run_to_block(20);
@@ -23,6 +23,7 @@ pub mod claims;
 pub mod parachains;
 pub mod slot_range;
 pub mod registrar;
+pub mod paras_registrar;
 pub mod slots;
 pub mod crowdfund;
 pub mod purchase;
@@ -1925,6 +1925,7 @@ mod tests {
 	parameter_types!{
 		pub const LeasePeriod: BlockNumber = 10;
 		pub const EndingPeriod: BlockNumber = 3;
+		pub const RandomnessRevealDelay: BlockNumber = 0;
 	}
 
 	impl slots::Trait for Test {
@@ -1934,12 +1935,14 @@ mod tests {
 		type EndingPeriod = EndingPeriod;
 		type LeasePeriod = LeasePeriod;
 		type Randomness = RandomnessCollectiveFlip;
+		type RandomnessRevealDelay = RandomnessRevealDelay;
 	}
 
 	parameter_types! {
 		pub const ParathreadDeposit: Balance = 10;
 		pub const QueueSize: usize = 2;
 		pub const MaxRetries: u32 = 3;
+		pub const MaxParathreads: u32 = 10;
 	}
 
 	impl registrar::Trait for Test {
@@ -1950,6 +1953,7 @@ mod tests {
 		type SwapAux = slots::Module<Test>;
 		type QueueSize = QueueSize;
 		type MaxRetries = MaxRetries;
+		type MaxParathreads = MaxParathreads;
 	}
 
 	parameter_types! {
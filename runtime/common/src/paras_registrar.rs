@@ -0,0 +1,383 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A `Registrar` backed by the v1 `paras` module.
+//!
+//! This allows the `slots` (and thus `crowdfund`) module to be used with the v1 parachains
+//! runtime: auction winners are registered by scheduling their initialization in `paras`, and
+//! removed by scheduling their cleanup. Unlike the v0 `registrar`, neither takes effect
+//! immediately; `paras` only applies them at the start of the next session.
+//!
+//! The module keeps track of the next free parachain ID. `migrate_from_v0` is provided to carry
+//! over the parachains registered with the v0 `registrar` along with its next free ID.
+
+use frame_support::{
+	decl_storage, decl_module, decl_error, ensure,
+	dispatch::DispatchResult,
+	traits::Get,
+	weights::Weight,
+};
+use primitives::v1::{Id as ParaId, ValidationCode, HeadData, LOWEST_USER_ID};
+use primitives::v0::{Info as ParaInfo, Scheduling};
+use runtime_parachains::{configuration, paras::{self, ParaGenesisArgs}};
+
+use crate::registrar::Registrar;
+
+pub trait Trait: paras::Trait { }
+
+decl_storage! {
+	trait Store for Module<T: Trait> as ParasRegistrar {
+		/// The next unused ParaId value.
+		NextFreeId get(fn next_free_id): ParaId = LOWEST_USER_ID;
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The para is already live or scheduled to be initialized.
+		ParaAlreadyExists,
+		/// The para is not live, or is already scheduled to be cleaned up.
+		ParaNotRegistered,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: <T as system::Trait>::Origin, system = system {
+		type Error = Error<T>;
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Migrate the state of the v0 `registrar` and `parachains` modules.
+	///
+	/// - `next_free_id` is the v0 `registrar`'s next free ID. The next free ID of this module is
+	///   never lowered, so that no ID is handed out twice.
+	/// - `paras` are the paras registered with the v0 `registrar`, along with their current
+	///   validation code and head data. They are scheduled to be initialized at the start of the
+	///   next session; any which are already known to `paras` are skipped.
+	pub fn migrate_from_v0(
+		next_free_id: ParaId,
+		paras: impl IntoIterator<Item = (ParaId, ParaInfo, ValidationCode, HeadData)>,
+	) -> Weight {
+		let mut weight = T::DbWeight::get().reads_writes(1, 1);
+		NextFreeId::mutate(|n| if next_free_id > *n { *n = next_free_id });
+
+		for (id, info, code, head) in paras {
+			if Self::is_registered(id) {
+				weight += T::DbWeight::get().reads(3);
+				continue;
+			}
+			weight += Self::schedule_initialize(id, info, code, head);
+		}
+
+		weight
+	}
+
+	/// Whether the para is live or scheduled to be initialized.
+	fn is_registered(id: ParaId) -> bool {
+		<paras::Module<T>>::is_parachain(id)
+			|| <paras::Module<T>>::is_parathread(id)
+			|| <paras::Module<T>>::is_upcoming(id)
+	}
+
+	fn schedule_initialize(
+		id: ParaId,
+		info: ParaInfo,
+		validation_code: ValidationCode,
+		genesis_head: HeadData,
+	) -> Weight {
+		<paras::Module<T>>::schedule_para_initialize(id, ParaGenesisArgs {
+			genesis_head,
+			validation_code,
+			parachain: info.scheduling == Scheduling::Always,
+		})
+	}
+}
+
+impl<T: Trait> Registrar<T::AccountId> for Module<T> {
+	fn new_id() -> ParaId {
+		NextFreeId::mutate(|n| { let r = *n; *n = ParaId::from(u32::from(*n) + 1); r })
+	}
+
+	fn head_data_size_allowed(head_data_size: u32) -> bool {
+		head_data_size <= <configuration::Module<T>>::config().max_head_data_size
+	}
+
+	fn code_size_allowed(code_size: u32) -> bool {
+		code_size <= <configuration::Module<T>>::config().max_code_size
+	}
+
+	fn para_info(id: ParaId) -> Option<ParaInfo> {
+		if <paras::Module<T>>::is_parachain(id) {
+			Some(ParaInfo { scheduling: Scheduling::Always })
+		} else if <paras::Module<T>>::is_parathread(id) {
+			Some(ParaInfo { scheduling: Scheduling::Dynamic })
+		} else {
+			None
+		}
+	}
+
+	fn register_para(
+		id: ParaId,
+		info: ParaInfo,
+		code: ValidationCode,
+		initial_head_data: HeadData,
+	) -> DispatchResult {
+		ensure!(!Self::is_registered(id), Error::<T>::ParaAlreadyExists);
+		Self::schedule_initialize(id, info, code, initial_head_data);
+		Ok(())
+	}
+
+	fn deregister_para(id: ParaId) -> DispatchResult {
+		ensure!(
+			Self::para_info(id).is_some() && !<paras::Module<T>>::is_outgoing(id),
+			Error::<T>::ParaNotRegistered,
+		);
+		<paras::Module<T>>::schedule_para_cleanup(id);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use sp_core::H256;
+	use sp_runtime::{
+		Perbill,
+		traits::{BlakeTwo256, Hash, IdentityLookup},
+		testing::TestXt,
+	};
+	use frame_support::{
+		impl_outer_origin, parameter_types, assert_ok, assert_noop,
+		traits::{OnInitialize, OnFinalize},
+	};
+	use primitives::v1::{BlockNumber, Header};
+	use runtime_parachains::configuration::HostConfiguration;
+	use crate::slots;
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	parameter_types! {
+		pub const BlockHashCount: u32 = 250;
+		pub const MaximumBlockWeight: u32 = 4 * 1024 * 1024;
+		pub const MaximumBlockLength: u32 = 4 * 1024 * 1024;
+		pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+	}
+	impl system::Trait for Test {
+		type BaseCallFilter = ();
+		type Origin = Origin;
+		type Call = ();
+		type Index = u64;
+		type BlockNumber = BlockNumber;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type BlockHashCount = BlockHashCount;
+		type MaximumBlockWeight = MaximumBlockWeight;
+		type DbWeight = ();
+		type BlockExecutionWeight = ();
+		type ExtrinsicBaseWeight = ();
+		type MaximumExtrinsicWeight = MaximumBlockWeight;
+		type MaximumBlockLength = MaximumBlockLength;
+		type AvailableBlockRatio = AvailableBlockRatio;
+		type Version = ();
+		type ModuleToIndex = ();
+		type AccountData = balances::AccountData<u64>;
+		type OnNewAccount = ();
+		type OnKilledAccount = Balances;
+		type SystemWeightInfo = ();
+	}
+
+	parameter_types! {
+		pub const ExistentialDeposit: u64 = 1;
+	}
+
+	impl balances::Trait for Test {
+		type Balance = u64;
+		type Event = ();
+		type DustRemoval = ();
+		type ExistentialDeposit = ExistentialDeposit;
+		type AccountStore = System;
+		type WeightInfo = ();
+	}
+
+	impl configuration::Trait for Test { }
+
+	impl paras::Trait for Test {
+		type Event = ();
+	}
+
+	impl<C> system::offchain::SendTransactionTypes<C> for Test where
+		C: codec::Codec + Send + Sync,
+	{
+		type OverarchingCall = C;
+		type Extrinsic = TestXt<C, ()>;
+	}
+
+	impl Trait for Test { }
+
+	parameter_types!{
+		pub const LeasePeriod: BlockNumber = 10;
+		pub const EndingPeriod: BlockNumber = 3;
+		pub const RandomnessRevealDelay: BlockNumber = 0;
+	}
+
+	impl slots::Trait for Test {
+		type Event = ();
+		type Currency = Balances;
+		type Parachains = ParasRegistrar;
+		type LeasePeriod = LeasePeriod;
+		type EndingPeriod = EndingPeriod;
+		type Randomness = RandomnessCollectiveFlip;
+		type RandomnessRevealDelay = RandomnessRevealDelay;
+	}
+
+	type System = system::Module<Test>;
+	type Balances = balances::Module<Test>;
+	type Paras = paras::Module<Test>;
+	type Slots = slots::Module<Test>;
+	type ParasRegistrar = Module<Test>;
+	type RandomnessCollectiveFlip = randomness_collective_flip::Module<Test>;
+
+	fn genesis_args(parachain: bool) -> ParaGenesisArgs {
+		ParaGenesisArgs {
+			genesis_head: vec![1].into(),
+			validation_code: vec![2].into(),
+			parachain,
+		}
+	}
+
+	fn new_test_ext() -> sp_io::TestExternalities {
+		let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+		balances::GenesisConfig::<Test>{
+			balances: vec![(1, 10), (2, 20)],
+		}.assimilate_storage(&mut t).unwrap();
+		configuration::GenesisConfig::<Test>{
+			config: HostConfiguration {
+				max_code_size: 100,
+				max_head_data_size: 10,
+				..Default::default()
+			},
+		}.assimilate_storage(&mut t).unwrap();
+		paras::GenesisConfig::<Test>{
+			paras: vec![(ParaId::from(1), genesis_args(true))],
+			_phdata: Default::default(),
+		}.assimilate_storage(&mut t).unwrap();
+		t.into()
+	}
+
+	fn run_to_block(n: BlockNumber) {
+		while System::block_number() < n {
+			Slots::on_finalize(System::block_number());
+			Balances::on_finalize(System::block_number());
+			System::on_finalize(System::block_number());
+			System::set_block_number(System::block_number() + 1);
+			System::on_initialize(System::block_number());
+			Balances::on_initialize(System::block_number());
+			Slots::on_initialize(System::block_number());
+		}
+	}
+
+	#[test]
+	fn registration_is_scheduled_in_paras() {
+		new_test_ext().execute_with(|| {
+			let chain = ParaInfo { scheduling: Scheduling::Always };
+			let id = ParasRegistrar::new_id();
+			assert_eq!(id, LOWEST_USER_ID);
+			assert_eq!(ParasRegistrar::new_id(), ParaId::from(u32::from(LOWEST_USER_ID) + 1));
+
+			assert!(ParasRegistrar::code_size_allowed(100));
+			assert!(!ParasRegistrar::code_size_allowed(101));
+			assert!(ParasRegistrar::head_data_size_allowed(10));
+			assert!(!ParasRegistrar::head_data_size_allowed(11));
+
+			// Registration only takes effect at the next session.
+			assert_ok!(ParasRegistrar::register_para(id, chain.clone(), vec![2].into(), vec![1].into()));
+			assert!(Paras::is_upcoming(id));
+			assert_eq!(ParasRegistrar::para_info(id), None);
+			assert_noop!(
+				ParasRegistrar::register_para(id, chain.clone(), vec![2].into(), vec![1].into()),
+				Error::<Test>::ParaAlreadyExists
+			);
+			assert_noop!(ParasRegistrar::deregister_para(id), Error::<Test>::ParaNotRegistered);
+
+			// Para 1 is live from genesis.
+			let live = ParaId::from(1);
+			assert_eq!(ParasRegistrar::para_info(live), Some(chain.clone()));
+			assert_noop!(
+				ParasRegistrar::register_para(live, chain, vec![2].into(), vec![1].into()),
+				Error::<Test>::ParaAlreadyExists
+			);
+			assert_ok!(ParasRegistrar::deregister_para(live));
+			assert!(Paras::is_outgoing(live));
+			assert_noop!(ParasRegistrar::deregister_para(live), Error::<Test>::ParaNotRegistered);
+		});
+	}
+
+	#[test]
+	fn auction_winners_are_registered_in_paras() {
+		new_test_ext().execute_with(|| {
+			run_to_block(1);
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
+			assert_ok!(Slots::bid(Origin::signed(1), 0, 1, 1, 4, 1));
+
+			run_to_block(9);
+			let id = LOWEST_USER_ID;
+			let h = BlakeTwo256::hash(&[42u8][..]);
+			assert_ok!(Slots::fix_deploy_data(Origin::signed(1), 0, id, h, 1, vec![69].into()));
+			assert_ok!(Slots::elaborate_deploy_data(Origin::signed(0), id, vec![42].into()));
+			assert!(!Paras::is_upcoming(id));
+
+			// The winner is scheduled for initialization at the start of its lease period.
+			run_to_block(10);
+			assert!(Paras::is_upcoming(id));
+		});
+	}
+
+	#[test]
+	fn migrate_from_v0_works() {
+		new_test_ext().execute_with(|| {
+			let chain = ParaInfo { scheduling: Scheduling::Always };
+			let thread = ParaInfo { scheduling: Scheduling::Dynamic };
+			let next_free_id = ParaId::from(u32::from(LOWEST_USER_ID) + 5);
+
+			ParasRegistrar::migrate_from_v0(next_free_id, vec![
+				(ParaId::from(1), chain.clone(), vec![3].into(), vec![4].into()),
+				(ParaId::from(2), chain, vec![2].into(), vec![1].into()),
+				(LOWEST_USER_ID, thread, vec![2].into(), vec![1].into()),
+			]);
+
+			// Para 1 is already live, and so is left untouched.
+			assert!(!Paras::is_upcoming(ParaId::from(1)));
+			assert_eq!(Paras::current_code(&ParaId::from(1)), Some(vec![2].into()));
+			assert!(Paras::is_upcoming(ParaId::from(2)));
+			assert!(Paras::is_upcoming(LOWEST_USER_ID));
+			assert_eq!(ParasRegistrar::new_id(), next_free_id);
+
+			// The next free ID is never lowered.
+			ParasRegistrar::migrate_from_v0(LOWEST_USER_ID, vec![]);
+			assert_eq!(ParasRegistrar::next_free_id(), ParaId::from(u32::from(next_free_id) + 1));
+		});
+	}
+}
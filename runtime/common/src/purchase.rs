@@ -18,8 +18,8 @@
 
 use codec::{Encode, Decode};
 use sp_runtime::{Permill, RuntimeDebug, DispatchResult, DispatchError, AnySignature};
-use sp_runtime::traits::{Zero, CheckedAdd, Verify, Saturating};
-use frame_support::{decl_event, decl_storage, decl_module, decl_error, ensure};
+use sp_runtime::traits::{Zero, One, CheckedAdd, Verify, Saturating, UniqueSaturatedInto};
+use frame_support::{decl_event, decl_storage, decl_module, decl_error, ensure, weights::Weight};
 use frame_support::traits::{
 	EnsureOrigin, Currency, ExistenceRequirement, VestingSchedule, Get
 };
@@ -45,6 +45,17 @@ pub trait Trait: system::Trait {
 	type UnlockedProportion: Get<Permill>;
 	/// The maximum amount of locked DOTs that we will unlock.
 	type MaxUnlocked: Get<BalanceOf<Self>>;
+	/// The maximum number of KYC providers whose attestations are counted.
+	type MaxProviders: Get<u32>;
+	/// The maximum number of payouts processed automatically at the start of each block.
+	type MaxPayoutsPerBlock: Get<u32>;
+	/// The maximum number of payout queue entries, whether paid out or dropped, examined at the
+	/// start of each block.
+	type MaxPayoutScansPerBlock: Get<u32>;
+	/// The maximum number of accounts waiting in the payout queue.
+	type MaxQueuedPayouts: Get<u32>;
+	/// The maximum number of vesting tiers.
+	type MaxVestingTiers: Get<u32>;
 }
 
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
@@ -85,6 +96,17 @@ impl AccountValidity {
 	}
 }
 
+/// A vesting tier, applying to purchases of at least `min_locked` locked DOTs.
+#[derive(Encode, Decode, Clone, Eq, PartialEq, RuntimeDebug)]
+pub struct VestingTier<Balance, BlockNumber> {
+	/// The minimum amount of locked DOTs for a purchase to fall into this tier.
+	pub min_locked: Balance,
+	/// The block when the locked DOTs start to unlock.
+	pub start: BlockNumber,
+	/// The number of blocks over which the locked DOTs unlock linearly.
+	pub duration: BlockNumber,
+}
+
 /// All information about an account regarding the purchase of DOTs.
 #[derive(Encode, Decode, Default, Clone, Eq, PartialEq, RuntimeDebug)]
 pub struct AccountStatus<Balance> {
@@ -121,6 +143,17 @@ decl_event!(
 		StatementUpdated,
 		/// A new statement was set.
 		UnlockBlockUpdated(BlockNumber),
+		/// The KYC providers were set, along with how many must agree. (Providers, Threshold)
+		ProvidersUpdated(u32, u32),
+		/// A KYC provider attested to someone's account validity. (Account, Provider, Validity)
+		ValidityAttested(AccountId, AccountId, AccountValidity),
+		/// The vesting tiers were set.
+		VestingTiersUpdated,
+		/// An automatic payout to a purchaser failed. It was removed from the payout queue.
+		PayoutFailed(AccountId, DispatchError),
+		/// The payout queue is full, so a valid account was not queued for an automatic payout.
+		/// It may still be paid out by the payment account.
+		PayoutNotQueued(AccountId),
 	}
 );
 
@@ -142,6 +175,16 @@ decl_error! {
 		InvalidUnlockBlock,
 		/// Vesting schedule already exists for this account.
 		VestingScheduleExists,
+		/// The sender is not a registered KYC provider.
+		NotProvider,
+		/// Too many providers, or a threshold which they could never reach.
+		InvalidProviders,
+		/// Providers may not attest to an account having completed the purchase process.
+		InvalidAttestation,
+		/// Vesting tiers must be sorted by strictly increasing `min_locked` and start in the future.
+		InvalidVestingTiers,
+		/// There are more than `MaxVestingTiers` vesting tiers.
+		TooManyVestingTiers,
 	}
 }
 
@@ -155,6 +198,24 @@ decl_storage! {
 		Statement: Vec<u8>;
 		// The block where all locked dots will unlock.
 		UnlockBlock: T::BlockNumber;
+		// The KYC providers whose attestations are counted towards an account's validity.
+		Providers: Vec<T::AccountId>;
+		// The number of providers which must attest to the same validity for it to be set.
+		AttestationThreshold: u32;
+		// The validity each provider attested to for an account, until the threshold is reached or
+		// the provider is removed.
+		Attestations: double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) T::AccountId
+			=> Option<AccountValidity>;
+		// Valid accounts waiting to be paid out automatically, by their position in the queue.
+		PayoutQueue: map hasher(twox_64_concat) u32 => Option<T::AccountId>;
+		// The positions of the front of the payout queue and one past its back. Positions wrap
+		// around, so the queue holds the `back.wrapping_sub(front)` accounts from `front` on.
+		PayoutQueueBounds: (u32, u32);
+		// The accounts currently in the payout queue.
+		QueuedPayouts: map hasher(blake2_128_concat) T::AccountId => bool;
+		// The vesting tiers of locked DOTs, by strictly increasing `min_locked`. Purchases falling
+		// into none of them unlock in whole at the `UnlockBlock`.
+		VestingTiers: Vec<VestingTier<BalanceOf<T>, T::BlockNumber>>;
 	}
 }
 
@@ -168,10 +229,65 @@ decl_module! {
 		const UnlockedProportion: Permill = T::UnlockedProportion::get();
 		/// The maximum amount of locked DOTs that we will unlock.
 		const MaxUnlocked: BalanceOf<T> = T::MaxUnlocked::get();
+		/// The maximum number of KYC providers whose attestations are counted.
+		const MaxProviders: u32 = T::MaxProviders::get();
+		/// The maximum number of payouts processed automatically at the start of each block.
+		const MaxPayoutsPerBlock: u32 = T::MaxPayoutsPerBlock::get();
+		/// The maximum number of payout queue entries, whether paid out or dropped, examined at the
+		/// start of each block.
+		const MaxPayoutScansPerBlock: u32 = T::MaxPayoutScansPerBlock::get();
+		/// The maximum number of accounts waiting in the payout queue.
+		const MaxQueuedPayouts: u32 = T::MaxQueuedPayouts::get();
+		/// The maximum number of vesting tiers.
+		const MaxVestingTiers: u32 = T::MaxVestingTiers::get();
 
 		/// Deposit one of this module's events by using the default implementation.
 		fn deposit_event() = default;
 
+		/// Pay out up to `MaxPayoutsPerBlock` accounts from the front of the payout queue,
+		/// examining at most `MaxPayoutScansPerBlock` entries of it.
+		///
+		/// Accounts which are no longer valid, or have no balance set yet, are dropped from the
+		/// queue without payment; they are queued again if their balance is updated. They don't
+		/// count towards the payouts.
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			let (front, back) = PayoutQueueBounds::get();
+			if front == back {
+				return T::DbWeight::get().reads(1)
+			}
+
+			let max_scans = back.wrapping_sub(front).min(T::MaxPayoutScansPerBlock::get());
+			let payment_account = PaymentAccount::<T>::get();
+			let mut scans = 0;
+			let mut payouts = 0;
+			while scans < max_scans && payouts < T::MaxPayoutsPerBlock::get() {
+				let position = front.wrapping_add(scans);
+				scans += 1;
+
+				let who = match PayoutQueue::<T>::take(position) {
+					Some(who) => who,
+					None => continue,
+				};
+				QueuedPayouts::<T>::remove(&who);
+
+				let status = Accounts::<T>::get(&who);
+				let total_balance = status.free_balance.saturating_add(status.locked_balance);
+				if !status.validity.is_valid() || total_balance.is_zero() {
+					continue
+				}
+				payouts += 1;
+				if let Err(e) = Self::do_payout(&payment_account, &who) {
+					Self::deposit_event(RawEvent::PayoutFailed(who, e));
+				}
+			}
+			PayoutQueueBounds::put((front.wrapping_add(scans), back));
+
+			T::DbWeight::get().reads_writes(2, 1)
+				+ T::DbWeight::get().reads_writes(2, 2).saturating_mul(scans as Weight)
+				+ T::DbWeight::get().reads_writes(6, 4).saturating_mul(payouts as Weight)
+				+ (200_000_000 as Weight).saturating_mul(payouts as Weight)
+		}
+
 		/// Create a new account. Proof of existence through a valid signed message.
 		///
 		/// We check that the account does not exist at this stage.
@@ -209,27 +325,21 @@ decl_module! {
 		/// We check tht the account exists at this stage, but has not completed the process.
 		///
 		/// Origin must match the `ValidityOrigin`.
-		#[weight = T::DbWeight::get().reads_writes(1, 1)]
+		#[weight = T::DbWeight::get().reads_writes(2, 2)]
 		fn update_validity_status(origin,
 			who: T::AccountId,
 			validity: AccountValidity
 		) {
 			T::ValidityOrigin::ensure_origin(origin)?;
-			ensure!(Accounts::<T>::contains_key(&who), Error::<T>::InvalidAccount);
-			Accounts::<T>::try_mutate(&who, |status: &mut AccountStatus<BalanceOf<T>>| -> DispatchResult {
-				ensure!(status.validity != AccountValidity::Completed, Error::<T>::AlreadyCompleted);
-				status.validity = validity;
-				Ok(())
-			})?;
-			Self::deposit_event(RawEvent::ValidityUpdated(who, validity));
+			Self::set_validity(who, validity)?;
 		}
 
-		/// Update the balance of a valid account.
+		/// Update the balance of a valid account, queueing it for an automatic payout.
 		///
 		/// We check tht the account is valid for a balance transfer at this point.
 		///
 		/// Origin must match the `ValidityOrigin`.
-		#[weight = T::DbWeight::get().reads_writes(2, 1)]
+		#[weight = T::DbWeight::get().reads_writes(3, 2)]
 		fn update_balance(origin,
 			who: T::AccountId,
 			free_balance: BalanceOf<T>,
@@ -248,6 +358,7 @@ decl_module! {
 				status.vat = vat;
 				Ok(())
 			})?;
+			Self::queue_payout(&who);
 			Self::deposit_event(RawEvent::BalanceUpdated(who, free_balance, locked_balance));
 		}
 
@@ -262,43 +373,7 @@ decl_module! {
 			let payment_account = ensure_signed(origin)?;
 			ensure!(payment_account == PaymentAccount::<T>::get(), DispatchError::BadOrigin);
 
-			// Account should not have a vesting schedule.
-			ensure!(T::VestingSchedule::vesting_balance(&who).is_none(), Error::<T>::VestingScheduleExists);
-
-			Accounts::<T>::try_mutate(&who, |status: &mut AccountStatus<BalanceOf<T>>| -> DispatchResult {
-				// Account has a valid status (not Invalid, Pending, or Completed)...
-				ensure!(status.validity.is_valid(), Error::<T>::InvalidAccount);
-
-				// Transfer funds from the payment account into the purchasing user.
-				let total_balance = status.free_balance
-					.checked_add(&status.locked_balance)
-					.ok_or(Error::<T>::Overflow)?;
-				T::Currency::transfer(&payment_account, &who, total_balance, ExistenceRequirement::AllowDeath)?;
-
-				if !status.locked_balance.is_zero() {
-					let unlock_block = UnlockBlock::<T>::get();
-					// We allow some configurable portion of the purchased locked DOTs to be unlocked for basic usage.
-					let unlocked = (T::UnlockedProportion::get() * status.locked_balance).min(T::MaxUnlocked::get());
-					let locked = status.locked_balance.saturating_sub(unlocked);
-					// We checked that this account has no existing vesting schedule. So this function should
-					// never fail, however if it does, not much we can do about it at this point.
-					let _ = T::VestingSchedule::add_vesting_schedule(
-						// Apply vesting schedule to this user
-						&who,
-						// For this much amount
-						locked,
-						// Unlocking the full amount after one block
-						locked,
-						// When everything unlocks
-						unlock_block
-					);
-				}
-
-				// Setting the user account to `Completed` ends the purchase process for this user.
-				status.validity = AccountValidity::Completed;
-				Self::deposit_event(RawEvent::PaymentComplete(who.clone(), status.free_balance, status.locked_balance));
-				Ok(())
-			})?;
+			Self::do_payout(&payment_account, &who)?;
 		}
 
 		/* Configuration Operations */
@@ -337,10 +412,180 @@ decl_module! {
 			UnlockBlock::<T>::set(unlock_block);
 			Self::deposit_event(RawEvent::UnlockBlockUpdated(unlock_block));
 		}
+
+		/// Attest to the validity status of an existing account, as a KYC provider. Once
+		/// `AttestationThreshold` providers agree, the account's status is updated as by
+		/// `update_validity_status`.
+		///
+		/// We check that the account exists at this stage, but has not completed the process.
+		///
+		/// Origin must be one of the registered `Providers`.
+		#[weight = T::DbWeight::get().reads_writes(
+			4 + T::MaxProviders::get() as Weight,
+			3 + T::MaxProviders::get() as Weight,
+		)]
+		fn attest_validity(origin,
+			who: T::AccountId,
+			validity: AccountValidity
+		) {
+			let provider = ensure_signed(origin)?;
+			let providers = Providers::<T>::get();
+			ensure!(providers.contains(&provider), Error::<T>::NotProvider);
+			ensure!(validity != AccountValidity::Completed, Error::<T>::InvalidAttestation);
+			ensure!(Accounts::<T>::contains_key(&who), Error::<T>::InvalidAccount);
+			ensure!(
+				Accounts::<T>::get(&who).validity != AccountValidity::Completed,
+				Error::<T>::AlreadyCompleted,
+			);
+
+			Attestations::<T>::insert(&provider, &who, validity);
+			Self::deposit_event(RawEvent::ValidityAttested(who.clone(), provider, validity));
+
+			let agreeing = providers.iter()
+				.filter(|p| Attestations::<T>::get(p, &who) == Some(validity))
+				.count();
+			if agreeing >= AttestationThreshold::get() as usize {
+				for p in providers.iter() {
+					Attestations::<T>::remove(p, &who);
+				}
+				Self::set_validity(who, validity)?;
+			}
+		}
+
+		/// Set the KYC providers whose attestations are counted towards an account's validity, and
+		/// how many of them must attest to the same validity for it to be set. The attestations of
+		/// providers which are removed are cleared.
+		///
+		/// Origin must match the `ConfigurationOrigin`
+		#[weight = T::DbWeight::get().reads_writes(1, 2 + T::MaxProviders::get() as Weight)]
+		fn set_providers(origin, providers: Vec<T::AccountId>, threshold: u32) {
+			T::ConfigurationOrigin::ensure_origin(origin)?;
+			ensure!(providers.len() <= T::MaxProviders::get() as usize, Error::<T>::InvalidProviders);
+			ensure!(
+				threshold > 0 && threshold as usize <= providers.len(),
+				Error::<T>::InvalidProviders,
+			);
+			for removed in Providers::<T>::get().iter().filter(|p| !providers.contains(p)) {
+				Attestations::<T>::remove_prefix(removed);
+			}
+			let count = providers.len() as u32;
+			Providers::<T>::set(providers);
+			AttestationThreshold::set(threshold);
+			Self::deposit_event(RawEvent::ProvidersUpdated(count, threshold));
+		}
+
+		/// Set the vesting tiers of locked DOTs, by strictly increasing `min_locked`. There may be
+		/// at most `MaxVestingTiers` of them.
+		///
+		/// Origin must match the `ConfigurationOrigin`
+		#[weight = T::DbWeight::get().writes(1)]
+		fn set_vesting_tiers(origin, tiers: Vec<VestingTier<BalanceOf<T>, T::BlockNumber>>) {
+			T::ConfigurationOrigin::ensure_origin(origin)?;
+			ensure!(
+				tiers.len() <= T::MaxVestingTiers::get() as usize,
+				Error::<T>::TooManyVestingTiers,
+			);
+			let now = system::Module::<T>::block_number();
+			ensure!(tiers.iter().all(|t| t.start > now), Error::<T>::InvalidVestingTiers);
+			ensure!(
+				tiers.windows(2).all(|w| w[0].min_locked < w[1].min_locked),
+				Error::<T>::InvalidVestingTiers,
+			);
+			VestingTiers::<T>::set(tiers);
+			Self::deposit_event(RawEvent::VestingTiersUpdated);
+		}
 	}
 }
 
 impl<T: Trait> Module<T> {
+	fn set_validity(who: T::AccountId, validity: AccountValidity) -> DispatchResult {
+		ensure!(Accounts::<T>::contains_key(&who), Error::<T>::InvalidAccount);
+		Accounts::<T>::try_mutate(&who, |status: &mut AccountStatus<BalanceOf<T>>| -> DispatchResult {
+			ensure!(status.validity != AccountValidity::Completed, Error::<T>::AlreadyCompleted);
+			status.validity = validity;
+			Ok(())
+		})?;
+		if validity.is_valid() {
+			Self::queue_payout(&who);
+		}
+		Self::deposit_event(RawEvent::ValidityUpdated(who, validity));
+		Ok(())
+	}
+
+	// Places `who` at the back of the payout queue, unless it is already queued or the queue is
+	// full.
+	fn queue_payout(who: &T::AccountId) {
+		if QueuedPayouts::<T>::get(who) {
+			return
+		}
+		let (front, back) = PayoutQueueBounds::get();
+		if back.wrapping_sub(front) >= T::MaxQueuedPayouts::get() {
+			Self::deposit_event(RawEvent::PayoutNotQueued(who.clone()));
+			return
+		}
+		PayoutQueue::<T>::insert(back, who);
+		PayoutQueueBounds::put((front, back.wrapping_add(1)));
+		QueuedPayouts::<T>::insert(who, true);
+	}
+
+	// Pays a valid account from the payment account and completes its purchase process.
+	fn do_payout(payment_account: &T::AccountId, who: &T::AccountId) -> DispatchResult {
+		// Account should not have a vesting schedule.
+		ensure!(T::VestingSchedule::vesting_balance(who).is_none(), Error::<T>::VestingScheduleExists);
+
+		Accounts::<T>::try_mutate(who, |status: &mut AccountStatus<BalanceOf<T>>| -> DispatchResult {
+			// Account has a valid status (not Invalid, Pending, or Completed)...
+			ensure!(status.validity.is_valid(), Error::<T>::InvalidAccount);
+
+			// Transfer funds from the payment account into the purchasing user.
+			let total_balance = status.free_balance
+				.checked_add(&status.locked_balance)
+				.ok_or(Error::<T>::Overflow)?;
+			T::Currency::transfer(payment_account, who, total_balance, ExistenceRequirement::AllowDeath)?;
+
+			if !status.locked_balance.is_zero() {
+				// We allow some configurable portion of the purchased locked DOTs to be unlocked for basic usage.
+				let unlocked = (T::UnlockedProportion::get() * status.locked_balance).min(T::MaxUnlocked::get());
+				let locked = status.locked_balance.saturating_sub(unlocked);
+				let (per_block, starting_block) = Self::vesting_terms(locked);
+				// We checked that this account has no existing vesting schedule. So this function should
+				// never fail, however if it does, not much we can do about it at this point.
+				let _ = T::VestingSchedule::add_vesting_schedule(
+					// Apply vesting schedule to this user
+					who,
+					// For this much amount
+					locked,
+					// Unlocking this much per block
+					per_block,
+					// From this block
+					starting_block,
+				);
+			}
+
+			// Setting the user account to `Completed` ends the purchase process for this user.
+			status.validity = AccountValidity::Completed;
+			Self::deposit_event(RawEvent::PaymentComplete(who.clone(), status.free_balance, status.locked_balance));
+			Ok(())
+		})
+	}
+
+	// The vesting terms `(per_block, starting_block)` of `locked` DOTs: those of the highest
+	// vesting tier they reach, or unlocking the full amount after the `UnlockBlock` otherwise.
+	fn vesting_terms(locked: BalanceOf<T>) -> (BalanceOf<T>, T::BlockNumber) {
+		match VestingTiers::<T>::get().into_iter().rev().find(|tier| locked >= tier.min_locked) {
+			Some(tier) => {
+				let duration: u32 = tier.duration.unique_saturated_into();
+				let per_block = if duration == 0 {
+					locked
+				} else {
+					(locked / duration.into()).max(One::one())
+				};
+				(per_block, tier.start)
+			}
+			None => (locked, UnlockBlock::<T>::get()),
+		}
+	}
+
 	fn verify_signature(who: &T::AccountId, signature: &[u8]) -> Result<(), DispatchError> {
 		// sr25519 always expects a 64 byte signature.
 		ensure!(signature.len() == 64, Error::<T>::InvalidSignature);
@@ -387,7 +632,7 @@ mod tests {
 		impl_outer_origin, impl_outer_dispatch, assert_ok, assert_noop, parameter_types,
 		ord_parameter_types, dispatch::DispatchError::BadOrigin,
 	};
-	use frame_support::traits::Currency;
+	use frame_support::traits::{Currency, OnInitialize};
 	use balances::Error as BalancesError;
 
 	impl_outer_origin! {
@@ -471,6 +716,11 @@ mod tests {
 		pub const MaxStatementLength: usize =  1_000;
 		pub const UnlockedProportion: Permill = Permill::from_percent(10);
 		pub const MaxUnlocked: u64 = 10;
+		pub const MaxProviders: u32 = 3;
+		pub const MaxPayoutsPerBlock: u32 = 1;
+		pub const MaxPayoutScansPerBlock: u32 = 2;
+		pub const MaxQueuedPayouts: u32 = 2;
+		pub const MaxVestingTiers: u32 = 2;
 	}
 
 	ord_parameter_types! {
@@ -488,6 +738,11 @@ mod tests {
 		type MaxStatementLength = MaxStatementLength;
 		type UnlockedProportion = UnlockedProportion;
 		type MaxUnlocked = MaxUnlocked;
+		type MaxProviders = MaxProviders;
+		type MaxPayoutsPerBlock = MaxPayoutsPerBlock;
+		type MaxPayoutScansPerBlock = MaxPayoutScansPerBlock;
+		type MaxQueuedPayouts = MaxQueuedPayouts;
+		type MaxVestingTiers = MaxVestingTiers;
 	}
 
 	type System = system::Module<Test>;
@@ -568,6 +823,17 @@ mod tests {
 		[42u8; 32].into()
 	}
 
+	fn payout_queue() -> Vec<AccountId> {
+		let (front, back) = PayoutQueueBounds::get();
+		(0..back.wrapping_sub(front))
+			.filter_map(|i| PayoutQueue::<Test>::get(front.wrapping_add(i)))
+			.collect()
+	}
+
+	fn provider(i: u8) -> AccountId {
+		[100 + i; 32].into()
+	}
+
 	#[test]
 	fn set_statement_works_and_handles_basic_errors() {
 		new_test_ext().execute_with(|| {
@@ -1011,4 +1277,303 @@ mod tests {
 			), BalancesError::<Test, _>::InsufficientBalance);
 		});
 	}
+
+	#[test]
+	fn set_providers_works_and_handles_basic_errors() {
+		new_test_ext().execute_with(|| {
+			let providers = vec![provider(0), provider(1), provider(2)];
+			// Invalid origin
+			assert_noop!(
+				Purchase::set_providers(Origin::signed(alice()), providers.clone(), 2),
+				BadOrigin,
+			);
+			// Unreachable or zero threshold
+			assert_noop!(
+				Purchase::set_providers(Origin::signed(configuration_origin()), providers.clone(), 4),
+				Error::<Test>::InvalidProviders,
+			);
+			assert_noop!(
+				Purchase::set_providers(Origin::signed(configuration_origin()), providers.clone(), 0),
+				Error::<Test>::InvalidProviders,
+			);
+			// Too many providers
+			let too_many = vec![provider(0), provider(1), provider(2), provider(3)];
+			assert_noop!(
+				Purchase::set_providers(Origin::signed(configuration_origin()), too_many, 2),
+				Error::<Test>::InvalidProviders,
+			);
+			// Just right...
+			assert_ok!(Purchase::set_providers(Origin::signed(configuration_origin()), providers.clone(), 2));
+			assert_eq!(Providers::<Test>::get(), providers);
+			assert_eq!(AttestationThreshold::get(), 2);
+		});
+	}
+
+	#[test]
+	fn attest_validity_works() {
+		new_test_ext().execute_with(|| {
+			let providers = vec![provider(0), provider(1), provider(2)];
+			assert_ok!(Purchase::set_providers(Origin::signed(configuration_origin()), providers, 2));
+
+			// Only providers may attest, and only to existing accounts.
+			assert_noop!(
+				Purchase::attest_validity(Origin::signed(provider(3)), alice(), AccountValidity::ValidLow),
+				Error::<Test>::NotProvider,
+			);
+			assert_noop!(
+				Purchase::attest_validity(Origin::signed(provider(0)), alice(), AccountValidity::ValidLow),
+				Error::<Test>::InvalidAccount,
+			);
+			assert_ok!(Purchase::create_account(
+				Origin::signed(validity_origin()),
+				alice(),
+				alice_signature().to_vec(),
+			));
+			assert_noop!(
+				Purchase::attest_validity(Origin::signed(provider(0)), alice(), AccountValidity::Completed),
+				Error::<Test>::InvalidAttestation,
+			);
+
+			// Disagreeing attestations do not reach the threshold.
+			assert_ok!(Purchase::attest_validity(Origin::signed(provider(0)), alice(), AccountValidity::ValidLow));
+			assert_ok!(Purchase::attest_validity(Origin::signed(provider(1)), alice(), AccountValidity::Invalid));
+			assert_eq!(Accounts::<Test>::get(alice()).validity, AccountValidity::Initiated);
+			assert!(payout_queue().is_empty());
+
+			// A provider may change its mind, and the agreeing attestations then reach it.
+			assert_ok!(Purchase::attest_validity(Origin::signed(provider(1)), alice(), AccountValidity::ValidLow));
+			assert_eq!(Accounts::<Test>::get(alice()).validity, AccountValidity::ValidLow);
+			assert_eq!(Attestations::<Test>::get(provider(0), alice()), None);
+			assert_eq!(payout_queue(), vec![alice()]);
+		});
+	}
+
+	#[test]
+	fn attestations_of_removed_providers_are_cleared() {
+		new_test_ext().execute_with(|| {
+			let providers = vec![provider(0), provider(1), provider(2)];
+			assert_ok!(Purchase::set_providers(Origin::signed(configuration_origin()), providers, 2));
+			assert_ok!(Purchase::create_account(
+				Origin::signed(validity_origin()),
+				alice(),
+				alice_signature().to_vec(),
+			));
+			assert_ok!(Purchase::attest_validity(Origin::signed(provider(0)), alice(), AccountValidity::ValidLow));
+			assert_ok!(Purchase::attest_validity(Origin::signed(provider(1)), alice(), AccountValidity::Invalid));
+
+			// Removing a provider clears its attestations, but not those of the others.
+			let providers = vec![provider(1), provider(2)];
+			assert_ok!(Purchase::set_providers(Origin::signed(configuration_origin()), providers, 2));
+			assert_eq!(Attestations::<Test>::get(provider(0), alice()), None);
+			assert_eq!(Attestations::<Test>::get(provider(1), alice()), Some(AccountValidity::Invalid));
+
+			// So once the provider is added again, its earlier attestation no longer counts.
+			let providers = vec![provider(0), provider(1), provider(2)];
+			assert_ok!(Purchase::set_providers(Origin::signed(configuration_origin()), providers, 2));
+			assert_ok!(Purchase::attest_validity(Origin::signed(provider(2)), alice(), AccountValidity::ValidLow));
+			assert_eq!(Accounts::<Test>::get(alice()).validity, AccountValidity::Initiated);
+
+			assert_ok!(Purchase::attest_validity(Origin::signed(provider(0)), alice(), AccountValidity::ValidLow));
+			assert_eq!(Accounts::<Test>::get(alice()).validity, AccountValidity::ValidLow);
+			assert_eq!(Attestations::<Test>::get(provider(1), alice()), None);
+		});
+	}
+
+	#[test]
+	fn automatic_payouts_work() {
+		new_test_ext().execute_with(|| {
+			for (who, signature) in vec![(alice(), alice_signature()), (bob(), bob_signature())] {
+				assert_ok!(Purchase::create_account(
+					Origin::signed(validity_origin()),
+					who.clone(),
+					signature.to_vec(),
+				));
+				assert_ok!(Purchase::update_validity_status(
+					Origin::signed(validity_origin()),
+					who,
+					AccountValidity::ValidLow,
+				));
+			}
+			assert_ok!(Purchase::update_balance(
+				Origin::signed(validity_origin()),
+				bob(),
+				100,
+				0,
+				Permill::zero(),
+			));
+			assert_eq!(payout_queue(), vec![alice(), bob()]);
+
+			// Alice has no balance yet, so she is dropped from the queue without payment, which
+			// doesn't count towards the one payout made per block.
+			Purchase::on_initialize(1);
+			assert!(payout_queue().is_empty());
+			assert_eq!(Accounts::<Test>::get(alice()).validity, AccountValidity::ValidLow);
+			assert_eq!(<Test as Trait>::Currency::free_balance(&bob()), 100);
+			assert_eq!(Accounts::<Test>::get(bob()).validity, AccountValidity::Completed);
+
+			// Setting her balance queues her again.
+			assert_ok!(Purchase::update_balance(
+				Origin::signed(validity_origin()),
+				alice(),
+				50,
+				50,
+				Permill::zero(),
+			));
+			assert_eq!(payout_queue(), vec![alice()]);
+
+			Purchase::on_initialize(2);
+			assert!(payout_queue().is_empty());
+			assert_eq!(<Test as Trait>::Currency::free_balance(&alice()), 100);
+			assert_eq!(<Test as Trait>::VestingSchedule::vesting_balance(&alice()), Some(45));
+			assert_eq!(Accounts::<Test>::get(alice()).validity, AccountValidity::Completed);
+			assert_eq!(<Test as Trait>::Currency::free_balance(&payment_account()), 99_800);
+		});
+	}
+
+	#[test]
+	fn payout_queue_is_bounded() {
+		new_test_ext().execute_with(|| {
+			let accounts = vec![
+				(alice(), alice_signature()),
+				(bob(), bob_signature()),
+				(alice_ed25519(), alice_signature_ed25519()),
+			];
+			for (who, signature) in accounts {
+				assert_ok!(Purchase::create_account(
+					Origin::signed(validity_origin()),
+					who.clone(),
+					signature.to_vec(),
+				));
+				assert_ok!(Purchase::update_validity_status(
+					Origin::signed(validity_origin()),
+					who,
+					AccountValidity::ValidLow,
+				));
+			}
+			// The third account did not fit into the queue.
+			assert_eq!(payout_queue(), vec![alice(), bob()]);
+			assert!(!QueuedPayouts::<Test>::get(alice_ed25519()));
+
+			// Queueing an account again does not duplicate it.
+			assert_ok!(Purchase::update_validity_status(
+				Origin::signed(validity_origin()),
+				alice(),
+				AccountValidity::ValidHigh,
+			));
+			assert_eq!(payout_queue(), vec![alice(), bob()]);
+
+			// Once there is room, it is queued when its balance is updated.
+			Purchase::on_initialize(1);
+			assert!(!QueuedPayouts::<Test>::get(alice()));
+			assert!(!QueuedPayouts::<Test>::get(bob()));
+			assert_ok!(Purchase::update_balance(
+				Origin::signed(validity_origin()),
+				alice_ed25519(),
+				100,
+				0,
+				Permill::zero(),
+			));
+			assert_eq!(payout_queue(), vec![alice_ed25519()]);
+		});
+	}
+
+	#[test]
+	fn failed_automatic_payouts_are_dropped() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Purchase::create_account(
+				Origin::signed(validity_origin()),
+				alice(),
+				alice_signature().to_vec(),
+			));
+			assert_ok!(Purchase::update_validity_status(
+				Origin::signed(validity_origin()),
+				alice(),
+				AccountValidity::ValidHigh,
+			));
+			assert_ok!(Purchase::update_balance(
+				Origin::signed(validity_origin()),
+				alice(),
+				100_000,
+				100_000,
+				Permill::zero(),
+			));
+
+			Purchase::on_initialize(1);
+			assert!(payout_queue().is_empty());
+			assert_eq!(<Test as Trait>::Currency::free_balance(&alice()), 0);
+			assert_eq!(Accounts::<Test>::get(alice()).validity, AccountValidity::ValidHigh);
+		});
+	}
+
+	#[test]
+	fn vesting_tiers_work() {
+		new_test_ext().execute_with(|| {
+			let tier = |min_locked, start, duration| VestingTier { min_locked, start, duration };
+			// Invalid origin
+			assert_noop!(
+				Purchase::set_vesting_tiers(Origin::signed(alice()), vec![tier(100, 50, 10)]),
+				BadOrigin,
+			);
+			// Unsorted
+			assert_noop!(
+				Purchase::set_vesting_tiers(
+					Origin::signed(configuration_origin()),
+					vec![tier(100, 50, 10), tier(100, 60, 10)],
+				),
+				Error::<Test>::InvalidVestingTiers,
+			);
+			// In the past
+			System::set_block_number(10);
+			assert_noop!(
+				Purchase::set_vesting_tiers(Origin::signed(configuration_origin()), vec![tier(100, 10, 10)]),
+				Error::<Test>::InvalidVestingTiers,
+			);
+			// Too many
+			assert_noop!(
+				Purchase::set_vesting_tiers(
+					Origin::signed(configuration_origin()),
+					vec![tier(100, 50, 10), tier(200, 60, 10), tier(300, 70, 10)],
+				),
+				Error::<Test>::TooManyVestingTiers,
+			);
+			// Just right...
+			assert_ok!(Purchase::set_vesting_tiers(
+				Origin::signed(configuration_origin()),
+				vec![tier(100, 50, 10), tier(200, 60, 100)],
+			));
+
+			// Below all tiers: everything unlocks after the unlock block.
+			assert_eq!(Purchase::vesting_terms(90), (90, 100));
+			// Tiers are picked by the locked amount, and unlock linearly over their duration.
+			assert_eq!(Purchase::vesting_terms(100), (10, 50));
+			assert_eq!(Purchase::vesting_terms(199), (19, 50));
+			assert_eq!(Purchase::vesting_terms(500), (5, 60));
+
+			// Payouts follow the tiers.
+			assert_ok!(Purchase::create_account(
+				Origin::signed(validity_origin()),
+				bob(),
+				bob_signature().to_vec(),
+			));
+			assert_ok!(Purchase::update_validity_status(
+				Origin::signed(validity_origin()),
+				bob(),
+				AccountValidity::ValidHigh,
+			));
+			assert_ok!(Purchase::update_balance(
+				Origin::signed(validity_origin()),
+				bob(),
+				0,
+				210,
+				Permill::zero(),
+			));
+			assert_ok!(Purchase::payout(Origin::signed(payment_account()), bob()));
+			// A max of 10 units is unlocked automatically, and the rest falls into the second tier.
+			assert_eq!(<Test as Trait>::VestingSchedule::vesting_balance(&bob()), Some(200));
+			System::set_block_number(70);
+			let vest_call = Call::Vesting(vesting::Call::<Test>::vest());
+			assert_ok!(vest_call.dispatch(Origin::signed(bob())));
+			assert_eq!(<Test as Trait>::VestingSchedule::vesting_balance(&bob()), Some(180));
+		});
+	}
 }
@@ -24,8 +24,9 @@ use sp_std::marker::PhantomData;
 use codec::{Encode, Decode};
 
 use sp_runtime::{
+	RuntimeDebug,
 	transaction_validity::{TransactionValidityError, ValidTransaction, TransactionValidity},
-	traits::{Hash as HashT, SignedExtension, DispatchInfoOf},
+	traits::{Hash as HashT, SignedExtension, DispatchInfoOf, UniqueSaturatedInto},
 };
 
 use frame_support::{
@@ -97,8 +98,8 @@ impl<T: Trait> Registrar<T::AccountId> for Module<T> {
 		initial_head_data: HeadData,
 	) -> DispatchResult {
 		ensure!(!Paras::contains_key(id), Error::<T>::ParaAlreadyExists);
-		if let Scheduling::Always = info.scheduling {
-			Parachains::mutate(|parachains|
+		match info.scheduling {
+			Scheduling::Always => Parachains::mutate(|parachains|
 				match parachains.binary_search(&id) {
 					Ok(_) => Err(Error::<T>::ParaAlreadyExists),
 					Err(idx) => {
@@ -106,7 +107,12 @@ impl<T: Trait> Registrar<T::AccountId> for Module<T> {
 						Ok(())
 					}
 				}
-			)?;
+			)?,
+			Scheduling::Dynamic => {
+				let count = ParathreadCount::get();
+				ensure!(count < T::MaxParathreads::get(), Error::<T>::TooManyParathreads);
+				ParathreadCount::put(count + 1);
+			}
 		}
 		<parachains::Module<T>>::initialize_para(id, code, initial_head_data);
 		Paras::insert(id, info);
@@ -115,12 +121,13 @@ impl<T: Trait> Registrar<T::AccountId> for Module<T> {
 
 	fn deregister_para(id: ParaId) -> DispatchResult {
 		let info = Paras::take(id).ok_or(Error::<T>::InvalidChainId)?;
-		if let Scheduling::Always = info.scheduling {
-			Parachains::mutate(|parachains|
+		match info.scheduling {
+			Scheduling::Always => Parachains::mutate(|parachains|
 				parachains.binary_search(&id)
 					.map(|index| parachains.remove(index))
 					.map_err(|_| Error::<T>::InvalidChainId)
-			)?;
+			)?,
+			Scheduling::Dynamic => ParathreadCount::mutate(|c| *c = c.saturating_sub(1)),
 		}
 		<parachains::Module<T>>::cleanup_para(id);
 		Paras::remove(id);
@@ -131,6 +138,19 @@ impl<T: Trait> Registrar<T::AccountId> for Module<T> {
 type BalanceOf<T> =
 	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
+/// A bid for a parathread to be progressed, placed in the parathread auction of a block.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct ThreadBid<AccountId, Balance> {
+	/// The parathread to be progressed.
+	pub para: ParaId,
+	/// The collator which will provide the parathread's block.
+	pub collator: CollatorId,
+	/// The account whose funds are reserved for the bid.
+	pub bidder: AccountId,
+	/// The amount bid.
+	pub amount: Balance,
+}
+
 pub trait Trait: parachains::Trait {
 	/// The overarching event type.
 	type Event: From<Event> + Into<<Self as system::Trait>::Event>;
@@ -157,8 +177,15 @@ pub trait Trait: parachains::Trait {
 
 	/// The number of rotations that you will have as grace if you miss a block.
 	type MaxRetries: Get<u32>;
+
+	/// The maximum number of parathreads which can be registered at once. As there is at most one
+	/// bid per parathread, this also bounds the number of bids in a parathread auction.
+	type MaxParathreads: Get<u32>;
 }
 
+/// The weight of handling a single bid in a parathread auction, besides its database accesses.
+const BID_WEIGHT: Weight = 1_000_000;
+
 decl_storage! {
 	trait Store for Module<T: Trait> as Registrar {
 		// Vector of all parachain IDs, in ascending order.
@@ -194,6 +221,16 @@ decl_storage! {
 
 		/// Users who have paid a parathread's deposit
 		Debtors: map hasher(twox_64_concat) ParaId => T::AccountId;
+
+		/// The number of registered parathreads.
+		ParathreadCount get(fn parathread_count): u32;
+
+		/// The bids placed in the parathread auction of the current block, in the order they
+		/// were placed. There is at most one bid per parathread.
+		ThreadBids: Vec<ThreadBid<T::AccountId, BalanceOf<T>>>;
+
+		/// The winning bids of the latest concluded parathread auction, ordered by para ID.
+		ThreadAuctionWinners get(fn thread_auction_winners): Vec<ThreadBid<T::AccountId, BalanceOf<T>>>;
 	}
 	add_extra_genesis {
 		config(parachains): Vec<(ParaId, ValidationCode, HeadData)>;
@@ -249,6 +286,12 @@ decl_error! {
 		CodeTooLarge,
 		/// Invalid para head data size.
 		HeadDataTooLarge,
+		/// The head hash of a parathread bid does not match its current head.
+		StaleHead,
+		/// A bid for the parathread with at least the same amount has already been placed.
+		BidTooLow,
+		/// The maximum number of parathreads is already registered.
+		TooManyParathreads,
 	}
 }
 
@@ -257,6 +300,9 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: <T as system::Trait>::Origin, system = system {
 		type Error = Error<T>;
 
+		/// The maximum number of parathreads which can be registered at once.
+		const MaxParathreads: u32 = T::MaxParathreads::get();
+
 		fn deposit_event() = default;
 
 		/// Register a parachain with given code. Must be called by root.
@@ -321,6 +367,11 @@ decl_module! {
 		) {
 			let who = ensure_signed(origin)?;
 
+			ensure!(
+				ParathreadCount::get() < T::MaxParathreads::get(),
+				Error::<T>::TooManyParathreads,
+			);
+
 			<T as Trait>::Currency::reserve(&who, T::ParathreadDeposit::get())?;
 
 			let info = ParaInfo {
@@ -352,16 +403,41 @@ decl_module! {
 		/// Place a bid for a parathread to be progressed in the next block.
 		///
 		/// This is a kind of special transaction that should be heavily prioritized in the
-		/// transaction pool according to the `value`; only `ThreadCount` of them may be presented
-		/// in any single block.
-		#[weight = 0]
+		/// transaction pool according to the `bid`. The bid is reserved from the sender, and
+		/// replaces any lower bid for the same parathread in this block, which is refunded.
+		///
+		/// At the end of the block, the highest `ThreadCount` bids win, ties being won by the
+		/// earliest bid: winners pay their bid and are scheduled, while losers are refunded.
+		///
+		/// The bids of the block are read and written whole, so this is weighed by the number
+		/// of bids, which is bounded by `MaxParathreads`.
+		#[weight = T::DbWeight::get().reads_writes(5, 4)
+			.saturating_add(BID_WEIGHT.saturating_mul(T::MaxParathreads::get() as Weight))]
 		fn select_parathread(origin,
-			#[compact] _id: ParaId,
-			_collator: CollatorId,
-			_head_hash: T::Hash,
+			#[compact] id: ParaId,
+			collator: CollatorId,
+			head_hash: T::Hash,
+			#[compact] bid: BalanceOf<T>,
 		) {
-			ensure_signed(origin)?;
-			// Everything else is checked for in the transaction `SignedExtension`.
+			let who = ensure_signed(origin)?;
+
+			Self::ensure_thread_id(id).ok_or(Error::<T>::InvalidThreadId)?;
+			let head = <parachains::Module<T>>::parachain_head(&id).ok_or(Error::<T>::InvalidThreadId)?;
+			ensure!(T::Hashing::hash(&head.0) == head_hash, Error::<T>::StaleHead);
+
+			let mut bids = ThreadBids::<T>::get();
+			let outbid = bids.iter().position(|b| b.para == id);
+			if let Some(i) = outbid {
+				ensure!(bid > bids[i].amount, Error::<T>::BidTooLow);
+			}
+
+			<T as Trait>::Currency::reserve(&who, bid)?;
+			if let Some(i) = outbid {
+				let old = bids.remove(i);
+				let _ = <T as Trait>::Currency::unreserve(&old.bidder, old.amount);
+			}
+			bids.push(ThreadBid { para: id, collator, bidder: who, amount: bid });
+			ThreadBids::<T>::put(bids);
 		}
 
 		/// Deregister a parathread and retrieve the deposit.
@@ -466,10 +542,13 @@ decl_module! {
 
 			Active::put(paras);
 
-			0
+			// Reserve the weight of concluding the parathread auction in `on_finalize`.
+			Self::thread_auction_weight(T::MaxParathreads::get())
 		}
 
 		fn on_finalize() {
+			Self::conclude_thread_auction();
+
 			// a block without this will panic, but let's not panic here.
 			if let Some(proceeded_vec) = parachains::DidUpdate::get() {
 				// Active is sorted and DidUpdate is a sorted subset of its elements.
@@ -501,6 +580,9 @@ decl_event!{
 
 		/// The parathread of the supplied ID was de-registered.
 		ParathreadDeregistered(ParaId),
+
+		/// The parathread of the supplied ID won a slot in the parathread auction of this block.
+		ParathreadSelected(ParaId),
 	}
 }
 
@@ -514,6 +596,52 @@ impl<T: Trait> Module<T> {
 		})
 	}
 
+	/// The weight of concluding a parathread auction with the given number of bids, each of
+	/// which is either paid or refunded by its bidder.
+	fn thread_auction_weight(bids: u32) -> Weight {
+		let bids = bids as Weight;
+		T::DbWeight::get().reads_writes(bids.saturating_add(3), bids.saturating_add(3))
+			.saturating_add(BID_WEIGHT.saturating_mul(bids))
+	}
+
+	/// Conclude the parathread auction of this block: the highest `ThreadCount` bids pay their
+	/// bid and are appended to the selected threads, while all other bids are refunded.
+	fn conclude_thread_auction() {
+		let mut bids = ThreadBids::<T>::take();
+		if bids.is_empty() {
+			ThreadAuctionWinners::<T>::kill();
+			return
+		}
+
+		// Highest bids first; the sort is stable, so ties are won by the earliest bid.
+		bids.sort_by(|a, b| b.amount.cmp(&a.amount));
+		let losers = bids.split_off(bids.len().min(ThreadCount::get() as usize));
+		for loser in losers {
+			let _ = <T as Trait>::Currency::unreserve(&loser.bidder, loser.amount);
+		}
+
+		let mut winners = bids;
+		winners.sort_by_key(|b| b.para);
+		SelectedThreads::mutate(|queue| {
+			if queue.is_empty() {
+				queue.push(vec![]);
+			}
+			let selected_threads = queue.last_mut().expect("queue is not empty, as ensured above; qed");
+			for winner in winners.iter() {
+				// The winner may already have been selected for this slot, e.g. before a swap.
+				if let Err(pos) = selected_threads.binary_search_by_key(&winner.para, |&(id, _)| id) {
+					selected_threads.insert(pos, (winner.para, winner.collator.clone()));
+				}
+			}
+		});
+		for winner in winners.iter() {
+			// The winning bid is burned.
+			let _ = <T as Trait>::Currency::slash_reserved(&winner.bidder, winner.amount);
+			Self::deposit_event(Event::ParathreadSelected(winner.para));
+		}
+		ThreadAuctionWinners::<T>::put(winners);
+	}
+
 	fn retry_later(sched: (ParaId, CollatorId), retries: u32) {
 		if retries < T::MaxRetries::get() {
 			RetryQueue::mutate(|q| {
@@ -536,6 +664,12 @@ impl<T: Trait> Module<T> {
 
 	/// Forcibly remove the threads matching `m` from all current and future scheduling.
 	fn force_unschedule(m: impl Fn(ParaId) -> bool) {
+		ThreadBids::<T>::mutate(|bids| bids.retain(|b| if m(b.para) {
+			let _ = <T as Trait>::Currency::unreserve(&b.bidder, b.amount);
+			false
+		} else {
+			true
+		}));
 		RetryQueue::mutate(|qs| for q in qs.iter_mut() {
 			q.retain(|i| !m(i.0))
 		});
@@ -558,6 +692,18 @@ impl<T: Trait> ActiveParas for Module<T> {
 	}
 }
 
+sp_api::decl_runtime_apis! {
+	/// The API for querying the per-block parathread auction.
+	pub trait ParathreadAuctionApi<AccountId, Balance> where
+		AccountId: codec::Codec,
+		Balance: codec::Codec,
+	{
+		/// The winning bids of the latest concluded parathread auction, ordered by para ID. Each
+		/// winner is scheduled with the bid's collator `QueueSize` blocks later.
+		fn parathread_auction_winners() -> Vec<ThreadBid<AccountId, Balance>>;
+	}
+}
+
 /// Ensure that parathread selections happen prioritized by fees.
 #[derive(Encode, Decode, Clone, Eq, PartialEq)]
 pub struct LimitParathreadCommits<T: Trait + Send + Sync>(sp_std::marker::PhantomData<T>) where
@@ -613,30 +759,21 @@ impl<T: Trait + Send + Sync> SignedExtension for LimitParathreadCommits<T> where
 	) -> TransactionValidity {
 		let mut r = ValidTransaction::default();
 		if let Some(local_call) = call.is_sub_type() {
-			if let Call::select_parathread(id, collator, hash) = local_call {
+			if let Call::select_parathread(id, _collator, hash, bid) = local_call {
 				// ensure that the para ID is actually a parathread.
 				let e = TransactionValidityError::from(InvalidTransaction::Custom(ValidityError::InvalidId as u8));
 				<Module<T>>::ensure_thread_id(*id).ok_or(e)?;
 
-				// ensure that we haven't already had a full complement of selected parathreads.
-				let mut upcoming_selected_threads = SelectedThreads::get();
-				if upcoming_selected_threads.is_empty() {
-					upcoming_selected_threads.push(vec![]);
-				}
-				let i = upcoming_selected_threads.len() - 1;
-				let selected_threads = &mut upcoming_selected_threads[i];
-				let thread_count = ThreadCount::get() as usize;
-				ensure!(
-					selected_threads.len() < thread_count,
-					InvalidTransaction::ExhaustsResources,
-				);
+				// ensure that parathreads may be scheduled at all.
+				ensure!(ThreadCount::get() > 0, InvalidTransaction::ExhaustsResources);
 
-				// ensure that this is not selecting a duplicate parathread ID
+				// ensure that this is not a duplicate of a bid at least as high for this
+				// parathread.
 				let e = TransactionValidityError::from(InvalidTransaction::Custom(ValidityError::Duplicate as u8));
-				let pos = selected_threads
-					.binary_search_by(|&(ref other_id, _)| other_id.cmp(id))
-					.err()
-					.ok_or(e)?;
+				ensure!(
+					!<ThreadBids<T>>::get().iter().any(|b| b.para == *id && b.amount >= *bid),
+					e,
+				);
 
 				// ensure that this is a live bid (i.e. that the thread's chain head matches)
 				let e = TransactionValidityError::from(InvalidTransaction::Custom(ValidityError::InvalidId as u8));
@@ -644,10 +781,8 @@ impl<T: Trait + Send + Sync> SignedExtension for LimitParathreadCommits<T> where
 				let actual = T::Hashing::hash(&head.0);
 				ensure!(&actual == hash, InvalidTransaction::Stale);
 
-				// updated the selected threads.
-				selected_threads.insert(pos, (*id, collator.clone()));
-				sp_std::mem::drop(selected_threads);
-				SelectedThreads::put(upcoming_selected_threads);
+				// bids are ranked by amount in the auction, so they should be in the pool too.
+				r.priority = (*bid).unique_saturated_into();
 
 				// provides the state-transition for this head-data-hash; this should cue the pool
 				// to throw out competing transactions with lesser fees.
@@ -771,6 +906,7 @@ mod tests {
 	parameter_types!{
 		pub const LeasePeriod: BlockNumber = 10;
 		pub const EndingPeriod: BlockNumber = 3;
+		pub const RandomnessRevealDelay: BlockNumber = 0;
 	}
 
 	impl slots::Trait for Test {
@@ -780,6 +916,7 @@ mod tests {
 		type EndingPeriod = EndingPeriod;
 		type LeasePeriod = LeasePeriod;
 		type Randomness = RandomnessCollectiveFlip;
+		type RandomnessRevealDelay = RandomnessRevealDelay;
 	}
 
 	parameter_types!{
@@ -944,6 +1081,7 @@ mod tests {
 		pub const ParathreadDeposit: Balance = 10;
 		pub const QueueSize: usize = 2;
 		pub const MaxRetries: u32 = 3;
+		pub const MaxParathreads: u32 = 5;
 	}
 
 	impl Trait for Test {
@@ -954,6 +1092,7 @@ mod tests {
 		type SwapAux = slots::Module<Test>;
 		type QueueSize = QueueSize;
 		type MaxRetries = MaxRetries;
+		type MaxParathreads = MaxParathreads;
 	}
 
 	type Balances = balances::Module<Test>;
@@ -1049,7 +1188,7 @@ mod tests {
 	fn schedule_thread(id: ParaId, head_data: &[u8], col: &CollatorId) {
 		let tx: LimitParathreadCommits<Test> = LimitParathreadCommits(Default::default());
 		let hdh = BlakeTwo256::hash(head_data);
-		let inner_call = super::Call::select_parathread(id, col.clone(), hdh);
+		let inner_call = super::Call::select_parathread(id, col.clone(), hdh, 1);
 		let call = Call::Registrar(inner_call);
 		let origin = 4u64;
 		assert!(tx.validate(&origin, &call, &Default::default(), 0).is_ok());
@@ -1501,38 +1640,63 @@ mod tests {
 			let bad_head_hash = <Test as system::Trait>::Hashing::hash(&vec![1, 2, 1]);
 			let good_head_hash = <Test as system::Trait>::Hashing::hash(&vec![1, 1, 1]);
 			let info = &DispatchInfo::default();
+			let col = CollatorId::default();
+
+			// No threads allowed
+			let inner = super::Call::select_parathread(good_para_id, col.clone(), good_head_hash, 10);
+			let call = Call::Registrar(inner);
+			assert_noop!(
+				LimitParathreadCommits::<Test>(std::marker::PhantomData)
+					.validate(&0, &call, info, 0),
+				InvalidTransaction::ExhaustsResources,
+			);
 
 			// Allow for threads
 			assert_ok!(Registrar::set_thread_count(Origin::root(), 10));
 
 			// Bad parathread id
-			let col = CollatorId::default();
-			let inner = super::Call::select_parathread(bad_para_id, col.clone(), good_head_hash);
+			let inner = super::Call::select_parathread(bad_para_id, col.clone(), good_head_hash, 10);
 			let call = Call::Registrar(inner);
 			assert!(
 				LimitParathreadCommits::<Test>(std::marker::PhantomData)
 					.validate(&0, &call, info, 0).is_err()
 			);
+			assert_noop!(call.dispatch(Origin::signed(0)), Error::<Test>::InvalidThreadId);
 
 			// Bad head data
-			let inner = super::Call::select_parathread(good_para_id, col.clone(), bad_head_hash);
+			let inner = super::Call::select_parathread(good_para_id, col.clone(), bad_head_hash, 10);
 			let call = Call::Registrar(inner);
 			assert!(
 				LimitParathreadCommits::<Test>(std::marker::PhantomData)
 					.validate(&0, &call, info, 0).is_err()
 			);
+			assert_noop!(call.dispatch(Origin::signed(0)), Error::<Test>::StaleHead);
 
-			// No duplicates
-			let inner = super::Call::select_parathread(good_para_id, col.clone(), good_head_hash);
+			// No duplicates, unless outbidding
+			let inner = super::Call::select_parathread(good_para_id, col.clone(), good_head_hash, 10);
 			let call = Call::Registrar(inner);
 			assert!(
 				LimitParathreadCommits::<Test>(std::marker::PhantomData)
 					.validate(&0, &call, info, 0).is_ok()
 			);
+			assert_ok!(call.clone().dispatch(Origin::signed(0)));
 			assert!(
 				LimitParathreadCommits::<Test>(std::marker::PhantomData)
 					.validate(&0, &call, info, 0).is_err()
 			);
+			assert_noop!(call.dispatch(Origin::signed(1)), Error::<Test>::BidTooLow);
+
+			let inner = super::Call::select_parathread(good_para_id, col.clone(), good_head_hash, 11);
+			let call = Call::Registrar(inner);
+			assert!(
+				LimitParathreadCommits::<Test>(std::marker::PhantomData)
+					.validate(&1, &call, info, 0).is_ok()
+			);
+			assert_ok!(call.dispatch(Origin::signed(1)));
+			// The outbid bid is refunded.
+			assert_eq!(Balances::reserved_balance(0), ParathreadDeposit::get());
+			assert_eq!(Balances::reserved_balance(1), 11);
+			assert_eq!(ThreadBids::<Test>::get().len(), 1);
 		});
 	}
 
@@ -1558,46 +1722,57 @@ mod tests {
 			// Only 3 slots available... who will win??
 			assert_ok!(Registrar::set_thread_count(Origin::root(), 3));
 
-			// Everyone wants a thread
-			for x in 0..5 {
+			// Everyone wants a thread, bidding from their own account.
+			let bids: [Balance; 5] = [5, 50, 20, 40, 20];
+			for x in 0..5u8 {
 				let para_id = user_id(x as u32);
 				let collator_id = CollatorId::default();
 				let head_hash = <Test as system::Trait>::Hashing::hash(&vec![x; 3]);
-				let inner = super::Call::select_parathread(para_id, collator_id, head_hash);
+				let inner = super::Call::select_parathread(para_id, collator_id, head_hash, bids[x as usize]);
 				let call = Call::Registrar(inner);
 				let info = &DispatchInfo::default();
 
-				// First 3 transactions win a slot
-				if x < 3 {
-					assert!(
-						LimitParathreadCommits::<Test>(std::marker::PhantomData)
-							.validate(&0, &call, info, 0)
-							.is_ok()
-					);
-				} else {
-					// All others lose
-					assert_noop!(
-						LimitParathreadCommits::<Test>(std::marker::PhantomData)
-							.validate(&0, &call, info, 0),
-						InvalidTransaction::ExhaustsResources,
-					);
-				}
+				// Bids are prioritized by amount in the pool.
+				let validity = LimitParathreadCommits::<Test>(std::marker::PhantomData)
+					.validate(&(x as u64), &call, info, 0)
+					.unwrap();
+				assert_eq!(validity.priority, bids[x as usize] as u64);
+				assert_ok!(call.dispatch(Origin::signed(x as u64)));
+				assert_eq!(Balances::reserved_balance(x as u64), ParathreadDeposit::get() + bids[x as usize]);
 			}
 
-			// 3 Threads are selected
+			// The auction concludes at the end of the block.
+			run_to_block(4);
+
+			// The 3 highest bids win, the earliest winning the tie.
 			assert_eq!(
-				SelectedThreads::get()[1],
+				SelectedThreads::get()[0],
 				vec![
-					(user_id(0), CollatorId::default()),
 					(user_id(1), CollatorId::default()),
 					(user_id(2), CollatorId::default()),
+					(user_id(3), CollatorId::default()),
 				]
 			);
+			assert_eq!(
+				Registrar::thread_auction_winners().into_iter().map(|b| (b.para, b.amount)).collect::<Vec<_>>(),
+				vec![(user_id(1), 50), (user_id(2), 20), (user_id(3), 40)],
+			);
+			assert!(ThreadBids::<Test>::get().is_empty());
+
+			// Winners paid their bid, losers were refunded.
+			for x in 0..5u64 {
+				assert_eq!(Balances::reserved_balance(x), ParathreadDeposit::get());
+				let paid = if x == 0 || x == 4 { 0 } else { bids[x as usize] };
+				assert_eq!(
+					Balances::free_balance(x),
+					10_000_000 - ParathreadDeposit::get() - paid,
+				);
+			}
 
 			// Assuming Queue Size is 2
 			assert_eq!(<Test as self::Trait>::QueueSize::get(), 2);
 
-			// 2 blocks later
+			// 2 blocks after the auction
 			run_to_block(5);
 			// Threads left queue
 			assert_eq!(SelectedThreads::get()[0], vec![]);
@@ -1605,11 +1780,63 @@ mod tests {
 			assert_eq!(
 				Registrar::active_paras(),
 				vec![
-					(user_id(0), Some((CollatorId::default(), Retriable::WithRetries(0)))),
 					(user_id(1), Some((CollatorId::default(), Retriable::WithRetries(0)))),
 					(user_id(2), Some((CollatorId::default(), Retriable::WithRetries(0)))),
+					(user_id(3), Some((CollatorId::default(), Retriable::WithRetries(0)))),
 				]
 			);
+
+			// No bids, no winners.
+			assert_eq!(Registrar::thread_auction_winners(), vec![]);
+		});
+	}
+
+	#[test]
+	fn parathread_registration_is_bounded() {
+		new_test_ext(vec![]).execute_with(|| {
+			run_to_block(2);
+			for x in 0..MaxParathreads::get() as u8 {
+				let o = Origin::signed(x as u64);
+				assert_ok!(Registrar::register_parathread(o, vec![x; 3].into(), vec![x; 3].into()));
+			}
+			assert_eq!(Registrar::parathread_count(), MaxParathreads::get());
+
+			assert_noop!(
+				Registrar::register_parathread(Origin::signed(9), vec![9].into(), vec![9].into()),
+				Error::<Test>::TooManyParathreads,
+			);
+			assert_noop!(
+				Registrar::register_para(
+					Origin::root(),
+					user_id(9),
+					ParaInfo { scheduling: Scheduling::Dynamic },
+					vec![9; 3].into(),
+					vec![9; 3].into(),
+				),
+				Error::<Test>::TooManyParathreads,
+			);
+			assert_eq!(Balances::reserved_balance(9), 0);
+
+			// Parachains are not bounded.
+			assert_ok!(Registrar::register_para(
+				Origin::root(),
+				user_id(9),
+				ParaInfo { scheduling: Scheduling::Always },
+				vec![9; 3].into(),
+				vec![9; 3].into(),
+			));
+
+			// Deregistering a parathread makes room for another.
+			run_to_block(3);
+			assert_ok!(Registrar::deregister_parathread(
+				parachains::Origin::Parachain(user_id(0)).into()
+			));
+			assert_eq!(Registrar::parathread_count(), MaxParathreads::get() - 1);
+			assert_ok!(Registrar::register_parathread(
+				Origin::signed(9),
+				vec![9; 3].into(),
+				vec![9; 3].into(),
+			));
 		});
 	}
 
@@ -17,6 +17,11 @@
 //! Auctioning system to determine the set of Parachains in operation. This includes logic for the
 //! auctioning mechanism, for locking balance as part of the "payment", and to provide the requisite
 //! information for commissioning and decommissioning them.
+//!
+//! Auctions are candle auctions: once the ending period of an auction has passed, the block at
+//! which the auction actually ended is sampled retroactively from within the ending period. To keep
+//! bidders from predicting that block, the sample is only taken once randomness which was revealed
+//! after the ending period is available, `RandomnessRevealDelay` blocks after it closes.
 
 use sp_std::{prelude::*, mem::swap, convert::TryInto};
 use sp_runtime::traits::{
@@ -54,8 +59,14 @@ pub trait Trait: system::Trait {
 	/// The number of blocks over which a single period lasts.
 	type LeasePeriod: Get<Self::BlockNumber>;
 
-	/// Something that provides randomness in the runtime.
+	/// Something that provides randomness in the runtime. This is used to sample the block at
+	/// which an auction ended from its ending period.
 	type Randomness: Randomness<Self::Hash>;
+
+	/// The number of blocks after the close of an auction's ending period until `Randomness` is
+	/// guaranteed to only depend on data revealed after the close, e.g. until BABE randomness is
+	/// derived only from VRF outputs of later epochs. The auction is concluded then.
+	type RandomnessRevealDelay: Get<Self::BlockNumber>;
 }
 
 /// A sub-bidder identifier. Used to distinguish between different logical bidders coming from the
@@ -87,6 +98,10 @@ pub enum Bidder<AccountId> {
 	/// An existing parachain, funds coming from the amount locked as part of a previous bid topped
 	/// up with funds administered by the parachain.
 	Existing(ParaId),
+
+	/// An existing parachain, funds coming from the amount locked as part of a previous bid topped
+	/// up with funds from the given account (e.g. a crowdfund raised to extend the lease).
+	Extension(ParaId, NewBidder<AccountId>),
 }
 
 impl<AccountId: Clone + Default + Codec> Bidder<AccountId> {
@@ -95,6 +110,15 @@ impl<AccountId: Clone + Default + Codec> Bidder<AccountId> {
 		match self {
 			Bidder::New(new_bidder) => new_bidder.who.clone(),
 			Bidder::Existing(para_id) => para_id.into_account(),
+			Bidder::Extension(_, funder) => funder.who.clone(),
+		}
+	}
+
+	/// Get the existing parachain that this bid would renew, if any.
+	fn existing_para(&self) -> Option<ParaId> {
+		match self {
+			Bidder::New(_) => None,
+			Bidder::Existing(para_id) | Bidder::Extension(para_id, _) => Some(*para_id),
 		}
 	}
 }
@@ -125,7 +149,7 @@ type WinningData<T> =
 // Winners data type. This encodes each of the final winners of a parachain auction, the parachain
 // index assigned to them, their winning bid and the range that they won.
 type WinnersData<T> =
-	Vec<(Option<NewBidder<<T as system::Trait>::AccountId>>, ParaId, BalanceOf<T>, SlotRange)>;
+	Vec<(Bidder<<T as system::Trait>::AccountId>, ParaId, BalanceOf<T>, SlotRange)>;
 
 // This module's storage items.
 decl_storage! {
@@ -157,6 +181,9 @@ decl_storage! {
 		/// The first item in the tuple is the lease period index that the first of the four
 		/// contiguous lease periods on auction is for. The second is the block number when the
 		/// auction will "begin to end", i.e. the first block of the Ending Period of the auction.
+		///
+		/// This is kept until the auction is concluded, after the ending period and the randomness
+		/// reveal delay have passed.
 		pub AuctionInfo get(fn auction_info): Option<(LeasePeriodOf<T>, T::BlockNumber)>;
 
 		/// The winning bids for each of the 10 ranges at each block in the final Ending Period of
@@ -184,6 +211,20 @@ decl_storage! {
 		/// Off-boarding account; currency held on deposit for the parachain gets placed here if the
 		/// parachain gets off-boarded; i.e. its lease period is up and it isn't renewed.
 		pub Offboarding get(fn offboarding): map hasher(twox_64_concat) ParaId => T::AccountId;
+
+		/// Extensions of existing parachains' leases that were won in an auction, keyed by the
+		/// account funding the extension, with the last lease period that was won. Kept until the
+		/// funder claims it (e.g. when a crowdfund is on-boarded).
+		pub ExtensionWins get(fn extension_wins):
+			map hasher(twox_64_concat) NewBidder<T::AccountId> => Option<LeasePeriodOf<T>>;
+
+		/// The accounts which funded extensions of each parachain's lease, with the last lease
+		/// period of the extension and the amount still owed to them. Once that lease period has
+		/// ended, the amount is repaid out of the deposit released by the parachain, ahead of any
+		/// rebate to the parachain or its off-boarding account.
+		pub ExtensionFunders get(fn extension_funders):
+			map hasher(twox_64_concat) ParaId =>
+			Vec<(T::AccountId, LeasePeriodOf<T>, BalanceOf<T>)>;
 	}
 }
 
@@ -197,6 +238,7 @@ impl<T: Trait> SwapAux for Module<T> {
 	fn on_swap(one: ParaId, other: ParaId) -> Result<(), &'static str> {
 		<Offboarding<T>>::swap(one, other);
 		<Deposits<T>>::swap(one, other);
+		<ExtensionFunders<T>>::swap(one, other);
 		ManagedIds::mutate(|ids| swap_ordered_existence(ids, one, other));
 		Ok(())
 	}
@@ -215,6 +257,11 @@ decl_event!(
 		/// An auction started. Provides its index and the block number where it will begin to
 		/// close and the first lease period of the quadruplet that is auctioned.
 		AuctionStarted(AuctionIndex, LeasePeriod, BlockNumber),
+		/// The ending period of an auction passed and bidding closed. The auction will be concluded
+		/// at the given block, once the randomness used to sample its end has been revealed.
+		BiddingClosed(AuctionIndex, BlockNumber),
+		/// The end of an auction was sampled as the given offset into its ending period.
+		EndingSampled(AuctionIndex, BlockNumber),
 		/// An auction ended. All funds become unreserved.
 		AuctionClosed(AuctionIndex),
 		/// Someone won the right to deploy a parachain. Balance amount is deducted for deposit.
@@ -236,6 +283,8 @@ decl_error! {
 		AuctionInProgress,
 		/// The lease period is in the past.
 		LeasePeriodInPast,
+		/// The auction would only be concluded after the first lease period it auctions has begun.
+		AuctionConcludesTooLate,
 		/// The origin for this call must be a parachain.
 		NotParaOrigin,
 		/// The parachain ID is not onboarding.
@@ -254,6 +303,8 @@ decl_error! {
 		NotCurrentAuction,
 		/// Not an auction.
 		NotAuction,
+		/// The ending period of the auction has passed and it no longer accepts bids.
+		BiddingClosed,
 		/// Given code size is too large.
 		CodeTooLarge,
 		/// Given initial head data is too large.
@@ -271,6 +322,14 @@ decl_module! {
 			let lease_period = T::LeasePeriod::get();
 			let lease_period_index: LeasePeriodOf<T> = (n / lease_period).into();
 
+			// Check to see if bidding on an auction just closed.
+			if let Some((_, early_end)) = <AuctionInfo<T>>::get() {
+				if early_end + T::EndingPeriod::get() == n {
+					let conclude_at = n + T::RandomnessRevealDelay::get();
+					Self::deposit_event(RawEvent::BiddingClosed(Self::auction_counter(), conclude_at));
+				}
+			}
+
 			// Check to see if an auction just ended.
 			if let Some((winning_ranges, auction_lease_period_index)) = Self::check_auction_end(n) {
 				// Auction is ended now. We have the winning ranges and the lease period index which
@@ -309,6 +368,9 @@ decl_module! {
 		/// This can only happen when there isn't already an auction in progress and may only be
 		/// called by the root origin. Accepts the `duration` of this auction and the
 		/// `lease_period_index` of the initial lease period of the four that are to be auctioned.
+		///
+		/// The auction is concluded `EndingPeriod + RandomnessRevealDelay` blocks after its
+		/// `duration`, which must be no later than the first block of the initial lease period.
 		#[weight = (100_000_000, DispatchClass::Operational)]
 		pub fn new_auction(origin,
 			#[compact] duration: T::BlockNumber,
@@ -318,11 +380,15 @@ decl_module! {
 			ensure!(!Self::is_in_progress(), Error::<T>::AuctionInProgress);
 			ensure!(lease_period_index >= Self::lease_period_index(), Error::<T>::LeasePeriodInPast);
 
+			let ending = <system::Module<T>>::block_number() + duration;
+			let conclude_at = ending + T::EndingPeriod::get() + T::RandomnessRevealDelay::get();
+			let lease_period_start = lease_period_index * T::LeasePeriod::get();
+			ensure!(conclude_at <= lease_period_start, Error::<T>::AuctionConcludesTooLate);
+
 			// Bump the counter.
 			let n = <AuctionCounter>::mutate(|n| { *n += 1; *n });
 
 			// Set the information.
-			let ending = <system::Module<T>>::block_number() + duration;
 			<AuctionInfo<T>>::put((lease_period_index, ending));
 
 			Self::deposit_event(RawEvent::AuctionStarted(n, lease_period_index, ending))
@@ -487,6 +553,13 @@ impl<T: Trait> Module<T> {
 		<Deposits<T>>::get(para_id).into_iter().max().unwrap_or_else(Zero::zero)
 	}
 
+	/// The last lease period for which a deposit is held for a particular parachain that we
+	/// administer, if any.
+	pub fn last_lease_period(para_id: &ParaId) -> Option<LeasePeriodOf<T>> {
+		let held = <Deposits<T>>::decode_len(para_id).unwrap_or(0) as u32;
+		held.checked_sub(1).map(|offset| Self::lease_period_index() + offset.into())
+	}
+
 	/// True if an auction is in progress.
 	pub fn is_in_progress() -> bool {
 		<AuctionInfo<T>>::exists()
@@ -506,27 +579,38 @@ impl<T: Trait> Module<T> {
 	}
 
 	/// Returns the current lease period.
-	fn lease_period_index() -> LeasePeriodOf<T> {
+	pub fn lease_period_index() -> LeasePeriodOf<T> {
 		(<system::Module<T>>::block_number() / T::LeasePeriod::get()).into()
 	}
 
-	/// Some when the auction's end is known (with the end block number). None if it is unknown.
-	/// If `Some` then the block number must be at most the previous block and at least the
-	/// previous block minus `T::EndingPeriod::get()`.
+	/// Some when the auction's end is known (with the winning bids at the end). None if it is
+	/// unknown. The end is sampled `T::RandomnessRevealDelay::get()` blocks after the ending period
+	/// of the auction closes, so that the randomness used is not known to bidders at the time they
+	/// bid.
 	///
 	/// This mutates the state, cleaning up `AuctionInfo` and `Winning` in the case of an auction
 	/// ending. An immediately subsequent call with the same argument will always return `None`.
 	fn check_auction_end(now: T::BlockNumber) -> Option<(WinningData<T>, LeasePeriodOf<T>)> {
 		if let Some((lease_period_index, early_end)) = <AuctionInfo<T>>::get() {
-			if early_end + T::EndingPeriod::get() == now {
-				// Just ended!
-				let ending_period = T::EndingPeriod::get();
+			let ending_period = T::EndingPeriod::get();
+			if early_end + ending_period + T::RandomnessRevealDelay::get() == now {
+				// Randomness revealed after the ending period is now available; sample the end.
 				let offset = T::BlockNumber::decode(&mut T::Randomness::random_seed().as_ref())
 					.expect("secure hashes always bigger than block numbers; qed") % ending_period;
+				Self::deposit_event(RawEvent::EndingSampled(Self::auction_counter(), offset));
+
 				let res = <Winning<T>>::get(offset).unwrap_or_default();
 				let mut i = T::BlockNumber::zero();
 				while i < ending_period {
-					<Winning<T>>::remove(i);
+					// Unreserve all amounts that were reserved for the bids, including those of
+					// bidders which were winning at other blocks of the ending period only. We
+					// will later deduct the amounts from the bidders that ended up being assigned
+					// the slot so there's no need to special-case them here.
+					for (bidder, _) in <Winning<T>>::take(i).iter().flatten().filter_map(|x| x.as_ref()) {
+						if let Some(amount) = <ReservedAmounts<T>>::take(bidder) {
+							T::Currency::unreserve(&bidder.funding_account(), amount);
+						}
+					}
 					i += One::one();
 				}
 				<AuctionInfo<T>>::kill();
@@ -536,34 +620,40 @@ impl<T: Trait> Module<T> {
 		None
 	}
 
-	/// Auction just ended. We have the current lease period, the auction's lease period (which
-	/// is guaranteed to be at least the current period) and the bidders that were winning each
-	/// range at the time of the auction's close.
+	/// Auction just ended. We have the current lease period, the auction's lease period and the
+	/// bidders that were winning each range at the time of the auction's close. The amounts
+	/// reserved for bids must already have been unreserved.
+	///
+	/// `new_auction` ensures that the auction's lease period has not begun before the auction's
+	/// conclusion, other than in the very block in which it is concluded. Should it have begun
+	/// nonetheless, the lease periods can't be honoured and the auction is void: no funds are
+	/// taken from the bidders.
 	fn manage_auction_end(
 		lease_period_index: LeasePeriodOf<T>,
 		auction_lease_period_index: LeasePeriodOf<T>,
 		winning_ranges: WinningData<T>,
 	) {
-		// First, unreserve all amounts that were reserved for the bids. We will later deduct the
-		// amounts from the bidders that ended up being assigned the slot so there's no need to
-		// special-case them here.
-		for (bidder, _) in winning_ranges.iter().filter_map(|x| x.as_ref()) {
-			if let Some(amount) = <ReservedAmounts<T>>::take(bidder) {
-				T::Currency::unreserve(&bidder.funding_account(), amount);
-			}
-		}
+		Self::deposit_event(RawEvent::AuctionClosed(Self::auction_counter()));
 
-		// Next, calculate the winning combination of slots and thus the final winners of the
+		// The offset of the auction's lease period from the current one.
+		let offset = match auction_lease_period_index
+			.checked_sub(&lease_period_index)
+			.and_then(|x| x.checked_into::<usize>())
+		{
+			Some(offset) => offset,
+			None => return,
+		};
+
+		// All amounts that were reserved for the bids have been unreserved when the auction ended.
+		// Calculate the winning combination of slots and thus the final winners of the
 		// auction.
 		let winners = Self::calculate_winners(winning_ranges, T::Parachains::new_id);
 
-		Self::deposit_event(RawEvent::AuctionClosed(Self::auction_counter()));
-
 		// Go through those winners and deduct their bid, updating our table of deposits
 		// accordingly.
-		for (maybe_new_deploy, para_id, amount, range) in winners.into_iter() {
-			match maybe_new_deploy {
-				Some(bidder) => {
+		for (winner, para_id, amount, range) in winners.into_iter() {
+			match winner {
+				Bidder::New(bidder) => {
 					// For new deployments we ensure the full amount is deducted. This should always
 					// succeed as we just unreserved the same amount above.
 					if T::Currency::withdraw(
@@ -597,14 +687,14 @@ impl<T: Trait> Module<T> {
 					let entry = (begin_lease_period, IncomingParachain::Unset(bidder));
 					<Onboarding<T>>::insert(&para_id, entry);
 				}
-				None => {
+				renewal => {
 					// For renewals, reserve any extra on top of what we already have held
 					// on deposit for their chain.
 					let extra = if let Some(additional) =
 						amount.checked_sub(&Self::deposit_held(&para_id))
 					{
 						if T::Currency::withdraw(
-							&para_id.into_account(),
+							&renewal.funding_account(),
 							additional,
 							WithdrawReason::Fee.into(),
 							ExistenceRequirement::AllowDeath
@@ -615,43 +705,47 @@ impl<T: Trait> Module<T> {
 					} else {
 						Default::default()
 					};
+					if let Bidder::Extension(_, funder) = renewal {
+						let last_lease_period = auction_lease_period_index
+							+ <LeasePeriodOf<T>>::from(range.as_pair().1 as u32);
+						// The funder is owed what it paid once the extension has ended.
+						if !extra.is_zero() {
+							<ExtensionFunders<T>>::mutate(para_id, |funders| {
+								funders.push((funder.who.clone(), last_lease_period, extra))
+							});
+						}
+						<ExtensionWins<T>>::insert(funder, last_lease_period);
+					}
 					Self::deposit_event(RawEvent::WonRenewal(para_id, range, extra, amount));
 				}
 			}
 
 			// Finally, we update the deposit held so it is `amount` for the new lease period
 			// indices that were won in the auction.
-			let maybe_offset = auction_lease_period_index
-				.checked_sub(&lease_period_index)
-				.and_then(|x| x.checked_into::<usize>());
-			if let Some(offset) = maybe_offset {
-				// Should always succeed; for it to fail it would mean we auctioned a lease period
-				// that already ended.
-
-				// The lease period index range (begin, end) that newly belongs to this parachain
-				// ID. We need to ensure that it features in `Deposits` to prevent it from being
-				// reaped too early (any managed parachain whose `Deposits` set runs low will be
-				// removed).
-				let pair = range.as_pair();
-				let pair = (pair.0 as usize + offset, pair.1 as usize + offset);
-				<Deposits<T>>::mutate(para_id, |d| {
-					// Left-pad with zeroes as necessary.
-					if d.len() < pair.0 {
-						d.resize_with(pair.0, Default::default);
-					}
-					// Then place the deposit values for as long as the chain should exist.
-					for i in pair.0 ..= pair.1 {
-						if d.len() > i {
-							// The chain bought the same lease period twice. Just take the maximum.
-							d[i] = d[i].max(amount);
-						} else if d.len() == i {
-							d.push(amount);
-						} else {
-							unreachable!("earlier resize means it must be >= i; qed")
-						}
+			//
+			// The lease period index range (begin, end) that newly belongs to this parachain
+			// ID. We need to ensure that it features in `Deposits` to prevent it from being
+			// reaped too early (any managed parachain whose `Deposits` set runs low will be
+			// removed).
+			let pair = range.as_pair();
+			let pair = (pair.0 as usize + offset, pair.1 as usize + offset);
+			<Deposits<T>>::mutate(para_id, |d| {
+				// Left-pad with zeroes as necessary.
+				if d.len() < pair.0 {
+					d.resize_with(pair.0, Default::default);
+				}
+				// Then place the deposit values for as long as the chain should exist.
+				for i in pair.0 ..= pair.1 {
+					if d.len() > i {
+						// The chain bought the same lease period twice. Just take the maximum.
+						d[i] = d[i].max(amount);
+					} else if d.len() == i {
+						d.push(amount);
+					} else {
+						unreachable!("earlier resize means it must be >= i; qed")
 					}
-				});
-			}
+				}
+			});
 		}
 	}
 
@@ -678,10 +772,13 @@ impl<T: Trait> Module<T> {
 							// commissioned.
 							let _ = T::Parachains::deregister_para(id.clone());
 						}
-						// Return the full deposit to the off-boarding account.
-						T::Currency::deposit_creating(&<Offboarding<T>>::take(id), d[0]);
+						// Return the full deposit, less what is owed to the funders of extensions,
+						// to the off-boarding account.
+						let remaining = Self::repay_extension_funders(id, lease_period_index, d[0]);
+						T::Currency::deposit_creating(&<Offboarding<T>>::take(id), remaining);
 						// Remove the now-empty deposits set and don't keep the ID around.
 						<Deposits<T>>::remove(id);
+						<ExtensionFunders<T>>::remove(id);
 						false
 					} else {
 						// The parachain entry is continuing into the next lease period.
@@ -693,9 +790,12 @@ impl<T: Trait> Module<T> {
 						// Then we need to get the new amount that should continue to be held on
 						// deposit for the parachain.
 						let new_held = d.into_iter().max().unwrap_or_default();
-						// If this is less than what we were holding previously, then return it
-						// to the parachain itself.
+						// If this is less than what we were holding previously, then repay the
+						// funders of extensions which have ended and return the rest to the
+						// parachain itself.
 						if let Some(rebate) = outgoing.checked_sub(&new_held) {
+							let rebate =
+								Self::repay_extension_funders(id, lease_period_index, rebate);
 							T::Currency::deposit_creating(
 								&id.into_account(),
 								rebate
@@ -726,6 +826,35 @@ impl<T: Trait> Module<T> {
 		}
 	}
 
+	/// Repay the funders of extensions of a parachain's lease which ended before the given lease
+	/// period out of the `released` deposit, in the order the extensions were won and for as far
+	/// as it goes. Anything still owed is repaid out of deposits released later. Returns what is
+	/// left of the released deposit.
+	fn repay_extension_funders(
+		para_id: &ParaId,
+		lease_period_index: LeasePeriodOf<T>,
+		mut released: BalanceOf<T>,
+	) -> BalanceOf<T> {
+		let mut funders = <ExtensionFunders<T>>::get(para_id);
+		for (funder, last_lease_period, owed) in funders.iter_mut() {
+			if *last_lease_period < lease_period_index {
+				let repaid = (*owed).min(released);
+				T::Currency::deposit_creating(funder, repaid);
+				*owed -= repaid;
+				released -= repaid;
+			}
+		}
+
+		funders.retain(|(_, _, owed)| !owed.is_zero());
+		if funders.is_empty() {
+			<ExtensionFunders<T>>::remove(para_id);
+		} else {
+			<ExtensionFunders<T>>::insert(para_id, funders);
+		}
+
+		released
+	}
+
 	/// Actually place a bid in the current auction.
 	///
 	/// - `bidder`: The account that will be funding this bid.
@@ -744,7 +873,12 @@ impl<T: Trait> Module<T> {
 		// Bidding on latest auction.
 		ensure!(auction_index == <AuctionCounter>::get(), Error::<T>::NotCurrentAuction);
 		// Assume it's actually an auction (this should never fail because of above).
-		let (first_lease_period, _) = <AuctionInfo<T>>::get().ok_or(Error::<T>::NotAuction)?;
+		let (first_lease_period, early_end) = <AuctionInfo<T>>::get().ok_or(Error::<T>::NotAuction)?;
+		// Bids may not be placed after the ending period, while the end is yet to be sampled.
+		ensure!(
+			<system::Module<T>>::block_number() < early_end + T::EndingPeriod::get(),
+			Error::<T>::BiddingClosed,
+		);
 
 		// Our range.
 		let range = SlotRange::new_bounded(first_lease_period, first_slot, last_slot)?;
@@ -773,7 +907,7 @@ impl<T: Trait> Module<T> {
 
 			// Get the amount already held on deposit on our behalf if this is a renewal bid from
 			// an existing parachain.
-			let deposit_held = if let Bidder::Existing(ref bidder_para_id) = bidder {
+			let deposit_held = if let Some(ref bidder_para_id) = bidder.existing_para() {
 				Self::deposit_held(bidder_para_id)
 			} else {
 				Zero::zero()
@@ -866,10 +1000,11 @@ impl<T: Trait> Module<T> {
 			swap(&mut final_winner, winning[r as u8 as usize].as_mut()
 				.expect("none values are filtered out in previous logic; qed"));
 			let (slot_winner, bid) = final_winner;
-			match slot_winner {
-				Bidder::New(new_bidder) => (Some(new_bidder), new_id(), bid, r),
-				Bidder::Existing(para_id) => (None, para_id, bid, r),
-			}
+			let para_id = match slot_winner {
+				Bidder::New(_) => new_id(),
+				Bidder::Existing(para_id) | Bidder::Extension(para_id, _) => para_id,
+			};
+			(slot_winner, para_id, bid, r)
 		}).collect::<Vec<_>>()
 	}
 }
@@ -952,6 +1087,28 @@ mod tests {
 		pub static PARACHAIN_COUNT: RefCell<u32> = RefCell::new(0);
 		pub static PARACHAINS:
 			RefCell<HashMap<u32, (ValidationCode, HeadData)>> = RefCell::new(HashMap::new());
+		pub static RANDOM_SEED: RefCell<H256> = RefCell::new(Default::default());
+		pub static RANDOMNESS_REVEAL_DELAY: RefCell<BlockNumber> = RefCell::new(0);
+	}
+
+	pub struct TestRandomness;
+	impl Randomness<H256> for TestRandomness {
+		fn random(_subject: &[u8]) -> H256 {
+			RANDOM_SEED.with(|r| *r.borrow())
+		}
+	}
+
+	fn set_random_seed(offset: BlockNumber) {
+		let mut seed = [0u8; 32];
+		seed[..4].copy_from_slice(&offset.to_le_bytes());
+		RANDOM_SEED.with(|r| *r.borrow_mut() = seed.into());
+	}
+
+	pub struct RandomnessRevealDelay;
+	impl Get<BlockNumber> for RandomnessRevealDelay {
+		fn get() -> BlockNumber {
+			RANDOMNESS_REVEAL_DELAY.with(|d| *d.borrow())
+		}
 	}
 
 	const MAX_CODE_SIZE: u32 = 100;
@@ -1022,13 +1179,13 @@ mod tests {
 		type Parachains = TestParachains;
 		type LeasePeriod = LeasePeriod;
 		type EndingPeriod = EndingPeriod;
-		type Randomness = RandomnessCollectiveFlip;
+		type Randomness = TestRandomness;
+		type RandomnessRevealDelay = RandomnessRevealDelay;
 	}
 
 	type System = system::Module<Test>;
 	type Balances = balances::Module<Test>;
 	type Slots = Module<Test>;
-	type RandomnessCollectiveFlip = randomness_collective_flip::Module<Test>;
 
 	// This function basically just builds a genesis storage key/value store according to
 	// our desired mock up.
@@ -1127,6 +1284,121 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn auction_end_is_sampled_after_reveal_delay() {
+		new_test_ext().execute_with(|| {
+			RANDOMNESS_REVEAL_DELAY.with(|d| *d.borrow_mut() = 3);
+			run_to_block(1);
+
+			// the auction is concluded at block 12, after the first lease period has begun.
+			assert_noop!(
+				Slots::new_auction(Origin::root(), 5, 1),
+				Error::<Test>::AuctionConcludesTooLate,
+			);
+
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 2));
+
+			// ending period is blocks 6, 7 and 8.
+			run_to_block(6);
+			assert_eq!(Slots::is_ending(System::block_number()), Some(0));
+			assert_ok!(Slots::bid(Origin::signed(1), 0, 1, 2, 5, 1));
+
+			run_to_block(7);
+			assert_ok!(Slots::bid(Origin::signed(2), 0, 1, 2, 5, 2));
+
+			run_to_block(8);
+			assert_ok!(Slots::bid(Origin::signed(3), 0, 1, 2, 5, 3));
+
+			// the ending period has closed, but the auction is not concluded yet.
+			run_to_block(9);
+			assert_eq!(Slots::is_ending(System::block_number()), None);
+			assert_eq!(Slots::is_in_progress(), true);
+			assert_noop!(
+				Slots::bid(Origin::signed(4), 0, 1, 2, 5, 4),
+				Error::<Test>::BiddingClosed,
+			);
+
+			// the randomness known when bidding closed would have sampled the last block of the
+			// ending period.
+			set_random_seed(2);
+
+			run_to_block(11);
+			assert_eq!(Slots::is_in_progress(), true);
+			assert_eq!(Balances::reserved_balance(3), 3);
+
+			// but the randomness revealed afterwards samples the first.
+			set_random_seed(3);
+
+			run_to_block(12);
+			assert_eq!(Slots::is_in_progress(), false);
+			assert_eq!(Slots::onboard_queue(2), vec![0.into()]);
+			assert_eq!(
+				Slots::onboarding(ParaId::from(0)),
+				Some((2, IncomingParachain::Unset(NewBidder { who: 1, sub: 0 }))),
+			);
+			assert_eq!(Balances::free_balance(1), 9);
+			assert_eq!(Balances::reserved_balance(3), 0);
+			assert_eq!(Balances::free_balance(3), 30);
+		});
+	}
+
+	#[test]
+	fn auction_concluding_after_its_lease_period_began_is_void() {
+		new_test_ext().execute_with(|| {
+			run_to_block(1);
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
+
+			run_to_block(6);
+			assert_ok!(Slots::bid(Origin::signed(1), 0, 1, 1, 4, 1));
+			assert_eq!(Balances::reserved_balance(1), 1);
+
+			// should the auction nonetheless be concluded only after the first lease period it
+			// auctions has begun, at block 20, the lease periods can't be honoured.
+			<AuctionInfo<Test>>::put((1, 17));
+
+			run_to_block(20);
+			assert_eq!(Slots::is_in_progress(), false);
+			assert_eq!(Slots::onboard_queue(1), Vec::<ParaId>::new());
+			assert_eq!(Slots::managed_ids(), Vec::<ParaId>::new());
+			assert_eq!(Slots::deposits(ParaId::from(0)), Vec::<u64>::new());
+			assert_eq!(Balances::reserved_balance(1), 0);
+			assert_eq!(Balances::free_balance(1), 10);
+		});
+	}
+
+	#[test]
+	fn sampled_block_decides_winner_and_other_bids_are_unreserved() {
+		new_test_ext().execute_with(|| {
+			run_to_block(1);
+
+			assert_ok!(Slots::new_auction(Origin::root(), 5, 1));
+
+			// ending period is blocks 6, 7 and 8; each block has a different winner.
+			run_to_block(6);
+			assert_ok!(Slots::bid(Origin::signed(1), 0, 1, 1, 4, 1));
+			run_to_block(7);
+			assert_ok!(Slots::bid(Origin::signed(2), 0, 1, 1, 4, 2));
+			run_to_block(8);
+			assert_ok!(Slots::bid(Origin::signed(3), 0, 1, 1, 4, 3));
+
+			// the middle block of the ending period is sampled.
+			set_random_seed(1);
+
+			run_to_block(9);
+			assert_eq!(Slots::is_in_progress(), false);
+			assert_eq!(
+				Slots::onboarding(ParaId::from(0)),
+				Some((1, IncomingParachain::Unset(NewBidder { who: 2, sub: 0 }))),
+			);
+			assert_eq!(Balances::free_balance(2), 18);
+			// bidders which were only winning at blocks that were not sampled get their funds back.
+			assert_eq!(Balances::reserved_balance(1), 0);
+			assert_eq!(Balances::free_balance(1), 10);
+			assert_eq!(Balances::reserved_balance(3), 0);
+			assert_eq!(Balances::free_balance(3), 30);
+		});
+	}
+
 	#[test]
 	fn can_win_auction() {
 		new_test_ext().execute_with(|| {
@@ -1532,7 +1804,7 @@ mod tests {
 			Some((Bidder::New(NewBidder{who: 1, sub: 0}), 1)),
 		];
 		let winners = vec![
-			(Some(NewBidder{who: 1, sub: 0}), 0.into(), 1, SlotRange::ThreeThree)
+			(Bidder::New(NewBidder{who: 1, sub: 0}), 0.into(), 1, SlotRange::ThreeThree)
 		];
 
 		assert_eq!(Slots::calculate_winners(winning, TestParachains::new_id), winners);
@@ -1553,7 +1825,7 @@ mod tests {
 			None,
 		];
 		let winners = vec![
-			(Some(NewBidder{who: 1, sub: 0}), 0.into(), 1, SlotRange::ZeroZero)
+			(Bidder::New(NewBidder{who: 1, sub: 0}), 0.into(), 1, SlotRange::ZeroZero)
 		];
 
 		assert_eq!(Slots::calculate_winners(winning, TestParachains::new_id), winners);
@@ -1585,10 +1857,10 @@ mod tests {
 			Some((Bidder::New(NewBidder{who: 5, sub: 0}), 1)),
 		];
 		let winners = vec![
-			(Some(NewBidder{who: 2,sub: 0}), 0.into(), 2, SlotRange::ZeroZero),
-			(Some(NewBidder{who: 3,sub: 0}), 1.into(), 1, SlotRange::OneOne),
-			(Some(NewBidder{who: 1,sub: 0}), 2.into(), 53, SlotRange::TwoTwo),
-			(Some(NewBidder{who: 5,sub: 0}), 3.into(), 1, SlotRange::ThreeThree)
+			(Bidder::New(NewBidder{who: 2, sub: 0}), 0.into(), 2, SlotRange::ZeroZero),
+			(Bidder::New(NewBidder{who: 3, sub: 0}), 1.into(), 1, SlotRange::OneOne),
+			(Bidder::New(NewBidder{who: 1, sub: 0}), 2.into(), 53, SlotRange::TwoTwo),
+			(Bidder::New(NewBidder{who: 5, sub: 0}), 3.into(), 1, SlotRange::ThreeThree)
 		];
 
 		assert_eq!(Slots::calculate_winners(winning.clone(), TestParachains::new_id), winners);
@@ -1596,19 +1868,19 @@ mod tests {
 		reset_count();
 		winning[SlotRange::ZeroThree as u8 as usize] = Some((Bidder::New(NewBidder{who: 1, sub: 0}), 2));
 		let winners = vec![
-			(Some(NewBidder{who: 2,sub: 0}), 0.into(), 2, SlotRange::ZeroZero),
-			(Some(NewBidder{who: 3,sub: 0}), 1.into(), 1, SlotRange::OneOne),
-			(Some(NewBidder{who: 1,sub: 0}), 2.into(), 53, SlotRange::TwoTwo),
-			(Some(NewBidder{who: 5,sub: 0}), 3.into(), 1, SlotRange::ThreeThree)
+			(Bidder::New(NewBidder{who: 2, sub: 0}), 0.into(), 2, SlotRange::ZeroZero),
+			(Bidder::New(NewBidder{who: 3, sub: 0}), 1.into(), 1, SlotRange::OneOne),
+			(Bidder::New(NewBidder{who: 1, sub: 0}), 2.into(), 53, SlotRange::TwoTwo),
+			(Bidder::New(NewBidder{who: 5, sub: 0}), 3.into(), 1, SlotRange::ThreeThree)
 		];
 		assert_eq!(Slots::calculate_winners(winning.clone(), TestParachains::new_id), winners);
 
 		reset_count();
 		winning[SlotRange::ZeroOne as u8 as usize] = Some((Bidder::New(NewBidder{who: 4, sub: 0}), 3));
 		let winners = vec![
-			(Some(NewBidder{who: 4,sub: 0}), 0.into(), 3, SlotRange::ZeroOne),
-			(Some(NewBidder{who: 1,sub: 0}), 1.into(), 53, SlotRange::TwoTwo),
-			(Some(NewBidder{who: 5,sub: 0}), 2.into(), 1, SlotRange::ThreeThree)
+			(Bidder::New(NewBidder{who: 4, sub: 0}), 0.into(), 3, SlotRange::ZeroOne),
+			(Bidder::New(NewBidder{who: 1, sub: 0}), 1.into(), 53, SlotRange::TwoTwo),
+			(Bidder::New(NewBidder{who: 5, sub: 0}), 2.into(), 1, SlotRange::ThreeThree)
 		];
 		assert_eq!(Slots::calculate_winners(winning.clone(), TestParachains::new_id), winners);
 	}
@@ -87,13 +87,13 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("kusama"),
 	impl_name: create_runtime_str!("parity-kusama"),
 	authoring_version: 2,
-	spec_version: 2015,
+	spec_version: 2017,
 	impl_version: 0,
 	#[cfg(not(feature = "disable-runtime-api"))]
 	apis: RUNTIME_API_VERSIONS,
 	#[cfg(feature = "disable-runtime-api")]
 	apis: version::create_apis_vec![[]],
-	transaction_version: 2,
+	transaction_version: 4,
 };
 
 /// Native version.
@@ -676,6 +676,8 @@ parameter_types! {
 	pub const ParathreadDeposit: Balance = 5 * DOLLARS;
 	pub const QueueSize: usize = 2;
 	pub const MaxRetries: u32 = 3;
+	// Concluding a full parathread auction weighs less than 1% of the maximum block weight.
+	pub const MaxParathreads: u32 = 100;
 }
 
 impl registrar::Trait for Runtime {
@@ -686,11 +688,15 @@ impl registrar::Trait for Runtime {
 	type SwapAux = Slots;
 	type QueueSize = QueueSize;
 	type MaxRetries = MaxRetries;
+	type MaxParathreads = MaxParathreads;
 }
 
 parameter_types! {
 	pub const LeasePeriod: BlockNumber = 100_000;
 	pub const EndingPeriod: BlockNumber = 1000;
+	// BABE randomness is derived from VRF outputs of two epochs before, so this guarantees that
+	// the randomness used to sample the end of an auction was revealed after its ending period.
+	pub const RandomnessRevealDelay: BlockNumber = 3 * EPOCH_DURATION_IN_BLOCKS;
 }
 
 impl slots::Trait for Runtime {
@@ -699,11 +705,14 @@ impl slots::Trait for Runtime {
 	type Parachains = Registrar;
 	type LeasePeriod = LeasePeriod;
 	type EndingPeriod = EndingPeriod;
-	type Randomness = RandomnessCollectiveFlip;
+	type Randomness = Babe;
+	type RandomnessRevealDelay = RandomnessRevealDelay;
 }
 
 parameter_types! {
 	pub Prefix: &'static [u8] = b"Pay KSMs to the Kusama account:";
+	// A full batch of claims weighs less than a tenth of the maximum block weight.
+	pub const MaxClaimsPerBatch: u32 = 100;
 }
 
 impl claims::Trait for Runtime {
@@ -711,6 +720,9 @@ impl claims::Trait for Runtime {
 	type VestingSchedule = Vesting;
 	type Prefix = Prefix;
 	type MoveClaimOrigin = collective::EnsureProportionMoreThan<_1, _2, AccountId, CouncilCollective>;
+	type Signer = <Signature as Verify>::Signer;
+	type Signature = Signature;
+	type MaxClaimsPerBatch = MaxClaimsPerBatch;
 }
 
 parameter_types! {
@@ -1248,6 +1260,18 @@ sp_api::impl_runtime_apis! {
 		}
 	}
 
+	impl registrar::ParathreadAuctionApi<Block, AccountId, Balance> for Runtime {
+		fn parathread_auction_winners() -> Vec<registrar::ThreadBid<AccountId, Balance>> {
+			Registrar::thread_auction_winners()
+		}
+	}
+
+	impl claims::ClaimsApi<Block, AccountId> for Runtime {
+		fn claim_payload(dest: AccountId, statement: Option<claims::StatementKind>) -> Vec<u8> {
+			Claims::claim_payload(&dest, statement)
+		}
+	}
+
 	impl transaction_payment_rpc_runtime_api::TransactionPaymentApi<
 		Block,
 		Balance,
@@ -17,49 +17,21 @@
 //! Configuration manager for the Polkadot runtime parachains logic.
 //!
 //! Configuration can change only at session boundaries and is buffered until then.
+//!
+//! Pending configurations are checked for consistency before they are buffered, so that the
+//! active configuration is always one the other modules can operate with.
 
 use sp_std::prelude::*;
-use primitives::v1::ValidatorId;
+use primitives::v1::{ValidatorId, MAX_CODE_MEM};
+pub use primitives::v1::HostConfiguration;
 use frame_support::{
 	decl_storage, decl_module, decl_error,
-	dispatch::DispatchResult,
+	dispatch::{DispatchError, DispatchResult},
 	weights::{DispatchClass, Weight},
 };
-use codec::{Encode, Decode};
+use sp_runtime::traits::Zero;
 use system::ensure_root;
 
-/// All configuration of the runtime with respect to parachains and parathreads.
-#[derive(Clone, Encode, Decode, PartialEq, Default)]
-#[cfg_attr(test, derive(Debug))]
-pub struct HostConfiguration<BlockNumber> {
-	/// The minimum frequency at which parachains can update their validation code.
-	pub validation_upgrade_frequency: BlockNumber,
-	/// The delay, in blocks, before a validation upgrade is applied.
-	pub validation_upgrade_delay: BlockNumber,
-	/// The acceptance period, in blocks. This is the amount of blocks after availability that validators
-	/// and fishermen have to perform secondary checks or issue reports.
-	pub acceptance_period: BlockNumber,
-	/// The maximum validation code size, in bytes.
-	pub max_code_size: u32,
-	/// The maximum head-data size, in bytes.
-	pub max_head_data_size: u32,
-	/// The amount of execution cores to dedicate to parathread execution.
-	pub parathread_cores: u32,
-	/// The number of retries that a parathread author has to submit their block.
-	pub parathread_retries: u32,
-	/// How often parachain groups should be rotated across parachains. Must be non-zero.
-	pub parachain_rotation_frequency: BlockNumber,
-	/// The availability period, in blocks, for parachains. This is the amount of blocks
-	/// after inclusion that validators have to make the block available and signal its availability to
-	/// the chain. Must be at least 1.
-	pub chain_availability_period: BlockNumber,
-	/// The availability period, in blocks, for parathreads. Same as the `chain_availability_period`,
-	/// but a differing timeout due to differing requirements. Must be at least 1.
-	pub thread_availability_period: BlockNumber,
-	/// The amount of blocks ahead to schedule parachains and parathreads.
-	pub scheduling_lookahead: u32,
-}
-
 pub trait Trait: system::Trait { }
 
 decl_storage! {
@@ -67,12 +39,37 @@ decl_storage! {
 		/// The active configuration for the current session.
 		Config get(fn config) config(): HostConfiguration<T::BlockNumber>;
 		/// Pending configuration (if any) for the next session.
-		PendingConfig: Option<HostConfiguration<T::BlockNumber>>;
+		PendingConfig get(fn pending_config): Option<HostConfiguration<T::BlockNumber>>;
 	}
 }
 
 decl_error! {
-	pub enum Error for Module<T: Trait> { }
+	pub enum Error for Module<T: Trait> {
+		/// The parachain rotation frequency must be non-zero.
+		ZeroParachainRotationFrequency,
+		/// The availability period for parachains must be at least 1.
+		ZeroChainAvailabilityPeriod,
+		/// The availability period for parathreads must be at least 1.
+		ZeroThreadAvailabilityPeriod,
+		/// The availability period for parachains must not exceed the parachain rotation
+		/// frequency, as availability timeouts are only triggered after rotations.
+		ChainAvailabilityPeriodExceedsRotation,
+		/// The availability period for parathreads must not exceed the parachain rotation
+		/// frequency, as availability timeouts are only triggered after rotations.
+		ThreadAvailabilityPeriodExceedsRotation,
+		/// The max validation code size exceeds the size of code the validation host can execute.
+		MaxCodeSizeExceedsExecutorLimit,
+		/// The number of approvals needed for an included candidate must be non-zero.
+		ZeroNeededApprovals,
+		/// The number of delay tranches for approval-checking assignments must be at least 1.
+		ZeroDelayTranches,
+		/// A pre-checking TTL of a single session leaves code upgrades proposed late in a session
+		/// no time to be pre-checked. It must be either zero or at least 2.
+		PvfCheckingTtlTooShort,
+		/// Validation must be metered, so that the validity of a candidate does not depend on the
+		/// speed of the validator executing it.
+		ZeroMaxValidationFuel,
+	}
 }
 
 decl_module! {
@@ -80,14 +77,28 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: <T as system::Trait>::Origin, system = system {
 		type Error = Error<T>;
 
+		/// Set the whole configuration for the next session at once.
+		///
+		/// Unlike the setters of individual members, this always requires the new configuration
+		/// to be consistent.
+		#[weight = (1_000, DispatchClass::Operational)]
+		pub fn set_config(origin, new: HostConfiguration<T::BlockNumber>) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::check_consistency(&new)?;
+
+			if <Self as Store>::PendingConfig::get().unwrap_or_else(Self::config) != new {
+				<Self as Store>::PendingConfig::set(Some(new));
+			}
+			Ok(())
+		}
+
 		/// Set the validation upgrade frequency.
 		#[weight = (1_000, DispatchClass::Operational)]
 		pub fn set_validation_upgrade_frequency(origin, new: T::BlockNumber) -> DispatchResult {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.validation_upgrade_frequency, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the validation upgrade delay.
@@ -96,8 +107,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.validation_upgrade_delay, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the acceptance period for an included candidate.
@@ -106,8 +116,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.acceptance_period, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the max validation code size for incoming upgrades.
@@ -116,8 +125,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.max_code_size, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the max head data size for paras.
@@ -126,8 +134,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.max_head_data_size, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the number of parathread execution cores.
@@ -136,8 +143,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.parathread_cores, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the number of retries for a particular parathread.
@@ -146,8 +152,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.parathread_retries, new) != new
-			});
-			Ok(())
+			})
 		}
 
 
@@ -157,8 +162,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.parachain_rotation_frequency, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the availability period for parachains.
@@ -167,8 +171,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.chain_availability_period, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the availability period for parathreads.
@@ -177,8 +180,7 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.thread_availability_period, new) != new
-			});
-			Ok(())
+			})
 		}
 
 		/// Set the scheduling lookahead, in expected number of blocks at peak throughput.
@@ -187,8 +189,43 @@ decl_module! {
 			ensure_root(origin)?;
 			Self::update_config_member(|config| {
 				sp_std::mem::replace(&mut config.scheduling_lookahead, new) != new
-			});
-			Ok(())
+			})
+		}
+
+		/// Set the number of approval votes needed to approve an included candidate.
+		#[weight = (1_000, DispatchClass::Operational)]
+		pub fn set_needed_approvals(origin, new: u32) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::update_config_member(|config| {
+				sp_std::mem::replace(&mut config.needed_approvals, new) != new
+			})
+		}
+
+		/// Set the number of delay tranches for approval-checking assignments.
+		#[weight = (1_000, DispatchClass::Operational)]
+		pub fn set_n_delay_tranches(origin, new: u32) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::update_config_member(|config| {
+				sp_std::mem::replace(&mut config.n_delay_tranches, new) != new
+			})
+		}
+
+		/// Set the number of sessions a code upgrade may spend being pre-checked.
+		#[weight = (1_000, DispatchClass::Operational)]
+		pub fn set_pvf_checking_ttl(origin, new: u32) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::update_config_member(|config| {
+				sp_std::mem::replace(&mut config.pvf_checking_ttl, new) != new
+			})
+		}
+
+		/// Set the maximum number of instructions a candidate may execute during validation.
+		#[weight = (1_000, DispatchClass::Operational)]
+		pub fn set_max_validation_fuel(origin, new: u64) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::update_config_member(|config| {
+				sp_std::mem::replace(&mut config.max_validation_fuel, new) != new
+			})
 		}
 	}
 }
@@ -209,22 +246,93 @@ impl<T: Trait> Module<T> {
 		}
 	}
 
+	/// Check that the configuration is consistent, i.e. that the other modules are able to
+	/// operate with it.
+	pub(crate) fn check_consistency(config: &HostConfiguration<T::BlockNumber>) -> DispatchResult {
+		match Self::consistency_violations(config).into_iter().next() {
+			Some(violation) => Err(violation),
+			None => Ok(()),
+		}
+	}
+
+	// All the ways in which the configuration is inconsistent, in a fixed order.
+	fn consistency_violations(config: &HostConfiguration<T::BlockNumber>) -> Vec<DispatchError> {
+		let mut violations = Vec::new();
+		let mut check = |consistent: bool, violation: Error<T>| if !consistent {
+			violations.push(violation.into());
+		};
+
+		check(
+			!config.parachain_rotation_frequency.is_zero(),
+			Error::<T>::ZeroParachainRotationFrequency,
+		);
+		check(
+			!config.chain_availability_period.is_zero(),
+			Error::<T>::ZeroChainAvailabilityPeriod,
+		);
+		check(
+			!config.thread_availability_period.is_zero(),
+			Error::<T>::ZeroThreadAvailabilityPeriod,
+		);
+		check(
+			config.chain_availability_period <= config.parachain_rotation_frequency,
+			Error::<T>::ChainAvailabilityPeriodExceedsRotation,
+		);
+		check(
+			config.thread_availability_period <= config.parachain_rotation_frequency,
+			Error::<T>::ThreadAvailabilityPeriodExceedsRotation,
+		);
+		check(
+			config.max_code_size as usize <= MAX_CODE_MEM,
+			Error::<T>::MaxCodeSizeExceedsExecutorLimit,
+		);
+		check(config.needed_approvals != 0, Error::<T>::ZeroNeededApprovals);
+		check(config.n_delay_tranches != 0, Error::<T>::ZeroDelayTranches);
+		check(config.pvf_checking_ttl != 1, Error::<T>::PvfCheckingTtlTooShort);
+		check(config.max_validation_fuel != 0, Error::<T>::ZeroMaxValidationFuel);
+
+		violations
+	}
+
+	// Apply the updater to the pending configuration, or the active one if none is pending, and
+	// buffer the result if the updater changed anything.
+	//
+	// The result must be consistent, unless the configuration it is based on already isn't. In that
+	// case, the update must fix at least one of its violations without introducing any new one.
+	// This allows an inconsistent configuration, e.g. from genesis, to be fixed one member at a
+	// time, but not to be changed in any other way.
 	fn update_config_member(
 		updater: impl FnOnce(&mut HostConfiguration<T::BlockNumber>) -> bool,
-	) {
+	) -> DispatchResult {
 		let pending = <Self as Store>::PendingConfig::get();
-		let mut prev = pending.unwrap_or_else(Self::config);
-
-		if updater(&mut prev) {
-			<Self as Store>::PendingConfig::set(Some(prev));
+		let prev = pending.unwrap_or_else(Self::config);
+		let mut new = prev.clone();
+
+		if updater(&mut new) {
+			let mut new_violations = Self::consistency_violations(&new);
+			if !new_violations.is_empty() {
+				let prev_violations = Self::consistency_violations(&prev);
+				let introduced = new_violations.iter().position(|v| !prev_violations.contains(v));
+
+				match introduced {
+					Some(i) => return Err(new_violations.swap_remove(i)),
+					None if new_violations.len() >= prev_violations.len() =>
+						return Err(new_violations.swap_remove(0)),
+					None => {}
+				}
+			}
+
+			<Self as Store>::PendingConfig::set(Some(new));
 		}
+
+		Ok(())
 	}
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use crate::mock::{new_test_ext, Initializer, Configuration, Origin};
+	use crate::mock::{new_test_ext, Initializer, Configuration, Origin, GenesisConfig, Test};
 
 	use frame_support::traits::{OnFinalize, OnInitialize};
 
@@ -268,10 +376,34 @@ mod tests {
 				chain_availability_period: 10,
 				thread_availability_period: 8,
 				scheduling_lookahead: 3,
+				needed_approvals: 5,
+				n_delay_tranches: 4,
+				pvf_checking_ttl: 2,
+				max_validation_fuel: 1_000_000_000,
 			};
 
 			assert!(<Configuration as Store>::PendingConfig::get().is_none());
 
+			// the default configuration is inconsistent, so it must be fixed first.
+			Configuration::set_parachain_rotation_frequency(
+				Origin::root(), new_config.parachain_rotation_frequency,
+			).unwrap();
+			Configuration::set_chain_availability_period(
+				Origin::root(), new_config.chain_availability_period,
+			).unwrap();
+			Configuration::set_thread_availability_period(
+				Origin::root(), new_config.thread_availability_period,
+			).unwrap();
+			Configuration::set_needed_approvals(
+				Origin::root(), new_config.needed_approvals,
+			).unwrap();
+			Configuration::set_n_delay_tranches(
+				Origin::root(), new_config.n_delay_tranches,
+			).unwrap();
+			Configuration::set_max_validation_fuel(
+				Origin::root(), new_config.max_validation_fuel,
+			).unwrap();
+
 			Configuration::set_validation_upgrade_frequency(
 				Origin::root(), new_config.validation_upgrade_frequency,
 			).unwrap();
@@ -293,23 +425,170 @@ mod tests {
 			Configuration::set_parathread_retries(
 				Origin::root(), new_config.parathread_retries,
 			).unwrap();
-			Configuration::set_parachain_rotation_frequency(
-				Origin::root(), new_config.parachain_rotation_frequency,
-			).unwrap();
-			Configuration::set_chain_availability_period(
-				Origin::root(), new_config.chain_availability_period,
-			).unwrap();
-			Configuration::set_thread_availability_period(
-				Origin::root(), new_config.thread_availability_period,
-			).unwrap();
 			Configuration::set_scheduling_lookahead(
 				Origin::root(), new_config.scheduling_lookahead,
 			).unwrap();
+			Configuration::set_pvf_checking_ttl(
+				Origin::root(), new_config.pvf_checking_ttl,
+			).unwrap();
 
 			assert_eq!(<Configuration as Store>::PendingConfig::get(), Some(new_config));
 		})
 	}
 
+	fn consistent_config() -> HostConfiguration<u32> {
+		HostConfiguration {
+			max_code_size: 100_000,
+			parachain_rotation_frequency: 10,
+			chain_availability_period: 4,
+			thread_availability_period: 4,
+			needed_approvals: 2,
+			n_delay_tranches: 1,
+			max_validation_fuel: 1_000_000,
+			..Default::default()
+		}
+	}
+
+	fn consistent_genesis() -> GenesisConfig {
+		GenesisConfig {
+			configuration: crate::configuration::GenesisConfig {
+				config: consistent_config(),
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn inconsistent_member_updates_are_rejected() {
+		new_test_ext(consistent_genesis()).execute_with(|| {
+			assert_eq!(
+				Configuration::set_parachain_rotation_frequency(Origin::root(), 0),
+				Err(Error::<Test>::ZeroParachainRotationFrequency.into()),
+			);
+			assert_eq!(
+				Configuration::set_chain_availability_period(Origin::root(), 0),
+				Err(Error::<Test>::ZeroChainAvailabilityPeriod.into()),
+			);
+			assert_eq!(
+				Configuration::set_thread_availability_period(Origin::root(), 0),
+				Err(Error::<Test>::ZeroThreadAvailabilityPeriod.into()),
+			);
+			assert_eq!(
+				Configuration::set_chain_availability_period(Origin::root(), 11),
+				Err(Error::<Test>::ChainAvailabilityPeriodExceedsRotation.into()),
+			);
+			assert_eq!(
+				Configuration::set_thread_availability_period(Origin::root(), 11),
+				Err(Error::<Test>::ThreadAvailabilityPeriodExceedsRotation.into()),
+			);
+			assert_eq!(
+				Configuration::set_parachain_rotation_frequency(Origin::root(), 3),
+				Err(Error::<Test>::ChainAvailabilityPeriodExceedsRotation.into()),
+			);
+			assert_eq!(
+				Configuration::set_max_code_size(Origin::root(), MAX_CODE_MEM as u32 + 1),
+				Err(Error::<Test>::MaxCodeSizeExceedsExecutorLimit.into()),
+			);
+			assert_eq!(
+				Configuration::set_needed_approvals(Origin::root(), 0),
+				Err(Error::<Test>::ZeroNeededApprovals.into()),
+			);
+			assert_eq!(
+				Configuration::set_n_delay_tranches(Origin::root(), 0),
+				Err(Error::<Test>::ZeroDelayTranches.into()),
+			);
+			assert_eq!(
+				Configuration::set_pvf_checking_ttl(Origin::root(), 1),
+				Err(Error::<Test>::PvfCheckingTtlTooShort.into()),
+			);
+			assert_eq!(
+				Configuration::set_max_validation_fuel(Origin::root(), 0),
+				Err(Error::<Test>::ZeroMaxValidationFuel.into()),
+			);
+
+			assert!(<Configuration as Store>::PendingConfig::get().is_none());
+
+			Configuration::set_parachain_rotation_frequency(Origin::root(), 4).unwrap();
+			Configuration::set_max_code_size(Origin::root(), MAX_CODE_MEM as u32).unwrap();
+			Configuration::set_pvf_checking_ttl(Origin::root(), 2).unwrap();
+
+			assert_eq!(<Configuration as Store>::PendingConfig::get(), Some(HostConfiguration {
+				parachain_rotation_frequency: 4,
+				max_code_size: MAX_CODE_MEM as u32,
+				pvf_checking_ttl: 2,
+				..consistent_config()
+			}));
+		});
+	}
+
+	#[test]
+	fn inconsistent_config_can_be_fixed_member_by_member() {
+		new_test_ext(Default::default()).execute_with(|| {
+			assert!(Configuration::check_consistency(&Configuration::config()).is_err());
+
+			// members which don't fix a violation can't be changed while the configuration is
+			// inconsistent.
+			assert_eq!(
+				Configuration::set_validation_upgrade_delay(Origin::root(), 10),
+				Err(Error::<Test>::ZeroParachainRotationFrequency.into()),
+			);
+
+			Configuration::set_parachain_rotation_frequency(Origin::root(), 10).unwrap();
+			Configuration::set_chain_availability_period(Origin::root(), 4).unwrap();
+			Configuration::set_needed_approvals(Origin::root(), 2).unwrap();
+			Configuration::set_n_delay_tranches(Origin::root(), 1).unwrap();
+			Configuration::set_max_validation_fuel(Origin::root(), 1_000_000).unwrap();
+
+			// a fix must not introduce a new violation.
+			assert_eq!(
+				Configuration::set_thread_availability_period(Origin::root(), 11),
+				Err(Error::<Test>::ThreadAvailabilityPeriodExceedsRotation.into()),
+			);
+			// nor may a remaining violation be made worse in another way.
+			assert_eq!(
+				Configuration::set_pvf_checking_ttl(Origin::root(), 1),
+				Err(Error::<Test>::PvfCheckingTtlTooShort.into()),
+			);
+
+			Configuration::set_thread_availability_period(Origin::root(), 4).unwrap();
+
+			let pending = <Configuration as Store>::PendingConfig::get().unwrap();
+			assert!(Configuration::check_consistency(&pending).is_ok());
+
+			// once consistent, it can't be made inconsistent again.
+			assert_eq!(
+				Configuration::set_parachain_rotation_frequency(Origin::root(), 0),
+				Err(Error::<Test>::ZeroParachainRotationFrequency.into()),
+			);
+			Configuration::set_validation_upgrade_delay(Origin::root(), 10).unwrap();
+		});
+	}
+
+	#[test]
+	fn set_config_updates_all_members_at_once() {
+		new_test_ext(consistent_genesis()).execute_with(|| {
+			// the availability period could not be raised past the current rotation frequency on its own.
+			let new_config = HostConfiguration {
+				parachain_rotation_frequency: 20,
+				chain_availability_period: 15,
+				..consistent_config()
+			};
+
+			Configuration::set_config(Origin::root(), new_config.clone()).unwrap();
+			assert_eq!(<Configuration as Store>::PendingConfig::get(), Some(new_config));
+
+			assert_eq!(
+				Configuration::set_config(Origin::root(), HostConfiguration {
+					thread_availability_period: 0,
+					..consistent_config()
+				}),
+				Err(Error::<Test>::ZeroThreadAvailabilityPeriod.into()),
+			);
+			assert!(Configuration::set_config(Origin::signed(1), consistent_config()).is_err());
+		});
+	}
+
 	#[test]
 	fn non_root_cannot_set_config() {
 		new_test_ext(Default::default()).execute_with(|| {
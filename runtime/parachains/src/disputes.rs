@@ -0,0 +1,933 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The disputes module is responsible for resolving disputes about the validity of included
+//! candidates.
+//!
+//! A dispute is opened by submitting a signed statement of invalidity about a candidate tracked by
+//! the validity module. This escalates the candidate to all validators of the session it was
+//! included in, who then submit signed statements of validity or invalidity. Once a supermajority
+//! of the session's validators agrees, the dispute concludes. If the candidate is found invalid,
+//! the head of its para is reverted to the head before the candidate was enacted.
+//!
+//! In either case, the validators on the losing side of the dispute are reported to the offences
+//! module for slashing. Disputes which do not reach a supermajority within a number of sessions
+//! time out and conclude as not proven, without reverting the para or slashing anyone.
+
+use sp_std::prelude::*;
+use primitives::v1::{
+	ValidatorId, ValidatorIndex, Id as ParaId, Hash, SessionIndex, DisputeStatement,
+	SignedDisputeStatement,
+};
+use frame_support::{
+	decl_storage, decl_module, decl_error, decl_event, ensure, debug,
+	dispatch::DispatchResult, weights::Weight, traits::Get, Parameter,
+};
+use codec::{Encode, Decode};
+use bitvec::{order::Lsb0 as BitOrderLsb0, vec::BitVec};
+use sp_runtime::{
+	Perbill, RuntimeDebug,
+	traits::{One, AppVerify, Convert},
+};
+use sp_staking::offence::{ReportOffence, Offence, Kind};
+use system::ensure_signed;
+
+use crate::{
+	configuration, paras, validity::{self, ApprovalStatus}, initializer::SessionChangeNotification,
+};
+
+/// The outcome of a concluded dispute.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum DisputeOutcome {
+	/// A supermajority of validators found the candidate valid.
+	Valid,
+	/// A supermajority of validators found the candidate invalid.
+	Invalid,
+	/// The dispute timed out before a supermajority was reached.
+	NotProven,
+}
+
+/// The state of a dispute about a candidate.
+#[derive(Encode, Decode)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct DisputeState<N> {
+	/// The para the disputed candidate is for.
+	para_id: ParaId,
+	/// The session the disputed candidate was included in.
+	session: SessionIndex,
+	/// The relay-chain block number the dispute was opened at.
+	opened_at: N,
+	/// One bit per validator of the session, set if the validator stated the candidate is valid.
+	validity_votes: BitVec<BitOrderLsb0, u8>,
+	/// One bit per validator of the session, set if the validator stated the candidate is invalid.
+	invalidity_votes: BitVec<BitOrderLsb0, u8>,
+	/// The relay-chain block number the dispute concluded at along with its outcome, if concluded.
+	concluded: Option<(N, DisputeOutcome)>,
+}
+
+impl<N> DisputeState<N> {
+	/// Get the outcome of the dispute, if it has concluded.
+	pub(crate) fn outcome(&self) -> Option<DisputeOutcome> {
+		self.concluded.as_ref().map(|&(_, outcome)| outcome)
+	}
+}
+
+pub trait Trait: system::Trait + configuration::Trait + paras::Trait + validity::Trait {
+	type Event: From<Event> + Into<<Self as system::Trait>::Event>;
+
+	/// The full identification of a validator, as known to the offences module.
+	type IdentificationTuple: Parameter;
+
+	/// Converts the parachain key of a validator in the given session into its full identification.
+	type ValidatorIdentification: Convert<
+		(SessionIndex, ValidatorId),
+		Option<Self::IdentificationTuple>,
+	>;
+
+	/// Report an offence.
+	type ReportOffence: ReportOffence<
+		Self::AccountId,
+		Self::IdentificationTuple,
+		DisputeOffence<Self::IdentificationTuple>,
+	>;
+
+	/// The number of sessions after which an open dispute times out and concludes as not proven.
+	type DisputeTimeout: Get<SessionIndex>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as ParaDisputes {
+		/// Open and recently concluded disputes, by candidate hash.
+		Disputes get(fn dispute): map hasher(twox_64_concat) Hash
+			=> Option<DisputeState<T::BlockNumber>>;
+
+		/// Concluded disputes along with the block number they concluded at, pending removal once
+		/// they have left the acceptance period. Ordered ascending by block number.
+		DisputePruning: Vec<(T::BlockNumber, Hash)>;
+
+		/// Open disputes along with the session they were opened in, pending their timeout.
+		OpenDisputes: Vec<(SessionIndex, Hash)>;
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The candidate is not tracked by the validity module.
+		UnknownCandidate,
+		/// The validators of the session the candidate was included in are no longer known.
+		UnknownSession,
+		/// Disputes must be opened by at least one statement of invalidity.
+		NoInvalidityStatement,
+		/// The dispute about the candidate has already concluded.
+		DisputeConcluded,
+		/// Validator index out of bounds.
+		ValidatorIndexOutOfBounds,
+		/// A dispute statement has an invalid signature.
+		InvalidStatementSignature,
+		/// The validator has already issued a statement in this dispute.
+		DuplicateStatement,
+		/// The given number of validators does not match the session of the candidate.
+		WrongValidatorsLen,
+	}
+}
+
+decl_event! {
+	pub enum Event {
+		/// A dispute about a candidate was opened, and all validators of its session should
+		/// check it.
+		DisputeInitiated(Hash, ParaId),
+		/// A dispute concluded. The validators on the losing side are given.
+		DisputeConcluded(Hash, DisputeOutcome, Vec<ValidatorIndex>),
+	}
+}
+
+decl_module! {
+	/// The parachain disputes module.
+	pub struct Module<T: Trait> for enum Call where origin: <T as system::Trait>::Origin, system = system {
+		type Error = Error<T>;
+
+		/// The number of sessions after which an open dispute times out.
+		const DisputeTimeout: SessionIndex = T::DisputeTimeout::get();
+
+		fn deposit_event() = default;
+
+		/// Submit signed statements about the validity of an included candidate.
+		///
+		/// If no dispute about the candidate is open, this opens one and at least one of the
+		/// statements must be a statement of invalidity. `validators_len` must be the number of
+		/// validators of the session the candidate was included in, and bounds the weight of
+		/// concluding the dispute.
+		#[weight = submit_dispute_statements_weight::<T>(statements.len() as u32, *validators_len)]
+		pub fn submit_dispute_statements(
+			origin,
+			candidate_hash: Hash,
+			validators_len: u32,
+			statements: Vec<SignedDisputeStatement>,
+		) -> DispatchResult {
+			let reporter = ensure_signed(origin)?;
+
+			let entry = <validity::Module<T>>::included_candidate(&candidate_hash)
+				.ok_or(Error::<T>::UnknownCandidate)?;
+			let session = entry.session();
+			let validators = <validity::Module<T>>::session_validators(&session)
+				.ok_or(Error::<T>::UnknownSession)?;
+			ensure!(validators.len() == validators_len as usize, Error::<T>::WrongValidatorsLen);
+
+			let now = <system::Module<T>>::block_number();
+			let existing = <Disputes<T>>::get(&candidate_hash);
+			let opened = existing.is_none();
+			let mut dispute = match existing {
+				Some(dispute) => {
+					ensure!(dispute.concluded.is_none(), Error::<T>::DisputeConcluded);
+					dispute
+				}
+				None => {
+					ensure!(
+						entry.status() != ApprovalStatus::Invalid,
+						Error::<T>::DisputeConcluded,
+					);
+					ensure!(
+						statements.iter().any(|s| s.statement == DisputeStatement::Invalid),
+						Error::<T>::NoInvalidityStatement,
+					);
+
+					DisputeState {
+						para_id: entry.para_id(),
+						session,
+						opened_at: now,
+						validity_votes: bitvec::bitvec![BitOrderLsb0, u8; 0; validators.len()],
+						invalidity_votes: bitvec::bitvec![BitOrderLsb0, u8; 0; validators.len()],
+						concluded: None,
+					}
+				}
+			};
+
+			for signed in &statements {
+				let validator_index = signed.validator_index as usize;
+				let validator_public = validators.get(validator_index)
+					.ok_or(Error::<T>::ValidatorIndexOutOfBounds)?;
+
+				let payload = signed.statement.signing_payload(&candidate_hash, session);
+				ensure!(
+					signed.signature.verify(&payload[..], validator_public),
+					Error::<T>::InvalidStatementSignature,
+				);
+
+				let already_stated = dispute.validity_votes.get(validator_index).map_or(false, |b| *b)
+					|| dispute.invalidity_votes.get(validator_index).map_or(false, |b| *b);
+				ensure!(!already_stated, Error::<T>::DuplicateStatement);
+
+				let votes = match signed.statement {
+					DisputeStatement::Valid => &mut dispute.validity_votes,
+					DisputeStatement::Invalid => &mut dispute.invalidity_votes,
+				};
+
+				if let Some(mut bit) = votes.get_mut(validator_index) {
+					*bit = true;
+				}
+			}
+
+			// all statements are checked, so nothing is written before this point.
+			if opened {
+				Self::deposit_event(Event::DisputeInitiated(candidate_hash, entry.para_id()));
+				<validity::Module<T>>::note_dispute_initiated(&candidate_hash);
+			}
+
+			let threshold = supermajority_threshold(validators.len());
+			let outcome = if dispute.validity_votes.count_ones() >= threshold {
+				Some(DisputeOutcome::Valid)
+			} else if dispute.invalidity_votes.count_ones() >= threshold {
+				Some(DisputeOutcome::Invalid)
+			} else {
+				None
+			};
+
+			match outcome {
+				Some(outcome) => {
+					dispute.concluded = Some((now, outcome));
+					Self::conclude(vec![reporter], candidate_hash, &dispute, &validators, outcome);
+					<DisputePruning<T>>::append((now, candidate_hash));
+
+					if !opened {
+						OpenDisputes::mutate(|open| open.retain(|&(_, h)| h != candidate_hash));
+					}
+				}
+				None if opened => {
+					let opened_in = <validity::Module<T>>::session_index();
+					OpenDisputes::append((opened_in, candidate_hash));
+				}
+				None => {}
+			}
+
+			<Disputes<T>>::insert(&candidate_hash, dispute);
+
+			Ok(())
+		}
+	}
+}
+
+/// The weight of a `submit_dispute_statements` call with the given number of statements, about a
+/// candidate of a session with the given number of validators.
+///
+/// This accounts for concluding the dispute, which reverts the head of the para and reports up to
+/// all validators of the session as offenders.
+fn submit_dispute_statements_weight<T: Trait>(statements_len: u32, validators_len: u32) -> Weight {
+	let db = T::DbWeight::get();
+
+	// verifying the statements.
+	100_000_000 * (statements_len as Weight + 1)
+		// the candidate, the validators, the dispute and the open disputes.
+		+ db.reads_writes(4, 2)
+		// concluding the dispute in the validity module and reverting the head of the para.
+		+ db.reads_writes(4, 3)
+		// tallying the votes and identifying and reporting each offender.
+		+ (1_000_000 + db.reads(1) + 10_000_000) * validators_len as Weight
+}
+
+impl<T: Trait> Module<T> {
+	/// Block initialization logic, called by initializer.
+	pub(crate) fn initializer_initialize(now: T::BlockNumber) -> Weight {
+		Self::prune_concluded(now)
+	}
+
+	/// Block finalization logic, called by initializer.
+	pub(crate) fn initializer_finalize() { }
+
+	/// Handle an incoming session change. Open disputes which have reached their timeout conclude
+	/// as not proven.
+	pub(crate) fn initializer_on_new_session(
+		notification: &SessionChangeNotification<T::BlockNumber>
+	) {
+		let timeout = T::DisputeTimeout::get();
+		let session_index = notification.session_index;
+		let now = <system::Module<T>>::block_number();
+
+		let timed_out: Vec<Hash> = OpenDisputes::mutate(|open| {
+			let mut timed_out = Vec::new();
+			open.retain(|&(opened_in, candidate_hash)| {
+				if opened_in.saturating_add(timeout) > session_index {
+					return true;
+				}
+
+				timed_out.push(candidate_hash);
+				false
+			});
+			timed_out
+		});
+
+		for candidate_hash in timed_out {
+			let mut dispute = match <Disputes<T>>::get(&candidate_hash) {
+				Some(dispute) => dispute,
+				None => continue,
+			};
+
+			let outcome = DisputeOutcome::NotProven;
+			dispute.concluded = Some((now, outcome));
+			Self::conclude(Vec::new(), candidate_hash, &dispute, &[], outcome);
+			<DisputePruning<T>>::append((now, candidate_hash));
+			<Disputes<T>>::insert(&candidate_hash, dispute);
+		}
+	}
+
+	// Apply the outcome of a dispute which has just concluded and report the validators on the
+	// losing side.
+	fn conclude(
+		reporters: Vec<T::AccountId>,
+		candidate_hash: Hash,
+		dispute: &DisputeState<T::BlockNumber>,
+		validators: &[ValidatorId],
+		outcome: DisputeOutcome,
+	) {
+		let losing_votes = match outcome {
+			DisputeOutcome::Valid => {
+				<validity::Module<T>>::note_dispute_concluded(&candidate_hash, true);
+				Some(&dispute.invalidity_votes)
+			}
+			DisputeOutcome::Invalid => {
+				<validity::Module<T>>::note_dispute_concluded(&candidate_hash, false);
+
+				let parent_head = <validity::Module<T>>::included_candidate(&candidate_hash)
+					.and_then(|entry| entry.parent_head().cloned());
+				if let Some(parent_head) = parent_head {
+					<paras::Module<T>>::revert_head(dispute.para_id, parent_head);
+				}
+
+				Some(&dispute.validity_votes)
+			}
+			DisputeOutcome::NotProven => {
+				<validity::Module<T>>::note_dispute_timed_out(&candidate_hash);
+				None
+			}
+		};
+
+		let losers: Vec<ValidatorIndex> = losing_votes.into_iter()
+			.flat_map(|votes| votes.iter().enumerate())
+			.filter(|(_, voted)| **voted)
+			.map(|(i, _)| i as ValidatorIndex)
+			.collect();
+
+		Self::deposit_event(Event::DisputeConcluded(candidate_hash, outcome, losers.clone()));
+
+		let offenders: Vec<_> = losers.iter()
+			.filter_map(|i| validators.get(*i as usize))
+			.filter_map(|id| T::ValidatorIdentification::convert((dispute.session, id.clone())))
+			.collect();
+
+		if offenders.is_empty() {
+			return;
+		}
+
+		let offence = DisputeOffence {
+			session_index: dispute.session,
+			validator_set_count: validators.len() as u32,
+			candidate_hash,
+			offenders,
+		};
+
+		if T::ReportOffence::report_offence(reporters, offence).is_err() {
+			debug::warn!("Failed to report offence for concluded dispute");
+		}
+	}
+
+	// Removes concluded disputes which have left the acceptance period.
+	fn prune_concluded(now: T::BlockNumber) -> Weight {
+		let config = <configuration::Module<T>>::config();
+		let acceptance_period = config.acceptance_period;
+		if now <= acceptance_period {
+			return T::DbWeight::get().reads_writes(1, 0);
+		}
+
+		// The height of any conclusions we no longer should keep around.
+		let pruning_height = now - (acceptance_period + One::one());
+
+		let pruned = <DisputePruning<T>>::mutate(|pruning| {
+			let up_to_idx = pruning.iter()
+				.take_while(|&(at, _)| at <= &pruning_height)
+				.count();
+
+			for (_, candidate_hash) in pruning.drain(..up_to_idx) {
+				<Disputes<T>>::remove(&candidate_hash);
+			}
+
+			up_to_idx as u64
+		});
+
+		T::DbWeight::get().reads_writes(2, 1 + pruned)
+	}
+}
+
+/// The number of validators needed for a supermajority.
+pub(crate) const fn supermajority_threshold(n_validators: usize) -> usize {
+	n_validators - n_validators.saturating_sub(1) / 3
+}
+
+/// An offence that is filed against validators on the losing side of a concluded dispute.
+#[derive(RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Clone, PartialEq, Eq))]
+pub struct DisputeOffence<Offender> {
+	/// The session the disputed candidate was included in.
+	session_index: SessionIndex,
+	/// The size of the validator set in that session.
+	validator_set_count: u32,
+	/// The hash of the disputed candidate.
+	candidate_hash: Hash,
+	/// The validators on the losing side of the dispute.
+	offenders: Vec<Offender>,
+}
+
+impl<Offender: Clone> Offence<Offender> for DisputeOffence<Offender> {
+	const ID: Kind = *b"para:bad-dispute";
+	type TimeSlot = (SessionIndex, Hash);
+
+	fn offenders(&self) -> Vec<Offender> {
+		self.offenders.clone()
+	}
+
+	fn session_index(&self) -> SessionIndex {
+		self.session_index
+	}
+
+	fn validator_set_count(&self) -> u32 {
+		self.validator_set_count
+	}
+
+	fn time_slot(&self) -> Self::TimeSlot {
+		(self.session_index, self.candidate_hash)
+	}
+
+	fn slash_fraction(offenders_count: u32, validator_set_count: u32) -> Perbill {
+		// The more validators end up on the losing side, the more likely it is they colluded.
+		// Slash `min(3k/n, 1)^2`, as for GRANDPA equivocations.
+		let x = Perbill::from_rational_approximation(3 * offenders_count, validator_set_count);
+		x.square()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use primitives::v1::{BlockNumber, ValidatorSignature, HeadData};
+	use frame_support::{assert_noop, traits::{OnFinalize, OnInitialize}};
+	use keyring::Sr25519Keyring;
+
+	use crate::mock::{
+		new_test_ext, System, Validity, Disputes, Paras, Origin, GenesisConfig as MockGenesisConfig,
+		Test, TestEvent,
+	};
+	use crate::configuration::HostConfiguration;
+	use crate::initializer::SessionChangeNotification;
+	use crate::paras::ParaGenesisArgs;
+
+	const VALIDATORS: [Sr25519Keyring; 4] = [
+		Sr25519Keyring::Alice,
+		Sr25519Keyring::Bob,
+		Sr25519Keyring::Charlie,
+		Sr25519Keyring::Dave,
+	];
+
+	fn genesis_config(para_id: ParaId, acceptance_period: BlockNumber) -> MockGenesisConfig {
+		MockGenesisConfig {
+			paras: paras::GenesisConfig {
+				paras: vec![(para_id, ParaGenesisArgs {
+					genesis_head: vec![1, 2, 3].into(),
+					validation_code: vec![4, 5, 6].into(),
+					parachain: true,
+				})],
+				..Default::default()
+			},
+			configuration: configuration::GenesisConfig {
+				config: HostConfiguration {
+					acceptance_period,
+					needed_approvals: 2,
+					..Default::default()
+				},
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	fn run_to_block(to: BlockNumber) {
+		while System::block_number() < to {
+			let b = System::block_number();
+
+			Disputes::initializer_finalize();
+			Validity::initializer_finalize();
+			System::on_finalize(b);
+
+			System::on_initialize(b + 1);
+			System::set_block_number(b + 1);
+
+			Validity::initializer_initialize(b + 1);
+			Disputes::initializer_initialize(b + 1);
+		}
+	}
+
+	fn start_session(session_index: SessionIndex) {
+		let notification = SessionChangeNotification {
+			validators: VALIDATORS.iter().map(|v| v.public().into()).collect(),
+			session_index,
+			..Default::default()
+		};
+
+		Validity::initializer_on_new_session(&notification);
+		Disputes::initializer_on_new_session(&notification);
+	}
+
+	// Include a candidate for the para, advancing its head to `new_head`.
+	fn include_candidate(candidate_hash: Hash, para_id: ParaId, new_head: HeadData) {
+		Validity::note_included(candidate_hash, para_id);
+		Paras::note_new_head(para_id, new_head, System::block_number() - 1);
+	}
+
+	fn sign_statement(
+		validator_index: ValidatorIndex,
+		statement: DisputeStatement,
+		candidate_hash: Hash,
+		session_index: SessionIndex,
+	) -> SignedDisputeStatement {
+		let payload = statement.signing_payload(&candidate_hash, session_index);
+		let signature: ValidatorSignature = VALIDATORS[validator_index as usize]
+			.sign(&payload[..])
+			.into();
+
+		SignedDisputeStatement {
+			statement,
+			validator_index,
+			signature,
+		}
+	}
+
+	fn concluded_events() -> Vec<(Hash, DisputeOutcome, Vec<ValidatorIndex>)> {
+		System::events().into_iter()
+			.filter_map(|r| match r.event {
+				TestEvent::disputes(Event::DisputeConcluded(h, o, l)) => Some((h, o, l)),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn supermajority_threshold_is_more_than_two_thirds() {
+		assert_eq!(supermajority_threshold(1), 1);
+		assert_eq!(supermajority_threshold(3), 3);
+		assert_eq!(supermajority_threshold(4), 3);
+		assert_eq!(supermajority_threshold(7), 5);
+		assert_eq!(supermajority_threshold(10), 7);
+	}
+
+	#[test]
+	fn dispute_must_be_opened_with_invalidity_statement() {
+		let para_id = ParaId::from(1);
+		new_test_ext(genesis_config(para_id, 10)).execute_with(|| {
+			start_session(1);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1)],
+				),
+				Error::<Test>::UnknownCandidate,
+			);
+
+			include_candidate(candidate_hash, para_id, vec![7, 8, 9].into());
+
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![sign_statement(0, DisputeStatement::Valid, candidate_hash, 1)],
+				),
+				Error::<Test>::NoInvalidityStatement,
+			);
+
+			Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1)],
+			).unwrap();
+
+			assert!(Disputes::dispute(&candidate_hash).unwrap().outcome().is_none());
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Disputed));
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(2));
+		});
+	}
+
+	#[test]
+	fn statement_checks() {
+		let para_id = ParaId::from(1);
+		new_test_ext(genesis_config(para_id, 10)).execute_with(|| {
+			start_session(1);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			include_candidate(candidate_hash, para_id, vec![7, 8, 9].into());
+
+			// wrong session.
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![sign_statement(0, DisputeStatement::Invalid, candidate_hash, 2)],
+				),
+				Error::<Test>::InvalidStatementSignature,
+			);
+
+			// wrong validator index.
+			let mut statement = sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1);
+			statement.validator_index = 1;
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![statement],
+				),
+				Error::<Test>::InvalidStatementSignature,
+			);
+
+			// out of bounds.
+			let mut statement = sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1);
+			statement.validator_index = 4;
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![statement],
+				),
+				Error::<Test>::ValidatorIndexOutOfBounds,
+			);
+
+			// wrong number of validators.
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					5,
+					vec![sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1)],
+				),
+				Error::<Test>::WrongValidatorsLen,
+			);
+
+			// conflicting statements by the same validator.
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![
+						sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1),
+						sign_statement(0, DisputeStatement::Valid, candidate_hash, 1),
+					],
+				),
+				Error::<Test>::DuplicateStatement,
+			);
+		});
+	}
+
+	#[test]
+	fn forged_statement_does_not_open_dispute() {
+		let para_id = ParaId::from(1);
+		new_test_ext(genesis_config(para_id, 10)).execute_with(|| {
+			start_session(1);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			include_candidate(candidate_hash, para_id, vec![7, 8, 9].into());
+
+			let mut forged = sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1);
+			forged.validator_index = 1;
+			assert!(Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![sign_statement(2, DisputeStatement::Valid, candidate_hash, 1), forged],
+			).is_err());
+
+			assert!(Disputes::dispute(&candidate_hash).is_none());
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Pending));
+			assert!(System::events().iter().all(|r| match r.event {
+				TestEvent::disputes(Event::DisputeInitiated(..)) => false,
+				_ => true,
+			}));
+		});
+	}
+
+	#[test]
+	fn dispute_concluding_valid_approves_candidate() {
+		let para_id = ParaId::from(1);
+		new_test_ext(genesis_config(para_id, 10)).execute_with(|| {
+			start_session(1);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			include_candidate(candidate_hash, para_id, vec![7, 8, 9].into());
+
+			Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![sign_statement(3, DisputeStatement::Invalid, candidate_hash, 1)],
+			).unwrap();
+
+			Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![
+					sign_statement(0, DisputeStatement::Valid, candidate_hash, 1),
+					sign_statement(1, DisputeStatement::Valid, candidate_hash, 1),
+				],
+			).unwrap();
+
+			assert!(Disputes::dispute(&candidate_hash).unwrap().outcome().is_none());
+			assert_eq!(OpenDisputes::get(), vec![(1, candidate_hash)]);
+
+			Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![sign_statement(2, DisputeStatement::Valid, candidate_hash, 1)],
+			).unwrap();
+
+			assert!(OpenDisputes::get().is_empty());
+
+			assert_eq!(
+				Disputes::dispute(&candidate_hash).unwrap().outcome(),
+				Some(DisputeOutcome::Valid),
+			);
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Approved));
+			assert_eq!(Paras::para_head(&para_id), Some(vec![7, 8, 9].into()));
+			assert_eq!(concluded_events(), vec![(candidate_hash, DisputeOutcome::Valid, vec![3])]);
+
+			// no more statements after conclusion.
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![sign_statement(3, DisputeStatement::Valid, candidate_hash, 1)],
+				),
+				Error::<Test>::DisputeConcluded,
+			);
+		});
+	}
+
+	#[test]
+	fn dispute_concluding_invalid_reverts_head() {
+		let para_id = ParaId::from(1);
+		new_test_ext(genesis_config(para_id, 10)).execute_with(|| {
+			start_session(1);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			include_candidate(candidate_hash, para_id, vec![7, 8, 9].into());
+			assert_eq!(Paras::para_head(&para_id), Some(vec![7, 8, 9].into()));
+
+			Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![
+					sign_statement(0, DisputeStatement::Valid, candidate_hash, 1),
+					sign_statement(1, DisputeStatement::Invalid, candidate_hash, 1),
+					sign_statement(2, DisputeStatement::Invalid, candidate_hash, 1),
+					sign_statement(3, DisputeStatement::Invalid, candidate_hash, 1),
+				],
+			).unwrap();
+
+			assert_eq!(
+				Disputes::dispute(&candidate_hash).unwrap().outcome(),
+				Some(DisputeOutcome::Invalid),
+			);
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Invalid));
+			assert_eq!(Paras::para_head(&para_id), Some(vec![1, 2, 3].into()));
+			assert_eq!(concluded_events(), vec![(candidate_hash, DisputeOutcome::Invalid, vec![0])]);
+
+			// the including block must never be finalized.
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(2));
+		});
+	}
+
+	#[test]
+	fn dispute_times_out_as_not_proven() {
+		let para_id = ParaId::from(1);
+		new_test_ext(genesis_config(para_id, 10)).execute_with(|| {
+			start_session(1);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			include_candidate(candidate_hash, para_id, vec![7, 8, 9].into());
+
+			Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![
+					sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1),
+					sign_statement(1, DisputeStatement::Valid, candidate_hash, 1),
+				],
+			).unwrap();
+
+			run_to_block(3);
+			start_session(2);
+			assert!(Disputes::dispute(&candidate_hash).unwrap().outcome().is_none());
+
+			// the dispute was opened in session 1 and times out after 2 sessions.
+			run_to_block(4);
+			start_session(3);
+
+			assert_eq!(
+				Disputes::dispute(&candidate_hash).unwrap().outcome(),
+				Some(DisputeOutcome::NotProven),
+			);
+			assert!(OpenDisputes::get().is_empty());
+			assert_eq!(<DisputePruning<Test>>::get(), vec![(4, candidate_hash)]);
+
+			// nobody is slashed and the para is not reverted.
+			assert_eq!(
+				concluded_events(),
+				vec![(candidate_hash, DisputeOutcome::NotProven, Vec::new())],
+			);
+			assert_eq!(Paras::para_head(&para_id), Some(vec![7, 8, 9].into()));
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Pending));
+
+			assert_noop!(
+				Disputes::submit_dispute_statements(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					vec![sign_statement(2, DisputeStatement::Invalid, candidate_hash, 1)],
+				),
+				Error::<Test>::DisputeConcluded,
+			);
+		});
+	}
+
+	#[test]
+	fn concluded_disputes_are_pruned_after_acceptance_period() {
+		let para_id = ParaId::from(1);
+		let acceptance_period = 5;
+		new_test_ext(genesis_config(para_id, acceptance_period)).execute_with(|| {
+			start_session(1);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			include_candidate(candidate_hash, para_id, vec![7, 8, 9].into());
+
+			run_to_block(3);
+
+			Disputes::submit_dispute_statements(
+				Origin::signed(1),
+				candidate_hash,
+				4,
+				vec![
+					sign_statement(0, DisputeStatement::Invalid, candidate_hash, 1),
+					sign_statement(1, DisputeStatement::Valid, candidate_hash, 1),
+					sign_statement(2, DisputeStatement::Valid, candidate_hash, 1),
+					sign_statement(3, DisputeStatement::Valid, candidate_hash, 1),
+				],
+			).unwrap();
+
+			let pruned_at = 3 + acceptance_period + 1;
+			run_to_block(pruned_at - 1);
+			assert!(Disputes::dispute(&candidate_hash).is_some());
+
+			run_to_block(pruned_at);
+			assert!(Disputes::dispute(&candidate_hash).is_none());
+			assert!(<DisputePruning<Test>>::get().is_empty());
+		});
+	}
+
+	#[test]
+	fn slash_fraction_grows_with_offenders() {
+		type O = DisputeOffence<u64>;
+
+		assert_eq!(O::slash_fraction(0, 100), Perbill::zero());
+		assert!(O::slash_fraction(1, 100) < O::slash_fraction(10, 100));
+		assert_eq!(O::slash_fraction(34, 100), Perbill::one());
+	}
+}
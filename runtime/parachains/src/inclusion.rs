@@ -19,6 +19,10 @@
 //!
 //! It is responsible for carrying candidates from being backable to being backed, and then from backed
 //! to included.
+//!
+//! The fees declared in a candidate's commitments are reserved from the sovereign account of its para
+//! when it is backed, and paid out to the backing validators and the validators that made it available
+//! when it is included.
 
 use sp_std::prelude::*;
 use primitives::v1::{
@@ -29,14 +33,21 @@ use primitives::v1::{
 };
 use frame_support::{
 	decl_storage, decl_module, decl_error, decl_event, ensure, debug,
-	dispatch::DispatchResult, IterableStorageMap, weights::Weight, traits::Get,
+	dispatch::DispatchResult, IterableStorageMap, weights::Weight,
+	traits::{Get, Currency, ReservableCurrency, BalanceStatus},
 };
 use codec::{Encode, Decode};
 use bitvec::{order::Lsb0 as BitOrderLsb0, vec::BitVec};
 use sp_staking::SessionIndex;
-use sp_runtime::{DispatchError, traits::{One, Saturating}};
+use sp_runtime::{
+	DispatchError, SaturatedConversion,
+	traits::{One, Saturating, Zero, Convert, AccountIdConversion},
+};
 
-use crate::{configuration, paras};
+use crate::{configuration, paras, validity};
+
+type BalanceOf<T> =
+	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
 /// A bitfield signed by a validator indicating that it is keeping its piece of the erasure-coding
 /// for any backed candidates referred to by a `1` bit available.
@@ -66,6 +77,8 @@ pub struct CandidatePendingAvailability<H, N> {
 	relay_parent_number: N,
 	/// The block number of the relay-chain block this was backed in.
 	backed_in_number: N,
+	/// The validators which backed the candidate, by their index in the validator set.
+	backers: Vec<ValidatorIndex>,
 }
 
 impl<H, N> CandidatePendingAvailability<H, N> {
@@ -86,9 +99,15 @@ impl<H, N> CandidatePendingAvailability<H, N> {
 }
 
 pub trait Trait:
-	system::Trait + paras::Trait + configuration::Trait
+	system::Trait + paras::Trait + configuration::Trait + validity::Trait
 {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The currency that candidate fees are paid in, from the sovereign accounts of paras.
+	type Currency: ReservableCurrency<Self::AccountId>;
+
+	/// Converts the parachain key of a validator into the account its share of fees is paid to.
+	type ValidatorAccount: Convert<ValidatorId, Option<Self::AccountId>>;
 }
 
 decl_storage! {
@@ -105,6 +124,10 @@ decl_storage! {
 		PendingAvailabilityCommitments: map hasher(twox_64_concat) ParaId
 			=> Option<CandidateCommitments>;
 
+		/// The fees actually reserved from the sovereign account of a para for its candidate
+		/// pending availability, by ParaId. Only this amount is ever paid out or refunded.
+		PendingAvailabilityFees: map hasher(twox_64_concat) ParaId => BalanceOf<T>;
+
 		/// The current validators, by their parachain session keys.
 		Validators get(fn validators) config(validators): Vec<ValidatorId>;
 
@@ -145,19 +168,25 @@ decl_error! {
 		InvalidBacking,
 		/// Collator did not sign PoV.
 		NotCollatorSigned,
+		/// The para cannot pay the fees declared by the candidate.
+		CannotPayFees,
 		/// Internal error only returned when compiled with debug assertions.
 		InternalError,
 	}
 }
 
 decl_event! {
-	pub enum Event<T> where <T as system::Trait>::Hash {
+	pub enum Event<T> where <T as system::Trait>::Hash, Balance = BalanceOf<T> {
 		/// A candidate was backed.
 		CandidateBacked(CandidateReceipt<Hash>, HeadData),
 		/// A candidate was included.
 		CandidateIncluded(CandidateReceipt<Hash>, HeadData),
 		/// A candidate timed out.
 		CandidateTimedOut(CandidateReceipt<Hash>, HeadData),
+		/// The fees of an included candidate were paid by the para to validators.
+		FeesPaid(ParaId, Balance),
+		/// The fees of a candidate which was not included were returned to the para.
+		FeesRefunded(ParaId, Balance),
 	}
 }
 
@@ -186,6 +215,9 @@ impl<T: Trait> Module<T> {
 	) {
 		// unlike most drain methods, drained elements are not cleared on `Drop` of the iterator
 		// and require consumption.
+		for (para_id, fees) in <PendingAvailabilityFees<T>>::drain() {
+			Self::refund_fees(para_id, fees);
+		}
 		for _ in <PendingAvailabilityCommitments>::drain() { }
 		for _ in <PendingAvailability<T>>::drain() { }
 		for _ in <AvailabilityBitfields<T>>::drain() { }
@@ -318,6 +350,12 @@ impl<T: Trait> Module<T> {
 					descriptor: pending_availability.descriptor,
 					commitments,
 				};
+				Self::pay_fees(
+					para_id,
+					<PendingAvailabilityFees<T>>::take(&para_id),
+					&pending_availability.backers,
+					&pending_availability.availability_votes,
+				);
 				Self::enact_candidate(
 					pending_availability.relay_parent_number,
 					receipt,
@@ -329,9 +367,6 @@ impl<T: Trait> Module<T> {
 			}
 		}
 
-		// TODO: pass available candidates onwards to validity module once implemented.
-		// https://github.com/paritytech/polkadot/issues/1251
-
 		Ok(freed_cores)
 	}
 
@@ -359,9 +394,10 @@ impl<T: Trait> Module<T> {
 		let relay_parent_number = now - One::one();
 
 		// do all checks before writing storage.
-		let core_indices = {
+		let (core_indices, candidate_backers) = {
 			let mut skip = 0;
 			let mut core_indices = Vec::with_capacity(candidates.len());
+			let mut candidate_backers = Vec::with_capacity(candidates.len());
 			let mut last_core = None;
 
 			let mut check_assignment_in_order = |assignment: &CoreAssignment| -> DispatchResult {
@@ -411,6 +447,13 @@ impl<T: Trait> Module<T> {
 					candidate.descriptor().check_collator_signature().is_ok(),
 					Error::<T>::NotCollatorSigned,
 				);
+				ensure!(
+					T::Currency::can_reserve(
+						&para_id.into_account(),
+						candidate.candidate.commitments.fees.saturated_into(),
+					),
+					Error::<T>::CannotPayFees,
+				);
 
 				for (i, assignment) in scheduled[skip..].iter().enumerate() {
 					check_assignment_in_order(assignment)?;
@@ -457,6 +500,13 @@ impl<T: Trait> Module<T> {
 						}
 
 						core_indices.push(assignment.core);
+						candidate_backers.push(
+							candidate.validator_indices.iter()
+								.enumerate()
+								.filter(|(_, signed)| **signed)
+								.filter_map(|(i, _)| group_vals.get(i).cloned())
+								.collect::<Vec<_>>()
+						);
 						continue 'a;
 					}
 				}
@@ -475,13 +525,28 @@ impl<T: Trait> Module<T> {
 				check_assignment_in_order(assignment)?;
 			}
 
-			core_indices
+			(core_indices, candidate_backers)
 		};
 
 		// one more sweep for actually writing to storage.
-		for (candidate, core) in candidates.into_iter().zip(core_indices.iter().cloned()) {
+		let sweep = candidates.into_iter()
+			.zip(core_indices.iter().cloned())
+			.zip(candidate_backers);
+		for ((candidate, core), backers) in sweep {
 			let para_id = candidate.descriptor().para_id;
 
+			// checked above that the para is able to pay. Should reserving fail nonetheless,
+			// only the amount actually reserved is recorded, so that unrelated reserves are never
+			// touched.
+			let fees = candidate.candidate.commitments.fees.saturated_into();
+			let reserved = if T::Currency::reserve(&para_id.into_account(), fees).is_ok() {
+				fees
+			} else {
+				debug::warn!("Inclusion::process_candidates: failed to reserve checked fees");
+				Zero::zero()
+			};
+			<PendingAvailabilityFees<T>>::insert(&para_id, reserved);
+
 			// initialize all availability votes to 0.
 			let availability_votes: BitVec<BitOrderLsb0, u8>
 				= bitvec::bitvec![BitOrderLsb0, u8; 0; validators.len()];
@@ -502,6 +567,7 @@ impl<T: Trait> Module<T> {
 				availability_votes,
 				relay_parent_number,
 				backed_in_number: now,
+				backers,
 			});
 			<PendingAvailabilityCommitments>::insert(&para_id, commitments);
 		}
@@ -519,12 +585,20 @@ impl<T: Trait> Module<T> {
 
 		// initial weight is config read.
 		let mut weight = T::DbWeight::get().reads_writes(1, 0);
+
+		// included candidates proceed into the acceptance period, where they are approved by
+		// secondary checkers.
+		weight += <validity::Module<T>>::note_included(plain.hash(), receipt.descriptor.para_id);
 		if let Some(new_code) = commitments.new_validation_code {
-			weight += <paras::Module<T>>::schedule_code_upgrade(
-				receipt.descriptor.para_id,
-				new_code,
-				relay_parent_number + config.validation_upgrade_delay,
-			);
+			weight += if config.pvf_checking_ttl == 0 {
+				<paras::Module<T>>::schedule_code_upgrade(
+					receipt.descriptor.para_id,
+					new_code,
+					relay_parent_number + config.validation_upgrade_delay,
+				)
+			} else {
+				<paras::Module<T>>::start_pvf_check(receipt.descriptor.para_id, new_code)
+			};
 		}
 
 		Self::deposit_event(
@@ -538,6 +612,77 @@ impl<T: Trait> Module<T> {
 		)
 	}
 
+	/// Pay out the fees reserved for an included candidate from the sovereign account of its para.
+	///
+	/// Half of the fees are split evenly among the validators which backed the candidate and the
+	/// other half among the validators which made it available. Anything which cannot be paid out,
+	/// e.g. due to rounding, is returned to the para.
+	fn pay_fees(
+		para_id: ParaId,
+		fees: BalanceOf<T>,
+		backers: &[ValidatorIndex],
+		availability_votes: &BitVec<BitOrderLsb0, u8>,
+	) -> Weight {
+		if fees.is_zero() {
+			return 0;
+		}
+
+		let sovereign: T::AccountId = para_id.into_account();
+		let validators = Validators::get();
+
+		let available: Vec<ValidatorIndex> = availability_votes.iter()
+			.enumerate()
+			.filter(|(_, voted)| **voted)
+			.map(|(i, _)| i as ValidatorIndex)
+			.collect();
+
+		let backing_fees = fees / 2u32.into();
+		let availability_fees = fees - backing_fees;
+
+		let mut paid = BalanceOf::<T>::zero();
+		let mut writes = 0;
+		for (share, recipients) in &[(backing_fees, backers), (availability_fees, &available[..])] {
+			if recipients.is_empty() {
+				continue;
+			}
+
+			let per_recipient = *share / (recipients.len() as u32).into();
+			if per_recipient.is_zero() {
+				continue;
+			}
+
+			for account in recipients.iter()
+				.filter_map(|i| validators.get(*i as usize))
+				.filter_map(|v| T::ValidatorAccount::convert(v.clone()))
+			{
+				let unpaid = T::Currency::repatriate_reserved(
+					&sovereign,
+					&account,
+					per_recipient,
+					BalanceStatus::Free,
+				).unwrap_or(per_recipient);
+
+				paid += per_recipient.saturating_sub(unpaid);
+				writes += 2;
+			}
+		}
+
+		T::Currency::unreserve(&sovereign, fees.saturating_sub(paid));
+		Self::deposit_event(Event::<T>::FeesPaid(para_id, paid));
+
+		T::DbWeight::get().reads_writes(1 + writes, 1 + writes)
+	}
+
+	/// Return the fees reserved for a candidate which will not be included to its para.
+	fn refund_fees(para_id: ParaId, fees: BalanceOf<T>) {
+		if fees.is_zero() {
+			return;
+		}
+
+		T::Currency::unreserve(&para_id.into_account(), fees);
+		Self::deposit_event(Event::<T>::FeesRefunded(para_id, fees));
+	}
+
 	/// Cleans up all paras pending availability that the predicate returns true for.
 	///
 	/// The predicate accepts the index of the core and the block number the core has been occupied
@@ -559,7 +704,12 @@ impl<T: Trait> Module<T> {
 			let pending = <PendingAvailability<T>>::take(&para_id);
 			let commitments = <PendingAvailabilityCommitments>::take(&para_id);
 
+			let fees = <PendingAvailabilityFees<T>>::take(&para_id);
+
 			if let (Some(pending), Some(commitments)) = (pending, commitments) {
+				Self::refund_fees(para_id, fees);
+				<validity::Module<T>>::note_unavailable(pending.backed_in_number);
+
 				// defensive: this should always be true.
 				let candidate = CandidateReceipt {
 					descriptor: pending.descriptor,
@@ -585,6 +735,7 @@ impl<T: Trait> Module<T> {
 	pub(crate) fn force_enact(para: ParaId) {
 		let pending = <PendingAvailability<T>>::take(&para);
 		let commitments = <PendingAvailabilityCommitments>::take(&para);
+		let fees = <PendingAvailabilityFees<T>>::take(&para);
 
 		if let (Some(pending), Some(commitments)) = (pending, commitments) {
 			let candidate = CommittedCandidateReceipt {
@@ -592,6 +743,7 @@ impl<T: Trait> Module<T> {
 				commitments,
 			};
 
+			Self::pay_fees(para, fees, &pending.backers, &pending.availability_votes);
 			Self::enact_candidate(
 				pending.relay_parent_number,
 				candidate,
@@ -638,8 +790,8 @@ mod tests {
 	use keyring::Sr25519Keyring;
 
 	use crate::mock::{
-		new_test_ext, Configuration, Paras, System, Inclusion,
-		GenesisConfig as MockGenesisConfig, Test,
+		new_test_ext, Configuration, Paras, System, Inclusion, Balances, TestValidatorAccount,
+		Validity, GenesisConfig as MockGenesisConfig, Test,
 	};
 	use crate::initializer::SessionChangeNotification;
 	use crate::configuration::HostConfiguration;
@@ -815,6 +967,7 @@ mod tests {
 		pov_hash: Hash,
 		relay_parent: Hash,
 		new_validation_code: Option<ValidationCode>,
+		fees: u128,
 	}
 
 	impl TestCandidateBuilder {
@@ -829,6 +982,7 @@ mod tests {
 				commitments: CandidateCommitments {
 					head_data: self.head_data,
 					new_validation_code: self.new_validation_code,
+					fees: self.fees,
 					..Default::default()
 				},
 			}
@@ -849,7 +1003,8 @@ mod tests {
 				descriptor: default_candidate.descriptor.clone(),
 				availability_votes: default_availability_votes(),
 				relay_parent_number: 0,
-				backed_in_number: 0,
+				backed_in_number: 2,
+				backers: Vec::new(),
 			});
 			PendingAvailabilityCommitments::insert(chain_a, default_candidate.commitments.clone());
 
@@ -859,6 +1014,7 @@ mod tests {
 				availability_votes: default_availability_votes(),
 				relay_parent_number: 0,
 				backed_in_number: 0,
+				backers: Vec::new(),
 			});
 			PendingAvailabilityCommitments::insert(chain_b, default_candidate.commitments);
 
@@ -875,6 +1031,9 @@ mod tests {
 			assert!(<PendingAvailability<Test>>::get(&chain_b).is_some());
 			assert!(<PendingAvailabilityCommitments>::get(&chain_a).is_none());
 			assert!(<PendingAvailabilityCommitments>::get(&chain_b).is_some());
+
+			// the block which backed the timed-out candidate is noted as unavailable.
+			assert_eq!(Validity::oldest_unavailable_inclusion(), Some(2));
 		});
 	}
 
@@ -1012,6 +1171,7 @@ mod tests {
 					availability_votes: default_availability_votes(),
 					relay_parent_number: 0,
 					backed_in_number: 0,
+					backers: Vec::new(),
 				});
 				PendingAvailabilityCommitments::insert(chain_a, default_candidate.commitments);
 
@@ -1045,6 +1205,7 @@ mod tests {
 					availability_votes: default_availability_votes(),
 					relay_parent_number: 0,
 					backed_in_number: 0,
+					backers: Vec::new(),
 				});
 
 				*bare_bitfield.0.get_mut(0).unwrap() = true;
@@ -1111,6 +1272,7 @@ mod tests {
 				availability_votes: default_availability_votes(),
 				relay_parent_number: 0,
 				backed_in_number: 0,
+				backers: Vec::new(),
 			});
 			PendingAvailabilityCommitments::insert(chain_a, candidate_a.commitments);
 
@@ -1126,6 +1288,7 @@ mod tests {
 				availability_votes: default_availability_votes(),
 				relay_parent_number: 0,
 				backed_in_number: 0,
+				backers: Vec::new(),
 			});
 			PendingAvailabilityCommitments::insert(chain_b, candidate_b.commitments);
 
@@ -1486,6 +1649,7 @@ mod tests {
 					availability_votes: default_availability_votes(),
 					relay_parent_number: 3,
 					backed_in_number: 4,
+					backers: Vec::new(),
 				});
 				<PendingAvailabilityCommitments>::insert(&chain_a, candidate.commitments);
 
@@ -1707,6 +1871,7 @@ mod tests {
 					availability_votes: default_availability_votes(),
 					relay_parent_number: System::block_number() - 1,
 					backed_in_number: System::block_number(),
+					backers: vec![0, 1],
 				})
 			);
 			assert_eq!(
@@ -1722,6 +1887,7 @@ mod tests {
 					availability_votes: default_availability_votes(),
 					relay_parent_number: System::block_number() - 1,
 					backed_in_number: System::block_number(),
+					backers: vec![2, 3],
 				})
 			);
 			assert_eq!(
@@ -1737,6 +1903,7 @@ mod tests {
 					availability_votes: default_availability_votes(),
 					relay_parent_number: System::block_number() - 1,
 					backed_in_number: System::block_number(),
+					backers: vec![4],
 				})
 			);
 			assert_eq!(
@@ -1746,6 +1913,215 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn fees_are_reserved_at_backing_and_paid_at_inclusion() {
+		let chain_a = ParaId::from(1);
+		let chain_b = ParaId::from(2);
+		let thread_a = ParaId::from(3);
+
+		let paras = vec![(chain_a, true), (chain_b, true), (thread_a, false)];
+		let validators = vec![
+			Sr25519Keyring::Alice,
+			Sr25519Keyring::Bob,
+			Sr25519Keyring::Charlie,
+			Sr25519Keyring::Dave,
+			Sr25519Keyring::Ferdie,
+		];
+		let validator_public = validator_pubkeys(&validators);
+
+		new_test_ext(genesis_config(paras)).execute_with(|| {
+			Validators::set(validator_public.clone());
+			CurrentSessionIndex::set(5);
+
+			run_to_block(5, |_| None);
+
+			let chain_a_account: u64 = chain_a.into_account();
+			let chain_b_account: u64 = chain_b.into_account();
+			Balances::make_free_balance_be(&chain_a_account, 1000);
+			Balances::make_free_balance_be(&chain_b_account, 50);
+
+			let signing_context = SigningContext {
+				parent_hash: System::parent_hash(),
+				session_index: 5,
+			};
+
+			let group_validators = |group_index: GroupIndex| match group_index {
+				group_index if group_index == GroupIndex::from(0) => Some(vec![0, 1]),
+				group_index if group_index == GroupIndex::from(1) => Some(vec![2, 3]),
+				_ => panic!("Group index out of bounds for 2 parachains"),
+			};
+
+			let chain_a_assignment = CoreAssignment {
+				core: CoreIndex::from(0),
+				para_id: chain_a,
+				kind: AssignmentKind::Parachain,
+				group_idx: GroupIndex::from(0),
+			};
+
+			let chain_b_assignment = CoreAssignment {
+				core: CoreIndex::from(1),
+				para_id: chain_b,
+				kind: AssignmentKind::Parachain,
+				group_idx: GroupIndex::from(1),
+			};
+
+			let make_backed = |para_id, group_index, pov_hash| {
+				let mut candidate = TestCandidateBuilder {
+					para_id,
+					relay_parent: System::parent_hash(),
+					pov_hash: Hash::from([pov_hash; 32]),
+					head_data: vec![pov_hash].into(),
+					fees: 80,
+					..Default::default()
+				}.build();
+				collator_sign_candidate(
+					Sr25519Keyring::One,
+					&mut candidate,
+				);
+
+				back_candidate(
+					candidate,
+					&validators,
+					group_validators(GroupIndex::from(group_index)).unwrap().as_ref(),
+					&signing_context,
+					BackingKind::Threshold,
+				)
+			};
+
+			// chain B cannot pay.
+			assert_eq!(
+				Inclusion::process_candidates(
+					vec![make_backed(chain_b, 1, 2)],
+					vec![chain_b_assignment.clone()],
+					&group_validators,
+				),
+				Err(Error::<Test>::CannotPayFees.into()),
+			);
+			assert_eq!(Balances::reserved_balance(&chain_b_account), 0);
+
+			Inclusion::process_candidates(
+				vec![make_backed(chain_a, 0, 1)],
+				vec![chain_a_assignment.clone()],
+				&group_validators,
+			).expect("candidates scheduled, in order, and backed");
+
+			assert_eq!(
+				<PendingAvailability<Test>>::get(&chain_a).unwrap().backers,
+				vec![0, 1],
+			);
+			assert_eq!(Balances::free_balance(&chain_a_account), 920);
+			assert_eq!(Balances::reserved_balance(&chain_a_account), 80);
+
+			let core_lookup = |core| match core {
+				core if core == CoreIndex::from(0) => Some(chain_a),
+				core if core == CoreIndex::from(1) => Some(chain_b),
+				core if core == CoreIndex::from(2) => Some(thread_a),
+				_ => panic!("Core out of bounds for 2 parachains and 1 parathread core."),
+			};
+
+			let signed_bitfields = validators.iter().enumerate().skip(1).map(|(i, key)| {
+				let mut bare_bitfield = default_bitfield();
+				*bare_bitfield.0.get_mut(0).unwrap() = true;
+
+				sign_bitfield(
+					key,
+					i as ValidatorIndex,
+					bare_bitfield,
+					&signing_context,
+				)
+			}).collect();
+
+			assert_eq!(
+				Inclusion::process_bitfields(signed_bitfields, &core_lookup),
+				Ok(vec![CoreIndex::from(0)]),
+			);
+
+			// half of the fees to validators 0 and 1 for backing, the other half to
+			// validators 1 through 4 for availability.
+			let account = |i: usize| TestValidatorAccount::convert(validator_public[i].clone()).unwrap();
+			assert_eq!(Balances::free_balance(&account(0)), 20);
+			assert_eq!(Balances::free_balance(&account(1)), 30);
+			assert_eq!(Balances::free_balance(&account(2)), 10);
+			assert_eq!(Balances::free_balance(&account(3)), 10);
+			assert_eq!(Balances::free_balance(&account(4)), 10);
+
+			assert_eq!(Balances::free_balance(&chain_a_account), 920);
+			assert_eq!(Balances::reserved_balance(&chain_a_account), 0);
+		});
+	}
+
+	#[test]
+	fn fees_are_refunded_on_timeout() {
+		let chain_a = ParaId::from(1);
+		let chain_b = ParaId::from(2);
+		let thread_a = ParaId::from(3);
+
+		let paras = vec![(chain_a, true), (chain_b, true), (thread_a, false)];
+		new_test_ext(genesis_config(paras)).execute_with(|| {
+			let chain_a_account: u64 = chain_a.into_account();
+			Balances::make_free_balance_be(&chain_a_account, 1000);
+			Balances::reserve(&chain_a_account, 80).unwrap();
+
+			let candidate = TestCandidateBuilder {
+				para_id: chain_a,
+				fees: 80,
+				..Default::default()
+			}.build();
+			<PendingAvailability<Test>>::insert(chain_a, CandidatePendingAvailability {
+				core: CoreIndex::from(0),
+				descriptor: candidate.descriptor,
+				availability_votes: default_availability_votes(),
+				relay_parent_number: 0,
+				backed_in_number: 0,
+				backers: vec![0, 1],
+			});
+			PendingAvailabilityCommitments::insert(chain_a, candidate.commitments);
+			<PendingAvailabilityFees<Test>>::insert(chain_a, 80);
+
+			Inclusion::collect_pending(|core, _since| core == CoreIndex::from(0));
+
+			assert_eq!(Balances::free_balance(&chain_a_account), 1000);
+			assert_eq!(Balances::reserved_balance(&chain_a_account), 0);
+		});
+	}
+
+	#[test]
+	fn only_reserved_fees_are_refunded() {
+		let chain_a = ParaId::from(1);
+		let chain_b = ParaId::from(2);
+		let thread_a = ParaId::from(3);
+
+		let paras = vec![(chain_a, true), (chain_b, true), (thread_a, false)];
+		new_test_ext(genesis_config(paras)).execute_with(|| {
+			let chain_a_account: u64 = chain_a.into_account();
+			Balances::make_free_balance_be(&chain_a_account, 1000);
+
+			// reserved for something other than the candidate, whose fees could not be reserved.
+			Balances::reserve(&chain_a_account, 80).unwrap();
+
+			let candidate = TestCandidateBuilder {
+				para_id: chain_a,
+				fees: 80,
+				..Default::default()
+			}.build();
+			<PendingAvailability<Test>>::insert(chain_a, CandidatePendingAvailability {
+				core: CoreIndex::from(0),
+				descriptor: candidate.descriptor,
+				availability_votes: default_availability_votes(),
+				relay_parent_number: 0,
+				backed_in_number: 0,
+				backers: vec![0, 1],
+			});
+			PendingAvailabilityCommitments::insert(chain_a, candidate.commitments);
+			<PendingAvailabilityFees<Test>>::insert(chain_a, 0);
+
+			Inclusion::collect_pending(|core, _since| core == CoreIndex::from(0));
+
+			assert_eq!(Balances::free_balance(&chain_a_account), 920);
+			assert_eq!(Balances::reserved_balance(&chain_a_account), 80);
+		});
+	}
+
 	#[test]
 	fn session_change_wipes_and_updates_session_info() {
 		let chain_a = ParaId::from(1);
@@ -1807,6 +2183,7 @@ mod tests {
 				availability_votes: default_availability_votes(),
 				relay_parent_number: 5,
 				backed_in_number: 6,
+				backers: Vec::new(),
 			});
 			<PendingAvailabilityCommitments>::insert(&chain_a, candidate.commitments.clone());
 
@@ -1816,6 +2193,7 @@ mod tests {
 				availability_votes: default_availability_votes(),
 				relay_parent_number: 6,
 				backed_in_number: 7,
+				backers: Vec::new(),
 			});
 			<PendingAvailabilityCommitments>::insert(&chain_b, candidate.commitments);
 
@@ -27,7 +27,7 @@ use frame_support::{
 };
 use sp_runtime::traits::One;
 use codec::{Encode, Decode};
-use crate::{configuration::{self, HostConfiguration}, paras, scheduler, inclusion};
+use crate::{configuration::{self, HostConfiguration}, paras, scheduler, inclusion, validity, disputes};
 
 /// Information about a session change that has just occurred.
 #[derive(Default, Clone)]
@@ -56,6 +56,8 @@ struct BufferedSessionChange<N> {
 
 pub trait Trait:
 	system::Trait + configuration::Trait + paras::Trait + scheduler::Trait + inclusion::Trait
+	+ validity::Trait
+	+ disputes::Trait
 {
 	/// A randomness beacon.
 	type Randomness: Randomness<Self::Hash>;
@@ -114,10 +116,13 @@ decl_module! {
 			// - Scheduler
 			// - Inclusion
 			// - Validity
+			// - Disputes
 			let total_weight = configuration::Module::<T>::initializer_initialize(now) +
 				paras::Module::<T>::initializer_initialize(now) +
 				scheduler::Module::<T>::initializer_initialize(now) +
-				inclusion::Module::<T>::initializer_initialize(now);
+				inclusion::Module::<T>::initializer_initialize(now) +
+				validity::Module::<T>::initializer_initialize(now) +
+				disputes::Module::<T>::initializer_initialize(now);
 
 			HasInitialized::set(Some(()));
 
@@ -127,6 +132,8 @@ decl_module! {
 		fn on_finalize() {
 			// reverse initialization order.
 
+			disputes::Module::<T>::initializer_finalize();
+			validity::Module::<T>::initializer_finalize();
 			inclusion::Module::<T>::initializer_finalize();
 			scheduler::Module::<T>::initializer_finalize();
 			paras::Module::<T>::initializer_finalize();
@@ -170,6 +177,14 @@ impl<T: Trait> Module<T> {
 		paras::Module::<T>::initializer_on_new_session(&notification);
 		scheduler::Module::<T>::initializer_on_new_session(&notification);
 		inclusion::Module::<T>::initializer_on_new_session(&notification);
+		validity::Module::<T>::initializer_on_new_session(&notification);
+		disputes::Module::<T>::initializer_on_new_session(&notification);
+	}
+
+	/// The index of the session that will be applied at the next block, if a session change is
+	/// buffered.
+	pub(crate) fn buffered_session_index() -> Option<sp_staking::SessionIndex> {
+		<BufferedSessionChanges<T>>::get().last().map(|buffered| buffered.session_index)
 	}
 
 	/// Should be called when a new session occurs. Buffers the session notification to be applied
@@ -268,6 +283,20 @@ mod tests {
 		});
 	}
 
+	#[test]
+	fn buffered_session_index_is_the_last_buffered() {
+		new_test_ext(Default::default()).execute_with(|| {
+			assert!(Initializer::buffered_session_index().is_none());
+
+			Initializer::on_new_session(false, 1, Vec::new().into_iter(), Some(Vec::new().into_iter()));
+			Initializer::on_new_session(false, 2, Vec::new().into_iter(), Some(Vec::new().into_iter()));
+			assert_eq!(Initializer::buffered_session_index(), Some(2));
+
+			Initializer::on_initialize(2);
+			assert!(Initializer::buffered_session_index().is_none());
+		});
+	}
+
 	#[test]
 	fn sets_flag_on_initialize() {
 		new_test_ext(Default::default()).execute_with(|| {
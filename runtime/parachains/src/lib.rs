@@ -20,11 +20,12 @@
 //! particular the `Initializer` module, as it is responsible for initializing the state
 //! of the other modules.
 
-mod configuration;
+pub mod configuration;
+mod disputes;
 mod inclusion;
 mod inclusion_inherent;
 mod initializer;
-mod paras;
+pub mod paras;
 mod scheduler;
 mod validity;
 
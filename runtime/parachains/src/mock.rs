@@ -16,20 +16,23 @@
 
 //! Mocks for all the traits.
 
+use std::cell::RefCell;
 use sp_io::TestExternalities;
+use codec::Decode;
 use sp_core::{H256};
 use sp_runtime::{
 	Perbill,
 	traits::{
-		BlakeTwo256, IdentityLookup,
+		BlakeTwo256, IdentityLookup, Convert,
 	},
+	testing::TestXt,
 };
-use primitives::v1::{BlockNumber, Header};
+use primitives::v1::{BlockNumber, Header, SessionIndex, ValidatorId};
 use frame_support::{
 	impl_outer_origin, impl_outer_dispatch, impl_outer_event, parameter_types,
 	weights::Weight, traits::Randomness as RandomnessT,
 };
-use crate::inclusion;
+use crate::{paras, inclusion, validity, disputes};
 
 /// A test runtime struct.
 #[derive(Clone, Eq, PartialEq)]
@@ -42,13 +45,18 @@ impl_outer_origin! {
 impl_outer_dispatch! {
 	pub enum Call for Test where origin: Origin {
 		initializer::Initializer,
+		paras::Paras,
 	}
 }
 
 impl_outer_event! {
 	pub enum TestEvent for Test {
 		system<T>,
+		balances<T>,
+		paras,
 		inclusion<T>,
+		validity,
+		disputes,
 	}
 }
 
@@ -65,6 +73,7 @@ parameter_types! {
 	pub const MaximumBlockWeight: Weight = 4 * 1024 * 1024;
 	pub const MaximumBlockLength: u32 = 4 * 1024 * 1024;
 	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+	pub const ExistentialDeposit: u128 = 1;
 }
 
 impl system::Trait for Test {
@@ -95,22 +104,93 @@ impl system::Trait for Test {
 	type SystemWeightInfo = ();
 }
 
+impl balances::Trait for Test {
+	type Balance = u128;
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
 impl crate::initializer::Trait for Test {
 	type Randomness = TestRandomness;
 }
 
 impl crate::configuration::Trait for Test { }
 
-impl crate::paras::Trait for Test { }
+impl crate::paras::Trait for Test {
+	type Event = TestEvent;
+}
+
+impl<C> system::offchain::SendTransactionTypes<C> for Test where
+	Call: From<C>,
+{
+	type OverarchingCall = Call;
+	type Extrinsic = TestXt<Call, ()>;
+}
 
 impl crate::scheduler::Trait for Test { }
 
+/// Derives the account of a validator from the leading bytes of its parachain key.
+pub struct TestValidatorAccount;
+
+impl Convert<ValidatorId, Option<u64>> for TestValidatorAccount {
+	fn convert(id: ValidatorId) -> Option<u64> {
+		u64::decode(&mut AsRef::<[u8]>::as_ref(&id)).ok()
+	}
+}
+
 impl crate::inclusion::Trait for Test {
 	type Event = TestEvent;
+	type Currency = Balances;
+	type ValidatorAccount = TestValidatorAccount;
+}
+
+thread_local! {
+	pub static BLOCK_VRF_OUTPUT: RefCell<Option<[u8; 32]>> = RefCell::new(None);
+}
+
+/// Returns the VRF output set in `BLOCK_VRF_OUTPUT`.
+pub struct TestVrfOutput;
+
+impl validity::BlockVrfOutput for TestVrfOutput {
+	fn block_vrf_output() -> Option<[u8; 32]> {
+		BLOCK_VRF_OUTPUT.with(|o| *o.borrow())
+	}
+}
+
+impl crate::validity::Trait for Test {
+	type Event = TestEvent;
+	type BlockVrfOutput = TestVrfOutput;
+}
+
+/// Identifies validators by their parachain key.
+pub struct IdentityValidatorIdentification;
+
+impl Convert<(SessionIndex, ValidatorId), Option<ValidatorId>> for IdentityValidatorIdentification {
+	fn convert((_, id): (SessionIndex, ValidatorId)) -> Option<ValidatorId> {
+		Some(id)
+	}
+}
+
+parameter_types! {
+	pub const DisputeTimeout: SessionIndex = 2;
+}
+
+impl crate::disputes::Trait for Test {
+	type Event = TestEvent;
+	type IdentificationTuple = ValidatorId;
+	type ValidatorIdentification = IdentityValidatorIdentification;
+	type ReportOffence = ();
+	type DisputeTimeout = DisputeTimeout;
 }
 
 pub type System = system::Module<Test>;
 
+/// Mocked balances.
+pub type Balances = balances::Module<Test>;
+
 /// Mocked initializer.
 pub type Initializer = crate::initializer::Module<Test>;
 
@@ -126,6 +206,12 @@ pub type Scheduler = crate::scheduler::Module<Test>;
 /// Mocked inclusion module.
 pub type Inclusion = crate::inclusion::Module<Test>;
 
+/// Mocked validity module.
+pub type Validity = crate::validity::Module<Test>;
+
+/// Mocked disputes module.
+pub type Disputes = crate::disputes::Module<Test>;
+
 /// Create a new set of test externalities.
 pub fn new_test_ext(state: GenesisConfig) -> TestExternalities {
 	let mut t = state.system.build_storage::<Test>().unwrap();
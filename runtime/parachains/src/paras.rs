@@ -20,27 +20,49 @@
 //! this fork of the relay chain, what their validation code is, and what their past and upcoming
 //! validation code is.
 //!
+//! Current and past validation code is stored by hash, along with the number of current and past
+//! code entries referring to it, so code shared between paras or re-used across upgrades is only
+//! stored once.
+//!
 //! A para is not considered live until it is registered and activated in this module. Activation can
 //! only occur at session boundaries.
+//!
+//! If `pvf_checking_ttl` is set in the configuration, new validation code is pre-checked before a
+//! code upgrade is scheduled: validators compile the code and submit signed statements on whether
+//! it compiles, as unsigned transactions. The upgrade is scheduled once a supermajority of the
+//! session's validators accepts the code, and rejected if a supermajority rejects it or no
+//! decision is reached in time.
 
 use sp_std::prelude::*;
 use sp_std::marker::PhantomData;
 use sp_runtime::traits::One;
+use sp_runtime::traits::{AppVerify, BlakeTwo256, Hash as HashT};
+use sp_runtime::transaction_validity::{
+	TransactionValidity, ValidTransaction, InvalidTransaction, TransactionSource,
+	TransactionLongevity,
+};
 use primitives::v1::{
-	Id as ParaId, ValidationCode, HeadData,
+	Id as ParaId, ValidationCode, HeadData, Hash, SessionIndex, ValidatorSignature,
+	PvfCheckStatement,
 };
 use frame_support::{
-	decl_storage, decl_module, decl_error,
-	traits::Get,
-	weights::Weight,
+	decl_storage, decl_module, decl_error, decl_event, ensure,
+	dispatch::DispatchResult, traits::Get, weights::Weight, IterableStorageMap,
 };
 use codec::{Encode, Decode};
-use crate::{configuration, initializer::SessionChangeNotification};
+use bitvec::{order::Lsb0 as BitOrderLsb0, vec::BitVec};
+use system::{ensure_none, offchain::{SendTransactionTypes, SubmitTransaction}};
+use crate::{
+	configuration, validity, disputes::supermajority_threshold,
+	initializer::SessionChangeNotification,
+};
 
 #[cfg(feature = "std")]
 use serde::{Serialize, Deserialize};
 
-pub trait Trait: system::Trait + configuration::Trait { }
+pub trait Trait: system::Trait + configuration::Trait + SendTransactionTypes<Call<Self>> {
+	type Event: From<Event> + Into<<Self as system::Trait>::Event>;
+}
 
 // the two key times necessary to track for every code replacement.
 #[derive(Default, Encode, Decode)]
@@ -154,6 +176,37 @@ impl<N: Ord + Copy> ParaPastCodeMeta<N> {
 	}
 }
 
+/// The state of a code upgrade which is being pre-checked by validators.
+#[derive(Encode, Decode)]
+#[cfg_attr(test, derive(Debug, Clone, PartialEq))]
+pub struct PvfCheck {
+	/// The hash of the new validation code.
+	code_hash: Hash,
+	/// The session the pre-check was started in.
+	started_in: SessionIndex,
+	/// One bit per validator of the current session, set if the validator accepted the code.
+	accept_votes: BitVec<BitOrderLsb0, u8>,
+	/// One bit per validator of the current session, set if the validator rejected the code.
+	reject_votes: BitVec<BitOrderLsb0, u8>,
+}
+
+impl PvfCheck {
+	fn new(code_hash: Hash, started_in: SessionIndex, n_validators: usize) -> Self {
+		PvfCheck {
+			code_hash,
+			started_in,
+			accept_votes: BitVec::repeat(false, n_validators),
+			reject_votes: BitVec::repeat(false, n_validators),
+		}
+	}
+
+	// Discard all votes, as they were given by the validators of a previous session.
+	fn reset_votes(&mut self, n_validators: usize) {
+		self.accept_votes = BitVec::repeat(false, n_validators);
+		self.reject_votes = BitVec::repeat(false, n_validators);
+	}
+}
+
 /// Arguments for initializing a para.
 #[derive(Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
@@ -175,10 +228,11 @@ decl_storage! {
 		Parathreads: map hasher(twox_64_concat) ParaId => Option<()>;
 		/// The head-data of every registered para.
 		Heads get(fn para_head): map hasher(twox_64_concat) ParaId => Option<HeadData>;
-		/// The validation code of every live para.
-		CurrentCode get(fn current_code): map hasher(twox_64_concat) ParaId => Option<ValidationCode>;
-		/// Actual past code, indicated by the para id as well as the block number at which it became outdated.
-		PastCode: map hasher(twox_64_concat) (ParaId, T::BlockNumber) => Option<ValidationCode>;
+		/// The validation code hash of every live para.
+		CurrentCodeHash get(fn current_code_hash): map hasher(twox_64_concat) ParaId => Option<Hash>;
+		/// Actual past code hash, indicated by the para id as well as the block number at which it
+		/// became outdated.
+		PastCodeHash: map hasher(twox_64_concat) (ParaId, T::BlockNumber) => Option<Hash>;
 		/// Past code of parachains. The parachains themselves may not be registered anymore,
 		/// but we also keep their code on-chain for the same amount of time as outdated code
 		/// to keep it available for secondary checkers.
@@ -196,7 +250,13 @@ decl_storage! {
 		/// in the context of a relay chain block with a number >= `expected_at`.
 		FutureCodeUpgrades get(fn future_code_upgrade_at): map hasher(twox_64_concat) ParaId => Option<T::BlockNumber>;
 		/// The actual future code of a para.
-		FutureCode: map hasher(twox_64_concat) ParaId => Option<ValidationCode>;
+		FutureCode get(fn future_code): map hasher(twox_64_concat) ParaId => Option<ValidationCode>;
+		/// Validation code stored by its hash. This is referenced by `CurrentCodeHash` and
+		/// `PastCodeHash` entries.
+		CodeByHash get(fn code_by_hash): map hasher(identity) Hash => Option<ValidationCode>;
+		/// The number of `CurrentCodeHash` and `PastCodeHash` entries referring to the validation
+		/// code with the given hash. The code is removed once this drops to zero.
+		CodeByHashRefs: map hasher(identity) Hash => u32;
 
 		/// Upcoming paras (chains and threads). These are only updated on session change. Corresponds to an
 		/// entry in the upcoming-genesis map.
@@ -206,6 +266,10 @@ decl_storage! {
 		/// Paras that are to be cleaned up at the end of the session.
 		OutgoingParas: Vec<ParaId>;
 
+		/// Code upgrades which are being pre-checked by validators, by para.
+		PvfChecks get(fn pvf_check): map hasher(twox_64_concat) ParaId => Option<PvfCheck>;
+		/// The new validation code of paras with a code upgrade being pre-checked.
+		PvfCheckCode get(fn pvf_check_code): map hasher(twox_64_concat) ParaId => Option<ValidationCode>;
 	}
 	add_extra_genesis {
 		config(paras): Vec<(ParaId, ParaGenesisArgs)>;
@@ -229,19 +293,125 @@ fn build<T: Trait>(config: &GenesisConfig<T>) {
 	Parachains::put(&parachains);
 
 	for (id, genesis_args) in &config.paras {
-		<Module<T> as Store>::CurrentCode::insert(&id, &genesis_args.validation_code);
+		let code_hash = BlakeTwo256::hash_of(&genesis_args.validation_code);
+		<Module<T>>::increase_code_ref(&code_hash, &genesis_args.validation_code);
+		<Module<T> as Store>::CurrentCodeHash::insert(&id, &code_hash);
 		<Module<T> as Store>::Heads::insert(&id, &genesis_args.genesis_head);
 	}
 }
 
 decl_error! {
-	pub enum Error for Module<T: Trait> { }
+	pub enum Error for Module<T: Trait> {
+		/// The statement is not for the current session.
+		PvfCheckStatementStale,
+		/// There is no code upgrade being pre-checked for the para.
+		PvfCheckNotFound,
+		/// The statement is about different validation code than is being pre-checked.
+		PvfCheckSubjectMismatch,
+		/// Validator index out of bounds.
+		ValidatorIndexOutOfBounds,
+		/// The statement has an invalid signature.
+		InvalidPvfCheckSignature,
+		/// The validator has already submitted a statement on the code.
+		DuplicatePvfCheckStatement,
+	}
+}
+
+decl_event! {
+	pub enum Event {
+		/// New validation code of a para is being pre-checked.
+		PvfCheckStarted(ParaId, Hash),
+		/// A supermajority of validators accepted new validation code and the code upgrade was
+		/// scheduled.
+		PvfCheckAccepted(ParaId, Hash),
+		/// New validation code of a para was rejected, either by a supermajority of validators
+		/// or because no decision was reached in time.
+		PvfCheckRejected(ParaId, Hash),
+		/// A code upgrade of a para was scheduled. Validators can use this to prepare the new
+		/// validation code ahead of the upgrade.
+		CodeUpgradeScheduled(ParaId, Hash),
+	}
 }
 
 decl_module! {
 	/// The parachains configuration module.
 	pub struct Module<T: Trait> for enum Call where origin: <T as system::Trait>::Origin, system = system {
 		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		/// Submit a statement on whether the new validation code of a para compiles, on behalf of a
+		/// validator of the current session. This is an unsigned transaction, checked by the
+		/// statement's signature.
+		///
+		/// Once a supermajority of the session's validators accepts the code, the code upgrade is
+		/// scheduled. Once a supermajority rejects it, the code upgrade is discarded.
+		#[weight = 100_000_000 + T::DbWeight::get().reads_writes(4, 3)]
+		pub fn include_pvf_check_statement(
+			origin,
+			statement: PvfCheckStatement,
+			signature: ValidatorSignature,
+		) -> DispatchResult {
+			ensure_none(origin)?;
+
+			let (mut check, n_validators) = Self::check_pvf_check_statement(&statement, &signature)?;
+			let para_id = statement.para_id;
+			let validator_index = statement.validator_index as usize;
+
+			if statement.accept {
+				check.accept_votes.set(validator_index, true);
+			} else {
+				check.reject_votes.set(validator_index, true);
+			}
+
+			let threshold = supermajority_threshold(n_validators);
+			if check.accept_votes.count_ones() >= threshold {
+				PvfChecks::remove(&para_id);
+				let new_code = PvfCheckCode::take(&para_id).unwrap_or_default();
+
+				let now = <system::Module<T>>::block_number();
+				let config = <configuration::Module<T>>::config();
+				Self::schedule_code_upgrade(para_id, new_code, now + config.validation_upgrade_delay);
+
+				Self::deposit_event(Event::PvfCheckAccepted(para_id, check.code_hash));
+			} else if check.reject_votes.count_ones() >= threshold {
+				Self::reject_pvf_check(para_id, check.code_hash);
+			} else {
+				PvfChecks::insert(&para_id, check);
+			}
+
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> sp_runtime::traits::ValidateUnsigned for Module<T> {
+	type Call = Call<T>;
+
+	fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+		const PRIORITY: u64 = 100;
+
+		match call {
+			Call::include_pvf_check_statement(statement, signature) => {
+				Self::check_pvf_check_statement(statement, signature).map_err(|e| match e {
+					Error::<T>::PvfCheckStatementStale => InvalidTransaction::Stale,
+					Error::<T>::InvalidPvfCheckSignature => InvalidTransaction::BadProof,
+					_ => InvalidTransaction::Call,
+				})?;
+
+				Ok(ValidTransaction {
+					priority: PRIORITY,
+					requires: vec![],
+					provides: vec![
+						("pvf_check", statement.session_index, statement.validator_index, statement.para_id)
+							.encode(),
+					],
+					longevity: TransactionLongevity::max_value(),
+					propagate: true,
+				})
+			}
+			_ => Err(InvalidTransaction::Call.into()),
+		}
 	}
 }
 
@@ -255,11 +425,90 @@ impl<T: Trait> Module<T> {
 	pub(crate) fn initializer_finalize() { }
 
 	/// Called by the initializer to note that a new session has started.
-	pub(crate) fn initializer_on_new_session(_notification: &SessionChangeNotification<T::BlockNumber>) {
+	pub(crate) fn initializer_on_new_session(notification: &SessionChangeNotification<T::BlockNumber>) {
 		let now = <system::Module<T>>::block_number();
 		let mut parachains = Self::clean_up_outgoing(now);
 		Self::apply_incoming(&mut parachains);
 		<Self as Store>::Parachains::set(parachains);
+
+		// The validity module notes the new session's validators only after this module.
+		Self::age_pvf_checks(
+			notification.session_index,
+			notification.validators.len(),
+			&notification.new_config,
+		);
+	}
+
+	// Reject all pre-checks which have run out of time, and discard the votes on all others, as the
+	// validator set has changed.
+	fn age_pvf_checks(
+		session_index: SessionIndex,
+		n_validators: usize,
+		config: &configuration::HostConfiguration<T::BlockNumber>,
+	) {
+		let checks: Vec<_> = <PvfChecks as IterableStorageMap<_, _>>::iter().collect();
+
+		for (para_id, mut check) in checks {
+			if session_index.saturating_sub(check.started_in) >= config.pvf_checking_ttl {
+				Self::reject_pvf_check(para_id, check.code_hash);
+			} else {
+				check.reset_votes(n_validators);
+				PvfChecks::insert(&para_id, check);
+			}
+		}
+	}
+
+	// Checks that a statement is on a pending pre-check and for the current session, and that it is
+	// signed by a validator of the session who has not yet submitted a statement on the code.
+	// Returns the pre-check and the number of validators of the session.
+	fn check_pvf_check_statement(
+		statement: &PvfCheckStatement,
+		signature: &ValidatorSignature,
+	) -> Result<(PvfCheck, usize), Error<T>> {
+		// The validators of the current session vote on pending pre-checks.
+		let session_index = validity::CurrentSessionIndex::get();
+		ensure!(
+			statement.session_index == session_index,
+			Error::<T>::PvfCheckStatementStale,
+		);
+
+		let check = PvfChecks::get(&statement.para_id).ok_or(Error::<T>::PvfCheckNotFound)?;
+		ensure!(check.code_hash == statement.code_hash, Error::<T>::PvfCheckSubjectMismatch);
+
+		let validators = validity::SessionValidators::get(&session_index).unwrap_or_default();
+		let validator_index = statement.validator_index as usize;
+		let validator_public = validators.get(validator_index)
+			.ok_or(Error::<T>::ValidatorIndexOutOfBounds)?;
+
+		ensure!(
+			signature.verify(&statement.signing_payload()[..], validator_public),
+			Error::<T>::InvalidPvfCheckSignature,
+		);
+		ensure!(
+			!check.accept_votes[validator_index] && !check.reject_votes[validator_index],
+			Error::<T>::DuplicatePvfCheckStatement,
+		);
+
+		Ok((check, validators.len()))
+	}
+
+	/// Submit a validator's signed statement on a pending pre-check as an unsigned transaction.
+	///
+	/// This is meant to be called from the runtime API by the validator's node, so that validators
+	/// need no funded account to vote.
+	pub(crate) fn submit_pvf_check_statement(
+		statement: PvfCheckStatement,
+		signature: ValidatorSignature,
+	) {
+		let call = Call::include_pvf_check_statement(statement, signature);
+		let _ = SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(call.into());
+	}
+
+	fn reject_pvf_check(id: ParaId, code_hash: Hash) {
+		PvfChecks::remove(&id);
+		PvfCheckCode::remove(&id);
+
+		Self::deposit_event(Event::PvfCheckRejected(id, code_hash));
 	}
 
 	/// Cleans up all outgoing paras. Returns the new set of parachains
@@ -277,10 +526,12 @@ impl<T: Trait> Module<T> {
 			<Self as Store>::Heads::remove(&outgoing_para);
 			<Self as Store>::FutureCodeUpgrades::remove(&outgoing_para);
 			<Self as Store>::FutureCode::remove(&outgoing_para);
+			<Self as Store>::PvfChecks::remove(&outgoing_para);
+			<Self as Store>::PvfCheckCode::remove(&outgoing_para);
 
-			let removed_code = <Self as Store>::CurrentCode::take(&outgoing_para);
-			if let Some(removed_code) = removed_code {
-				Self::note_past_code(outgoing_para, now, now, removed_code);
+			let removed_code_hash = <Self as Store>::CurrentCodeHash::take(&outgoing_para);
+			if let Some(removed_code_hash) = removed_code_hash {
+				Self::note_past_code(outgoing_para, now, now, removed_code_hash);
 			}
 		}
 
@@ -305,12 +556,15 @@ impl<T: Trait> Module<T> {
 			}
 
 			<Self as Store>::Heads::insert(&upcoming_para, genesis_data.genesis_head);
-			<Self as Store>::CurrentCode::insert(&upcoming_para, genesis_data.validation_code);
+			let code_hash = BlakeTwo256::hash_of(&genesis_data.validation_code);
+			Self::increase_code_ref(&code_hash, &genesis_data.validation_code);
+			<Self as Store>::CurrentCodeHash::insert(&upcoming_para, code_hash);
 		}
 	}
 
 	// note replacement of the code of para with given `id`, which occured in the
-	// context of the given relay-chain block number. provide the replaced code.
+	// context of the given relay-chain block number. provide the hash of the replaced code,
+	// whose reference is moved from the current code to the past code.
 	//
 	// `at` for para-triggered replacement is the block number of the relay-chain
 	// block in whose context the parablock was executed
@@ -319,14 +573,14 @@ impl<T: Trait> Module<T> {
 		id: ParaId,
 		at: T::BlockNumber,
 		now: T::BlockNumber,
-		old_code: ValidationCode,
+		old_code_hash: Hash,
 	) -> Weight {
 
 		<Self as Store>::PastCodeMeta::mutate(&id, |past_meta| {
 			past_meta.note_replacement(at, now);
 		});
 
-		<Self as Store>::PastCode::insert(&(id, at), old_code);
+		<Self as Store>::PastCodeHash::insert(&(id, at), old_code_hash);
 
 		// Schedule pruning for this past-code to be removed as soon as it
 		// exits the slashing window.
@@ -365,7 +619,10 @@ impl<T: Trait> Module<T> {
 				for (para_id, _) in pruning_tasks_to_do {
 					let full_deactivate = <Self as Store>::PastCodeMeta::mutate(&para_id, |meta| {
 						for pruned_repl_at in meta.prune_up_to(pruning_height) {
-							<Self as Store>::PastCode::remove(&(para_id, pruned_repl_at));
+							let pruned_code_hash = <Self as Store>::PastCodeHash::take(&(para_id, pruned_repl_at));
+							if let Some(pruned_code_hash) = pruned_code_hash {
+								Self::decrease_code_ref(&pruned_code_hash);
+							}
 						}
 
 						meta.most_recent_change().is_none() && Self::para_head(&para_id).is_none()
@@ -381,14 +638,42 @@ impl<T: Trait> Module<T> {
 				pruning_tasks_done as u64
 			});
 
-		// 1 read for the meta for each pruning task, 1 read for the config
-		// 2 writes: updating the meta and pruning the code
-		T::DbWeight::get().reads_writes(1 + pruning_tasks_done, 2 * pruning_tasks_done)
+		// 1 read for the meta and 1 for the code refs for each pruning task, 1 read for the config
+		// 3 writes: updating the meta, pruning the code hash and updating the code refs
+		T::DbWeight::get().reads_writes(1 + 2 * pruning_tasks_done, 3 * pruning_tasks_done)
+	}
+
+	// Add a reference to the given validation code, storing the code if it is not yet referenced.
+	fn increase_code_ref(code_hash: &Hash, code: &ValidationCode) -> Weight {
+		let refs = <Self as Store>::CodeByHashRefs::mutate(code_hash, |refs| {
+			*refs += 1;
+			*refs
+		});
+
+		if refs == 1 {
+			<Self as Store>::CodeByHash::insert(code_hash, code);
+			T::DbWeight::get().reads_writes(1, 2)
+		} else {
+			T::DbWeight::get().reads_writes(1, 1)
+		}
+	}
+
+	// Remove a reference to the validation code with the given hash, removing the code if it is
+	// no longer referenced.
+	fn decrease_code_ref(code_hash: &Hash) -> Weight {
+		let refs = <Self as Store>::CodeByHashRefs::get(code_hash);
+		if refs <= 1 {
+			<Self as Store>::CodeByHashRefs::remove(code_hash);
+			<Self as Store>::CodeByHash::remove(code_hash);
+			T::DbWeight::get().reads_writes(1, 2)
+		} else {
+			<Self as Store>::CodeByHashRefs::insert(code_hash, refs - 1);
+			T::DbWeight::get().reads_writes(1, 1)
+		}
 	}
 
 	/// Schedule a para to be initialized at the start of the next session.
-	#[allow(unused)]
-	pub(crate) fn schedule_para_initialize(id: ParaId, genesis: ParaGenesisArgs) -> Weight {
+	pub fn schedule_para_initialize(id: ParaId, genesis: ParaGenesisArgs) -> Weight {
 		let dup = UpcomingParas::mutate(|v| {
 			match v.binary_search(&id) {
 				Ok(_) => true,
@@ -410,8 +695,7 @@ impl<T: Trait> Module<T> {
 	}
 
 	/// Schedule a para to be cleaned up at the start of the next session.
-	#[allow(unused)]
-	pub(crate) fn schedule_para_cleanup(id: ParaId) -> Weight {
+	pub fn schedule_para_cleanup(id: ParaId) -> Weight {
 		OutgoingParas::mutate(|v| {
 			match v.binary_search(&id) {
 				Ok(_) => T::DbWeight::get().reads_writes(1, 0),
@@ -438,12 +722,42 @@ impl<T: Trait> Module<T> {
 				T::DbWeight::get().reads_writes(1, 0)
 			} else {
 				*up = Some(expected_at);
+				let code_hash = BlakeTwo256::hash_of(&new_code);
 				FutureCode::insert(&id, new_code);
+				Self::deposit_event(Event::CodeUpgradeScheduled(id, code_hash));
 				T::DbWeight::get().reads_writes(1, 2)
 			}
 		})
 	}
 
+	/// Start pre-checking new validation code of the given para. The code upgrade is scheduled
+	/// once a supermajority of validators accepts the code.
+	///
+	/// If there is already a scheduled code upgrade or a pre-check for the para, this is a no-op.
+	pub(crate) fn start_pvf_check(id: ParaId, new_code: ValidationCode) -> Weight {
+		if <Self as Store>::FutureCodeUpgrades::contains_key(&id) || PvfChecks::contains_key(&id) {
+			return T::DbWeight::get().reads_writes(2, 0);
+		}
+
+		let code_hash = BlakeTwo256::hash_of(&new_code);
+		let session_index = validity::CurrentSessionIndex::get();
+		let n_validators = validity::SessionValidators::decode_len(&session_index).unwrap_or(0);
+
+		PvfChecks::insert(&id, PvfCheck::new(code_hash, session_index, n_validators));
+		PvfCheckCode::insert(&id, new_code);
+
+		Self::deposit_event(Event::PvfCheckStarted(id, code_hash));
+
+		T::DbWeight::get().reads_writes(4, 2)
+	}
+
+	/// Get the paras with a code upgrade being pre-checked, along with the hash of the new code.
+	pub(crate) fn pending_pvf_checks() -> Vec<(ParaId, Hash)> {
+		<PvfChecks as IterableStorageMap<_, _>>::iter()
+			.map(|(id, check)| (id, check.code_hash))
+			.collect()
+	}
+
 	/// Note that a para has progressed to a new head, where the new head was executed in the context
 	/// of a relay-chain block with given number. This will apply pending code upgrades based
 	/// on the block number provided.
@@ -460,17 +774,19 @@ impl<T: Trait> Module<T> {
 
 				// Both should always be `Some` in this case, since a code upgrade is scheduled.
 				let new_code = FutureCode::take(&id).unwrap_or_default();
-				let prior_code = CurrentCode::get(&id).unwrap_or_default();
-				CurrentCode::insert(&id, &new_code);
+				let new_code_hash = BlakeTwo256::hash_of(&new_code);
+				let prior_code_hash = CurrentCodeHash::get(&id).unwrap_or_default();
+				let mut weight = Self::increase_code_ref(&new_code_hash, &new_code);
+				CurrentCodeHash::insert(&id, &new_code_hash);
 
 				// `now` is only used for registering pruning as part of `fn note_past_code`
 				let now = <system::Module<T>>::block_number();
 
-				let weight = Self::note_past_code(
+				weight += Self::note_past_code(
 					id,
 					expected_at,
 					now,
-					prior_code,
+					prior_code_hash,
 				);
 
 				// add 1 to writes due to heads update.
@@ -483,6 +799,17 @@ impl<T: Trait> Module<T> {
 		}
 	}
 
+	/// Revert the head of a para to a prior head, e.g. after a candidate it was built upon has been
+	/// found invalid. This has no effect if the para is no longer live.
+	pub(crate) fn revert_head(id: ParaId, head: HeadData) -> Weight {
+		if Heads::contains_key(&id) {
+			Heads::insert(&id, head);
+			T::DbWeight::get().reads_writes(1, 1)
+		} else {
+			T::DbWeight::get().reads_writes(1, 0)
+		}
+	}
+
 	/// Fetches the validation code to be used when validating a block in the context of the given
 	/// relay-chain height. A second block number parameter may be used to tell the lookup to proceed
 	/// as if an intermediate parablock has been with the given relay-chain height as its context.
@@ -514,17 +841,38 @@ impl<T: Trait> Module<T> {
 		} else {
 			match Self::past_code_meta(&id).code_at(at) {
 				None => None,
-				Some(UseCodeAt::Current) => CurrentCode::get(&id),
-				Some(UseCodeAt::ReplacedAt(replaced)) => <Self as Store>::PastCode::get(&(id, replaced))
+				Some(UseCodeAt::Current) => Self::current_code(&id),
+				Some(UseCodeAt::ReplacedAt(replaced)) => <Self as Store>::PastCodeHash::get(&(id, replaced))
+					.and_then(|code_hash| Self::code_by_hash(&code_hash)),
 			}
 		}
 	}
 
+	/// The validation code of a live para.
+	pub fn current_code(id: &ParaId) -> Option<ValidationCode> {
+		Self::current_code_hash(id).and_then(|code_hash| Self::code_by_hash(&code_hash))
+	}
+
 	/// Whether a para ID corresponds to any live parathread.
-	pub(crate) fn is_parathread(id: ParaId) -> bool {
+	pub fn is_parathread(id: ParaId) -> bool {
 		Parathreads::get(&id).is_some()
 	}
 
+	/// Whether a para ID corresponds to any live parachain.
+	pub fn is_parachain(id: ParaId) -> bool {
+		Parachains::get().binary_search(&id).is_ok()
+	}
+
+	/// Whether a para ID is scheduled to be initialized at the start of the next session.
+	pub fn is_upcoming(id: ParaId) -> bool {
+		UpcomingParasGenesis::contains_key(&id)
+	}
+
+	/// Whether a para ID is scheduled to be cleaned up at the start of the next session.
+	pub fn is_outgoing(id: ParaId) -> bool {
+		OutgoingParas::get().binary_search(&id).is_ok()
+	}
+
 	/// The block number of the last scheduled upgrade of the requested para. Includes future upgrades
 	/// if the flag is set. This is the `expected_at` number, not the `activated_at` number.
 	pub(crate) fn last_code_upgrade(id: ParaId, include_future: bool) -> Option<T::BlockNumber> {
@@ -541,10 +889,14 @@ impl<T: Trait> Module<T> {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use primitives::v1::BlockNumber;
-	use frame_support::traits::{OnFinalize, OnInitialize};
-
-	use crate::mock::{new_test_ext, Paras, System, GenesisConfig as MockGenesisConfig};
+	use primitives::v1::{BlockNumber, ValidatorIndex};
+	use frame_support::{assert_noop, assert_ok, traits::{OnFinalize, OnInitialize}};
+	use keyring::Sr25519Keyring;
+
+	use crate::mock::{
+		new_test_ext, Configuration, Paras, Validity, System, Origin, TestEvent, Test,
+		GenesisConfig as MockGenesisConfig,
+	};
 	use crate::configuration::HostConfiguration;
 
 	fn run_to_block(to: BlockNumber, new_session: Option<Vec<BlockNumber>>) {
@@ -563,6 +915,10 @@ mod tests {
 		}
 	}
 
+	fn past_code(id: ParaId, at: BlockNumber) -> Option<ValidationCode> {
+		<Paras as Store>::PastCodeHash::get(&(id, at)).and_then(|code_hash| Paras::code_by_hash(&code_hash))
+	}
+
 	fn upgrade_at(expected_at: BlockNumber, activated_at: BlockNumber) -> ReplacementTimes<BlockNumber> {
 		ReplacementTimes { expected_at, activated_at }
 	}
@@ -671,7 +1027,10 @@ mod tests {
 			let at_block: BlockNumber = 10;
 			let included_block: BlockNumber = 12;
 
-			<Paras as Store>::PastCode::insert(&(id, at_block), &ValidationCode(vec![1, 2, 3]));
+			let code = ValidationCode(vec![1, 2, 3]);
+			let code_hash = BlakeTwo256::hash_of(&code);
+			Paras::increase_code_ref(&code_hash, &code);
+			<Paras as Store>::PastCodeHash::insert(&(id, at_block), &code_hash);
 			<Paras as Store>::PastCodePruning::put(&vec![(id, included_block)]);
 
 			{
@@ -681,14 +1040,15 @@ mod tests {
 			}
 
 			let pruned_at: BlockNumber = included_block + acceptance_period + 1;
-			assert_eq!(<Paras as Store>::PastCode::get(&(id, at_block)), Some(vec![1, 2, 3].into()));
+			assert_eq!(past_code(id, at_block), Some(vec![1, 2, 3].into()));
 
 			run_to_block(pruned_at - 1, None);
-			assert_eq!(<Paras as Store>::PastCode::get(&(id, at_block)), Some(vec![1, 2, 3].into()));
+			assert_eq!(past_code(id, at_block), Some(vec![1, 2, 3].into()));
 			assert_eq!(Paras::past_code_meta(&id).most_recent_change(), Some(at_block));
 
 			run_to_block(pruned_at, None);
-			assert!(<Paras as Store>::PastCode::get(&(id, at_block)).is_none());
+			assert!(past_code(id, at_block).is_none());
+			assert!(Paras::code_by_hash(&code_hash).is_none());
 			assert!(Paras::past_code_meta(&id).most_recent_change().is_none());
 		});
 	}
@@ -759,8 +1119,8 @@ mod tests {
 			let id_a = ParaId::from(0u32);
 			let id_b = ParaId::from(1u32);
 
-			Paras::note_past_code(id_a, 10, 12, vec![1, 2, 3].into());
-			Paras::note_past_code(id_b, 20, 23, vec![4, 5, 6].into());
+			Paras::note_past_code(id_a, 10, 12, BlakeTwo256::hash_of(&ValidationCode(vec![1, 2, 3])));
+			Paras::note_past_code(id_b, 20, 23, BlakeTwo256::hash_of(&ValidationCode(vec![4, 5, 6])));
 
 			assert_eq!(<Paras as Store>::PastCodePruning::get(), vec![(id_a, 12), (id_b, 23)]);
 			assert_eq!(
@@ -822,6 +1182,10 @@ mod tests {
 				assert!(Paras::past_code_meta(&para_id).most_recent_change().is_none());
 				assert_eq!(<Paras as Store>::FutureCodeUpgrades::get(&para_id), Some(expected_at));
 				assert_eq!(<Paras as Store>::FutureCode::get(&para_id), Some(new_code.clone()));
+				assert_eq!(
+					paras_events(),
+					vec![Event::CodeUpgradeScheduled(para_id, BlakeTwo256::hash_of(&new_code))],
+				);
 				assert_eq!(Paras::current_code(&para_id), Some(vec![1, 2, 3].into()));
 
 				expected_at
@@ -852,7 +1216,7 @@ mod tests {
 					Some(expected_at),
 				);
 				assert_eq!(
-					<Paras as Store>::PastCode::get(&(para_id, expected_at)),
+					past_code(para_id, expected_at),
 					Some(vec![1, 2, 3,].into()),
 				);
 				assert!(<Paras as Store>::FutureCodeUpgrades::get(&para_id).is_none());
@@ -921,7 +1285,7 @@ mod tests {
 					Some(expected_at),
 				);
 				assert_eq!(
-					<Paras as Store>::PastCode::get(&(para_id, expected_at)),
+					past_code(para_id, expected_at),
 					Some(vec![1, 2, 3,].into()),
 				);
 				assert!(<Paras as Store>::FutureCodeUpgrades::get(&para_id).is_none());
@@ -1038,7 +1402,7 @@ mod tests {
 			// cleaning up the parachain should place the current parachain code
 			// into the past code buffer & schedule cleanup.
 			assert_eq!(Paras::past_code_meta(&para_id).most_recent_change(), Some(3));
-			assert_eq!(<Paras as Store>::PastCode::get(&(para_id, 3)), Some(vec![1, 2, 3].into()));
+			assert_eq!(past_code(para_id, 3), Some(vec![1, 2, 3].into()));
 			assert_eq!(<Paras as Store>::PastCodePruning::get(), vec![(para_id, 3)]);
 
 			// any future upgrades haven't been used to validate yet, so those
@@ -1053,8 +1417,71 @@ mod tests {
 
 			// now the final cleanup: last past code cleaned up, and this triggers meta cleanup.
 			assert_eq!(Paras::past_code_meta(&para_id), Default::default());
-			assert!(<Paras as Store>::PastCode::get(&(para_id, 3)).is_none());
+			assert!(past_code(para_id, 3).is_none());
 			assert!(<Paras as Store>::PastCodePruning::get().is_empty());
+			assert!(Paras::code_by_hash(&BlakeTwo256::hash_of(&ValidationCode(vec![1, 2, 3]))).is_none());
+		});
+	}
+
+	#[test]
+	fn shared_code_is_stored_once_and_reference_counted() {
+		let acceptance_period = 10;
+
+		let paras = vec![
+			(0u32.into(), ParaGenesisArgs {
+				parachain: true,
+				genesis_head: Default::default(),
+				validation_code: vec![1, 2, 3].into(),
+			}),
+			(1u32.into(), ParaGenesisArgs {
+				parachain: true,
+				genesis_head: Default::default(),
+				validation_code: vec![1, 2, 3].into(),
+			}),
+		];
+
+		let genesis_config = MockGenesisConfig {
+			paras: GenesisConfig { paras, ..Default::default() },
+			configuration: crate::configuration::GenesisConfig {
+				config: HostConfiguration {
+					acceptance_period,
+					..Default::default()
+				},
+				..Default::default()
+			},
+			..Default::default()
+		};
+
+		new_test_ext(genesis_config).execute_with(|| {
+			let id_a = ParaId::from(0);
+			let id_b = ParaId::from(1);
+			let old_code = ValidationCode(vec![1, 2, 3]);
+			let old_code_hash = BlakeTwo256::hash_of(&old_code);
+			let new_code = ValidationCode(vec![4, 5, 6]);
+			let new_code_hash = BlakeTwo256::hash_of(&new_code);
+
+			assert_eq!(Paras::current_code_hash(&id_a), Some(old_code_hash));
+			assert_eq!(Paras::current_code_hash(&id_b), Some(old_code_hash));
+			assert_eq!(Paras::code_by_hash(&old_code_hash), Some(old_code.clone()));
+			assert_eq!(<Paras as Store>::CodeByHashRefs::get(&old_code_hash), 2);
+
+			run_to_block(2, None);
+			Paras::schedule_code_upgrade(id_a, new_code.clone(), 2);
+			Paras::note_new_head(id_a, Default::default(), 2);
+
+			// the replaced code is still referenced as past code of `id_a`.
+			assert_eq!(Paras::current_code(&id_a), Some(new_code.clone()));
+			assert_eq!(past_code(id_a, 2), Some(old_code.clone()));
+			assert_eq!(<Paras as Store>::CodeByHashRefs::get(&old_code_hash), 2);
+			assert_eq!(<Paras as Store>::CodeByHashRefs::get(&new_code_hash), 1);
+
+			run_to_block(2 + acceptance_period + 1, None);
+
+			// pruning the past code of `id_a` keeps the code around for `id_b`.
+			assert!(past_code(id_a, 2).is_none());
+			assert_eq!(<Paras as Store>::CodeByHashRefs::get(&old_code_hash), 1);
+			assert_eq!(Paras::current_code(&id_b), Some(old_code));
+			assert_eq!(Paras::validation_code_at(id_b, 2, None), Paras::code_by_hash(&old_code_hash));
 		});
 	}
 
@@ -1233,4 +1660,288 @@ mod tests {
 			assert_eq!(Paras::validation_code_at(para_id, 3, None), Some(new_code.clone()));
 		});
 	}
+
+	const VALIDATORS: [Sr25519Keyring; 4] = [
+		Sr25519Keyring::Alice,
+		Sr25519Keyring::Bob,
+		Sr25519Keyring::Charlie,
+		Sr25519Keyring::Dave,
+	];
+
+	fn pvf_checking_genesis(pvf_checking_ttl: u32) -> MockGenesisConfig {
+		let paras = vec![
+			(0u32.into(), ParaGenesisArgs {
+				parachain: true,
+				genesis_head: Default::default(),
+				validation_code: vec![1, 2, 3].into(),
+			}),
+		];
+
+		MockGenesisConfig {
+			paras: GenesisConfig { paras, ..Default::default() },
+			configuration: crate::configuration::GenesisConfig {
+				config: HostConfiguration {
+					validation_upgrade_delay: 5,
+					pvf_checking_ttl,
+					..Default::default()
+				},
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	fn start_session(session_index: SessionIndex) {
+		let notification = SessionChangeNotification {
+			validators: VALIDATORS.iter().map(|v| v.public().into()).collect(),
+			new_config: Configuration::config(),
+			session_index,
+			..Default::default()
+		};
+		Paras::initializer_on_new_session(&notification);
+		Validity::initializer_on_new_session(&notification);
+	}
+
+	fn pvf_check_statement(
+		accept: bool,
+		code: &ValidationCode,
+		session_index: SessionIndex,
+		validator_index: ValidatorIndex,
+	) -> (PvfCheckStatement, ValidatorSignature) {
+		let statement = PvfCheckStatement {
+			accept,
+			code_hash: BlakeTwo256::hash_of(code),
+			para_id: ParaId::from(0),
+			session_index,
+			validator_index,
+		};
+		let signature = VALIDATORS[validator_index as usize]
+			.sign(&statement.signing_payload()[..])
+			.into();
+
+		(statement, signature)
+	}
+
+	fn submit_pvf_check_statement(
+		accept: bool,
+		code: &ValidationCode,
+		session_index: SessionIndex,
+		validator_index: ValidatorIndex,
+	) -> DispatchResult {
+		let (statement, signature) = pvf_check_statement(accept, code, session_index, validator_index);
+		Paras::include_pvf_check_statement(Origin::none(), statement, signature)
+	}
+
+	fn paras_events() -> Vec<Event> {
+		System::events().into_iter()
+			.filter_map(|record| match record.event {
+				TestEvent::paras(event) => Some(event),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn pvf_check_accepted_by_supermajority_schedules_upgrade() {
+		new_test_ext(pvf_checking_genesis(2)).execute_with(|| {
+			let para_id = ParaId::from(0);
+			let new_code = ValidationCode(vec![4, 5, 6]);
+			let code_hash = BlakeTwo256::hash_of(&new_code);
+
+			run_to_block(1, None);
+			start_session(1);
+
+			Paras::start_pvf_check(para_id, new_code.clone());
+			assert_eq!(Paras::pending_pvf_checks(), vec![(para_id, code_hash)]);
+			assert_eq!(Paras::pvf_check_code(&para_id), Some(new_code.clone()));
+			assert!(Paras::future_code_upgrade_at(&para_id).is_none());
+
+			// 3 of 4 validators are needed for a supermajority.
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 1, 0));
+			assert_ok!(submit_pvf_check_statement(false, &new_code, 1, 1));
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 1, 2));
+			assert!(Paras::future_code_upgrade_at(&para_id).is_none());
+
+			run_to_block(3, None);
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 1, 3));
+
+			assert!(Paras::pending_pvf_checks().is_empty());
+			assert!(Paras::pvf_check_code(&para_id).is_none());
+			assert_eq!(Paras::future_code_upgrade_at(&para_id), Some(3 + 5));
+			assert_eq!(<Paras as Store>::FutureCode::get(&para_id), Some(new_code));
+
+			assert_eq!(paras_events(), vec![
+				Event::PvfCheckStarted(para_id, code_hash),
+				Event::CodeUpgradeScheduled(para_id, code_hash),
+				Event::PvfCheckAccepted(para_id, code_hash),
+			]);
+		});
+	}
+
+	#[test]
+	fn pvf_check_rejected_by_supermajority() {
+		new_test_ext(pvf_checking_genesis(2)).execute_with(|| {
+			let para_id = ParaId::from(0);
+			let new_code = ValidationCode(vec![4, 5, 6]);
+			let code_hash = BlakeTwo256::hash_of(&new_code);
+
+			run_to_block(1, None);
+			start_session(1);
+
+			Paras::start_pvf_check(para_id, new_code.clone());
+
+			assert_ok!(submit_pvf_check_statement(false, &new_code, 1, 0));
+			assert_ok!(submit_pvf_check_statement(false, &new_code, 1, 1));
+			assert_eq!(Paras::pending_pvf_checks(), vec![(para_id, code_hash)]);
+
+			assert_ok!(submit_pvf_check_statement(false, &new_code, 1, 2));
+
+			assert!(Paras::pending_pvf_checks().is_empty());
+			assert!(Paras::pvf_check_code(&para_id).is_none());
+			assert!(Paras::future_code_upgrade_at(&para_id).is_none());
+			assert_eq!(Paras::current_code(&para_id), Some(vec![1, 2, 3].into()));
+
+			assert_eq!(paras_events(), vec![
+				Event::PvfCheckStarted(para_id, code_hash),
+				Event::PvfCheckRejected(para_id, code_hash),
+			]);
+		});
+	}
+
+	#[test]
+	fn pvf_check_statement_checks() {
+		new_test_ext(pvf_checking_genesis(2)).execute_with(|| {
+			let para_id = ParaId::from(0);
+			let new_code = ValidationCode(vec![4, 5, 6]);
+
+			run_to_block(1, None);
+			start_session(1);
+
+			assert_noop!(
+				submit_pvf_check_statement(true, &new_code, 1, 0),
+				Error::<Test>::PvfCheckNotFound,
+			);
+
+			Paras::start_pvf_check(para_id, new_code.clone());
+
+			assert_noop!(
+				submit_pvf_check_statement(true, &new_code, 0, 0),
+				Error::<Test>::PvfCheckStatementStale,
+			);
+			assert_noop!(
+				submit_pvf_check_statement(true, &ValidationCode(vec![7, 8, 9]), 1, 0),
+				Error::<Test>::PvfCheckSubjectMismatch,
+			);
+
+			let (mut statement, signature) = pvf_check_statement(true, &new_code, 1, 0);
+			statement.validator_index = 4;
+			assert_noop!(
+				Paras::include_pvf_check_statement(Origin::none(), statement.clone(), signature.clone()),
+				Error::<Test>::ValidatorIndexOutOfBounds,
+			);
+
+			statement.validator_index = 1;
+			assert_noop!(
+				Paras::include_pvf_check_statement(Origin::none(), statement, signature),
+				Error::<Test>::InvalidPvfCheckSignature,
+			);
+
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 1, 0));
+			assert_noop!(
+				submit_pvf_check_statement(false, &new_code, 1, 0),
+				Error::<Test>::DuplicatePvfCheckStatement,
+			);
+		});
+	}
+
+	#[test]
+	fn pvf_check_statements_are_validated_as_unsigned_transactions() {
+		use sp_runtime::traits::ValidateUnsigned;
+
+		new_test_ext(pvf_checking_genesis(2)).execute_with(|| {
+			let para_id = ParaId::from(0);
+			let new_code = ValidationCode(vec![4, 5, 6]);
+			let validate = |(statement, signature)| Paras::validate_unsigned(
+				TransactionSource::External,
+				&Call::include_pvf_check_statement(statement, signature),
+			);
+
+			run_to_block(1, None);
+			start_session(1);
+			Paras::start_pvf_check(para_id, new_code.clone());
+
+			assert!(validate(pvf_check_statement(true, &new_code, 1, 0)).is_ok());
+			assert_eq!(
+				validate(pvf_check_statement(true, &new_code, 0, 0)),
+				Err(InvalidTransaction::Stale.into()),
+			);
+
+			let (mut statement, signature) = pvf_check_statement(true, &new_code, 1, 0);
+			statement.validator_index = 1;
+			assert_eq!(
+				validate((statement, signature)),
+				Err(InvalidTransaction::BadProof.into()),
+			);
+
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 1, 0));
+			assert_eq!(
+				validate(pvf_check_statement(false, &new_code, 1, 0)),
+				Err(InvalidTransaction::Call.into()),
+			);
+		});
+	}
+
+	#[test]
+	fn pvf_check_votes_reset_on_new_session_and_time_out() {
+		new_test_ext(pvf_checking_genesis(2)).execute_with(|| {
+			let para_id = ParaId::from(0);
+			let new_code = ValidationCode(vec![4, 5, 6]);
+			let code_hash = BlakeTwo256::hash_of(&new_code);
+
+			run_to_block(1, None);
+			start_session(1);
+
+			Paras::start_pvf_check(para_id, new_code.clone());
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 1, 0));
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 1, 1));
+
+			start_session(2);
+
+			// votes from the previous session are discarded.
+			let check = Paras::pvf_check(&para_id).unwrap();
+			assert_eq!(check.accept_votes.count_ones(), 0);
+			assert_ok!(submit_pvf_check_statement(true, &new_code, 2, 0));
+
+			start_session(3);
+
+			assert!(Paras::pvf_check(&para_id).is_none());
+			assert!(Paras::pvf_check_code(&para_id).is_none());
+			assert!(Paras::future_code_upgrade_at(&para_id).is_none());
+			assert_eq!(paras_events().last(), Some(&Event::PvfCheckRejected(para_id, code_hash)));
+		});
+	}
+
+	#[test]
+	fn pvf_check_not_started_when_upgrade_pending() {
+		new_test_ext(pvf_checking_genesis(2)).execute_with(|| {
+			let para_id = ParaId::from(0);
+			let new_code = ValidationCode(vec![4, 5, 6]);
+			let newer_code = ValidationCode(vec![7, 8, 9]);
+
+			run_to_block(1, None);
+			start_session(1);
+
+			Paras::start_pvf_check(para_id, new_code.clone());
+			Paras::start_pvf_check(para_id, newer_code.clone());
+			assert_eq!(Paras::pvf_check_code(&para_id), Some(new_code.clone()));
+
+			for validator_index in 0..3 {
+				assert_ok!(submit_pvf_check_statement(true, &new_code, 1, validator_index));
+			}
+
+			Paras::start_pvf_check(para_id, newer_code);
+			assert!(Paras::pvf_check(&para_id).is_none());
+			assert_eq!(<Paras as Store>::FutureCode::get(&para_id), Some(new_code));
+		});
+	}
 }
@@ -21,11 +21,12 @@ use primitives::v1::{
 	ValidatorId, ValidatorIndex, GroupRotationInfo, CoreState, GlobalValidationSchedule,
 	Id as ParaId, OccupiedCoreAssumption, LocalValidationData, SessionIndex, ValidationCode,
 	CommittedCandidateReceipt, ScheduledCore, OccupiedCore, CoreOccupied, CoreIndex,
-	GroupIndex, CandidateEvent,
+	GroupIndex, CandidateEvent, PendingApproval, HostConfigurationInfo, Hash,
+	PvfCheckStatement, ValidatorSignature,
 };
 use sp_runtime::traits::{One, BlakeTwo256, Hash as HashT, Saturating, Zero};
 use frame_support::debug;
-use crate::{initializer, inclusion, scheduler, configuration, paras};
+use crate::{initializer, inclusion, scheduler, configuration, paras, validity};
 
 /// Implementation for the `validators` function of the runtime API.
 pub fn validators<T: initializer::Trait>() -> Vec<ValidatorId> {
@@ -169,6 +170,7 @@ pub fn global_validation_schedule<T: initializer::Trait>()
 		max_code_size: config.max_code_size,
 		max_head_data_size: config.max_head_data_size,
 		block_number: <system::Module<T>>::block_number() - One::one(),
+		max_validation_fuel: config.max_validation_fuel,
 	}
 }
 
@@ -261,6 +263,11 @@ pub fn validation_code<T: initializer::Trait>(
 	}
 }
 
+/// Implementation for the `validation_code_by_hash` function of the runtime API.
+pub fn validation_code_by_hash<T: initializer::Trait>(hash: Hash) -> Option<ValidationCode> {
+	<paras::Module<T>>::code_by_hash(&hash)
+}
+
 /// Implementation for the `candidate_pending_availability` function of the runtime API.
 pub fn candidate_pending_availability<T: initializer::Trait>(para_id: ParaId)
 	-> Option<CommittedCandidateReceipt<T::Hash>>
@@ -285,3 +292,71 @@ pub fn candidate_events<T: initializer::Trait>(
 		})
 		.collect()
 }
+
+/// Implementation for the `pending_approvals` function of the runtime API.
+pub fn pending_approvals<T: initializer::Trait>() -> Vec<PendingApproval<T::BlockNumber>> {
+	<validity::Module<T>>::pending_approvals()
+}
+
+/// Implementation for the `oldest_unapproved_inclusion` function of the runtime API.
+pub fn oldest_unapproved_inclusion<T: initializer::Trait>() -> Option<T::BlockNumber> {
+	<validity::Module<T>>::oldest_unapproved_inclusion()
+}
+
+/// Implementation for the `oldest_invalid_inclusion` function of the runtime API.
+pub fn oldest_invalid_inclusion<T: initializer::Trait>() -> Option<T::BlockNumber> {
+	<validity::Module<T>>::oldest_invalid_inclusion()
+}
+
+/// Implementation for the `oldest_unavailable_inclusion` function of the runtime API.
+pub fn oldest_unavailable_inclusion<T: initializer::Trait>() -> Option<T::BlockNumber> {
+	<validity::Module<T>>::oldest_unavailable_inclusion()
+}
+
+/// Implementation for the `host_configuration` function of the runtime API.
+pub fn host_configuration<T: initializer::Trait>() -> HostConfigurationInfo<T::BlockNumber> {
+	// A pending configuration is applied on the next session change. If a session change is
+	// already buffered, that is the one applied at the child of this block.
+	let next_session = <initializer::Module<T>>::buffered_session_index()
+		.unwrap_or_else(|| session_index_for_child::<T>() + 1);
+	let pending = <configuration::Module<T>>::pending_config()
+		.map(|config| (next_session, config));
+
+	HostConfigurationInfo {
+		active: <configuration::Module<T>>::config(),
+		pending,
+	}
+}
+
+/// Implementation for the `pending_pvf_checks` function of the runtime API.
+pub fn pending_pvf_checks<T: initializer::Trait>() -> Vec<(ParaId, Hash)> {
+	<paras::Module<T>>::pending_pvf_checks()
+}
+
+/// Implementation for the `pending_pvf_check_code` function of the runtime API.
+pub fn pending_pvf_check_code<T: initializer::Trait>(para_id: ParaId) -> Option<ValidationCode> {
+	<paras::Module<T>>::pvf_check_code(&para_id)
+}
+
+/// Implementation for the `scheduled_code_upgrades` function of the runtime API.
+// NOTE: this runs without block initialization, as it accesses events.
+pub fn scheduled_code_upgrades<T: initializer::Trait>(
+	extract_event: impl Fn(<T as system::Trait>::Event) -> Option<paras::Event>,
+) -> Vec<(ParaId, ValidationCode)> {
+	<system::Module<T>>::events().into_iter()
+		.filter_map(|record| extract_event(record.event))
+		.filter_map(|event| match event {
+			paras::Event::CodeUpgradeScheduled(para_id, _) => Some(para_id),
+			_ => None,
+		})
+		.filter_map(|para_id| <paras::Module<T>>::future_code(&para_id).map(|code| (para_id, code)))
+		.collect()
+}
+
+/// Implementation for the `submit_pvf_check_statement` function of the runtime API.
+pub fn submit_pvf_check_statement<T: initializer::Trait>(
+	statement: PvfCheckStatement,
+	signature: ValidatorSignature,
+) {
+	<paras::Module<T>>::submit_pvf_check_statement(statement, signature)
+}
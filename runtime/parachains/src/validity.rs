@@ -13,3 +13,1055 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The validity module is responsible for secondary approval checks of included parablocks.
+//!
+//! Every candidate enacted by the inclusion module is noted here along with the session and the
+//! relay-chain block it was included in. Validators of that session are assigned to check the
+//! candidate in delay tranches derived from the session randomness and the VRF output of the
+//! including block, and submit approval votes for it. A candidate is approved once it has gathered
+//! enough votes from assigned validators.
+//!
+//! Candidates are tracked until they leave the acceptance period. Candidates which are not
+//! approved by then time out, unless their validity is disputed. The validators of a session are
+//! kept until all candidates included in it are resolved, so that they can still approve them or
+//! take part in disputes about them. The oldest relay-chain block containing an unapproved
+//! candidate is exposed via the runtime API, so that the node does not finalize relay-chain blocks
+//! containing unapproved candidates.
+
+use sp_std::{prelude::*, marker::PhantomData};
+use primitives::v1::{
+	ValidatorId, ValidatorIndex, ValidatorSignature, Id as ParaId, Hash, SessionIndex, HeadData,
+	ApprovalVote, PendingApproval, approval_delay_tranche,
+};
+use frame_support::{
+	decl_storage, decl_module, decl_error, decl_event, ensure,
+	dispatch::DispatchResult, IterableStorageMap, weights::Weight, traits::Get,
+};
+use codec::{Encode, Decode};
+use bitvec::{order::Lsb0 as BitOrderLsb0, vec::BitVec};
+use sp_runtime::traits::{One, AppVerify, BlakeTwo256, Hash as HashT};
+use babe_primitives::{BABE_ENGINE_ID, digests::PreDigest};
+use system::ensure_signed;
+
+use crate::{configuration, paras, initializer::SessionChangeNotification};
+
+/// The approval status of an included candidate.
+#[derive(Encode, Decode, Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum ApprovalStatus {
+	/// The candidate is still awaiting approval votes.
+	Pending,
+	/// The candidate has gathered enough approval votes, or a dispute concluded in its favor.
+	Approved,
+	/// The validity of the candidate is disputed. Approval votes are still accepted but cannot
+	/// approve the candidate until the dispute concludes.
+	Disputed,
+	/// A dispute concluded that the candidate is invalid. The relay-chain block including it
+	/// must never be finalized.
+	Invalid,
+}
+
+/// An included candidate tracked by the validity module.
+#[derive(Encode, Decode)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct ApprovalEntry<N> {
+	/// The para the candidate is for.
+	para_id: ParaId,
+	/// The head of the para before the candidate was enacted, if any.
+	parent_head: Option<HeadData>,
+	/// The session the candidate was included in.
+	session: SessionIndex,
+	/// The relay-chain block number the candidate was included at.
+	included_at: N,
+	/// The seed that approval assignments are derived from.
+	assignment_seed: [u8; 32],
+	/// One bit per validator of the session, set if the validator has approved the candidate.
+	approvals: BitVec<BitOrderLsb0, u8>,
+	/// The number of approvals needed, fixed at the time of inclusion.
+	needed_approvals: u32,
+	/// The number of delay tranches assignments are spread over, fixed at the time of inclusion.
+	n_delay_tranches: u32,
+	/// The approval status of the candidate.
+	status: ApprovalStatus,
+}
+
+impl<N> ApprovalEntry<N> {
+	/// Get the approval status of the candidate.
+	pub(crate) fn status(&self) -> ApprovalStatus {
+		self.status
+	}
+
+	/// Get the para the candidate is for.
+	pub(crate) fn para_id(&self) -> ParaId {
+		self.para_id
+	}
+
+	/// Get the head of the para before the candidate was enacted.
+	pub(crate) fn parent_head(&self) -> Option<&HeadData> {
+		self.parent_head.as_ref()
+	}
+
+	/// Get the session the candidate was included in.
+	pub(crate) fn session(&self) -> SessionIndex {
+		self.session
+	}
+}
+
+/// Provides the VRF output of the block being built, if it has one.
+pub trait BlockVrfOutput {
+	/// Get the VRF output of the current block.
+	fn block_vrf_output() -> Option<[u8; 32]>;
+}
+
+/// Reads the VRF output from the BABE pre-runtime digest of the current block.
+///
+/// Blocks authored in secondary slots without a VRF have no output.
+pub struct BabeVrfOutput<T>(PhantomData<T>);
+
+impl<T: system::Trait> BlockVrfOutput for BabeVrfOutput<T> {
+	fn block_vrf_output() -> Option<[u8; 32]> {
+		<system::Module<T>>::digest().logs().iter()
+			.filter_map(|item| item.as_pre_runtime())
+			.filter_map(|(id, mut data)| if id == BABE_ENGINE_ID {
+				PreDigest::decode(&mut data).ok()
+			} else {
+				None
+			})
+			.next()
+			.and_then(|pre_digest| pre_digest.vrf_output().map(BlakeTwo256::hash_of))
+			.map(|output| output.to_fixed_bytes())
+	}
+}
+
+pub trait Trait: system::Trait + configuration::Trait + paras::Trait {
+	type Event: From<Event> + Into<<Self as system::Trait>::Event>;
+
+	/// The VRF output of the including block, which approval assignments are derived from.
+	type BlockVrfOutput: BlockVrfOutput;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as ParaValidity {
+		/// Candidates included in this fork of the relay chain which are either awaiting approval
+		/// or still within the acceptance period, by candidate hash.
+		IncludedCandidates get(fn included_candidate): map hasher(twox_64_concat) Hash
+			=> Option<ApprovalEntry<T::BlockNumber>>;
+
+		/// The hashes of all tracked candidates along with the block number they were included at.
+		/// Ordered ascending by block number.
+		///
+		/// Bounded by the number of cores multiplied by the acceptance period, plus any candidates
+		/// whose validity is still disputed after the acceptance period.
+		TrackedCandidates: Vec<(T::BlockNumber, Hash)>;
+
+		/// The number of the oldest relay-chain block in this fork which included a candidate that
+		/// a dispute found to be invalid. Kept after the candidate itself is no longer tracked.
+		OldestInvalidInclusion: Option<T::BlockNumber>;
+
+		/// The numbers of the relay-chain blocks in this fork which backed a candidate that then
+		/// timed out while pending availability. Ordered ascending and pruned once the blocks leave
+		/// the acceptance period.
+		UnavailableInclusions: Vec<T::BlockNumber>;
+
+		/// The validators of the current and the previous session, and of any earlier session
+		/// which included candidates still awaiting approval or disputed, by session index.
+		pub(crate) SessionValidators get(fn session_validators): map hasher(twox_64_concat) SessionIndex
+			=> Option<Vec<ValidatorId>>;
+
+		/// The current session index.
+		pub(crate) CurrentSessionIndex get(fn session_index): SessionIndex;
+
+		/// The random seed of the current session, gathered from BABE.
+		CurrentRandomSeed: [u8; 32];
+	}
+}
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The candidate is not tracked by the validity module.
+		UnknownCandidate,
+		/// The validators of the session the candidate was included in are no longer known.
+		UnknownSession,
+		/// Validator index out of bounds.
+		ValidatorIndexOutOfBounds,
+		/// The approval vote has an invalid signature.
+		InvalidApprovalSignature,
+		/// The validator's delay tranche has not been reached yet.
+		TrancheNotReached,
+		/// The validator has already approved the candidate.
+		DuplicateApproval,
+		/// The candidate has been found to be invalid.
+		CandidateInvalid,
+		/// The given number of validators does not match the session of the candidate.
+		WrongValidatorsLen,
+	}
+}
+
+decl_event! {
+	pub enum Event {
+		/// A candidate was included and is awaiting approval.
+		CandidateAwaitingApproval(Hash, ParaId),
+		/// A validator approved a candidate.
+		ApprovalNoted(Hash, ValidatorIndex),
+		/// A candidate gathered enough approvals and is now approved.
+		CandidateApproved(Hash, ParaId),
+		/// A candidate was not approved within the acceptance period and is no longer tracked.
+		ApprovalTimedOut(Hash, ParaId),
+	}
+}
+
+decl_module! {
+	/// The parachain validity module.
+	pub struct Module<T: Trait> for enum Call where origin: <T as system::Trait>::Origin, system = system {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		/// Submit an approval vote on an included candidate, on behalf of a validator of the
+		/// session it was included in.
+		///
+		/// The validator must have been assigned to check the candidate in a delay tranche which
+		/// has already been reached. `validators_len` must be the number of validators of the
+		/// session the candidate was included in, and bounds the weight of the call.
+		#[weight = approve_weight::<T>(*validators_len)]
+		pub fn approve(
+			origin,
+			candidate_hash: Hash,
+			validator_index: ValidatorIndex,
+			validators_len: u32,
+			signature: ValidatorSignature,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+
+			let mut entry = <IncludedCandidates<T>>::get(&candidate_hash)
+				.ok_or(Error::<T>::UnknownCandidate)?;
+			ensure!(entry.status != ApprovalStatus::Invalid, Error::<T>::CandidateInvalid);
+
+			let validators = SessionValidators::get(&entry.session)
+				.ok_or(Error::<T>::UnknownSession)?;
+			ensure!(validators.len() == validators_len as usize, Error::<T>::WrongValidatorsLen);
+			let validator_public = validators.get(validator_index as usize)
+				.ok_or(Error::<T>::ValidatorIndexOutOfBounds)?;
+
+			let payload = ApprovalVote(candidate_hash).signing_payload(entry.session);
+			ensure!(
+				signature.verify(&payload[..], validator_public),
+				Error::<T>::InvalidApprovalSignature,
+			);
+
+			let tranche = approval_delay_tranche(
+				&entry.assignment_seed,
+				&candidate_hash,
+				validator_index,
+				entry.n_delay_tranches,
+			);
+
+			let now = <system::Module<T>>::block_number();
+			let tranche_start = entry.included_at + T::BlockNumber::from(tranche);
+			ensure!(tranche_start <= now, Error::<T>::TrancheNotReached);
+
+			match entry.approvals.get_mut(validator_index as usize) {
+				Some(mut bit) => {
+					ensure!(!*bit, Error::<T>::DuplicateApproval);
+					*bit = true;
+				}
+				None => { Err(Error::<T>::ValidatorIndexOutOfBounds)?; }
+			}
+
+			Self::deposit_event(Event::ApprovalNoted(candidate_hash, validator_index));
+
+			if entry.status == ApprovalStatus::Pending
+				&& entry.approvals.count_ones() as u32 >= entry.needed_approvals
+			{
+				entry.status = ApprovalStatus::Approved;
+				Self::deposit_event(Event::CandidateApproved(candidate_hash, entry.para_id));
+			}
+
+			<IncludedCandidates<T>>::insert(&candidate_hash, entry);
+
+			Ok(())
+		}
+	}
+}
+
+/// The weight of an `approve` call for a session with the given number of validators.
+///
+/// The validators of the session and the approvals of the candidate are both proportional to the
+/// number of validators.
+fn approve_weight<T: Trait>(validators_len: u32) -> Weight {
+	100_000_000
+		+ 1_000_000 * validators_len as Weight
+		+ T::DbWeight::get().reads_writes(2, 1)
+}
+
+impl<T: Trait> Module<T> {
+	/// Block initialization logic, called by initializer.
+	pub(crate) fn initializer_initialize(now: T::BlockNumber) -> Weight {
+		Self::prune_tracked(now) + Self::prune_unavailable(now)
+	}
+
+	/// Block finalization logic, called by initializer.
+	pub(crate) fn initializer_finalize() { }
+
+	/// Handle an incoming session change.
+	///
+	/// Validators remain responsible for the candidates included in their session for at least one
+	/// further session, and until these candidates are approved or found invalid. So the validators
+	/// of the previous session and of any session with unresolved candidates are kept around.
+	pub(crate) fn initializer_on_new_session(
+		notification: &SessionChangeNotification<T::BlockNumber>
+	) {
+		let session_index = notification.session_index;
+		let prev_session = session_index.saturating_sub(1);
+
+		let oldest_unresolved = <TrackedCandidates<T>>::get().into_iter()
+			.filter_map(|(_, candidate_hash)| <IncludedCandidates<T>>::get(&candidate_hash))
+			.filter(|entry| match entry.status {
+				ApprovalStatus::Pending | ApprovalStatus::Disputed => true,
+				ApprovalStatus::Approved | ApprovalStatus::Invalid => false,
+			})
+			.map(|entry| entry.session)
+			.min();
+		let oldest_kept = oldest_unresolved.map_or(prev_session, |s| s.min(prev_session));
+
+		let outdated: Vec<_> = <SessionValidators as IterableStorageMap<_, _>>::iter()
+			.map(|(s, _)| s)
+			.filter(|s| *s < oldest_kept)
+			.collect();
+
+		for s in outdated {
+			SessionValidators::remove(&s);
+		}
+
+		SessionValidators::insert(&session_index, notification.validators.clone());
+		CurrentSessionIndex::set(session_index);
+		CurrentRandomSeed::set(notification.random_seed);
+	}
+
+	/// Note that a candidate has been included at the current block. This begins the approval
+	/// process for the candidate.
+	///
+	/// This must be called before the new head of the para is noted.
+	pub(crate) fn note_included(candidate_hash: Hash, para_id: ParaId) -> Weight {
+		let now = <system::Module<T>>::block_number();
+		let session = CurrentSessionIndex::get();
+		let n_validators = SessionValidators::decode_len(&session).unwrap_or(0);
+		let config = <configuration::Module<T>>::config();
+
+		// The seed binds the session randomness to the VRF output of the including block, so
+		// assignments are not known before the block is authored. Blocks without a VRF output fall
+		// back to the parent hash, which is known in advance.
+		let assignment_seed = match T::BlockVrfOutput::block_vrf_output() {
+			Some(vrf_output) => BlakeTwo256::hash_of(&(CurrentRandomSeed::get(), vrf_output)),
+			None => BlakeTwo256::hash_of(
+				&(CurrentRandomSeed::get(), <system::Module<T>>::parent_hash())
+			),
+		}.to_fixed_bytes();
+
+		// the candidate could never be approved if more approvals are needed than there
+		// are validators.
+		let needed_approvals = sp_std::cmp::min(config.needed_approvals, n_validators as u32);
+		let status = if needed_approvals == 0 {
+			ApprovalStatus::Approved
+		} else {
+			ApprovalStatus::Pending
+		};
+
+		<IncludedCandidates<T>>::insert(&candidate_hash, ApprovalEntry {
+			para_id,
+			parent_head: <paras::Module<T>>::para_head(&para_id),
+			session,
+			included_at: now,
+			assignment_seed,
+			approvals: bitvec::bitvec![BitOrderLsb0, u8; 0; n_validators],
+			needed_approvals,
+			n_delay_tranches: config.n_delay_tranches,
+			status,
+		});
+
+		<TrackedCandidates<T>>::mutate(|tracked| {
+			// candidates are always noted at the current block, so this preserves the ordering.
+			tracked.push((now, candidate_hash));
+		});
+
+		if status == ApprovalStatus::Approved {
+			Self::deposit_event(Event::CandidateApproved(candidate_hash, para_id));
+		} else {
+			Self::deposit_event(Event::CandidateAwaitingApproval(candidate_hash, para_id));
+		}
+
+		T::DbWeight::get().reads_writes(6, 2)
+	}
+
+	// Stops tracking all candidates which have left the acceptance period, timing out those which
+	// are still awaiting approval.
+	//
+	// Disputed candidates are kept around regardless of the acceptance period, as the relay-chain
+	// blocks including them must not be finalized before the dispute concludes. Candidates found
+	// invalid are dropped, as their inclusion is recorded separately.
+	fn prune_tracked(now: T::BlockNumber) -> Weight {
+		let config = <configuration::Module<T>>::config();
+		let acceptance_period = config.acceptance_period;
+		if now <= acceptance_period {
+			return T::DbWeight::get().reads_writes(1, 0);
+		}
+
+		// The height of any inclusions we no longer should keep around.
+		let pruning_height = now - (acceptance_period + One::one());
+
+		let (checked, pruned) = <TrackedCandidates<T>>::mutate(|tracked| {
+			let mut checked = 0u64;
+			let mut pruned = 0u64;
+
+			tracked.retain(|&(included_at, ref candidate_hash)| {
+				if included_at > pruning_height {
+					return true;
+				}
+
+				checked += 1;
+				let entry = match <IncludedCandidates<T>>::get(candidate_hash) {
+					Some(entry) => entry,
+					None => return false,
+				};
+
+				match entry.status {
+					ApprovalStatus::Disputed => return true,
+					ApprovalStatus::Pending => Self::deposit_event(
+						Event::ApprovalTimedOut(*candidate_hash, entry.para_id)
+					),
+					ApprovalStatus::Approved | ApprovalStatus::Invalid => {}
+				}
+
+				<IncludedCandidates<T>>::remove(candidate_hash);
+				pruned += 1;
+
+				false
+			});
+
+			(checked, pruned)
+		});
+
+		// 1 read for the config, 1 read for the tracked candidates and 1 for each candidate checked.
+		// 1 write for the tracked candidates and 1 for each candidate pruned.
+		T::DbWeight::get().reads_writes(2 + checked, 1 + pruned)
+	}
+
+	// Stops tracking all timed-out candidates which were backed before the acceptance period.
+	fn prune_unavailable(now: T::BlockNumber) -> Weight {
+		let acceptance_period = <configuration::Module<T>>::config().acceptance_period;
+		if now <= acceptance_period {
+			return T::DbWeight::get().reads_writes(1, 0);
+		}
+
+		let pruning_height = now - (acceptance_period + One::one());
+		<UnavailableInclusions<T>>::mutate(|unavailable| {
+			unavailable.retain(|&backed_in| backed_in > pruning_height);
+		});
+
+		T::DbWeight::get().reads_writes(2, 1)
+	}
+
+	/// Note that a candidate backed in the relay-chain block with the given number timed out
+	/// while pending availability.
+	pub(crate) fn note_unavailable(backed_in: T::BlockNumber) -> Weight {
+		<UnavailableInclusions<T>>::mutate(|unavailable| {
+			if let Err(pos) = unavailable.binary_search(&backed_in) {
+				unavailable.insert(pos, backed_in);
+			}
+		});
+
+		T::DbWeight::get().reads_writes(1, 1)
+	}
+
+	/// Note that the validity of a tracked candidate is disputed. The candidate can no longer be
+	/// approved by approval votes.
+	pub(crate) fn note_dispute_initiated(candidate_hash: &Hash) -> Weight {
+		<IncludedCandidates<T>>::mutate(candidate_hash, |entry| {
+			if let Some(entry) = entry.as_mut() {
+				entry.status = ApprovalStatus::Disputed;
+			}
+		});
+
+		T::DbWeight::get().reads_writes(1, 1)
+	}
+
+	/// Note that a dispute about a tracked candidate has concluded.
+	///
+	/// If the candidate was found valid, it is approved. Otherwise it is marked invalid and the
+	/// block including it is recorded, so that it is never finalized.
+	pub(crate) fn note_dispute_concluded(candidate_hash: &Hash, valid: bool) -> Weight {
+		<IncludedCandidates<T>>::mutate(candidate_hash, |entry| {
+			if let Some(entry) = entry.as_mut() {
+				if valid {
+					entry.status = ApprovalStatus::Approved;
+					Self::deposit_event(Event::CandidateApproved(*candidate_hash, entry.para_id));
+				} else {
+					entry.status = ApprovalStatus::Invalid;
+					<OldestInvalidInclusion<T>>::mutate(|oldest| {
+						if oldest.map_or(true, |at| entry.included_at < at) {
+							*oldest = Some(entry.included_at);
+						}
+					});
+				}
+			}
+		});
+
+		T::DbWeight::get().reads_writes(2, 2)
+	}
+
+	/// Note that a dispute about a tracked candidate timed out before concluding.
+	///
+	/// The candidate is approved if it gathered enough approval votes in the meantime. Otherwise it
+	/// awaits approval votes again, and times out once it leaves the acceptance period.
+	pub(crate) fn note_dispute_timed_out(candidate_hash: &Hash) -> Weight {
+		<IncludedCandidates<T>>::mutate(candidate_hash, |entry| {
+			if let Some(entry) = entry.as_mut() {
+				if entry.approvals.count_ones() as u32 >= entry.needed_approvals {
+					entry.status = ApprovalStatus::Approved;
+					Self::deposit_event(Event::CandidateApproved(*candidate_hash, entry.para_id));
+				} else {
+					entry.status = ApprovalStatus::Pending;
+				}
+			}
+		});
+
+		T::DbWeight::get().reads_writes(1, 1)
+	}
+
+	/// Returns the approval status of the given candidate, if it is still tracked.
+	pub(crate) fn approval_status(candidate_hash: &Hash) -> Option<ApprovalStatus> {
+		<IncludedCandidates<T>>::get(candidate_hash).map(|entry| entry.status())
+	}
+
+	/// Returns all tracked candidates which are still awaiting approval, ordered ascending by the
+	/// block number they were included at.
+	pub(crate) fn pending_approvals() -> Vec<PendingApproval<T::BlockNumber>> {
+		<TrackedCandidates<T>>::get().into_iter()
+			.filter_map(|(_, candidate_hash)| {
+				let entry = <IncludedCandidates<T>>::get(&candidate_hash)?;
+				if entry.status != ApprovalStatus::Pending {
+					return None;
+				}
+
+				Some(PendingApproval {
+					candidate_hash,
+					para_id: entry.para_id,
+					session_index: entry.session,
+					included_at: entry.included_at,
+					assignment_seed: entry.assignment_seed,
+					approvals: entry.approvals.count_ones() as u32,
+					needed_approvals: entry.needed_approvals,
+					n_delay_tranches: entry.n_delay_tranches,
+				})
+			})
+			.collect()
+	}
+
+	/// Returns the number of the oldest relay-chain block which included a candidate that has not
+	/// yet been approved, or that a dispute found to be invalid, if any.
+	pub(crate) fn oldest_unapproved_inclusion() -> Option<T::BlockNumber> {
+		let oldest_unresolved = <TrackedCandidates<T>>::get().into_iter()
+			.find(|(_, candidate_hash)| match Self::approval_status(candidate_hash) {
+				None | Some(ApprovalStatus::Approved) => false,
+				Some(_) => true,
+			})
+			.map(|(included_at, _)| included_at);
+
+		match (oldest_unresolved, Self::oldest_invalid_inclusion()) {
+			(Some(a), Some(b)) => Some(sp_std::cmp::min(a, b)),
+			(a, b) => a.or(b),
+		}
+	}
+
+	/// Returns the number of the oldest relay-chain block which included a candidate that a dispute
+	/// found to be invalid, if any.
+	pub(crate) fn oldest_invalid_inclusion() -> Option<T::BlockNumber> {
+		<OldestInvalidInclusion<T>>::get()
+	}
+
+	/// Returns the number of the oldest relay-chain block which backed a candidate that then timed
+	/// out while pending availability, if any.
+	pub(crate) fn oldest_unavailable_inclusion() -> Option<T::BlockNumber> {
+		<UnavailableInclusions<T>>::get().first().cloned()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use primitives::v1::BlockNumber;
+	use frame_support::{assert_noop, traits::{OnFinalize, OnInitialize}};
+	use keyring::Sr25519Keyring;
+
+	use crate::mock::{
+		new_test_ext, System, Validity, Origin, GenesisConfig as MockGenesisConfig, Test,
+		TestEvent, BLOCK_VRF_OUTPUT,
+	};
+	use crate::configuration::HostConfiguration;
+
+	fn genesis_config(
+		acceptance_period: BlockNumber,
+		needed_approvals: u32,
+		n_delay_tranches: u32,
+	) -> MockGenesisConfig {
+		MockGenesisConfig {
+			configuration: configuration::GenesisConfig {
+				config: HostConfiguration {
+					acceptance_period,
+					needed_approvals,
+					n_delay_tranches,
+					..Default::default()
+				},
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	fn run_to_block(to: BlockNumber) {
+		while System::block_number() < to {
+			let b = System::block_number();
+
+			Validity::initializer_finalize();
+			System::on_finalize(b);
+
+			System::on_initialize(b + 1);
+			System::set_block_number(b + 1);
+
+			Validity::initializer_initialize(b + 1);
+		}
+	}
+
+	fn start_session(session_index: SessionIndex, validators: &[Sr25519Keyring]) {
+		Validity::initializer_on_new_session(&SessionChangeNotification {
+			validators: validators.iter().map(|v| v.public().into()).collect(),
+			session_index,
+			random_seed: [session_index as u8; 32],
+			..Default::default()
+		});
+	}
+
+	fn sign_approval(
+		key: Sr25519Keyring,
+		candidate_hash: Hash,
+		session_index: SessionIndex,
+	) -> ValidatorSignature {
+		let payload = ApprovalVote(candidate_hash).signing_payload(session_index);
+		key.sign(&payload[..]).into()
+	}
+
+	fn tranche_of(candidate_hash: Hash, validator_index: ValidatorIndex) -> u32 {
+		let entry = Validity::included_candidate(&candidate_hash).unwrap();
+		approval_delay_tranche(
+			&entry.assignment_seed,
+			&candidate_hash,
+			validator_index,
+			entry.n_delay_tranches,
+		)
+	}
+
+	const VALIDATORS: [Sr25519Keyring; 4] = [
+		Sr25519Keyring::Alice,
+		Sr25519Keyring::Bob,
+		Sr25519Keyring::Charlie,
+		Sr25519Keyring::Dave,
+	];
+
+	#[test]
+	fn candidate_approved_immediately_if_no_approvals_needed() {
+		new_test_ext(genesis_config(10, 0, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			Validity::note_included(candidate_hash, ParaId::from(1));
+
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Approved));
+			assert!(Validity::pending_approvals().is_empty());
+			assert!(Validity::oldest_unapproved_inclusion().is_none());
+		});
+	}
+
+	#[test]
+	fn approvals_are_counted_until_approved() {
+		new_test_ext(genesis_config(10, 2, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			Validity::note_included(candidate_hash, ParaId::from(1));
+
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Pending));
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(2));
+
+			Validity::approve(
+				Origin::signed(1),
+				candidate_hash,
+				0,
+				4,
+				sign_approval(VALIDATORS[0], candidate_hash, 1),
+			).unwrap();
+
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Pending));
+			assert_eq!(Validity::pending_approvals()[0].approvals, 1);
+
+			// duplicate approval.
+			assert_noop!(
+				Validity::approve(
+					Origin::signed(1),
+					candidate_hash,
+					0,
+					4,
+					sign_approval(VALIDATORS[0], candidate_hash, 1),
+				),
+				Error::<Test>::DuplicateApproval,
+			);
+
+			Validity::approve(
+				Origin::signed(1),
+				candidate_hash,
+				2,
+				4,
+				sign_approval(VALIDATORS[2], candidate_hash, 1),
+			).unwrap();
+
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Approved));
+			assert!(Validity::oldest_unapproved_inclusion().is_none());
+		});
+	}
+
+	#[test]
+	fn approval_checks() {
+		new_test_ext(genesis_config(10, 2, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			Validity::note_included(candidate_hash, ParaId::from(1));
+
+			// unknown candidate.
+			assert_noop!(
+				Validity::approve(
+					Origin::signed(1),
+					Hash::repeat_byte(2),
+					0,
+					4,
+					sign_approval(VALIDATORS[0], Hash::repeat_byte(2), 1),
+				),
+				Error::<Test>::UnknownCandidate,
+			);
+
+			// signed by the wrong validator.
+			assert_noop!(
+				Validity::approve(
+					Origin::signed(1),
+					candidate_hash,
+					0,
+					4,
+					sign_approval(VALIDATORS[1], candidate_hash, 1),
+				),
+				Error::<Test>::InvalidApprovalSignature,
+			);
+
+			// signed for the wrong session.
+			assert_noop!(
+				Validity::approve(
+					Origin::signed(1),
+					candidate_hash,
+					0,
+					4,
+					sign_approval(VALIDATORS[0], candidate_hash, 2),
+				),
+				Error::<Test>::InvalidApprovalSignature,
+			);
+
+			// out of bounds.
+			assert_noop!(
+				Validity::approve(
+					Origin::signed(1),
+					candidate_hash,
+					4,
+					4,
+					sign_approval(Sr25519Keyring::Ferdie, candidate_hash, 1),
+				),
+				Error::<Test>::ValidatorIndexOutOfBounds,
+			);
+
+			// wrong number of validators.
+			assert_noop!(
+				Validity::approve(
+					Origin::signed(1),
+					candidate_hash,
+					0,
+					5,
+					sign_approval(VALIDATORS[0], candidate_hash, 1),
+				),
+				Error::<Test>::WrongValidatorsLen,
+			);
+
+			// not signed.
+			assert!(Validity::approve(
+				Origin::none(),
+				candidate_hash,
+				0,
+				4,
+				sign_approval(VALIDATORS[0], candidate_hash, 1),
+			).is_err());
+		});
+	}
+
+	#[test]
+	fn approvals_respect_delay_tranches() {
+		let n_delay_tranches = 8;
+		new_test_ext(genesis_config(20, 4, n_delay_tranches)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			Validity::note_included(candidate_hash, ParaId::from(1));
+
+			let tranches: Vec<_> = (0..VALIDATORS.len() as ValidatorIndex)
+				.map(|i| tranche_of(candidate_hash, i))
+				.collect();
+
+			assert!(tranches.iter().all(|t| *t < n_delay_tranches));
+
+			for block in 2..(2 + n_delay_tranches) {
+				run_to_block(block);
+
+				for (i, tranche) in tranches.iter().enumerate() {
+					let approval = Validity::approve(
+						Origin::signed(1),
+						candidate_hash,
+						i as ValidatorIndex,
+						4,
+						sign_approval(VALIDATORS[i], candidate_hash, 1),
+					);
+
+					if 2 + tranche > block {
+						assert_noop!(approval, Error::<Test>::TrancheNotReached);
+					} else if 2 + tranche == block {
+						assert!(approval.is_ok());
+					} else {
+						assert_noop!(approval, Error::<Test>::DuplicateApproval);
+					}
+				}
+			}
+
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Approved));
+		});
+	}
+
+	#[test]
+	fn previous_session_validators_can_still_approve() {
+		new_test_ext(genesis_config(10, 1, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let candidate_hash = Hash::repeat_byte(1);
+			Validity::note_included(candidate_hash, ParaId::from(1));
+
+			start_session(2, &[Sr25519Keyring::Ferdie]);
+
+			Validity::approve(
+				Origin::signed(1),
+				candidate_hash,
+				3,
+				4,
+				sign_approval(VALIDATORS[3], candidate_hash, 1),
+			).unwrap();
+
+			assert_eq!(Validity::approval_status(&candidate_hash), Some(ApprovalStatus::Approved));
+
+			start_session(3, &[Sr25519Keyring::Ferdie]);
+			assert!(Validity::session_validators(1).is_none());
+			assert!(Validity::session_validators(2).is_some());
+			assert!(Validity::session_validators(3).is_some());
+		});
+	}
+
+	#[test]
+	fn validators_are_kept_until_candidates_are_resolved() {
+		new_test_ext(genesis_config(10, 1, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let pending_hash = Hash::repeat_byte(1);
+			let disputed_hash = Hash::repeat_byte(2);
+			Validity::note_included(pending_hash, ParaId::from(1));
+			Validity::note_included(disputed_hash, ParaId::from(2));
+			Validity::note_dispute_initiated(&disputed_hash);
+
+			start_session(2, &[Sr25519Keyring::Ferdie]);
+			start_session(3, &[Sr25519Keyring::Ferdie]);
+			start_session(4, &[Sr25519Keyring::Ferdie]);
+
+			assert!(Validity::session_validators(1).is_some());
+
+			// the validators of the session can still approve the candidate.
+			Validity::approve(
+				Origin::signed(1),
+				pending_hash,
+				1,
+				4,
+				sign_approval(VALIDATORS[1], pending_hash, 1),
+			).unwrap();
+			assert_eq!(Validity::approval_status(&pending_hash), Some(ApprovalStatus::Approved));
+
+			start_session(5, &[Sr25519Keyring::Ferdie]);
+			assert!(Validity::session_validators(1).is_some());
+
+			Validity::note_dispute_concluded(&disputed_hash, false);
+
+			start_session(6, &[Sr25519Keyring::Ferdie]);
+			assert!(Validity::session_validators(1).is_none());
+			assert!(Validity::session_validators(4).is_none());
+			assert!(Validity::session_validators(5).is_some());
+			assert!(Validity::session_validators(6).is_some());
+		});
+	}
+
+	#[test]
+	fn unapproved_candidates_time_out() {
+		let acceptance_period = 5;
+		new_test_ext(genesis_config(acceptance_period, 1, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let approved_hash = Hash::repeat_byte(1);
+			let unapproved_hash = Hash::repeat_byte(2);
+			let disputed_hash = Hash::repeat_byte(3);
+			Validity::note_included(approved_hash, ParaId::from(1));
+			Validity::note_included(unapproved_hash, ParaId::from(2));
+			Validity::note_included(disputed_hash, ParaId::from(3));
+			Validity::note_dispute_initiated(&disputed_hash);
+
+			Validity::approve(
+				Origin::signed(1),
+				approved_hash,
+				0,
+				4,
+				sign_approval(VALIDATORS[0], approved_hash, 1),
+			).unwrap();
+
+			let pruned_at = 2 + acceptance_period + 1;
+			run_to_block(pruned_at - 1);
+
+			assert!(Validity::included_candidate(&approved_hash).is_some());
+			assert!(Validity::included_candidate(&unapproved_hash).is_some());
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(2));
+
+			run_to_block(pruned_at);
+
+			assert!(Validity::included_candidate(&approved_hash).is_none());
+			assert!(Validity::included_candidate(&unapproved_hash).is_none());
+			assert!(System::events().iter().any(|r| {
+				r.event == TestEvent::validity(
+					Event::ApprovalTimedOut(unapproved_hash, ParaId::from(2))
+				)
+			}));
+			assert!(Validity::pending_approvals().is_empty());
+
+			// the disputed candidate is kept until the dispute concludes.
+			assert_eq!(<TrackedCandidates<Test>>::get(), vec![(2, disputed_hash)]);
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(2));
+
+			Validity::note_dispute_concluded(&disputed_hash, true);
+			run_to_block(pruned_at + 1);
+
+			assert!(<TrackedCandidates<Test>>::get().is_empty());
+			assert!(Validity::oldest_unapproved_inclusion().is_none());
+		});
+	}
+
+	#[test]
+	fn oldest_invalid_inclusion_tracks_concluded_disputes() {
+		new_test_ext(genesis_config(5, 1, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let valid_hash = Hash::repeat_byte(1);
+			Validity::note_included(valid_hash, ParaId::from(1));
+
+			run_to_block(3);
+
+			let invalid_hash = Hash::repeat_byte(2);
+			Validity::note_included(invalid_hash, ParaId::from(2));
+
+			Validity::note_dispute_initiated(&valid_hash);
+			Validity::note_dispute_initiated(&invalid_hash);
+			assert!(Validity::oldest_invalid_inclusion().is_none());
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(2));
+
+			Validity::note_dispute_concluded(&valid_hash, true);
+			Validity::note_dispute_concluded(&invalid_hash, false);
+
+			assert_eq!(Validity::oldest_invalid_inclusion(), Some(3));
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(3));
+
+			// the invalid inclusion outlives the tracking of the candidate.
+			run_to_block(10);
+			assert!(Validity::included_candidate(&invalid_hash).is_none());
+			assert_eq!(Validity::oldest_invalid_inclusion(), Some(3));
+			assert_eq!(Validity::oldest_unapproved_inclusion(), Some(3));
+		});
+	}
+
+	#[test]
+	fn assignment_seed_is_derived_from_vrf_output() {
+		new_test_ext(genesis_config(10, 1, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(2);
+
+			let without_vrf = Hash::repeat_byte(1);
+			Validity::note_included(without_vrf, ParaId::from(1));
+
+			BLOCK_VRF_OUTPUT.with(|o| *o.borrow_mut() = Some([1; 32]));
+			let with_vrf = Hash::repeat_byte(2);
+			Validity::note_included(with_vrf, ParaId::from(2));
+
+			BLOCK_VRF_OUTPUT.with(|o| *o.borrow_mut() = Some([2; 32]));
+			let with_other_vrf = Hash::repeat_byte(3);
+			Validity::note_included(with_other_vrf, ParaId::from(3));
+
+			BLOCK_VRF_OUTPUT.with(|o| *o.borrow_mut() = None);
+
+			let seed_of = |h: Hash| Validity::included_candidate(&h).unwrap().assignment_seed;
+			let parent_hash = System::parent_hash();
+
+			assert_eq!(
+				seed_of(without_vrf),
+				BlakeTwo256::hash_of(&([1u8; 32], parent_hash)).to_fixed_bytes(),
+			);
+			assert_eq!(
+				seed_of(with_vrf),
+				BlakeTwo256::hash_of(&([1u8; 32], [1u8; 32])).to_fixed_bytes(),
+			);
+			assert_ne!(seed_of(with_vrf), seed_of(with_other_vrf));
+		});
+	}
+
+	#[test]
+	fn oldest_unavailable_inclusion_is_pruned_after_acceptance_period() {
+		new_test_ext(genesis_config(5, 1, 1)).execute_with(|| {
+			start_session(1, &VALIDATORS);
+			run_to_block(4);
+
+			Validity::note_unavailable(3);
+			Validity::note_unavailable(2);
+			Validity::note_unavailable(3);
+
+			assert_eq!(<UnavailableInclusions<Test>>::get(), vec![2, 3]);
+			assert_eq!(Validity::oldest_unavailable_inclusion(), Some(2));
+
+			// block #2 leaves the acceptance period of 5 blocks.
+			run_to_block(8);
+			assert_eq!(Validity::oldest_unavailable_inclusion(), Some(3));
+
+			run_to_block(9);
+			assert!(Validity::oldest_unavailable_inclusion().is_none());
+		});
+	}
+}
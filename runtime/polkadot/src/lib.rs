@@ -93,13 +93,13 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("polkadot"),
 	impl_name: create_runtime_str!("parity-polkadot"),
 	authoring_version: 0,
-	spec_version: 15,
+	spec_version: 17,
 	impl_version: 0,
 	#[cfg(not(feature = "disable-runtime-api"))]
 	apis: RUNTIME_API_VERSIONS,
 	#[cfg(feature = "disable-runtime-api")]
 	apis: version::create_apis_vec![[]],
-	transaction_version: 2,
+	transaction_version: 5,
 };
 
 /// Native version.
@@ -743,6 +743,8 @@ parameter_types! {
 	pub const ParathreadDeposit: Balance = 500 * DOLLARS;
 	pub const QueueSize: usize = 2;
 	pub const MaxRetries: u32 = 3;
+	// Concluding a full parathread auction weighs less than 1% of the maximum block weight.
+	pub const MaxParathreads: u32 = 100;
 }
 
 impl registrar::Trait for Runtime {
@@ -753,11 +755,15 @@ impl registrar::Trait for Runtime {
 	type SwapAux = Slots;
 	type QueueSize = QueueSize;
 	type MaxRetries = MaxRetries;
+	type MaxParathreads = MaxParathreads;
 }
 
 parameter_types! {
 	pub const LeasePeriod: BlockNumber = 100_000;
 	pub const EndingPeriod: BlockNumber = 1000;
+	// BABE randomness is derived from VRF outputs of two epochs before, so this guarantees that
+	// the randomness used to sample the end of an auction was revealed after its ending period.
+	pub const RandomnessRevealDelay: BlockNumber = 3 * EPOCH_DURATION_IN_BLOCKS;
 }
 
 impl slots::Trait for Runtime {
@@ -766,11 +772,14 @@ impl slots::Trait for Runtime {
 	type Parachains = Registrar;
 	type EndingPeriod = EndingPeriod;
 	type LeasePeriod = LeasePeriod;
-	type Randomness = RandomnessCollectiveFlip;
+	type Randomness = Babe;
+	type RandomnessRevealDelay = RandomnessRevealDelay;
 }
 
 parameter_types! {
 	pub Prefix: &'static [u8] = b"Pay DOTs to the Polkadot account:";
+	// A full batch of claims weighs less than a tenth of the maximum block weight.
+	pub const MaxClaimsPerBatch: u32 = 100;
 }
 
 impl claims::Trait for Runtime {
@@ -779,6 +788,9 @@ impl claims::Trait for Runtime {
 	type Prefix = Prefix;
 	/// At least 3/4 of the council must agree to a claim move before it can happen.
 	type MoveClaimOrigin = collective::EnsureProportionAtLeast<_3, _4, AccountId, CouncilCollective>;
+	type Signer = <Signature as Verify>::Signer;
+	type Signature = Signature;
+	type MaxClaimsPerBatch = MaxClaimsPerBatch;
 }
 
 parameter_types! {
@@ -949,6 +961,11 @@ parameter_types! {
 	pub const MaxStatementLength: usize = 1_000;
 	pub const UnlockedProportion: Permill = Permill::zero();
 	pub const MaxUnlocked: Balance = 0;
+	pub const MaxProviders: u32 = 16;
+	pub const MaxPayoutsPerBlock: u32 = 10;
+	pub const MaxPayoutScansPerBlock: u32 = 100;
+	pub const MaxQueuedPayouts: u32 = 10_000;
+	pub const MaxVestingTiers: u32 = 16;
 }
 
 ord_parameter_types! {
@@ -983,6 +1000,11 @@ impl purchase::Trait for Runtime {
 	type MaxStatementLength = MaxStatementLength;
 	type UnlockedProportion = UnlockedProportion;
 	type MaxUnlocked = MaxUnlocked;
+	type MaxProviders = MaxProviders;
+	type MaxPayoutsPerBlock = MaxPayoutsPerBlock;
+	type MaxPayoutScansPerBlock = MaxPayoutScansPerBlock;
+	type MaxQueuedPayouts = MaxQueuedPayouts;
+	type MaxVestingTiers = MaxVestingTiers;
 }
 
 construct_runtime! {
@@ -1303,6 +1325,18 @@ sp_api::impl_runtime_apis! {
 		}
 	}
 
+	impl registrar::ParathreadAuctionApi<Block, AccountId, Balance> for Runtime {
+		fn parathread_auction_winners() -> Vec<registrar::ThreadBid<AccountId, Balance>> {
+			Registrar::thread_auction_winners()
+		}
+	}
+
+	impl claims::ClaimsApi<Block, AccountId> for Runtime {
+		fn claim_payload(dest: AccountId, statement: Option<claims::StatementKind>) -> Vec<u8> {
+			Claims::claim_payload(&dest, statement)
+		}
+	}
+
 	impl transaction_payment_rpc_runtime_api::TransactionPaymentApi<
 		Block,
 		Balance,
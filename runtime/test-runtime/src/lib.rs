@@ -82,10 +82,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("polkadot-test-runtime"),
 	impl_name: create_runtime_str!("parity-polkadot-test-runtime"),
 	authoring_version: 2,
-	spec_version: 1053,
+	spec_version: 1055,
 	impl_version: 0,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 3,
 };
 
 /// Native version.
@@ -465,6 +465,8 @@ parameter_types! {
 	pub storage ParathreadDeposit: Balance = 5 * DOLLARS;
 	pub const QueueSize: usize = 2;
 	pub storage MaxRetries: u32 = 3;
+	// Concluding a full parathread auction weighs less than 1% of the maximum block weight.
+	pub const MaxParathreads: u32 = 100;
 }
 
 impl registrar::Trait for Runtime {
@@ -475,11 +477,15 @@ impl registrar::Trait for Runtime {
 	type SwapAux = Slots;
 	type QueueSize = QueueSize;
 	type MaxRetries = MaxRetries;
+	type MaxParathreads = MaxParathreads;
 }
 
 parameter_types! {
 	pub storage LeasePeriod: BlockNumber = 100_000;
 	pub storage EndingPeriod: BlockNumber = 1000;
+	// BABE randomness is derived from VRF outputs of two epochs before, so this guarantees that
+	// the randomness used to sample the end of an auction was revealed after its ending period.
+	pub storage RandomnessRevealDelay: BlockNumber = 3 * EPOCH_DURATION_IN_BLOCKS;
 }
 
 impl slots::Trait for Runtime {
@@ -488,11 +494,14 @@ impl slots::Trait for Runtime {
 	type Parachains = Registrar;
 	type LeasePeriod = LeasePeriod;
 	type EndingPeriod = EndingPeriod;
-	type Randomness = RandomnessCollectiveFlip;
+	type Randomness = Babe;
+	type RandomnessRevealDelay = RandomnessRevealDelay;
 }
 
 parameter_types! {
 	pub Prefix: &'static [u8] = b"Pay KSMs to the Kusama account:";
+	// A full batch of claims weighs less than a tenth of the maximum block weight.
+	pub const MaxClaimsPerBatch: u32 = 100;
 }
 
 impl claims::Trait for Runtime {
@@ -500,6 +509,9 @@ impl claims::Trait for Runtime {
 	type VestingSchedule = Vesting;
 	type Prefix = Prefix;
 	type MoveClaimOrigin = system::EnsureRoot<AccountId>;
+	type Signer = <Signature as Verify>::Signer;
+	type Signature = Signature;
+	type MaxClaimsPerBatch = MaxClaimsPerBatch;
 }
 
 parameter_types! {
@@ -785,6 +797,18 @@ sp_api::impl_runtime_apis! {
 		}
 	}
 
+	impl registrar::ParathreadAuctionApi<Block, AccountId, Balance> for Runtime {
+		fn parathread_auction_winners() -> Vec<registrar::ThreadBid<AccountId, Balance>> {
+			Registrar::thread_auction_winners()
+		}
+	}
+
+	impl claims::ClaimsApi<Block, AccountId> for Runtime {
+		fn claim_payload(dest: AccountId, statement: Option<claims::StatementKind>) -> Vec<u8> {
+			Claims::claim_payload(&dest, statement)
+		}
+	}
+
 	impl pallet_transaction_payment_rpc_runtime_api::TransactionPaymentApi<
 		Block,
 		Balance,
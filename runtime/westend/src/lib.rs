@@ -28,7 +28,7 @@ use primitives::v0::{
 	ActiveParas, AbridgedCandidateReceipt, SigningContext,
 };
 use runtime_common::{
-	attestations, parachains, registrar, purchase, SlowAdjustingFeeUpdate,
+	attestations, claims, parachains, registrar, purchase, SlowAdjustingFeeUpdate,
 	impls::{CurrencyToVoteHandler, ToAuthor},
 	BlockHashCount, MaximumBlockWeight, AvailableBlockRatio, MaximumBlockLength,
 	BlockExecutionWeight, ExtrinsicBaseWeight, RocksDbWeight, MaximumExtrinsicWeight,
@@ -83,13 +83,13 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("westend"),
 	impl_name: create_runtime_str!("parity-westend"),
 	authoring_version: 2,
-	spec_version: 35,
+	spec_version: 36,
 	impl_version: 0,
 	#[cfg(not(feature = "disable-runtime-api"))]
 	apis: RUNTIME_API_VERSIONS,
 	#[cfg(feature = "disable-runtime-api")]
 	apis: version::create_apis_vec![[]],
-	transaction_version: 2,
+	transaction_version: 4,
 };
 
 /// Native version.
@@ -500,6 +500,8 @@ parameter_types! {
 	pub const ParathreadDeposit: Balance = 5 * DOLLARS;
 	pub const QueueSize: usize = 2;
 	pub const MaxRetries: u32 = 3;
+	// Concluding a full parathread auction weighs less than 1% of the maximum block weight.
+	pub const MaxParathreads: u32 = 100;
 }
 
 impl registrar::Trait for Runtime {
@@ -510,6 +512,7 @@ impl registrar::Trait for Runtime {
 	type SwapAux = ();
 	type QueueSize = QueueSize;
 	type MaxRetries = MaxRetries;
+	type MaxParathreads = MaxParathreads;
 }
 
 parameter_types! {
@@ -694,6 +697,11 @@ parameter_types! {
 	pub const MaxStatementLength: usize = 1_000;
 	pub const UnlockedProportion: Permill = Permill::zero();
 	pub const MaxUnlocked: Balance = 0;
+	pub const MaxProviders: u32 = 16;
+	pub const MaxPayoutsPerBlock: u32 = 10;
+	pub const MaxPayoutScansPerBlock: u32 = 100;
+	pub const MaxQueuedPayouts: u32 = 10_000;
+	pub const MaxVestingTiers: u32 = 16;
 }
 
 ord_parameter_types! {
@@ -728,6 +736,11 @@ impl purchase::Trait for Runtime {
 	type MaxStatementLength = MaxStatementLength;
 	type UnlockedProportion = UnlockedProportion;
 	type MaxUnlocked = MaxUnlocked;
+	type MaxProviders = MaxProviders;
+	type MaxPayoutsPerBlock = MaxPayoutsPerBlock;
+	type MaxPayoutScansPerBlock = MaxPayoutScansPerBlock;
+	type MaxQueuedPayouts = MaxQueuedPayouts;
+	type MaxVestingTiers = MaxVestingTiers;
 }
 
 construct_runtime! {
@@ -1030,6 +1043,19 @@ sp_api::impl_runtime_apis! {
 		}
 	}
 
+	impl registrar::ParathreadAuctionApi<Block, AccountId, Balance> for Runtime {
+		fn parathread_auction_winners() -> Vec<registrar::ThreadBid<AccountId, Balance>> {
+			Registrar::thread_auction_winners()
+		}
+	}
+
+	impl claims::ClaimsApi<Block, AccountId> for Runtime {
+		fn claim_payload(_dest: AccountId, _statement: Option<claims::StatementKind>) -> Vec<u8> {
+			// Westend has no claims, so there is nothing for claimants to sign.
+			Vec::new()
+		}
+	}
+
 	impl transaction_payment_rpc_runtime_api::TransactionPaymentApi<
 		Block,
 		Balance,
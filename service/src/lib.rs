@@ -47,6 +47,8 @@ pub use sp_runtime::traits::{Block as BlockT, self as runtime_traits, BlakeTwo25
 pub use chain_spec::{PolkadotChainSpec, KusamaChainSpec, WestendChainSpec};
 #[cfg(feature = "full-node")]
 pub use consensus::run_validation_worker;
+#[cfg(feature = "full-node")]
+pub use consensus::pipeline::ValidationPoolConfig;
 pub use codec::Codec;
 pub use polkadot_runtime;
 pub use kusama_runtime;
@@ -89,6 +91,7 @@ pub trait RuntimeApiCollection<Extrinsic: codec::Codec + Send + Sync + 'static>:
 	+ sp_offchain::OffchainWorkerApi<Block>
 	+ sp_session::SessionKeys<Block>
 	+ authority_discovery_primitives::AuthorityDiscoveryApi<Block>
+	+ polkadot_runtime_common::claims::ClaimsApi<Block, AccountId>
 where
 	Extrinsic: RuntimeExtrinsic,
 	<Self as sp_api::ApiExt<Block>>::StateBackend: sp_api::StateBackend<BlakeTwo256>,
@@ -108,7 +111,8 @@ where
 	+ sp_api::Metadata<Block>
 	+ sp_offchain::OffchainWorkerApi<Block>
 	+ sp_session::SessionKeys<Block>
-	+ authority_discovery_primitives::AuthorityDiscoveryApi<Block>,
+	+ authority_discovery_primitives::AuthorityDiscoveryApi<Block>
+	+ polkadot_runtime_common::claims::ClaimsApi<Block, AccountId>,
 	Extrinsic: RuntimeExtrinsic,
 	<Self as sp_api::ApiExt<Block>>::StateBackend: sp_api::StateBackend<BlakeTwo256>,
 {}
@@ -300,6 +304,7 @@ macro_rules! new_full {
 		$authority_discovery_disabled:expr,
 		$slot_duration:expr,
 		$grandpa_pause:expr,
+		$validation_pool_config:expr,
 		$new_full_start:expr $(,)?
 	) => {{
 		use sc_network::Event;
@@ -375,6 +380,11 @@ macro_rules! new_full {
 		).map_err(|e| format!("Could not spawn network worker: {:?}", e))?;
 
 		let authority_handles = if is_collator || role.is_authority() {
+			let mut validation_pool_config: consensus::pipeline::ValidationPoolConfig = $validation_pool_config;
+			if validation_pool_config.artifacts_path.is_none() {
+				validation_pool_config.artifacts_path = Some(db_path.join("pvf-artifacts"));
+			}
+
 			let availability_store = {
 				use std::path::PathBuf;
 
@@ -407,6 +417,7 @@ macro_rules! new_full {
 				select_chain: select_chain.clone(),
 				keystore: keystore.clone(),
 				max_block_data_size,
+				validation_pool_config,
 			}.build();
 
 			task_manager.spawn_essential_handle().spawn("validation-service", Box::pin(validation_service));
@@ -571,6 +582,7 @@ macro_rules! new_full {
 		$authority_discovery_disabled:expr,
 		$slot_duration:expr,
 		$grandpa_pause:expr,
+		$validation_pool_config:expr,
 		$runtime:ty,
 		$dispatch:ty,
 	) => {{
@@ -581,6 +593,7 @@ macro_rules! new_full {
 			$authority_discovery_disabled,
 			$slot_duration,
 			$grandpa_pause,
+			$validation_pool_config,
 			new_full_start!($config, $runtime, $dispatch),
 		)
 	}};
@@ -601,6 +614,7 @@ macro_rules! new_full {
 			$authority_discovery_disabled,
 			$slot_duration,
 			None,
+			Default::default(),
 			new_full_start!(test $config, $runtime, $dispatch),
 		)
 	}};
@@ -737,6 +751,7 @@ pub fn polkadot_new_full(
 	authority_discovery_disabled: bool,
 	slot_duration: u64,
 	grandpa_pause: Option<(u32, u32)>,
+	validation_pool_config: ValidationPoolConfig,
 )
 	-> Result<(
 		TaskManager,
@@ -755,6 +770,7 @@ pub fn polkadot_new_full(
 		authority_discovery_disabled,
 		slot_duration,
 		grandpa_pause,
+		validation_pool_config,
 		polkadot_runtime::RuntimeApi,
 		PolkadotExecutor,
 	);
@@ -771,6 +787,7 @@ pub fn kusama_new_full(
 	authority_discovery_disabled: bool,
 	slot_duration: u64,
 	grandpa_pause: Option<(u32, u32)>,
+	validation_pool_config: ValidationPoolConfig,
 ) -> Result<(
 		TaskManager,
 		Arc<impl PolkadotClient<
@@ -789,6 +806,7 @@ pub fn kusama_new_full(
 		authority_discovery_disabled,
 		slot_duration,
 		grandpa_pause,
+		validation_pool_config,
 		kusama_runtime::RuntimeApi,
 		KusamaExecutor,
 	);
@@ -805,6 +823,7 @@ pub fn westend_new_full(
 	authority_discovery_disabled: bool,
 	slot_duration: u64,
 	grandpa_pause: Option<(u32, u32)>,
+	validation_pool_config: ValidationPoolConfig,
 )
 	-> Result<(
 		TaskManager,
@@ -823,6 +842,7 @@ pub fn westend_new_full(
 		authority_discovery_disabled,
 		slot_duration,
 		grandpa_pause,
+		validation_pool_config,
 		westend_runtime::RuntimeApi,
 		WestendExecutor,
 	);
@@ -33,7 +33,7 @@ use runtime_primitives::traits::{BlakeTwo256, Hash as HashT};
 use sp_api::ProvideRuntimeApi;
 use crate::Error;
 
-pub use parachain::wasm_executor::ValidationPool;
+pub use parachain::wasm_executor::{ValidationPool, ValidationPoolConfig};
 
 /// Does basic checks of a collation. Provide the encoded PoV-block.
 pub fn basic_checks(
@@ -216,7 +216,9 @@ pub fn validate<'a>(
 
 	let execution_mode = validation_pool
 		.map(ExecutionMode::Remote)
-		.unwrap_or(ExecutionMode::Local);
+		.unwrap_or(ExecutionMode::Local {
+			max_code_size: global_validation.max_code_size as _,
+		});
 
 	match wasm_executor::validate_candidate(
 		&validation_code.0,